@@ -34,11 +34,9 @@ fn dump(pfx: &str, pm: &mailparse::ParsedMail) {
             pm.get_body().unwrap().len()
         );
     }
-    let mut c = 1;
-    for s in &pm.subparts {
-        println!(">> Subpart {} <<", c);
+    for (i, s) in pm.subparts.iter().enumerate() {
+        println!(">> Subpart {} <<", i + 1);
         dump("subpart", s);
-        c += 1;
     }
 }
 
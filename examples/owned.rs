@@ -1,3 +1,6 @@
+// The code generated by ouroboros needs a newer Rust than the MSRV.
+#![allow(clippy::incompatible_msrv)]
+
 extern crate mailparse;
 extern crate ouroboros;
 
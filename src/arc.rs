@@ -0,0 +1,712 @@
+//! Parsing and validation of Authenticated Received Chain (ARC) headers as
+//! defined in IETF RFC 8617. This module is only available with the `dkim`
+//! feature.
+//!
+//! Intermediaries such as mailing lists often modify the messages they
+//! forward, which breaks DKIM signatures. An ARC participant records the
+//! authentication results it saw in an `ARC-Authentication-Results` header,
+//! signs the message with an `ARC-Message-Signature` and seals the chain so
+//! far with an `ARC-Seal`. The three headers form a set numbered by their
+//! `i=` tag. A later receiver that validates the chain can then decide to
+//! rely on the results recorded by participants it trusts.
+//!
+//! Keys are looked up through the same `KeyLookup` trait as for DKIM.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::authres::{parse_authentication_results, AuthResult, AuthenticationResults};
+use crate::body::decode_base64;
+use crate::canonicalization::{field_name, split_fields, write_field, Canonicalization};
+use crate::dkim::{
+    invalid, lookup_key, parse_algorithm, parse_signature_tags, strip_signature, verify_hash,
+    verify_signature, Algorithm, DkimFailure, DkimSignature, KeyLookup,
+};
+use crate::{split_once, MailParseError, ParsedMail};
+
+/// The highest instance number allowed by RFC 8617 section 4.2.1.
+const MAX_INSTANCE: u32 = 50;
+
+/// A chain validation status, as found in the `cv=` tag of an `ARC-Seal`
+/// and as the overall outcome of validating a chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChainValidation {
+    /// There is no chain, i.e. this is the first ARC set.
+    None,
+    /// The chain validated.
+    Pass,
+    /// The chain did not validate.
+    Fail,
+}
+
+impl ChainValidation {
+    /// Returns the result to record for the `arc` method in an
+    /// `Authentication-Results` header.
+    pub const fn auth_result(self) -> AuthResult {
+        match self {
+            ChainValidation::None => AuthResult::None,
+            ChainValidation::Pass => AuthResult::Pass,
+            ChainValidation::Fail => AuthResult::Fail,
+        }
+    }
+}
+
+impl FromStr for ChainValidation {
+    type Err = MailParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(ChainValidation::None),
+            "pass" => Ok(ChainValidation::Pass),
+            "fail" => Ok(ChainValidation::Fail),
+            _ => Err(MailParseError::Generic("Unknown chain validation status")),
+        }
+    }
+}
+
+impl fmt::Display for ChainValidation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ChainValidation::None => "none",
+            ChainValidation::Pass => "pass",
+            ChainValidation::Fail => "fail",
+        })
+    }
+}
+
+/// The parsed value of an `ARC-Seal` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArcSeal {
+    /// The instance number of the set (`i=`).
+    pub instance: u32,
+    /// The signing algorithm (`a=`).
+    pub algorithm: Algorithm,
+    /// The signature (`b=`).
+    pub signature: Vec<u8>,
+    /// The status of the chain as seen by the sealer (`cv=`).
+    pub chain_validation: ChainValidation,
+    /// The signing domain (`d=`).
+    pub domain: String,
+    /// The selector (`s=`) that, along with the domain, locates the key.
+    pub selector: String,
+    /// The signature timestamp (`t=`), as a UNIX timestamp.
+    pub timestamp: Option<u64>,
+}
+
+/// The parsed value of an `ARC-Message-Signature` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArcMessageSignature {
+    /// The instance number of the set (`i=`).
+    pub instance: u32,
+    /// The other tags, which are the same as those of a `DKIM-Signature`.
+    /// Since `i=` holds the instance, the identity is always `@` followed
+    /// by the domain.
+    pub signature: DkimSignature,
+}
+
+/// A complete ARC set, i.e. the three headers with the same instance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArcSet {
+    /// The instance number, starting at 1 for the first participant.
+    pub instance: u32,
+    /// The results recorded by the participant, from its
+    /// `ARC-Authentication-Results` header.
+    pub authentication_results: AuthenticationResults,
+    /// The `ARC-Message-Signature` header.
+    pub message_signature: ArcMessageSignature,
+    /// The `ARC-Seal` header.
+    pub seal: ArcSeal,
+}
+
+/// The reason an ARC chain did not validate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArcFailure {
+    /// An ARC header is malformed or uses an unsupported algorithm.
+    InvalidHeader(DkimFailure),
+    /// The ARC sets are incomplete, duplicated or not numbered from 1
+    /// without gaps.
+    InvalidStructure(&'static str),
+    /// The `cv=` tag of the given instance is not the expected one, which is
+    /// `none` for the first set and `pass` for the others. In particular, a
+    /// `fail` means that the sealer found the chain broken.
+    UnexpectedChainValidation(u32),
+    /// The `ARC-Message-Signature` of the given instance did not verify.
+    MessageSignature(u32, DkimFailure),
+    /// The `ARC-Seal` of the given instance did not verify.
+    Seal(u32, DkimFailure),
+}
+
+impl fmt::Display for ArcFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArcFailure::InvalidHeader(failure) => write!(f, "invalid ARC header: {}", failure),
+            ArcFailure::InvalidStructure(reason) => f.write_str(reason),
+            ArcFailure::UnexpectedChainValidation(i) => {
+                write!(f, "unexpected cv= tag in ARC set {}", i)
+            }
+            ArcFailure::MessageSignature(i, failure) => {
+                write!(f, "ARC-Message-Signature {}: {}", i, failure)
+            }
+            ArcFailure::Seal(i, failure) => write!(f, "ARC-Seal {}: {}", i, failure),
+        }
+    }
+}
+
+/// The outcome of validating the ARC chain of a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArcVerification {
+    /// The ARC sets, oldest first. This is empty if the ARC headers could
+    /// not be parsed into complete sets.
+    pub sets: Vec<ArcSet>,
+    /// `Ok(ChainValidation::Pass)` if the chain validated,
+    /// `Ok(ChainValidation::None)` if there is no chain, or the reason the
+    /// chain did not validate.
+    pub result: Result<ChainValidation, ArcFailure>,
+    /// For a chain that validated, the lowest instance from which all the
+    /// `ARC-Message-Signature` headers still verify. Anything higher means
+    /// that the message was modified after the first participant saw it.
+    pub oldest_pass: Option<u32>,
+}
+
+impl ArcVerification {
+    /// Returns the overall chain validation status.
+    pub const fn status(&self) -> ChainValidation {
+        match &self.result {
+            Ok(status) => *status,
+            Err(_) => ChainValidation::Fail,
+        }
+    }
+
+    /// Returns the result to record for the `arc` method in an
+    /// `Authentication-Results` header.
+    pub const fn auth_result(&self) -> AuthResult {
+        self.status().auth_result()
+    }
+}
+
+/// Parses an `i=` tag.
+fn parse_instance(value: &str) -> Result<u32, DkimFailure> {
+    match value.parse() {
+        Ok(i) if (1..=MAX_INSTANCE).contains(&i) => Ok(i),
+        _ => Err(invalid("Invalid ARC instance number")),
+    }
+}
+
+impl ArcSeal {
+    fn parse(value: &str) -> Result<ArcSeal, DkimFailure> {
+        let tags = parse_signature_tags(value)?;
+        let tags: HashMap<&str, &str> =
+            tags.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+        let required = |name| {
+            tags.get(name)
+                .copied()
+                .ok_or(invalid("Missing required tag in ARC-Seal"))
+        };
+        // RFC 8617 section 4.1.3: the seal covers the ARC headers only.
+        if tags.contains_key("h") {
+            return Err(invalid("The ARC-Seal has an h= tag"));
+        }
+        let timestamp = tags
+            .get("t")
+            .map(|t| t.parse().map_err(|_| invalid("Invalid number in ARC-Seal")))
+            .transpose()?;
+        Ok(ArcSeal {
+            instance: parse_instance(required("i")?)?,
+            algorithm: parse_algorithm(required("a")?)?,
+            signature: decode_base64(required("b")?.as_bytes())
+                .ok()
+                .ok_or(invalid("Invalid base64 in b= tag"))?,
+            chain_validation: required("cv")?
+                .parse()
+                .map_err(|_| invalid("Invalid cv= tag in ARC-Seal"))?,
+            domain: required("d")?.to_string(),
+            selector: required("s")?.to_string(),
+            timestamp,
+        })
+    }
+}
+
+impl FromStr for ArcSeal {
+    type Err = MailParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ArcSeal::parse(s).map_err(to_parse_error)
+    }
+}
+
+impl ArcMessageSignature {
+    fn parse(value: &str) -> Result<ArcMessageSignature, DkimFailure> {
+        let tags = parse_signature_tags(value)?;
+        let mut tags: HashMap<&str, &str> =
+            tags.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+        let instance = tags
+            .remove("i")
+            .ok_or(invalid("Missing required tag in ARC-Message-Signature"))?;
+        let instance = parse_instance(instance)?;
+        let signature = DkimSignature::from_tags(&tags)?;
+        if signature
+            .signed_headers
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case("ARC-Seal"))
+        {
+            return Err(invalid("The ARC-Message-Signature signs ARC-Seal headers"));
+        }
+        Ok(ArcMessageSignature {
+            instance,
+            signature,
+        })
+    }
+}
+
+impl FromStr for ArcMessageSignature {
+    type Err = MailParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ArcMessageSignature::parse(s).map_err(to_parse_error)
+    }
+}
+
+fn to_parse_error(failure: DkimFailure) -> MailParseError {
+    match failure {
+        DkimFailure::InvalidSignature(reason) => MailParseError::Generic(reason),
+        _ => MailParseError::Generic("Unsupported ARC algorithm"),
+    }
+}
+
+/// Parses the value of an `ARC-Authentication-Results` header, which is
+/// that of an `Authentication-Results` header preceded by an `i=` tag.
+fn parse_arc_authentication_results(
+    value: &str,
+) -> Result<(u32, AuthenticationResults), DkimFailure> {
+    let malformed = invalid("Malformed ARC-Authentication-Results header");
+    let (instance, rest) = split_once(value, ';').ok_or(malformed.clone())?;
+    let instance = match split_once(instance, '=') {
+        Some((name, i)) if name.trim() == "i" => parse_instance(i.trim())?,
+        _ => return Err(malformed),
+    };
+    let results = parse_authentication_results(rest).map_err(|_| malformed)?;
+    Ok((instance, results))
+}
+
+/// An ARC set along with its raw header fields, which are needed to verify
+/// the seals.
+struct RawSet<'a> {
+    set: ArcSet,
+    authentication_results: &'a [u8],
+    message_signature: &'a [u8],
+    seal: &'a [u8],
+}
+
+/// Finds and parses the ARC headers among the given raw header fields, and
+/// groups them into sets ordered by instance.
+fn collect_sets<'a>(fields: &[&'a [u8]]) -> Result<Vec<RawSet<'a>>, ArcFailure> {
+    // For each instance, the parsed headers and their raw fields.
+    type Parts<'a> = (
+        Vec<(AuthenticationResults, &'a [u8])>,
+        Vec<(ArcMessageSignature, &'a [u8])>,
+        Vec<(ArcSeal, &'a [u8])>,
+    );
+    let mut instances: HashMap<u32, Parts> = HashMap::new();
+    for &field in fields {
+        let name = field_name(field);
+        let colon = field.iter().position(|&c| c == b':').unwrap_or(0);
+        let value = String::from_utf8_lossy(&field[colon + 1..]);
+        if name.eq_ignore_ascii_case(b"ARC-Authentication-Results") {
+            let (i, results) =
+                parse_arc_authentication_results(&value).map_err(ArcFailure::InvalidHeader)?;
+            instances.entry(i).or_default().0.push((results, field));
+        } else if name.eq_ignore_ascii_case(b"ARC-Message-Signature") {
+            let ams = ArcMessageSignature::parse(&value).map_err(ArcFailure::InvalidHeader)?;
+            instances
+                .entry(ams.instance)
+                .or_default()
+                .1
+                .push((ams, field));
+        } else if name.eq_ignore_ascii_case(b"ARC-Seal") {
+            let seal = ArcSeal::parse(&value).map_err(ArcFailure::InvalidHeader)?;
+            instances
+                .entry(seal.instance)
+                .or_default()
+                .2
+                .push((seal, field));
+        }
+    }
+
+    let mut sets = Vec::new();
+    for (instance, (mut aar, mut ams, mut seal)) in instances {
+        if aar.len() > 1 || ams.len() > 1 || seal.len() > 1 {
+            return Err(ArcFailure::InvalidStructure("Duplicate ARC header"));
+        }
+        match (aar.pop(), ams.pop(), seal.pop()) {
+            (Some(aar), Some(ams), Some(seal)) => sets.push(RawSet {
+                set: ArcSet {
+                    instance,
+                    authentication_results: aar.0,
+                    message_signature: ams.0,
+                    seal: seal.0,
+                },
+                authentication_results: aar.1,
+                message_signature: ams.1,
+                seal: seal.1,
+            }),
+            _ => return Err(ArcFailure::InvalidStructure("Incomplete ARC set")),
+        }
+    }
+    sets.sort_by_key(|s| s.set.instance);
+    Ok(sets)
+}
+
+/// Checks the structure of an ARC chain, as returned by
+/// `ParsedMail::arc_sets`: the sets must be numbered from 1 without gaps,
+/// the first seal must have `cv=none` and the others `cv=pass`. This does
+/// not verify any signature.
+///
+/// # Examples
+/// ```
+///     use mailparse::parse_mail;
+///     use mailparse::arc::{check_chain_structure, ArcFailure};
+///     # let raw = std::fs::read("tests/files/dkim/arc.eml").unwrap();
+///     let mail = parse_mail(&raw).unwrap();
+///     let mut sets = mail.arc_sets().unwrap();
+///     assert_eq!(check_chain_structure(&sets), Ok(()));
+///     sets.remove(0);
+///     assert_eq!(check_chain_structure(&sets),
+///         Err(ArcFailure::InvalidStructure("ARC sets are not numbered from 1 without gaps")));
+/// ```
+pub fn check_chain_structure(sets: &[ArcSet]) -> Result<(), ArcFailure> {
+    // A sealer that found the chain broken makes it fail for good, so check
+    // that first (RFC 8617 section 5.2, step 2).
+    if let Some(last) = sets.last() {
+        if last.seal.chain_validation == ChainValidation::Fail {
+            return Err(ArcFailure::UnexpectedChainValidation(last.instance));
+        }
+    }
+    for (ix, set) in sets.iter().enumerate() {
+        if set.instance as usize != ix + 1 {
+            return Err(ArcFailure::InvalidStructure(
+                "ARC sets are not numbered from 1 without gaps",
+            ));
+        }
+        let expected = if ix == 0 {
+            ChainValidation::None
+        } else {
+            ChainValidation::Pass
+        };
+        if set.seal.chain_validation != expected {
+            return Err(ArcFailure::UnexpectedChainValidation(set.instance));
+        }
+    }
+    Ok(())
+}
+
+fn verify_message_signature<K: KeyLookup + ?Sized>(
+    mail: &ParsedMail,
+    fields: &[&[u8]],
+    raw: &RawSet,
+    keys: &K,
+) -> Result<(), DkimFailure> {
+    let sig = &raw.set.message_signature.signature;
+    lookup_key(
+        keys,
+        sig.algorithm,
+        &sig.domain,
+        &sig.selector,
+        &sig.identity,
+    )
+    .and_then(|key| verify_signature(mail, fields, raw.message_signature, sig, &key))
+}
+
+/// Verifies the seal of `sets[ix]`, which covers all the ARC headers of the
+/// sets up to and including it.
+fn verify_seal<K: KeyLookup + ?Sized>(
+    sets: &[RawSet],
+    ix: usize,
+    keys: &K,
+) -> Result<(), DkimFailure> {
+    let seal = &sets[ix].set.seal;
+    let key = lookup_key(
+        keys,
+        seal.algorithm,
+        &seal.domain,
+        &seal.selector,
+        &format!("@{}", seal.domain),
+    )?;
+    let mut data = Vec::new();
+    for (jx, raw) in sets[..=ix].iter().enumerate() {
+        write_field(
+            Canonicalization::Relaxed,
+            raw.authentication_results,
+            &mut data,
+        );
+        write_field(Canonicalization::Relaxed, raw.message_signature, &mut data);
+        if jx < ix {
+            write_field(Canonicalization::Relaxed, raw.seal, &mut data);
+        } else {
+            write_field(
+                Canonicalization::Relaxed,
+                &strip_signature(raw.seal),
+                &mut data,
+            );
+            data.truncate(data.len() - 2);
+        }
+    }
+    if verify_hash(&key, &data, &seal.signature) {
+        Ok(())
+    } else {
+        Err(DkimFailure::SignatureMismatch)
+    }
+}
+
+impl<'a> ParsedMail<'a> {
+    /// Parses the ARC headers of this message into sets, oldest first.
+    /// Returns an error if a header is malformed or if a set is missing
+    /// a header or has duplicates. Use `arc::check_chain_structure` to check
+    /// that the sets form a proper chain.
+    pub fn arc_sets(&self) -> Result<Vec<ArcSet>, ArcFailure> {
+        let fields = split_fields(self.get_headers().get_raw_bytes());
+        Ok(collect_sets(&fields)?
+            .into_iter()
+            .map(|raw| raw.set)
+            .collect())
+    }
+
+    /// Validates the ARC chain of this message as described in RFC 8617
+    /// section 5.2, looking up the public keys with `keys`. This should be
+    /// called on the top-level message, parsed from the bytes exactly as
+    /// received.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     use mailparse::arc::ChainValidation;
+    ///     use mailparse::authres::AuthResult;
+    ///     use mailparse::dkim::InMemoryKeyStore;
+    ///     # let raw = std::fs::read("tests/files/dkim/arc.eml").unwrap();
+    ///     # let mut keys = InMemoryKeyStore::new();
+    ///     # for line in std::fs::read_to_string("tests/files/dkim/keys.txt").unwrap().lines() {
+    ///     #     let mut parts = line.splitn(2, ' ');
+    ///     #     keys.add_txt(parts.next().unwrap(), parts.next().unwrap());
+    ///     # }
+    ///     let mail = parse_mail(&raw).unwrap();
+    ///     let arc = mail.verify_arc(&keys);
+    ///     assert_eq!(arc.status(), ChainValidation::Pass);
+    ///     // What the first participant saw, before the message was modified.
+    ///     let first = &arc.sets[0].authentication_results;
+    ///     assert_eq!(first.method("spf").unwrap().result, AuthResult::Pass);
+    ///     assert_eq!(arc.oldest_pass, Some(2));
+    /// ```
+    pub fn verify_arc<K: KeyLookup + ?Sized>(&self, keys: &K) -> ArcVerification {
+        let fields = split_fields(self.get_headers().get_raw_bytes());
+        let raw_sets = match collect_sets(&fields) {
+            Ok(raw_sets) => raw_sets,
+            Err(failure) => {
+                return ArcVerification {
+                    sets: Vec::new(),
+                    result: Err(failure),
+                    oldest_pass: None,
+                }
+            }
+        };
+        let sets: Vec<ArcSet> = raw_sets.iter().map(|raw| raw.set.clone()).collect();
+        let last = match raw_sets.last() {
+            Some(last) => last,
+            None => {
+                return ArcVerification {
+                    sets,
+                    result: Ok(ChainValidation::None),
+                    oldest_pass: None,
+                }
+            }
+        };
+
+        let result = check_chain_structure(&sets)
+            .and_then(|()| {
+                verify_message_signature(self, &fields, last, keys)
+                    .map_err(|e| ArcFailure::MessageSignature(last.set.instance, e))
+            })
+            .and_then(|()| {
+                (0..raw_sets.len()).rev().try_for_each(|ix| {
+                    verify_seal(&raw_sets, ix, keys)
+                        .map_err(|e| ArcFailure::Seal(raw_sets[ix].set.instance, e))
+                })
+            })
+            .map(|()| ChainValidation::Pass);
+
+        let oldest_pass = result.as_ref().ok().map(|_| {
+            let mut oldest = last.set.instance;
+            for raw in raw_sets.iter().rev().skip(1) {
+                if verify_message_signature(self, &fields, raw, keys).is_err() {
+                    break;
+                }
+                oldest = raw.set.instance;
+            }
+            oldest
+        });
+        ArcVerification {
+            sets,
+            result,
+            oldest_pass,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dkim::InMemoryKeyStore;
+    use crate::parse_mail;
+
+    fn read(name: &str) -> Vec<u8> {
+        let path = format!("./tests/files/dkim/{}", name);
+        std::fs::read(&path).unwrap_or_else(|_| panic!("Unable to open the file [{}]", path))
+    }
+
+    fn key_store() -> InMemoryKeyStore {
+        let mut keys = InMemoryKeyStore::new();
+        for line in String::from_utf8(read("keys.txt")).unwrap().lines() {
+            let (name, record) = split_once(line, ' ').unwrap();
+            keys.add_txt(name, record);
+        }
+        keys
+    }
+
+    #[test]
+    fn parse_headers() {
+        let seal: ArcSeal = "i=2; a=rsa-sha256; cv=Pass; d=example.org; s=arc; t=12; b=AAAA"
+            .parse()
+            .unwrap();
+        assert_eq!(seal.instance, 2);
+        assert_eq!(seal.chain_validation, ChainValidation::Pass);
+        assert_eq!(seal.timestamp, Some(12));
+        assert!(
+            "i=1; a=rsa-sha256; cv=none; d=example.org; s=arc; h=from; b=AAAA"
+                .parse::<ArcSeal>()
+                .is_err()
+        );
+        assert!("i=51; a=rsa-sha256; cv=none; d=example.org; s=arc; b=AAAA"
+            .parse::<ArcSeal>()
+            .is_err());
+        assert!("i=1; a=rsa-sha256; cv=maybe; d=example.org; s=arc; b=AAAA"
+            .parse::<ArcSeal>()
+            .is_err());
+
+        let ams: ArcMessageSignature =
+            "i=1; a=ed25519-sha256; c=relaxed; d=example.org; s=arc; h=from:to; bh=AAAA; b=AAAA"
+                .parse()
+                .unwrap();
+        assert_eq!(ams.instance, 1);
+        assert_eq!(ams.signature.identity, "@example.org");
+        assert_eq!(ams.signature.signed_headers, vec!["from", "to"]);
+        assert!(
+            "i=1; a=rsa-sha256; d=example.org; s=arc; h=from:arc-seal; bh=AAAA; b=AAAA"
+                .parse::<ArcMessageSignature>()
+                .is_err()
+        );
+        assert!(
+            "a=rsa-sha256; d=example.org; s=arc; h=from; bh=AAAA; b=AAAA"
+                .parse::<ArcMessageSignature>()
+                .is_err()
+        );
+
+        let (i, results) =
+            parse_arc_authentication_results("i=3; mx.example.org;\r\n\tspf=pass").unwrap();
+        assert_eq!(i, 3);
+        assert_eq!(results.authserv_id, "mx.example.org");
+        assert_eq!(results.results[0].result, AuthResult::Pass);
+        assert!(parse_arc_authentication_results("mx.example.org; spf=pass").is_err());
+        assert!(parse_arc_authentication_results("i=0; mx.example.org; spf=pass").is_err());
+    }
+
+    #[test]
+    fn structure() {
+        let raw = String::from_utf8(read("arc.eml")).unwrap();
+        let mail = parse_mail(raw.as_bytes()).unwrap();
+        let sets = mail.arc_sets().unwrap();
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].instance, 1);
+        assert_eq!(
+            sets[0].authentication_results.authserv_id,
+            "mx1.example.com"
+        );
+        assert_eq!(sets[1].seal.algorithm, Algorithm::Ed25519Sha256);
+        assert_eq!(check_chain_structure(&sets), Ok(()));
+        assert_eq!(check_chain_structure(&[]), Ok(()));
+
+        let mut failed = sets.clone();
+        failed[1].seal.chain_validation = ChainValidation::Fail;
+        assert_eq!(
+            check_chain_structure(&failed),
+            Err(ArcFailure::UnexpectedChainValidation(2))
+        );
+        let mut reversed = sets.clone();
+        reversed.reverse();
+        assert!(check_chain_structure(&reversed).is_err());
+
+        let duplicate = format!(
+            "ARC-Seal: i=1; a=rsa-sha256; cv=none; d=x; s=y; b=AAAA\r\n{}",
+            raw
+        );
+        let mail = parse_mail(duplicate.as_bytes()).unwrap();
+        assert_eq!(
+            mail.arc_sets(),
+            Err(ArcFailure::InvalidStructure("Duplicate ARC header"))
+        );
+        let incomplete = raw.replace("ARC-Authentication-Results: i=2", "X-Removed: i=2");
+        let mail = parse_mail(incomplete.as_bytes()).unwrap();
+        assert_eq!(
+            mail.arc_sets(),
+            Err(ArcFailure::InvalidStructure("Incomplete ARC set"))
+        );
+        let verification = mail.verify_arc(&key_store());
+        assert!(verification.sets.is_empty());
+        assert_eq!(verification.status(), ChainValidation::Fail);
+    }
+
+    #[test]
+    fn verify() {
+        let keys = key_store();
+        let raw = String::from_utf8(read("arc.eml")).unwrap();
+        let mail = parse_mail(raw.as_bytes()).unwrap();
+        let arc = mail.verify_arc(&keys);
+        assert_eq!(arc.result, Ok(ChainValidation::Pass));
+        assert_eq!(arc.auth_result(), AuthResult::Pass);
+        // The mailing list modified the message after the first set.
+        assert_eq!(arc.oldest_pass, Some(2));
+
+        let mail = parse_mail(b"From: alice@example.com\n\nHi\n").unwrap();
+        let arc = mail.verify_arc(&keys);
+        assert_eq!(arc.result, Ok(ChainValidation::None));
+        assert_eq!(arc.auth_result(), AuthResult::None);
+
+        let modified = raw.replace("Subject: [dev]", "Subject: [spam]");
+        let mail = parse_mail(modified.as_bytes()).unwrap();
+        assert_eq!(
+            mail.verify_arc(&keys).result,
+            Err(ArcFailure::MessageSignature(
+                2,
+                DkimFailure::SignatureMismatch
+            ))
+        );
+
+        // Changing the results recorded by an earlier participant breaks
+        // all the seals from that one on.
+        let modified = raw.replace("spf=pass", "spf=fail");
+        let mail = parse_mail(modified.as_bytes()).unwrap();
+        let arc = mail.verify_arc(&keys);
+        assert_eq!(
+            arc.result,
+            Err(ArcFailure::Seal(2, DkimFailure::SignatureMismatch))
+        );
+        assert_eq!(arc.auth_result(), AuthResult::Fail);
+        assert_eq!(arc.oldest_pass, None);
+
+        let arc = parse_mail(raw.as_bytes())
+            .unwrap()
+            .verify_arc(&InMemoryKeyStore::new());
+        assert_eq!(
+            arc.result,
+            Err(ArcFailure::MessageSignature(2, DkimFailure::KeyNotFound))
+        );
+    }
+}
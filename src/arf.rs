@@ -0,0 +1,348 @@
+//! Parsing of abuse feedback reports in the Abuse Reporting Format (ARF)
+//! defined in IETF RFC 5965, as sent by mailbox providers' feedback loops
+//! when a user marks a message as spam.
+//!
+//! A feedback report is a `multipart/report; report-type=feedback-report`
+//! message whose second part, of type `message/feedback-report`, holds the
+//! machine-readable fields, and whose third part holds the reported message
+//! (or just its headers).
+
+use std::net::IpAddr;
+
+use crate::dsn::{find_report, TypedValue};
+use crate::{
+    addrparse_header, dateparse, parse_headers, parse_mail, MailAddr, MailHeaderMap,
+    MailParseError, ParsedMail,
+};
+
+/// The type of a feedback report, from its `Feedback-Type` field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FeedbackType {
+    /// Unsolicited email or some other kind of email abuse, e.g. a message
+    /// the user marked as spam.
+    Abuse,
+    /// An email authentication failure report.
+    AuthFailure,
+    /// Indicates some kind of fraud or phishing activity.
+    Fraud,
+    /// The message was marked as not being spam.
+    NotSpam,
+    /// Any other feedback that doesn't fit into the other types.
+    Other,
+    /// A report of a virus found in the message.
+    Virus,
+    /// A feedback type not defined by RFC 5965, lowercased.
+    Unknown(String),
+}
+
+impl FeedbackType {
+    fn parse(value: &str) -> Self {
+        let value = value.trim().to_lowercase();
+        match value.as_str() {
+            "abuse" => FeedbackType::Abuse,
+            "auth-failure" => FeedbackType::AuthFailure,
+            "fraud" => FeedbackType::Fraud,
+            "not-spam" => FeedbackType::NotSpam,
+            "other" => FeedbackType::Other,
+            "virus" => FeedbackType::Virus,
+            _ => FeedbackType::Unknown(value),
+        }
+    }
+}
+
+/// The parsed contents of a `message/feedback-report` part.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Feedback {
+    /// The kind of report, from the `Feedback-Type` field.
+    pub feedback_type: FeedbackType,
+    /// The software that generated the report, e.g. `SomeGenerator/1.0`.
+    pub user_agent: Option<String>,
+    /// The version of the format; always 1 for RFC 5965 reports.
+    pub version: Option<String>,
+    /// The envelope ID of the reported message, if known.
+    pub original_envelope_id: Option<String>,
+    /// The envelope sender of the reported message, without angle brackets.
+    pub original_mail_from: Option<String>,
+    /// The envelope recipients of the reported message, without angle
+    /// brackets. For abuse reports, these are the users who complained.
+    pub original_rcpt_to: Vec<String>,
+    /// When the reported message was received, as a UNIX timestamp.
+    pub arrival_date: Option<i64>,
+    /// The MTA that received the reported message.
+    pub reporting_mta: Option<TypedValue>,
+    /// The IP address the reported message was received from.
+    pub source_ip: Option<IpAddr>,
+    /// How many times this kind of message was reported.
+    pub incidents: Option<u64>,
+    /// The `Authentication-Results` of the reported message.
+    pub authentication_results: Vec<String>,
+    /// The domains the report is about.
+    pub reported_domains: Vec<String>,
+    /// The URIs the report is about.
+    pub reported_uris: Vec<String>,
+}
+
+fn strip_angle_brackets(value: &str) -> String {
+    let value = value.trim();
+    value
+        .strip_prefix('<')
+        .and_then(|v| v.strip_suffix('>'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+/// Parses the (decoded) body of a `message/feedback-report` part. Returns an
+/// error if the required `Feedback-Type` field is missing.
+///
+/// # Examples
+/// ```
+///     use mailparse::arf::{parse_feedback, FeedbackType};
+///     let feedback = parse_feedback(concat!(
+///             "Feedback-Type: abuse\n",
+///             "User-Agent: SomeGenerator/1.0\n",
+///             "Version: 1\n",
+///             "Original-Rcpt-To: <user@example.com>\n",
+///             "Source-IP: 192.0.2.1\n").as_bytes())
+///         .unwrap();
+///     assert_eq!(feedback.feedback_type, FeedbackType::Abuse);
+///     assert_eq!(feedback.original_rcpt_to, vec!["user@example.com"]);
+///     assert_eq!(feedback.source_ip.unwrap().to_string(), "192.0.2.1");
+/// ```
+pub fn parse_feedback(body: &[u8]) -> Result<Feedback, MailParseError> {
+    let (fields, _) = parse_headers(body)?;
+    let feedback_type = fields
+        .get_first_value("Feedback-Type")
+        .map(|value| FeedbackType::parse(&value))
+        .ok_or(MailParseError::Generic(
+            "Missing Feedback-Type field in feedback report",
+        ))?;
+    let single = |name| {
+        fields
+            .get_first_value(name)
+            .map(|value| value.trim().to_string())
+    };
+    let all = |name| {
+        fields
+            .get_all_values(name)
+            .into_iter()
+            .map(|value| value.trim().to_string())
+            .collect::<Vec<_>>()
+    };
+    Ok(Feedback {
+        feedback_type,
+        user_agent: single("User-Agent"),
+        version: single("Version"),
+        original_envelope_id: single("Original-Envelope-Id"),
+        original_mail_from: single("Original-Mail-From").map(|v| strip_angle_brackets(&v)),
+        original_rcpt_to: all("Original-Rcpt-To")
+            .iter()
+            .map(|v| strip_angle_brackets(v))
+            .collect(),
+        arrival_date: single("Arrival-Date").and_then(|v| dateparse(&v).ok()),
+        reporting_mta: single("Reporting-MTA").map(|v| TypedValue::parse(&v)),
+        source_ip: single("Source-IP")
+            .and_then(|v| v.trim_start_matches('[').trim_end_matches(']').parse().ok()),
+        incidents: single("Incidents").and_then(|v| v.parse().ok()),
+        authentication_results: all("Authentication-Results"),
+        reported_domains: all("Reported-Domain"),
+        reported_uris: all("Reported-URI"),
+    })
+}
+
+/// A feedback report found in a message by `ParsedMail::feedback_report`.
+#[derive(Debug)]
+pub struct FeedbackReport<'a> {
+    /// The parsed `message/feedback-report` part.
+    pub feedback: Feedback,
+    /// The human-readable explanation of the report, if present.
+    pub explanation: Option<&'a ParsedMail<'a>>,
+    /// The part holding the reported message (`message/rfc822`) or just its
+    /// headers (`text/rfc822-headers`), if included. Either way, its decoded
+    /// body can be parsed with `parse_mail`.
+    pub original: Option<&'a ParsedMail<'a>>,
+}
+
+impl FeedbackReport<'_> {
+    /// Returns the addresses of the users the report is about, e.g. the
+    /// users who marked the message as spam. These are taken from the
+    /// `Original-Rcpt-To` fields if present, and otherwise from the `To`
+    /// header of the reported message. Note that many feedback loops redact
+    /// the recipient addresses, in which case they have to be recovered from
+    /// other information in the reported message.
+    pub fn recipients(&self) -> Vec<String> {
+        if !self.feedback.original_rcpt_to.is_empty() {
+            return self.feedback.original_rcpt_to.clone();
+        }
+        let body = match self.original.map(|part| part.get_body_raw()) {
+            Some(Ok(body)) => body,
+            _ => return Vec::new(),
+        };
+        let original = match parse_mail(&body) {
+            Ok(original) => original,
+            Err(_) => return Vec::new(),
+        };
+        let mut recipients = Vec::new();
+        for header in original.headers.get_all_headers("To") {
+            if let Ok(list) = addrparse_header(header) {
+                for addr in list.into_inner() {
+                    match addr {
+                        MailAddr::Single(info) => recipients.push(info.addr),
+                        MailAddr::Group(group) => {
+                            recipients.extend(group.addrs.into_iter().map(|info| info.addr))
+                        }
+                    }
+                }
+            }
+        }
+        recipients
+    }
+}
+
+impl<'a> ParsedMail<'a> {
+    /// Looks for an ARF feedback report in this message, i.e. a
+    /// `multipart/report` part with a `message/feedback-report` subpart, and
+    /// parses it. Returns `Ok(None)` if there is no such part, and an error
+    /// if it could not be parsed.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     use mailparse::arf::FeedbackType;
+    ///     let mail = parse_mail(concat!(
+    ///             "From: <abusedesk@example.com>\n",
+    ///             "Content-Type: multipart/report; report-type=feedback-report;\n",
+    ///             "    boundary=\"part1\"\n",
+    ///             "\n",
+    ///             "--part1\n",
+    ///             "Content-Type: text/plain\n",
+    ///             "\n",
+    ///             "This is an email abuse report.\n",
+    ///             "--part1\n",
+    ///             "Content-Type: message/feedback-report\n",
+    ///             "\n",
+    ///             "Feedback-Type: abuse\n",
+    ///             "User-Agent: SomeGenerator/1.0\n",
+    ///             "Version: 1\n",
+    ///             "--part1\n",
+    ///             "Content-Type: message/rfc822\n",
+    ///             "\n",
+    ///             "From: <somespammer@example.net>\n",
+    ///             "To: <complainer@example.com>\n",
+    ///             "Subject: Earn money\n",
+    ///             "\n",
+    ///             "Spam\n",
+    ///             "--part1--\n").as_bytes())
+    ///         .unwrap();
+    ///     let report = mail.feedback_report().unwrap().unwrap();
+    ///     assert_eq!(report.feedback.feedback_type, FeedbackType::Abuse);
+    ///     assert_eq!(report.recipients(), vec!["complainer@example.com"]);
+    /// ```
+    pub fn feedback_report(&'a self) -> Result<Option<FeedbackReport<'a>>, MailParseError> {
+        let parts = match find_report(self, &["message/feedback-report"]) {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+        Ok(Some(FeedbackReport {
+            feedback: parse_feedback(&parts.report.get_body_raw()?)?,
+            explanation: parts.explanation,
+            original: parts.original,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_all_fields() {
+        // The example from RFC 5965 appendix B.2, plus a few fields.
+        let feedback = parse_feedback(
+            concat!(
+                "Feedback-Type: abuse\r\n",
+                "User-Agent: SomeGenerator/1.0\r\n",
+                "Version: 1\r\n",
+                "Original-Mail-From: <somespammer@example.net>\r\n",
+                "Original-Rcpt-To: <user@example.com>\r\n",
+                "Original-Rcpt-To: <other@example.com>\r\n",
+                "Arrival-Date: Thu, 8 Mar 2005 14:00:00 EDT\r\n",
+                "Reporting-MTA: dns; mail.example.com\r\n",
+                "Source-IP: [2001:db8::1]\r\n",
+                "Incidents: 3\r\n",
+                "Authentication-Results: mail.example.com;\r\n",
+                "               spf=fail smtp.mail=somespammer@example.com\r\n",
+                "Reported-Domain: example.net\r\n",
+                "Reported-URI: http://example.net/earn_money.html\r\n",
+                "Reported-URI: mailto:user@example.com\r\n",
+                "Removal-Recipient: user@example.com\r\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(feedback.feedback_type, FeedbackType::Abuse);
+        assert_eq!(feedback.user_agent.as_deref(), Some("SomeGenerator/1.0"));
+        assert_eq!(feedback.version.as_deref(), Some("1"));
+        assert_eq!(
+            feedback.original_mail_from.as_deref(),
+            Some("somespammer@example.net")
+        );
+        assert_eq!(
+            feedback.original_rcpt_to,
+            vec!["user@example.com", "other@example.com"]
+        );
+        assert_eq!(feedback.arrival_date, Some(1110304800));
+        assert_eq!(feedback.reporting_mta.unwrap().value, "mail.example.com");
+        assert_eq!(
+            feedback.source_ip,
+            Some("2001:db8::1".parse::<IpAddr>().unwrap())
+        );
+        assert_eq!(feedback.incidents, Some(3));
+        assert_eq!(
+            feedback.authentication_results,
+            vec!["mail.example.com; spf=fail smtp.mail=somespammer@example.com"]
+        );
+        assert_eq!(feedback.reported_domains, vec!["example.net"]);
+        assert_eq!(feedback.reported_uris.len(), 2);
+    }
+
+    #[test]
+    fn feedback_types() {
+        let parse = |t: &str| {
+            parse_feedback(format!("Feedback-Type: {}\n", t).as_bytes())
+                .unwrap()
+                .feedback_type
+        };
+        assert_eq!(parse("Fraud"), FeedbackType::Fraud);
+        assert_eq!(parse("not-spam"), FeedbackType::NotSpam);
+        assert_eq!(parse("auth-failure"), FeedbackType::AuthFailure);
+        assert_eq!(parse("dmarc"), FeedbackType::Unknown("dmarc".to_string()));
+        assert!(parse_feedback(b"User-Agent: x\n").is_err());
+    }
+
+    #[test]
+    fn report_with_headers_only() {
+        let mail = parse_mail(
+            concat!(
+                "Content-Type: multipart/report; report-type=feedback-report; boundary=b\n",
+                "\n",
+                "--b\n",
+                "Content-Type: message/feedback-report\n",
+                "\n",
+                "Feedback-Type: abuse\n",
+                "--b\n",
+                "Content-Type: text/rfc822-headers\n",
+                "\n",
+                "To: a@example.com, list: b@example.com;\n",
+                "--b--\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let report = mail.feedback_report().unwrap().unwrap();
+        assert!(report.explanation.is_none());
+        assert_eq!(report.recipients(), vec!["a@example.com", "b@example.com"]);
+
+        let plain = parse_mail(b"Subject: hi\n\nhello\n").unwrap();
+        assert!(plain.feedback_report().unwrap().is_none());
+    }
+}
@@ -0,0 +1,463 @@
+//! Parsing of `Authentication-Results` headers as defined in IETF RFC 8601.
+//!
+//! MTAs that verify DKIM signatures, SPF, DMARC, ARC and so on record the
+//! outcome in an `Authentication-Results` header such as
+//! `mx.example.org; dkim=pass header.d=example.com; spf=fail
+//! smtp.mailfrom=example.net`. Since anyone can add such a header to a
+//! message, only the ones whose authserv-id is that of a trusted MTA should
+//! be relied on.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::msgidparse::{quoted_len, skip_cfws, unquote};
+use crate::{MailHeader, MailHeaderMap, MailParseError};
+
+/// The result of an authentication method, as registered by RFC 8601 and
+/// later documents.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuthResult {
+    /// The message passed the check.
+    Pass,
+    /// The message failed the check.
+    Fail,
+    /// The check failed weakly, e.g. SPF with a `~all` policy.
+    SoftFail,
+    /// The check was done, but the result is neither pass nor fail.
+    Neutral,
+    /// The check could not be done, e.g. because the message has no DKIM
+    /// signature.
+    None,
+    /// A policy prevented the check from passing despite its result.
+    Policy,
+    /// The check could not be completed because of a temporary error.
+    TempError,
+    /// The check could not be completed because of a permanent error, such
+    /// as a malformed record.
+    PermError,
+    /// Any other result, lowercased.
+    Other(String),
+}
+
+impl FromStr for AuthResult {
+    type Err = MailParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "pass" => AuthResult::Pass,
+            "fail" => AuthResult::Fail,
+            "softfail" => AuthResult::SoftFail,
+            "neutral" => AuthResult::Neutral,
+            "none" => AuthResult::None,
+            "policy" => AuthResult::Policy,
+            "temperror" => AuthResult::TempError,
+            "permerror" => AuthResult::PermError,
+            other => AuthResult::Other(other.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for AuthResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AuthResult::Pass => "pass",
+            AuthResult::Fail => "fail",
+            AuthResult::SoftFail => "softfail",
+            AuthResult::Neutral => "neutral",
+            AuthResult::None => "none",
+            AuthResult::Policy => "policy",
+            AuthResult::TempError => "temperror",
+            AuthResult::PermError => "permerror",
+            AuthResult::Other(other) => other,
+        })
+    }
+}
+
+/// A property of a method result, as in `header.d=example.com`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Property {
+    /// The property type, such as `smtp`, `header`, `body` or `policy`,
+    /// lowercased. This is empty for properties without a type, which some
+    /// MTAs add, as in `action=none`.
+    pub ptype: String,
+    /// The property name, such as `d` or `mailfrom`, lowercased.
+    pub name: String,
+    /// The property value, with any quoting removed.
+    pub value: String,
+}
+
+/// The result of one authentication method, as in
+/// `dkim=pass reason="good signature" header.d=example.com`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MethodResult {
+    /// The method, such as `dkim`, `spf`, `dmarc` or `arc`, lowercased.
+    pub method: String,
+    /// The version of the method, if given (as in `dkim/1=pass`).
+    pub method_version: Option<u32>,
+    /// The result.
+    pub result: AuthResult,
+    /// The explanation given with `reason=`, if any.
+    pub reason: Option<String>,
+    /// The properties, in the order given.
+    pub properties: Vec<Property>,
+}
+
+impl MethodResult {
+    /// Returns the value of the first property with the given type and
+    /// name, matched case-insensitively.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::authres::parse_authentication_results;
+    ///     let results = parse_authentication_results(
+    ///         "mx.example.org; spf=pass smtp.mailfrom=alice@example.com").unwrap();
+    ///     assert_eq!(results.results[0].property("smtp", "mailfrom"),
+    ///         Some("alice@example.com"));
+    /// ```
+    pub fn property(&self, ptype: &str, name: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|p| p.ptype.eq_ignore_ascii_case(ptype) && p.name.eq_ignore_ascii_case(name))
+            .map(|p| p.value.as_str())
+    }
+}
+
+/// The parsed value of an `Authentication-Results` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthenticationResults {
+    /// The identifier of the MTA that did the checks.
+    pub authserv_id: String,
+    /// The version of the header format, if given.
+    pub version: Option<u32>,
+    /// The results, in the order given. This is empty if the header says
+    /// `none`, i.e. that no checks were done.
+    pub results: Vec<MethodResult>,
+}
+
+impl AuthenticationResults {
+    /// Returns the first result for the given method, matched
+    /// case-insensitively.
+    pub fn method(&self, method: &str) -> Option<&MethodResult> {
+        self.results
+            .iter()
+            .find(|r| r.method.eq_ignore_ascii_case(method))
+    }
+}
+
+fn cfws(s: &str) -> Result<&str, MailParseError> {
+    skip_cfws(s).map_err(|_| {
+        MailParseError::Generic("Unterminated comment in Authentication-Results header")
+    })
+}
+
+/// Returns the length of the keyword (letters, digits, '-' and '_') that `s`
+/// starts with.
+fn keyword_len(s: &str) -> usize {
+    s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(s.len())
+}
+
+fn keyword(s: &str) -> Result<(&str, &str), MailParseError> {
+    match keyword_len(s) {
+        0 => Err(MailParseError::Generic(
+            "Expected a keyword in Authentication-Results header",
+        )),
+        len => Ok((&s[..len], &s[len..])),
+    }
+}
+
+fn expect(s: &str, c: char) -> Result<&str, MailParseError> {
+    cfws(s)?.strip_prefix(c).ok_or(MailParseError::Generic(
+        "Unexpected character in Authentication-Results header",
+    ))
+}
+
+/// Parses a value, which is a quoted string or a run of characters that
+/// ends at whitespace, a comment or a semicolon. This is more lenient than
+/// the RFC's token, so that property values such as `user@example.com` or
+/// base64 signature prefixes are read whole. A quoted local part followed
+/// by a domain, as in `"john doe"@example.com`, is read as well.
+fn value(s: &str) -> Result<(String, &str), MailParseError> {
+    let mut result = String::new();
+    let mut rest = s;
+    if rest.starts_with('"') {
+        let len = quoted_len(rest).ok_or(MailParseError::Generic(
+            "Unterminated quoted string in Authentication-Results header",
+        ))?;
+        result.push_str(&unquote(&rest[1..len - 1]));
+        rest = &rest[len..];
+    }
+    let end = rest
+        .find(|c: char| c.is_whitespace() || c == ';' || c == '(' || c == '"')
+        .unwrap_or(rest.len());
+    result.push_str(&rest[..end]);
+    Ok((result, &rest[end..]))
+}
+
+fn digits(s: &str) -> Option<(u32, &str)> {
+    let len = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s[..len].parse().ok().map(|n| (n, &s[len..]))
+}
+
+/// Parses the properties and reason of a method result, up to the next
+/// semicolon.
+fn parse_properties<'a>(
+    mut rest: &'a str,
+    result: &mut MethodResult,
+) -> Result<&'a str, MailParseError> {
+    loop {
+        rest = cfws(rest)?;
+        if rest.is_empty() || rest.starts_with(';') {
+            return Ok(rest);
+        }
+        let (word, after) = match keyword(rest) {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                // Skip anything unrecognizable up to the next property.
+                let (_, after) = value(&rest[rest.chars().next().map_or(1, char::len_utf8)..])?;
+                rest = after;
+                continue;
+            }
+        };
+        let after = cfws(after)?;
+        if let Some(after) = after.strip_prefix('.') {
+            let (name, after) = keyword(cfws(after)?)?;
+            let (value, after) = value(cfws(expect(after, '=')?)?)?;
+            result.properties.push(Property {
+                ptype: word.to_lowercase(),
+                name: name.to_lowercase(),
+                value,
+            });
+            rest = after;
+        } else if let Some(after) = after.strip_prefix('=') {
+            let (value, after) = value(cfws(after)?)?;
+            if word.eq_ignore_ascii_case("reason") && result.reason.is_none() {
+                result.reason = Some(value);
+            } else {
+                result.properties.push(Property {
+                    ptype: String::new(),
+                    name: word.to_lowercase(),
+                    value,
+                });
+            }
+            rest = after;
+        } else {
+            rest = after;
+        }
+    }
+}
+
+/// Parses the value of an `Authentication-Results` header.
+///
+/// # Examples
+/// ```
+///     use mailparse::authres::{parse_authentication_results, AuthResult};
+///     let results = parse_authentication_results(concat!(
+///             "mx.example.org 1; dkim=pass (good signature) header.d=example.com\n",
+///             " header.s=sel1; spf=fail smtp.mailfrom=example.net;\n",
+///             " dmarc=fail reason=\"p=reject\" header.from=example.com")).unwrap();
+///     assert_eq!(results.authserv_id, "mx.example.org");
+///     assert_eq!(results.version, Some(1));
+///     assert_eq!(results.results.len(), 3);
+///     let dkim = results.method("dkim").unwrap();
+///     assert_eq!(dkim.result, AuthResult::Pass);
+///     assert_eq!(dkim.property("header", "d"), Some("example.com"));
+///     let dmarc = results.method("dmarc").unwrap();
+///     assert_eq!(dmarc.reason, Some("p=reject".to_string()));
+/// ```
+pub fn parse_authentication_results(s: &str) -> Result<AuthenticationResults, MailParseError> {
+    let rest = cfws(s)?;
+    let (authserv_id, rest) = value(rest)?;
+    if authserv_id.is_empty() {
+        return Err(MailParseError::Generic(
+            "Missing authserv-id in Authentication-Results header",
+        ));
+    }
+    let mut rest = cfws(rest)?;
+    let mut version = None;
+    if let Some((v, after)) = digits(rest) {
+        version = Some(v);
+        rest = cfws(after)?;
+    }
+
+    let mut results = Vec::new();
+    while !rest.is_empty() {
+        rest = cfws(expect(rest, ';')?)?;
+        if rest.is_empty() {
+            // Tolerate a trailing semicolon.
+            break;
+        }
+        let (method, after) = keyword(rest)?;
+        let after = cfws(after)?;
+        if method.eq_ignore_ascii_case("none") && !after.starts_with(['/', '=']) {
+            rest = after;
+            continue;
+        }
+        let mut method_version = None;
+        let mut after = after;
+        if let Some(v) = after.strip_prefix('/') {
+            let (v, a) = digits(cfws(v)?).ok_or(MailParseError::Generic(
+                "Invalid method version in Authentication-Results header",
+            ))?;
+            method_version = Some(v);
+            after = a;
+        }
+        let (result, after) = keyword(cfws(expect(after, '=')?)?)?;
+        let mut method_result = MethodResult {
+            method: method.to_lowercase(),
+            method_version,
+            result: result.parse()?,
+            reason: None,
+            properties: Vec::new(),
+        };
+        rest = parse_properties(after, &mut method_result)?;
+        results.push(method_result);
+    }
+
+    Ok(AuthenticationResults {
+        authserv_id,
+        version,
+        results,
+    })
+}
+
+/// Parses all the `Authentication-Results` headers in the given headers,
+/// skipping any that are malformed. The results are in the order of the
+/// headers, i.e. the most recently added first.
+pub fn authentication_results(headers: &[MailHeader]) -> Vec<AuthenticationResults> {
+    headers
+        .get_all_values("Authentication-Results")
+        .iter()
+        .filter_map(|v| parse_authentication_results(v).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prop(ptype: &str, name: &str, value: &str) -> Property {
+        Property {
+            ptype: ptype.to_string(),
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn rfc_examples() {
+        let r = parse_authentication_results("example.org 1; none").unwrap();
+        assert_eq!(r.authserv_id, "example.org");
+        assert_eq!(r.version, Some(1));
+        assert!(r.results.is_empty());
+
+        let r = parse_authentication_results(
+            "example.com;\n\
+             \x20         auth=pass (cram-md5) smtp.auth=sender@example.net;\n\
+             \x20         spf=pass smtp.mailfrom=example.net",
+        )
+        .unwrap();
+        assert_eq!(r.version, None);
+        assert_eq!(r.results.len(), 2);
+        assert_eq!(r.results[0].method, "auth");
+        assert_eq!(
+            r.results[0].properties,
+            vec![prop("smtp", "auth", "sender@example.net")]
+        );
+        assert_eq!(r.results[1].result, AuthResult::Pass);
+
+        let r = parse_authentication_results(
+            "mail-router.example.net;\n\
+             \x20     dkim=pass (good signature) header.d=newyork.example.com\n\
+             \x20           header.b=oINEO8hg;\n\
+             \x20     dkim=fail (bad signature) header.d=newyork.example.com\n\
+             \x20           header.b=EToRSuvU",
+        )
+        .unwrap();
+        assert_eq!(r.results[1].result, AuthResult::Fail);
+        assert_eq!(r.results[1].property("header", "b"), Some("EToRSuvU"));
+
+        let r = parse_authentication_results(
+            "foo.example.net (foobar) 1 (baz);\n\
+             \x20  dkim (Because I like it) / 1 (One yay) = (wait for it) fail\n\
+             \x20    policy (A dot can go here) . (like that) expired\n\
+             \x20    (this surprised me) = (as I wasn't expecting it) 1362471462",
+        )
+        .unwrap();
+        assert_eq!(r.authserv_id, "foo.example.net");
+        assert_eq!(r.version, Some(1));
+        assert_eq!(r.results[0].method_version, Some(1));
+        assert_eq!(r.results[0].result, AuthResult::Fail);
+        assert_eq!(
+            r.results[0].properties,
+            vec![prop("policy", "expired", "1362471462")]
+        );
+    }
+
+    #[test]
+    fn real_world() {
+        let r = parse_authentication_results(
+            "mx.google.com;\n       dkim=pass header.i=@example.com header.s=20210112 \
+             header.b=fgKP0Ozg;\n       spf=pass (google.com: domain of alice@example.com \
+             designates 192.0.2.1 as permitted sender) smtp.mailfrom=alice@example.com;\n\
+             \x20      dmarc=pass (p=NONE sp=QUARANTINE dis=NONE) header.from=example.com",
+        )
+        .unwrap();
+        assert_eq!(r.results.len(), 3);
+        assert_eq!(r.results[0].property("header", "i"), Some("@example.com"));
+        assert_eq!(
+            r.method("dmarc").unwrap().property("header", "from"),
+            Some("example.com")
+        );
+
+        let r = parse_authentication_results(
+            "spf=pass (sender IP is 192.0.2.1) smtp.mailfrom=example.com; dkim=none \
+             (message not signed) header.d=none;dmarc=none action=none \
+             header.from=example.com;compauth=pass reason=100",
+        );
+        // Outlook.com omits the required authserv-id.
+        assert!(r.is_err());
+
+        let r = parse_authentication_results(
+            "example.com; dkim=neutral reason=\"invalid (public key: not available)\" \
+             header.d=example.com; arc=pass (i=1 spf=pass) smtp.remote-ip=192.0.2.1;",
+        )
+        .unwrap();
+        assert_eq!(
+            r.results[0].reason.as_deref(),
+            Some("invalid (public key: not available)")
+        );
+        assert_eq!(r.results[1].method, "arc");
+        assert_eq!(
+            r.results[1].property("smtp", "remote-ip"),
+            Some("192.0.2.1")
+        );
+
+        let r = parse_authentication_results(
+            "example.com; dmarc=none action=none header.from=\"john doe\"@example.com",
+        )
+        .unwrap();
+        assert_eq!(
+            r.results[0].properties,
+            vec![
+                prop("", "action", "none"),
+                prop("header", "from", "john doe@example.com")
+            ]
+        );
+        assert_eq!(r.results[0].result, AuthResult::None);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_authentication_results("").is_err());
+        assert!(parse_authentication_results("example.com; dkim").is_err());
+        assert!(parse_authentication_results("example.com (unterminated").is_err());
+        assert!(parse_authentication_results("example.com; dkim=pass header.d=\"x").is_err());
+        let r = parse_authentication_results("example.com; x-custom=hardfail").unwrap();
+        assert_eq!(
+            r.results[0].result,
+            AuthResult::Other("hardfail".to_string())
+        );
+        assert_eq!(r.results[0].result.to_string(), "hardfail");
+    }
+}
@@ -0,0 +1,306 @@
+//! Parsing and generation of the `Autocrypt` and `Autocrypt-Gossip` headers
+//! defined by the Autocrypt Level 1 specification.
+//!
+//! An `Autocrypt` header carries the OpenPGP public key of the sender and
+//! their encryption preference. `Autocrypt-Gossip` headers, found in the
+//! headers of the decrypted payload of an encrypted message, carry the keys
+//! of the other recipients.
+
+use std::fmt;
+use std::str::FromStr;
+
+use data_encoding::BASE64;
+
+use crate::body::decode_base64;
+use crate::{addrparse_header, split_once, MailHeaderMap, MailParseError, ParsedMail};
+
+/// The encryption preference of the sender of an `Autocrypt` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreferEncrypt {
+    /// The sender wants encryption whenever the recipients support it.
+    Mutual,
+    /// The sender has no preference. This is the default when the
+    /// `prefer-encrypt` attribute is missing or has another value.
+    NoPreference,
+}
+
+impl Default for PreferEncrypt {
+    fn default() -> Self {
+        PreferEncrypt::NoPreference
+    }
+}
+
+impl fmt::Display for PreferEncrypt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreferEncrypt::Mutual => f.write_str("mutual"),
+            PreferEncrypt::NoPreference => f.write_str("nopreference"),
+        }
+    }
+}
+
+/// The value of an `Autocrypt` or `Autocrypt-Gossip` header, as in
+/// `Autocrypt: addr=alice@example.com; prefer-encrypt=mutual; keydata=mDMEX...`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AutocryptHeader {
+    /// The address the key belongs to.
+    pub addr: String,
+    /// The encryption preference. This is always `NoPreference` for
+    /// `Autocrypt-Gossip` headers.
+    pub prefer_encrypt: PreferEncrypt,
+    /// The binary OpenPGP public key (a transferable public key, without
+    /// ASCII armor).
+    pub keydata: Vec<u8>,
+}
+
+impl FromStr for AutocryptHeader {
+    type Err = MailParseError;
+
+    /// Parses an unfolded header value, as returned by
+    /// `MailHeader::get_value`. The whitespace left by unfolding the key
+    /// data is ignored. Attributes whose name starts with an underscore are
+    /// ignored, while other unknown attributes make the header invalid.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut addr = None;
+        let mut prefer_encrypt = PreferEncrypt::NoPreference;
+        let mut keydata = None;
+        for attr in s.split(';') {
+            let attr = attr.trim();
+            if attr.is_empty() {
+                continue;
+            }
+            let (name, value) = split_once(attr, '=').ok_or(MailParseError::Generic(
+                "Autocrypt attribute is missing a '='",
+            ))?;
+            match name.trim().to_lowercase().as_str() {
+                "addr" => addr = Some(value.trim().to_string()),
+                "prefer-encrypt" => {
+                    if value.trim().eq_ignore_ascii_case("mutual") {
+                        prefer_encrypt = PreferEncrypt::Mutual;
+                    }
+                }
+                "keydata" => keydata = Some(decode_base64(value.as_bytes())?),
+                name if name.starts_with('_') => (),
+                _ => {
+                    return Err(MailParseError::Generic(
+                        "Unknown critical Autocrypt attribute",
+                    ))
+                }
+            }
+        }
+        let addr = addr
+            .filter(|a| !a.is_empty())
+            .ok_or(MailParseError::Generic("Autocrypt header is missing addr"))?;
+        let keydata = keydata
+            .filter(|k| !k.is_empty())
+            .ok_or(MailParseError::Generic(
+                "Autocrypt header is missing keydata",
+            ))?;
+        Ok(AutocryptHeader {
+            addr,
+            prefer_encrypt,
+            keydata,
+        })
+    }
+}
+
+impl fmt::Display for AutocryptHeader {
+    /// Formats the header value on a single line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "addr={}; ", self.addr)?;
+        if self.prefer_encrypt == PreferEncrypt::Mutual {
+            write!(f, "prefer-encrypt=mutual; ")?;
+        }
+        write!(f, "keydata={}", BASE64.encode(&self.keydata))
+    }
+}
+
+impl AutocryptHeader {
+    /// Formats a complete header with the given name, ending with CRLF, with
+    /// the key data folded into lines of 76 characters.
+    fn to_field(&self, name: &str, prefer_encrypt: PreferEncrypt) -> String {
+        let mut field = format!("{}: addr={}; ", name, self.addr);
+        if prefer_encrypt == PreferEncrypt::Mutual {
+            field.push_str("prefer-encrypt=mutual; ");
+        }
+        field.push_str("keydata=");
+        let keydata = BASE64.encode(&self.keydata);
+        for chunk in keydata.as_bytes().chunks(76) {
+            field.push_str("\r\n ");
+            // Base64 is ASCII, so the chunks are valid UTF-8.
+            field.push_str(std::str::from_utf8(chunk).unwrap());
+        }
+        field.push_str("\r\n");
+        field
+    }
+
+    /// Formats this as an `Autocrypt` header for an outgoing message,
+    /// ending with CRLF.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::autocrypt::{AutocryptHeader, PreferEncrypt};
+    ///     let header = AutocryptHeader {
+    ///         addr: "alice@example.com".to_string(),
+    ///         prefer_encrypt: PreferEncrypt::Mutual,
+    ///         keydata: vec![0x98, 0x33, 0x04],
+    ///     };
+    ///     assert_eq!(
+    ///         header.to_header(),
+    ///         "Autocrypt: addr=alice@example.com; prefer-encrypt=mutual; keydata=\r\n mDME\r\n"
+    ///     );
+    /// ```
+    pub fn to_header(&self) -> String {
+        self.to_field("Autocrypt", self.prefer_encrypt)
+    }
+
+    /// Formats this as an `Autocrypt-Gossip` header, ending with CRLF. This
+    /// goes in the headers of the payload of an encrypted message, and does
+    /// not include the `prefer-encrypt` attribute.
+    pub fn to_gossip_header(&self) -> String {
+        self.to_field("Autocrypt-Gossip", PreferEncrypt::NoPreference)
+    }
+}
+
+impl<'a> ParsedMail<'a> {
+    /// Returns the `Autocrypt` header of the sender. As the Autocrypt
+    /// specification requires, this returns `None` unless there is exactly
+    /// one valid `Autocrypt` header whose address matches the single `From`
+    /// address.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     use mailparse::autocrypt::PreferEncrypt;
+    ///     let mail = parse_mail(concat!(
+    ///             "From: Alice <Alice@example.com>\n",
+    ///             "Autocrypt: addr=alice@example.com; prefer-encrypt=mutual; keydata=\n",
+    ///             " mDME\n",
+    ///             "Autocrypt: addr=mallory@example.com; keydata=mDME\n",
+    ///             "\n",
+    ///             "Hello\n").as_bytes())
+    ///         .unwrap();
+    ///     let autocrypt = mail.autocrypt().unwrap();
+    ///     assert_eq!(autocrypt.addr, "alice@example.com");
+    ///     assert_eq!(autocrypt.prefer_encrypt, PreferEncrypt::Mutual);
+    ///     assert_eq!(autocrypt.keydata, vec![0x98, 0x33, 0x04]);
+    /// ```
+    pub fn autocrypt(&self) -> Option<AutocryptHeader> {
+        let mut from = self.headers.get_all_headers("From").into_iter();
+        let from = match (from.next(), from.next()) {
+            (Some(header), None) => addrparse_header(header).ok()?.extract_single_info()?,
+            _ => return None,
+        };
+        let mut headers = self
+            .headers
+            .get_all_values("Autocrypt")
+            .into_iter()
+            .filter_map(|value| value.parse::<AutocryptHeader>().ok())
+            .filter(|header| header.addr.eq_ignore_ascii_case(&from.addr));
+        match (headers.next(), headers.next()) {
+            (Some(header), None) => Some(header),
+            _ => None,
+        }
+    }
+
+    /// Returns the valid `Autocrypt-Gossip` headers. These are only
+    /// meaningful in the headers of a decrypted payload, and callers should
+    /// only keep the ones whose address is a recipient of the message.
+    pub fn autocrypt_gossip(&self) -> Vec<AutocryptHeader> {
+        self.headers
+            .get_all_values("Autocrypt-Gossip")
+            .into_iter()
+            .filter_map(|value| value.parse::<AutocryptHeader>().ok())
+            .map(|mut header| {
+                header.prefer_encrypt = PreferEncrypt::NoPreference;
+                header
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mail;
+
+    fn read(name: &str) -> Vec<u8> {
+        let path = format!("./tests/files/autocrypt/{}", name);
+        std::fs::read(&path).unwrap_or_else(|_| panic!("Unable to open the file [{}]", path))
+    }
+
+    #[test]
+    fn parse_headers() {
+        let raw = read("autocrypt.eml");
+        let mail = parse_mail(&raw).unwrap();
+        let autocrypt = mail.autocrypt().unwrap();
+        assert_eq!(autocrypt.addr, "alice@example.com");
+        assert_eq!(autocrypt.prefer_encrypt, PreferEncrypt::Mutual);
+        assert_eq!(autocrypt.keydata, read("alice.pgp"));
+
+        let gossip = mail.autocrypt_gossip();
+        assert_eq!(gossip.len(), 1);
+        assert_eq!(gossip[0].addr, "bob@example.org");
+        assert_eq!(gossip[0].prefer_encrypt, PreferEncrypt::NoPreference);
+        assert_eq!(gossip[0].keydata.len(), 1737);
+    }
+
+    #[test]
+    fn round_trip() {
+        let header = AutocryptHeader {
+            addr: "alice@example.com".to_string(),
+            prefer_encrypt: PreferEncrypt::Mutual,
+            keydata: read("alice.pgp"),
+        };
+        let field = header.to_header();
+        assert!(field.lines().all(|line| line.len() <= 78));
+        let (parsed, _) = crate::parse_header(field.as_bytes()).unwrap();
+        assert_eq!(parsed.get_key(), "Autocrypt");
+        assert_eq!(
+            parsed.get_value().parse::<AutocryptHeader>().unwrap(),
+            header
+        );
+        assert_eq!(
+            header.to_string().parse::<AutocryptHeader>().unwrap(),
+            header
+        );
+
+        let gossip = header.to_gossip_header();
+        assert!(gossip.starts_with("Autocrypt-Gossip: addr=alice@example.com; keydata=\r\n "));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!("addr=a@example.com; keydata=mDME; _extra=1"
+            .parse::<AutocryptHeader>()
+            .is_ok());
+        assert_eq!(
+            "addr=a@example.com; prefer-encrypt=never; keydata=mDME"
+                .parse::<AutocryptHeader>()
+                .unwrap()
+                .prefer_encrypt,
+            PreferEncrypt::NoPreference
+        );
+        assert!("addr=a@example.com; keydata=mDME; critical=1"
+            .parse::<AutocryptHeader>()
+            .is_err());
+        assert!("addr=a@example.com".parse::<AutocryptHeader>().is_err());
+        assert!("keydata=mDME".parse::<AutocryptHeader>().is_err());
+        assert!("addr=a@example.com; keydata=!!"
+            .parse::<AutocryptHeader>()
+            .is_err());
+
+        // Two valid headers for the sender cancel each other out.
+        let mail = parse_mail(
+            concat!(
+                "From: a@example.com\n",
+                "Autocrypt: addr=a@example.com; keydata=mDME\n",
+                "Autocrypt: addr=a@example.com; keydata=mDMF\n",
+                "\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(mail.autocrypt(), None);
+    }
+}
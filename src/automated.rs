@@ -0,0 +1,344 @@
+//! Detection of automatically generated messages.
+//!
+//! Software that responds to messages automatically (vacation responders,
+//! ticket systems, ...) must take care not to respond to messages that were
+//! themselves sent automatically, to avoid mail loops and flooding mailing
+//! lists. `classify_automated` implements the checks recommended by IETF
+//! RFC 3834 section 2, plus the common non-standard headers and
+//! autoresponder conventions seen in practice.
+
+use crate::{addrparse_header, MailAddr, MailHeaderMap, ParsedMail};
+
+/// What kind of automated message a message is. The variants are ordered by
+/// how specific they are, so a message that is both an auto-reply and sent
+/// to a mailing list is classified as an auto-reply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AutomatedKind {
+    /// An automatic response to another message, such as a vacation notice.
+    AutoReply,
+    /// A message generated by software, such as a notification, a bounce,
+    /// or a message whose sender asked not to be auto-responded to.
+    AutoGenerated,
+    /// A message distributed through a mailing list.
+    MailingList,
+    /// Bulk mail such as newsletters or advertising.
+    Bulk,
+}
+
+/// A sign that a message was sent automatically.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// An `Auto-Submitted` header (RFC 3834) with a value other than `no`,
+    /// such as `auto-replied` or `auto-generated`. Holds the value,
+    /// lowercased.
+    AutoSubmitted(String),
+    /// An `X-Auto-Response-Suppress` header, used by Microsoft software to
+    /// ask for automatic responses to be suppressed. Holds the value.
+    AutoResponseSuppress(String),
+    /// A `Precedence` header with the value `bulk`, `list` or `junk`.
+    /// Holds the value, lowercased.
+    Precedence(String),
+    /// A `List-Id` header (or another `List-*` header, RFC 2369). Holds the
+    /// header name.
+    ListHeader(String),
+    /// An `X-Autoreply`, `X-Autorespond` or similar header, as added by
+    /// various autoresponders. Holds the header name.
+    AutoreplyHeader(String),
+    /// An empty `Return-Path` (`<>`), as used by bounces and other
+    /// notifications.
+    NullReturnPath,
+    /// A subject that starts the way autoresponders' subjects do, such as
+    /// "Out of Office" or "Automatic reply:". Holds the subject.
+    AutoresponderSubject(String),
+    /// A sender address that is used by software rather than people, such
+    /// as `MAILER-DAEMON`, `noreply` or `owner-*` addresses. Holds the
+    /// address.
+    AutomatedSender(String),
+}
+
+impl Reason {
+    /// Returns the kind of automated message this reason indicates.
+    pub fn kind(&self) -> AutomatedKind {
+        match self {
+            Reason::AutoSubmitted(value) if value.starts_with("auto-replied") => {
+                AutomatedKind::AutoReply
+            }
+            Reason::AutoreplyHeader(_) | Reason::AutoresponderSubject(_) => {
+                AutomatedKind::AutoReply
+            }
+            Reason::Precedence(value) if value == "list" => AutomatedKind::MailingList,
+            Reason::ListHeader(_) => AutomatedKind::MailingList,
+            Reason::Precedence(_) => AutomatedKind::Bulk,
+            Reason::AutoSubmitted(_)
+            | Reason::AutoResponseSuppress(_)
+            | Reason::NullReturnPath
+            | Reason::AutomatedSender(_) => AutomatedKind::AutoGenerated,
+        }
+    }
+}
+
+/// The result of `classify_automated`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AutomatedClassification {
+    /// The most specific kind among the reasons found, or `None` if there
+    /// were no signs of the message being automated.
+    pub kind: Option<AutomatedKind>,
+    /// All the signs found that the message was sent automatically.
+    pub reasons: Vec<Reason>,
+}
+
+impl AutomatedClassification {
+    /// Returns true if there are any signs that the message was sent
+    /// automatically, in which case it should not be automatically
+    /// responded to.
+    pub fn is_automated(&self) -> bool {
+        !self.reasons.is_empty()
+    }
+}
+
+/// Headers added by autoresponders to their replies.
+const AUTOREPLY_HEADERS: &[&str] = &[
+    "X-Autoreply",
+    "X-Autorespond",
+    "X-Autoresponder",
+    "X-Autogenerated",
+];
+
+/// Subject prefixes of automatic replies, lowercased.
+const AUTOREPLY_SUBJECTS: &[&str] = &[
+    "automatic reply:",
+    "auto:",
+    "autoreply",
+    "auto-reply",
+    "auto reply",
+    "auto response",
+    "autoresponse",
+    "out of office",
+    "out of the office",
+    "abwesenheitsnotiz",
+    "réponse automatique",
+    "respuesta automática",
+];
+
+/// Local parts of addresses used for automated mail, lowercased. RFC 3834
+/// section 2 lists most of these.
+const AUTOMATED_NAMES: &[&str] = &[
+    "mailer-daemon",
+    "mailerdaemon",
+    "mail-daemon",
+    "postmaster",
+    "listserv",
+    "majordomo",
+];
+
+fn is_automated_local_part(local: &str) -> bool {
+    let local = local.to_lowercase();
+    AUTOMATED_NAMES.contains(&local.as_str())
+        || local.starts_with("owner-")
+        || local.ends_with("-request")
+        || local.ends_with("-bounces")
+        || [
+            "noreply",
+            "no-reply",
+            "no_reply",
+            "donotreply",
+            "do-not-reply",
+        ]
+        .iter()
+        .any(|n| local.starts_with(n))
+}
+
+/// Inspects the headers of a message for signs that it was sent
+/// automatically, and returns the kind of automated message along with all
+/// the reasons found.
+///
+/// # Examples
+/// ```
+///     use mailparse::parse_mail;
+///     use mailparse::automated::{classify_automated, AutomatedKind, Reason};
+///     let mail = parse_mail(concat!(
+///             "From: Bob <bob@example.com>\n",
+///             "Subject: Out of Office: Lunch\n",
+///             "Auto-Submitted: auto-replied (vacation)\n",
+///             "\n",
+///             "I'm away.\n").as_bytes())
+///         .unwrap();
+///     let classification = classify_automated(&mail);
+///     assert_eq!(classification.kind, Some(AutomatedKind::AutoReply));
+///     assert!(classification.reasons.contains(&Reason::AutoSubmitted("auto-replied".to_string())));
+///
+///     let human = parse_mail(b"From: bob@example.com\nSubject: Lunch\n\nPizza?\n").unwrap();
+///     assert!(!classify_automated(&human).is_automated());
+/// ```
+pub fn classify_automated(mail: &ParsedMail) -> AutomatedClassification {
+    let headers = &mail.headers;
+    let mut reasons = Vec::new();
+
+    if let Some(value) = headers.get_first_value("Auto-Submitted") {
+        // Drop any comment or parameters, as in "auto-replied (vacation)".
+        let value = value
+            .split(['(', ';'])
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+        if !value.is_empty() && value != "no" {
+            reasons.push(Reason::AutoSubmitted(value));
+        }
+    }
+    if let Some(value) = headers.get_first_value("X-Auto-Response-Suppress") {
+        let value = value.trim().to_string();
+        if !value.is_empty() && !value.eq_ignore_ascii_case("none") {
+            reasons.push(Reason::AutoResponseSuppress(value));
+        }
+    }
+    if let Some(value) = headers.get_first_value("Precedence") {
+        let value = value.trim().to_lowercase();
+        if matches!(value.as_str(), "bulk" | "list" | "junk") {
+            reasons.push(Reason::Precedence(value));
+        }
+    }
+    for &name in &["List-Id", "List-Unsubscribe", "List-Post", "List-Owner"] {
+        if headers.get_first_header(name).is_some() {
+            reasons.push(Reason::ListHeader(name.to_string()));
+            break;
+        }
+    }
+    for name in AUTOREPLY_HEADERS {
+        if headers.get_first_header(name).is_some() {
+            reasons.push(Reason::AutoreplyHeader(name.to_string()));
+        }
+    }
+    if let Some(value) = headers.get_first_value("Return-Path") {
+        if value.trim() == "<>" {
+            reasons.push(Reason::NullReturnPath);
+        }
+    }
+    if let Some(subject) = headers.get_first_value("Subject") {
+        let lower = subject.trim().to_lowercase();
+        if AUTOREPLY_SUBJECTS.iter().any(|p| lower.starts_with(p)) {
+            reasons.push(Reason::AutoresponderSubject(subject.trim().to_string()));
+        }
+    }
+    for &name in &["From", "Sender"] {
+        let list = match headers.get_first_header(name).map(addrparse_header) {
+            Some(Ok(list)) => list,
+            _ => continue,
+        };
+        for addr in list.iter() {
+            if let MailAddr::Single(info) = addr {
+                let local = info.addr.split('@').next().unwrap_or("");
+                if is_automated_local_part(local) {
+                    reasons.push(Reason::AutomatedSender(info.addr.clone()));
+                }
+            }
+        }
+    }
+
+    AutomatedClassification {
+        kind: reasons.iter().map(Reason::kind).min(),
+        reasons,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mail;
+
+    fn classify(headers: &str) -> AutomatedClassification {
+        let raw = format!("{}\nbody\n", headers);
+        classify_automated(&parse_mail(raw.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn auto_submitted() {
+        let c = classify("Auto-Submitted: No\n");
+        assert!(!c.is_automated());
+        assert_eq!(c.kind, None);
+
+        let c = classify("Auto-Submitted: auto-generated\n");
+        assert_eq!(c.kind, Some(AutomatedKind::AutoGenerated));
+        assert_eq!(
+            c.reasons,
+            vec![Reason::AutoSubmitted("auto-generated".to_string())]
+        );
+
+        let c = classify("Auto-Submitted: Auto-Notified; owner-email=\"a@b.c\"\n");
+        assert_eq!(
+            c.reasons,
+            vec![Reason::AutoSubmitted("auto-notified".to_string())]
+        );
+    }
+
+    #[test]
+    fn lists_and_bulk() {
+        let c = classify("Precedence: bulk\n");
+        assert_eq!(c.kind, Some(AutomatedKind::Bulk));
+
+        let c = classify("Precedence: junk\nList-Id: Dev <dev.lists.example.com>\n");
+        assert_eq!(c.kind, Some(AutomatedKind::MailingList));
+        assert_eq!(
+            c.reasons,
+            vec![
+                Reason::Precedence("junk".to_string()),
+                Reason::ListHeader("List-Id".to_string()),
+            ]
+        );
+
+        let c = classify("Precedence: first-class\n");
+        assert!(!c.is_automated());
+    }
+
+    #[test]
+    fn autoresponders() {
+        let c = classify("Subject: Automatic reply: Lunch\nX-Auto-Response-Suppress: All\n");
+        assert_eq!(c.kind, Some(AutomatedKind::AutoReply));
+        assert_eq!(
+            c.reasons,
+            vec![
+                Reason::AutoResponseSuppress("All".to_string()),
+                Reason::AutoresponderSubject("Automatic reply: Lunch".to_string()),
+            ]
+        );
+
+        let c = classify("X-Autoreply: yes\nSubject: Re: Lunch\n");
+        assert_eq!(
+            c.reasons,
+            vec![Reason::AutoreplyHeader("X-Autoreply".to_string())]
+        );
+
+        assert!(!classify("X-Auto-Response-Suppress: None\n").is_automated());
+        assert!(!classify("Subject: Automation ideas\n").is_automated());
+    }
+
+    #[test]
+    fn automated_senders() {
+        let c = classify("Return-Path: <>\nFrom: MAILER-DAEMON@example.com\n");
+        assert_eq!(c.kind, Some(AutomatedKind::AutoGenerated));
+        assert_eq!(
+            c.reasons,
+            vec![
+                Reason::NullReturnPath,
+                Reason::AutomatedSender("MAILER-DAEMON@example.com".to_string()),
+            ]
+        );
+
+        for &sender in &[
+            "noreply@example.com",
+            "no-reply-123@example.com",
+            "owner-dev@lists.example.com",
+            "dev-request@lists.example.com",
+        ] {
+            let c = classify(&format!("From: {}\n", sender));
+            assert_eq!(
+                c.reasons,
+                vec![Reason::AutomatedSender(sender.to_string())],
+                "{}",
+                sender
+            );
+        }
+        assert!(!classify("From: Norah <norah@example.com>\n").is_automated());
+        assert!(!classify("Return-Path: <alice@example.com>\n").is_automated());
+    }
+}
@@ -0,0 +1,341 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+use mailparse::{
+    addrparse_header, dateparse, parse_mail, DispositionType, MailAddr, MailHeaderMap, ParsedMail,
+    SingleInfo,
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    filename, find_part, inputs_or_stdin, join_path, numbered_parts, read_input, report_error,
+};
+
+/// Headers whose values are parsed as address lists.
+const ADDRESS_HEADERS: &[&str] = &[
+    "From",
+    "Sender",
+    "Reply-To",
+    "To",
+    "Cc",
+    "Bcc",
+    "Resent-From",
+    "Resent-To",
+    "Resent-Cc",
+];
+
+/// Headers whose values are parsed as dates.
+const DATE_HEADERS: &[&str] = &["Date", "Resent-Date"];
+
+enum Format {
+    Json,
+    Tree,
+}
+
+enum Output {
+    Description(Format),
+    Decoded,
+    Raw,
+}
+
+struct Options {
+    output: Output,
+    part: Option<String>,
+    inputs: Vec<String>,
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut format = Format::Json;
+    let mut output = None;
+    let mut part = None;
+    let mut inputs = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => format = Format::Json,
+            "--tree" => format = Format::Tree,
+            "--decoded" => output = Some(Output::Decoded),
+            "--raw" => output = Some(Output::Raw),
+            "--part" => match args.next() {
+                Some(path) => part = Some(path),
+                None => return Err("--part requires a part path".to_string()),
+            },
+            s if s.starts_with("--") => return Err(format!("unknown option '{}'", s)),
+            _ => inputs.push(arg),
+        }
+    }
+    Ok(Options {
+        output: output.unwrap_or(Output::Description(format)),
+        part,
+        inputs: inputs_or_stdin(inputs),
+    })
+}
+
+pub fn run(args: Vec<String>) -> crate::CommandResult {
+    let options = parse_options(args)?;
+    let mut success = true;
+    for input in &options.inputs {
+        if let Err(e) = dump_input(input, &options) {
+            report_error(input, &e);
+            success = false;
+        }
+    }
+    Ok(success)
+}
+
+fn dump_input(input: &str, options: &Options) -> Result<(), String> {
+    let data = read_input(input).map_err(|e| e.to_string())?;
+    let mail = parse_mail(&data).map_err(|e| e.to_string())?;
+    let path = options.part.as_deref().unwrap_or("");
+    let part = find_part(&mail, path).ok_or_else(|| format!("no part {}", path))?;
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = match &options.output {
+        Output::Description(Format::Json) => {
+            let mut doc = part_json(path, part);
+            doc["source"] = json!(input);
+            writeln!(out, "{:#}", doc)
+        }
+        Output::Description(Format::Tree) => write_tree(&mut out, input, path, part),
+        Output::Decoded => {
+            let body = part.get_body_raw().map_err(|e| e.to_string())?;
+            out.write_all(&body)
+        }
+        Output::Raw => out.write_all(part.raw_bytes),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+fn disposition_name(disposition: &DispositionType) -> &str {
+    match disposition {
+        DispositionType::Inline => "inline",
+        DispositionType::Attachment => "attachment",
+        DispositionType::FormData => "form-data",
+        DispositionType::Extension(s) => s,
+    }
+}
+
+fn mailbox_json(info: &SingleInfo) -> Value {
+    json!({ "name": info.display_name, "addr": info.addr })
+}
+
+fn span_json(span: Range<usize>) -> Value {
+    json!([span.start, span.end])
+}
+
+fn part_json(path: &str, part: &ParsedMail) -> Value {
+    let mut obj = Map::new();
+    obj.insert("path".to_string(), json!(path));
+    obj.insert("content_type".to_string(), json!(part.ctype.mimetype));
+    obj.insert("charset".to_string(), json!(part.ctype.charset));
+    obj.insert("params".to_string(), json!(part.ctype.params));
+    obj.insert(
+        "span".to_string(),
+        json!({
+            "part": span_json(part.get_span()),
+            "outer": span_json(part.get_outer_span()),
+            "headers": span_json(part.get_header_span()),
+            "body": span_json(part.get_body_span()),
+        }),
+    );
+
+    let headers: Vec<Value> = part
+        .headers
+        .iter()
+        .map(|h| {
+            json!({
+                "name": h.get_key(),
+                "value": h.get_value(),
+                "span": span_json(h.get_span()),
+            })
+        })
+        .collect();
+    obj.insert("headers".to_string(), Value::Array(headers));
+
+    let mut addresses = Map::new();
+    for key in ADDRESS_HEADERS {
+        if let Some(header) = part.headers.get_first_header(key) {
+            let value = match addrparse_header(header) {
+                Ok(list) => Value::Array(
+                    list.iter()
+                        .map(|addr| match addr {
+                            MailAddr::Single(info) => mailbox_json(info),
+                            MailAddr::Group(group) => json!({
+                                "group": group.group_name,
+                                "addrs": group.addrs.iter().map(mailbox_json).collect::<Vec<_>>(),
+                            }),
+                        })
+                        .collect(),
+                ),
+                Err(e) => json!({ "error": e.to_string() }),
+            };
+            addresses.insert(key.to_string(), value);
+        }
+    }
+    if !addresses.is_empty() {
+        obj.insert("addresses".to_string(), Value::Object(addresses));
+    }
+
+    let mut dates = Map::new();
+    for key in DATE_HEADERS {
+        if let Some(value) = part.headers.get_first_value(key) {
+            let parsed = match dateparse(&value) {
+                Ok(ts) => json!({ "value": value, "timestamp": ts }),
+                Err(e) => json!({ "value": value, "error": e.to_string() }),
+            };
+            dates.insert(key.to_string(), parsed);
+        }
+    }
+    if !dates.is_empty() {
+        obj.insert("dates".to_string(), Value::Object(dates));
+    }
+
+    if part.subparts.is_empty() {
+        let disposition = part.get_content_disposition();
+        obj.insert(
+            "disposition".to_string(),
+            json!(disposition_name(&disposition.disposition)),
+        );
+        if let Some(name) = filename(part) {
+            obj.insert("filename".to_string(), json!(name));
+        }
+        if let Some(cid) = part.headers.get_first_value("Content-ID") {
+            obj.insert("content_id".to_string(), json!(cid));
+        }
+        match part.get_body_raw() {
+            Ok(body) => {
+                obj.insert("size".to_string(), json!(body.len()));
+                if part.ctype.mimetype.starts_with("text/") {
+                    if let Ok(text) = part.get_body() {
+                        obj.insert("text".to_string(), json!(text));
+                    }
+                }
+            }
+            Err(e) => {
+                obj.insert("decode_error".to_string(), json!(e.to_string()));
+            }
+        }
+    } else {
+        let subparts: Vec<Value> = part
+            .subparts
+            .iter()
+            .enumerate()
+            .map(|(i, subpart)| part_json(&join_path(path, i + 1), subpart))
+            .collect();
+        obj.insert("subparts".to_string(), Value::Array(subparts));
+    }
+    Value::Object(obj)
+}
+
+fn write_tree(out: &mut dyn Write, input: &str, path: &str, part: &ParsedMail) -> io::Result<()> {
+    writeln!(out, "== {} ==", input)?;
+    for key in ADDRESS_HEADERS.iter().chain(&["Subject", "Message-ID"]) {
+        for value in part.headers.get_all_values(key) {
+            writeln!(out, "{}: {}", key, value)?;
+        }
+    }
+    for key in DATE_HEADERS {
+        if let Some(value) = part.headers.get_first_value(key) {
+            match dateparse(&value) {
+                Ok(ts) => writeln!(out, "{}: {} ({})", key, value, ts)?,
+                Err(e) => writeln!(out, "{}: {} ({})", key, value, e)?,
+            }
+        }
+    }
+    for (subpath, subpart) in numbered_parts(part) {
+        // Indent relative to the selected part, but show the full path.
+        let depth = if subpath.is_empty() {
+            0
+        } else {
+            subpath.split('.').count()
+        };
+        let full_path = match (path.is_empty(), subpath.is_empty()) {
+            (_, true) => path.to_string(),
+            (true, false) => subpath,
+            (false, false) => format!("{}.{}", path, subpath),
+        };
+        write!(
+            out,
+            "{:indent$}[{}] {}",
+            "",
+            full_path,
+            subpart.ctype.mimetype,
+            indent = depth * 2
+        )?;
+        if subpart.subparts.is_empty() {
+            if subpart.ctype.mimetype.starts_with("text/") {
+                write!(out, "; charset={}", subpart.ctype.charset)?;
+            }
+            let disposition = subpart.get_content_disposition().disposition;
+            if disposition != DispositionType::Inline {
+                write!(out, "; {}", disposition_name(&disposition))?;
+            }
+            if let Some(name) = filename(subpart) {
+                write!(out, " {:?}", name)?;
+            }
+            match subpart.get_body_raw() {
+                Ok(body) => write!(out, ", {} bytes", body.len())?,
+                Err(e) => write!(out, ", undecodable ({})", e)?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_description() {
+        let mail = parse_mail(
+            concat!(
+                "From: John Doe <john@doe.com>\n",
+                "To: team: a@b.com, c@d.com;\n",
+                "Date: Sun, 02 Oct 2016 07:06:22 -0700 (PDT)\n",
+                "Content-Type: multipart/mixed; boundary=b\n",
+                "\n",
+                "--b\n",
+                "Content-Type: text/plain; charset=utf-8\n",
+                "\n",
+                "Hello\n",
+                "--b\n",
+                "Content-Type: application/pdf; name=a.pdf\n",
+                "Content-Disposition: attachment\n",
+                "Content-Transfer-Encoding: base64\n",
+                "\n",
+                "aGVsbG8=\n",
+                "--b--\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let doc = part_json("", &mail);
+        assert_eq!(doc["content_type"], "multipart/mixed");
+        assert_eq!(doc["addresses"]["From"][0]["addr"], "john@doe.com");
+        assert_eq!(doc["addresses"]["From"][0]["name"], "John Doe");
+        assert_eq!(doc["addresses"]["To"][0]["group"], "team");
+        assert_eq!(doc["addresses"]["To"][0]["addrs"][1]["addr"], "c@d.com");
+        assert_eq!(doc["dates"]["Date"]["timestamp"], 1475417182);
+        assert_eq!(doc["subparts"][0]["path"], "1");
+        assert_eq!(doc["subparts"][0]["text"], "Hello\n");
+        assert_eq!(doc["subparts"][1]["path"], "2");
+        assert_eq!(doc["subparts"][1]["disposition"], "attachment");
+        assert_eq!(doc["subparts"][1]["filename"], "a.pdf");
+        assert_eq!(doc["subparts"][1]["size"], 5);
+        assert_eq!(doc["headers"][0]["span"], json!([0, 30]));
+        assert_eq!(doc["subparts"][0]["span"]["part"], json!([149, 195]));
+        assert_eq!(doc["subparts"][0]["span"]["body"], json!([190, 195]));
+        assert_eq!(doc["subparts"][0]["span"]["outer"], json!([145, 196]));
+
+        let mut tree = Vec::new();
+        write_tree(&mut tree, "test", "", &mail).unwrap();
+        let tree = String::from_utf8(tree).unwrap();
+        assert!(tree.contains("[] multipart/mixed\n"));
+        assert!(tree.contains("  [1] text/plain; charset=utf-8, 6 bytes\n"));
+        assert!(tree.contains("  [2] application/pdf; attachment \"a.pdf\", 5 bytes\n"));
+    }
+}
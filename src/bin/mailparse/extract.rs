@@ -0,0 +1,311 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use mailparse::{parse_mail, DispositionType, MailHeaderMap, ParsedMail};
+
+use crate::mailbox::read_messages;
+use crate::{filename, inputs_or_stdin, numbered_parts, report_error};
+
+/// Maximum length in bytes of an extracted file name, which keeps us within
+/// the limits of common filesystems.
+const MAX_FILENAME_LEN: usize = 200;
+
+struct Options {
+    output_dir: PathBuf,
+    include_bodies: bool,
+    inputs: Vec<String>,
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut output_dir = PathBuf::from(".");
+    let mut include_bodies = false;
+    let mut inputs = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" | "-o" => match args.next() {
+                Some(dir) => output_dir = PathBuf::from(dir),
+                None => return Err(format!("{} requires a directory", arg)),
+            },
+            "--all" => include_bodies = true,
+            s if s.starts_with("--") => return Err(format!("unknown option '{}'", s)),
+            _ => inputs.push(arg),
+        }
+    }
+    Ok(Options {
+        output_dir,
+        include_bodies,
+        inputs: inputs_or_stdin(inputs),
+    })
+}
+
+pub fn run(args: Vec<String>) -> crate::CommandResult {
+    let options = parse_options(args)?;
+    fs::create_dir_all(&options.output_dir).map_err(|e| {
+        format!(
+            "cannot create output directory {}: {}",
+            options.output_dir.display(),
+            e
+        )
+    })?;
+
+    let mut extractor = Extractor {
+        output_dir: &options.output_dir,
+        used_names: HashSet::new(),
+    };
+    let mut success = true;
+    for input in &options.inputs {
+        let messages = match read_messages(input) {
+            Ok(messages) => messages,
+            Err(e) => {
+                report_error(input, &e);
+                success = false;
+                continue;
+            }
+        };
+        for message in messages {
+            let result = parse_mail(&message.data)
+                .map_err(|e| e.to_string())
+                .and_then(|mail| extractor.extract(&mail, options.include_bodies));
+            if let Err(e) = result {
+                report_error(&message.source, &e);
+                success = false;
+            }
+        }
+    }
+    Ok(success)
+}
+
+/// Returns true if the part should be extracted. Multipart containers are
+/// never extracted, and text parts without a filename are treated as the
+/// message body (rather than an attachment) unless `include_bodies` is set.
+fn is_extractable(part: &ParsedMail, include_bodies: bool) -> bool {
+    if !part.subparts.is_empty() || part.ctype.mimetype.starts_with("multipart/") {
+        return false;
+    }
+    if include_bodies {
+        return true;
+    }
+    let disposition = part.get_content_disposition();
+    disposition.disposition == DispositionType::Attachment
+        || disposition.params.contains_key("filename")
+        || part.ctype.params.contains_key("name")
+        || part.headers.get_first_header("Content-ID").is_some()
+        || !part.ctype.mimetype.starts_with("text/")
+}
+
+/// Returns a file extension for the common types that turn up without a
+/// filename, such as inline images and message bodies.
+fn extension_for(mimetype: &str) -> &'static str {
+    match mimetype {
+        "text/plain" => "txt",
+        "text/html" => "html",
+        "text/calendar" => "ics",
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "application/pdf" => "pdf",
+        "message/rfc822" => "eml",
+        _ => "bin",
+    }
+}
+
+/// Turns an untrusted filename from a message into something that is safe to
+/// create in the output directory: any directory components are dropped,
+/// characters that are reserved or unprintable on common filesystems are
+/// replaced, leading dots (hidden files, `..`) and trailing dots and spaces
+/// are removed, Windows device names like `CON` get a `_` prefix, and the
+/// name is truncated to a sane length. Returns `None` if nothing usable is
+/// left.
+fn sanitize_filename(name: &str) -> Option<String> {
+    let base = name.rsplit(&['/', '\\'][..]).next().unwrap_or("");
+    let mut sanitized: String = base
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    sanitized = sanitized
+        .trim_start_matches(|c: char| c == '.' || c.is_whitespace())
+        .trim_end_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_string();
+    if is_device_name(&sanitized) {
+        sanitized.insert(0, '_');
+    }
+    if sanitized.len() > MAX_FILENAME_LEN {
+        // Keep the extension if there is a reasonable one.
+        let ext = match sanitized.rfind('.') {
+            Some(ix) if sanitized.len() - ix <= 16 => sanitized[ix..].to_string(),
+            _ => String::new(),
+        };
+        let mut cut = MAX_FILENAME_LEN - ext.len();
+        while !sanitized.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        sanitized.truncate(cut);
+        sanitized.push_str(&ext);
+    }
+    if sanitized.is_empty() {
+        None
+    } else {
+        Some(sanitized)
+    }
+}
+
+/// Returns whether Windows reserves the name for a device, regardless of
+/// case and extension: `nul` and `Com1.txt` can't be created as files there.
+fn is_device_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or("").trim_end();
+    let upper = stem.to_ascii_uppercase();
+    match upper.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" => true,
+        _ => {
+            let bytes = upper.as_bytes();
+            bytes.len() == 4
+                && (bytes.starts_with(b"COM") || bytes.starts_with(b"LPT"))
+                && (b'1'..=b'9').contains(&bytes[3])
+        }
+    }
+}
+
+/// Returns the `n`th alternative for a name that is already taken, by
+/// inserting a counter before the extension: `a.pdf` becomes `a-1.pdf`.
+fn numbered_name(name: &str, n: usize) -> String {
+    match name.rfind('.') {
+        Some(ix) if ix > 0 => format!("{}-{}{}", &name[..ix], n, &name[ix..]),
+        _ => format!("{}-{}", name, n),
+    }
+}
+
+struct Extractor<'a> {
+    output_dir: &'a Path,
+    used_names: HashSet<String>,
+}
+
+impl Extractor<'_> {
+    fn extract(&mut self, mail: &ParsedMail, include_bodies: bool) -> Result<(), String> {
+        for (path, part) in numbered_parts(mail) {
+            if !is_extractable(part, include_bodies) {
+                continue;
+            }
+            let data = part
+                .get_body_raw()
+                .map_err(|e| format!("part {}: {}", path, e))?;
+            let name = filename(part)
+                .and_then(|n| sanitize_filename(&n))
+                .unwrap_or_else(|| {
+                    let path = if path.is_empty() { "1" } else { &path };
+                    format!("part-{}.{}", path, extension_for(&part.ctype.mimetype))
+                });
+            let written = self
+                .write_unique(&name, &data)
+                .map_err(|e| format!("part {}: {}", path, e))?;
+            println!("{} ({} bytes)", written.display(), data.len());
+        }
+        Ok(())
+    }
+
+    /// Writes the data to a new file in the output directory, picking a name
+    /// that does not clash with existing files or files written earlier.
+    fn write_unique(&mut self, name: &str, data: &[u8]) -> io::Result<PathBuf> {
+        let mut n = 0;
+        loop {
+            let candidate = if n == 0 {
+                name.to_string()
+            } else {
+                numbered_name(name, n)
+            };
+            n += 1;
+            if self.used_names.contains(&candidate) {
+                continue;
+            }
+            let path = self.output_dir.join(&candidate);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(data)?;
+                    self.used_names.insert(candidate);
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filename_sanitization() {
+        assert_eq!(sanitize_filename("report.pdf").unwrap(), "report.pdf");
+        assert_eq!(sanitize_filename("../../etc/passwd").unwrap(), "passwd");
+        assert_eq!(sanitize_filename("C:\\evil\\run.exe").unwrap(), "run.exe");
+        assert_eq!(sanitize_filename(".bashrc").unwrap(), "bashrc");
+        assert_eq!(sanitize_filename("a<b>c?.txt").unwrap(), "a_b_c_.txt");
+        assert_eq!(sanitize_filename("tab\there.txt ").unwrap(), "tab_here.txt");
+        assert_eq!(sanitize_filename("CON").unwrap(), "_CON");
+        assert_eq!(sanitize_filename("nul.txt").unwrap(), "_nul.txt");
+        assert_eq!(sanitize_filename("Com1.tar.gz").unwrap(), "_Com1.tar.gz");
+        assert_eq!(sanitize_filename("lpt9 .log").unwrap(), "_lpt9 .log");
+        assert_eq!(sanitize_filename("COM0.txt").unwrap(), "COM0.txt");
+        assert_eq!(sanitize_filename("console.txt").unwrap(), "console.txt");
+        assert_eq!(sanitize_filename(".."), None);
+        assert_eq!(sanitize_filename("dir/"), None);
+
+        let long = format!("{}.pdf", "\u{e9}".repeat(150));
+        let sanitized = sanitize_filename(&long).unwrap();
+        assert!(sanitized.len() <= MAX_FILENAME_LEN);
+        assert!(sanitized.ends_with(".pdf"));
+    }
+
+    #[test]
+    fn numbered_names() {
+        assert_eq!(numbered_name("a.pdf", 1), "a-1.pdf");
+        assert_eq!(numbered_name("a.tar.gz", 2), "a.tar-2.gz");
+        assert_eq!(numbered_name("README", 3), "README-3");
+        assert_eq!(numbered_name(".hidden", 1), ".hidden-1");
+    }
+
+    #[test]
+    fn extractable_parts() {
+        let mail = parse_mail(
+            concat!(
+                "Content-Type: multipart/mixed; boundary=b\n",
+                "\n",
+                "--b\n",
+                "Content-Type: text/plain\n",
+                "\n",
+                "Body text\n",
+                "--b\n",
+                "Content-Type: image/png\n",
+                "Content-ID: <logo@example.com>\n",
+                "\n",
+                "png\n",
+                "--b\n",
+                "Content-Type: text/plain; name=notes.txt\n",
+                "\n",
+                "Notes\n",
+                "--b--\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let extractable: Vec<String> = numbered_parts(&mail)
+            .into_iter()
+            .filter(|(_, p)| is_extractable(p, false))
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(extractable, vec!["2", "3"]);
+        let all = numbered_parts(&mail)
+            .into_iter()
+            .filter(|(_, p)| is_extractable(p, true))
+            .count();
+        assert_eq!(all, 3);
+    }
+}
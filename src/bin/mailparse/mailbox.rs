@@ -0,0 +1,157 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::read_input;
+
+/// A single raw message read from an input, along with a human-readable
+/// description of where it came from (used for reporting).
+pub struct RawMessage {
+    pub source: String,
+    pub data: Vec<u8>,
+}
+
+/// Reads all the messages from the given input. Directories are treated as
+/// Maildirs (messages are read from the `cur` and `new` subdirectories, or
+/// from the directory itself if it has neither), files starting with an mbox
+/// `From ` line are split into their messages, and anything else is treated
+/// as a single message.
+pub fn read_messages(input: &str) -> io::Result<Vec<RawMessage>> {
+    let path = Path::new(input);
+    if input != "-" && path.is_dir() {
+        return read_maildir(path);
+    }
+    let data = read_input(input)?;
+    if data.starts_with(b"From ") {
+        Ok(split_mbox(&data)
+            .into_iter()
+            .enumerate()
+            .map(|(i, data)| RawMessage {
+                source: format!("{}#{}", input, i + 1),
+                data,
+            })
+            .collect())
+    } else {
+        Ok(vec![RawMessage {
+            source: input.to_string(),
+            data,
+        }])
+    }
+}
+
+fn read_maildir(path: &Path) -> io::Result<Vec<RawMessage>> {
+    let subdirs: Vec<_> = ["cur", "new"]
+        .iter()
+        .map(|d| path.join(d))
+        .filter(|d| d.is_dir())
+        .collect();
+    let dirs = if subdirs.is_empty() {
+        vec![path.to_path_buf()]
+    } else {
+        subdirs
+    };
+
+    let mut files = Vec::new();
+    for dir in dirs {
+        for entry in fs::read_dir(dir)? {
+            let entry_path = entry?.path();
+            let hidden = match entry_path.file_name() {
+                Some(name) => name.to_string_lossy().starts_with('.'),
+                None => true,
+            };
+            if entry_path.is_file() && !hidden {
+                files.push(entry_path);
+            }
+        }
+    }
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|file| {
+            Ok(RawMessage {
+                source: file.display().to_string(),
+                data: fs::read(&file)?,
+            })
+        })
+        .collect()
+}
+
+/// Splits mbox data into its messages. A message starts at every `From ` line
+/// that is at the start of the data or follows an empty line; the `From ` line
+/// itself is not part of the message. Lines quoted as `>From ` (in any of the
+/// mboxo/mboxrd variants) have one level of quoting removed.
+pub fn split_mbox(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages = Vec::new();
+    let mut current: Option<Vec<u8>> = None;
+    let mut prev_blank = true;
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        if prev_blank && line.starts_with(b"From ") {
+            if let Some(mut message) = current.take() {
+                // Drop the blank line that separated the messages.
+                strip_trailing_newline(&mut message);
+                messages.push(message);
+            }
+            current = Some(Vec::new());
+            prev_blank = false;
+            continue;
+        }
+        prev_blank = line == b"\n" || line == b"\r\n";
+        if let Some(message) = current.as_mut() {
+            let unquoted_from = matches!(
+                line.iter().position(|&b| b != b'>'),
+                Some(ix) if ix > 0 && line[ix..].starts_with(b"From ")
+            );
+            if unquoted_from {
+                message.extend_from_slice(&line[1..]);
+            } else {
+                message.extend_from_slice(line);
+            }
+        }
+    }
+    if let Some(message) = current {
+        messages.push(message);
+    }
+    messages
+}
+
+fn strip_trailing_newline(message: &mut Vec<u8>) {
+    if message.ends_with(b"\r\n") {
+        message.truncate(message.len() - 2);
+    } else if message.ends_with(b"\n") {
+        message.truncate(message.len() - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mbox_splitting() {
+        let mbox = concat!(
+            "From alice@example.com Sat Jan  3 01:05:34 1996\n",
+            "Subject: one\n",
+            "\n",
+            "Hello\n",
+            ">From the start\n",
+            ">>From deeper\n",
+            "\n",
+            "From bob@example.com Sat Jan  3 01:05:35 1996\n",
+            "Subject: two\n",
+            "\n",
+            "Body\n",
+            "From here is not a separator\n",
+        );
+        let messages = split_mbox(mbox.as_bytes());
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0],
+            b"Subject: one\n\nHello\nFrom the start\n>From deeper\n".to_vec()
+        );
+        assert_eq!(
+            messages[1],
+            b"Subject: two\n\nBody\nFrom here is not a separator\n".to_vec()
+        );
+    }
+}
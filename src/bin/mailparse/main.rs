@@ -0,0 +1,216 @@
+//! Command-line tool for inspecting MIME email messages. This is built only
+//! when the `cli` feature is enabled, e.g. with
+//! `cargo install mailparse --features cli`.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+use mailparse::ParsedMail;
+
+mod dump;
+mod extract;
+mod mailbox;
+
+const USAGE: &str = "\
+Usage: mailparse <command> [options] [FILE...]
+
+Reads each FILE (or standard input if no FILE is given, or FILE is -)
+as a raw email message.
+
+Commands:
+    dump        Print the MIME tree, headers, addresses, dates and
+                attachment metadata of each message.
+    extract     Save the attachments and inline parts of each message
+                into a directory. Inputs may also be mbox files or
+                Maildir directories, in which case every message in
+                them is processed.
+    help        Print this message.
+
+Options for dump:
+    --json          Print a JSON document per message (default).
+    --tree          Print an indented tree view instead of JSON.
+    --part PATH     Only consider the part at PATH, using IMAP-style part
+                    numbers (e.g. 1, 2.1). The empty path is the message.
+    --decoded       Write the decoded body of the selected part to
+                    standard output instead of describing it.
+    --raw           Write the raw bytes of the selected part to standard
+                    output instead of describing it.
+
+Options for extract:
+    -o, --output DIR
+                    Write the extracted files into DIR, creating it if
+                    needed (default: the current directory). Files are
+                    never overwritten; clashing names get a numeric suffix.
+    --all           Also extract text parts that are the message body
+                    rather than attachments.
+";
+
+/// The outcome of a command. Usage errors are reported as `Err`; failures
+/// that only affect a single input are reported on stderr as they happen
+/// and make the command return `Ok(false)`.
+pub type CommandResult = Result<bool, String>;
+
+/// Reads the given input file, treating `-` as standard input.
+pub fn read_input(path: &str) -> io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        fs::read(path)
+    }
+}
+
+/// Reports a failure for a single input on stderr.
+pub fn report_error(source: &str, err: &dyn std::fmt::Display) {
+    eprintln!("mailparse: {}: {}", source, err);
+}
+
+/// Returns the given list of inputs, defaulting to standard input.
+pub fn inputs_or_stdin(inputs: Vec<String>) -> Vec<String> {
+    if inputs.is_empty() {
+        vec!["-".to_string()]
+    } else {
+        inputs
+    }
+}
+
+/// Returns the filename of an attachment, from either the Content-Disposition
+/// or the Content-Type parameters.
+pub fn filename(part: &ParsedMail) -> Option<String> {
+    part.get_content_disposition()
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .cloned()
+}
+
+/// Returns the IMAP-style path of the `index`th (1-based) subpart of the part
+/// at `parent`.
+pub fn join_path(parent: &str, index: usize) -> String {
+    if parent.is_empty() {
+        index.to_string()
+    } else {
+        format!("{}.{}", parent, index)
+    }
+}
+
+/// Returns every part of the message along with its IMAP-style part path,
+/// in depth-first order. The message itself has the empty path, and the
+/// subparts of a multipart are numbered starting from 1.
+pub fn numbered_parts<'a>(mail: &'a ParsedMail<'a>) -> Vec<(String, &'a ParsedMail<'a>)> {
+    fn walk<'a>(
+        path: String,
+        part: &'a ParsedMail<'a>,
+        result: &mut Vec<(String, &'a ParsedMail<'a>)>,
+    ) {
+        result.push((path.clone(), part));
+        for (i, subpart) in part.subparts.iter().enumerate() {
+            walk(join_path(&path, i + 1), subpart, result);
+        }
+    }
+
+    let mut result = Vec::new();
+    walk(String::new(), mail, &mut result);
+    result
+}
+
+/// Looks up a part by its IMAP-style part path. As in IMAP, part `1` of a
+/// message that is not multipart is the message itself.
+pub fn find_part<'a>(mail: &'a ParsedMail<'a>, path: &str) -> Option<&'a ParsedMail<'a>> {
+    let mut part = mail;
+    if path.is_empty() {
+        return Some(part);
+    }
+    for component in path.split('.') {
+        let index = component.parse::<usize>().ok()?;
+        if part.subparts.is_empty() && index == 1 {
+            continue;
+        }
+        part = part.subparts.get(index.checked_sub(1)?)?;
+    }
+    Some(part)
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let result = match args.next().as_deref() {
+        Some("dump") => dump::run(args.collect()),
+        Some("extract") => extract::run(args.collect()),
+        Some("help") | Some("-h") | Some("--help") => {
+            print!("{}", USAGE);
+            Ok(true)
+        }
+        Some(command) => Err(format!("unknown command '{}'", command)),
+        None => Err("no command given".to_string()),
+    };
+    match result {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("mailparse: {}\n", e);
+            eprint!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mailparse::parse_mail;
+
+    #[test]
+    fn part_paths() {
+        let mail = parse_mail(
+            concat!(
+                "Content-Type: multipart/mixed; boundary=outer\n",
+                "\n",
+                "--outer\n",
+                "Content-Type: multipart/alternative; boundary=inner\n",
+                "\n",
+                "--inner\n",
+                "Content-Type: text/plain\n",
+                "\n",
+                "plain\n",
+                "--inner\n",
+                "Content-Type: text/html\n",
+                "\n",
+                "<b>html</b>\n",
+                "--inner--\n",
+                "--outer\n",
+                "Content-Type: application/pdf\n",
+                "\n",
+                "pdf\n",
+                "--outer--\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let paths: Vec<String> = numbered_parts(&mail).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(paths, vec!["", "1", "1.1", "1.2", "2"]);
+
+        assert_eq!(
+            find_part(&mail, "").unwrap().ctype.mimetype,
+            "multipart/mixed"
+        );
+        assert_eq!(find_part(&mail, "1.2").unwrap().ctype.mimetype, "text/html");
+        assert_eq!(
+            find_part(&mail, "2").unwrap().ctype.mimetype,
+            "application/pdf"
+        );
+        assert_eq!(
+            find_part(&mail, "2.1").unwrap().ctype.mimetype,
+            "application/pdf"
+        );
+        assert!(find_part(&mail, "3").is_none());
+        assert!(find_part(&mail, "0").is_none());
+        assert!(find_part(&mail, "x").is_none());
+
+        let single = parse_mail(b"Subject: hi\n\nbody").unwrap();
+        assert_eq!(find_part(&single, "1").unwrap().get_body().unwrap(), "body");
+    }
+}
@@ -136,7 +136,7 @@ impl<'a> BinaryBody<'a> {
     }
 }
 
-fn decode_base64(body: &[u8]) -> Result<Vec<u8>, MailParseError> {
+pub(crate) fn decode_base64(body: &[u8]) -> Result<Vec<u8>, MailParseError> {
     let cleaned = body
         .iter()
         .filter(|c| !c.is_ascii_whitespace())
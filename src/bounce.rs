@@ -0,0 +1,729 @@
+//! Heuristic detection and classification of bounce messages.
+//!
+//! Standard delivery status notifications (see the `dsn` module) are easy to
+//! interpret, but many MTAs still send bounces as plain text in their own
+//! format. `classify_bounce` recognizes RFC 3464 DSNs as well as the
+//! plain-text formats of Exim, qmail (QSBMF) and Microsoft Exchange, and
+//! auto-replies, and extracts the failed recipients along with the SMTP
+//! status codes that explain the failure.
+
+use crate::automated::{classify_automated, AutomatedKind};
+use crate::dsn::{Action, StatusCode};
+use crate::reply::text_body;
+use crate::{addrparse_header, split_once, MailAddr, MailHeaderMap, ParsedMail};
+
+/// How severe a delivery failure is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BounceKind {
+    /// The delivery failed permanently (e.g. the mailbox doesn't exist), so
+    /// there is no point in sending to the address again.
+    Hard,
+    /// The delivery failed temporarily (e.g. the mailbox is full, or the
+    /// message was greylisted) and may succeed later.
+    Soft,
+    /// The message is an automatic reply such as a vacation notice; the
+    /// message was delivered.
+    AutoReply,
+    /// The message is a bounce but the reason could not be determined.
+    Unknown,
+}
+
+/// The format of a bounce message, i.e. which heuristics recognized it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BounceFormat {
+    /// A standard delivery status notification as defined in RFC 3464.
+    Dsn,
+    /// The plain-text format used by Exim.
+    Exim,
+    /// The qmail-send bounce message format (QSBMF).
+    Qmail,
+    /// The plain-text non-delivery reports of Microsoft Exchange.
+    Exchange,
+    /// An automatic reply.
+    AutoReply,
+    /// A message from a mailer daemon in an unrecognized format.
+    Generic,
+}
+
+/// A recipient that a bounce reports a delivery failure for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailedRecipient {
+    /// The address of the recipient.
+    pub address: String,
+    /// The enhanced status code (RFC 3463) given for the failure, if any.
+    pub status: Option<StatusCode>,
+    /// The SMTP reply code given for the failure, if any.
+    pub smtp_code: Option<u16>,
+    /// The explanation of the failure, as given in the bounce.
+    pub diagnostic: Option<String>,
+    /// The classification of the failure.
+    pub kind: BounceKind,
+}
+
+/// The result of classifying a bounce message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bounce {
+    pub format: BounceFormat,
+    /// The overall classification: `Hard` if any recipient failed hard,
+    /// otherwise `Soft` if any failed softly, and `Unknown` if the reason
+    /// could not be determined for any recipient.
+    pub kind: BounceKind,
+    /// The failed recipients. For auto-replies this is the address that sent
+    /// the reply. This may be empty if the bounce does not say which
+    /// recipients failed.
+    pub recipients: Vec<FailedRecipient>,
+}
+
+/// Phrases indicating that a failure is temporary. These are checked before
+/// `HARD_PHRASES`, so "mailbox full" is not taken to be "mailbox ...".
+const SOFT_PHRASES: &[&str] = &[
+    "mailbox full",
+    "mailbox is full",
+    "quota",
+    "insufficient storage",
+    "try again",
+    "temporar",
+    "greylist",
+    "deferred",
+    "delayed",
+];
+
+/// Phrases indicating that a failure is permanent.
+const HARD_PHRASES: &[&str] = &[
+    "user unknown",
+    "unknown user",
+    "no such user",
+    "no such mailbox",
+    "does not exist",
+    "doesn't exist",
+    "not found",
+    "couldn't be found",
+    "invalid recipient",
+    "recipient rejected",
+    "address rejected",
+    "permanent error",
+];
+
+/// Lines that start the copy of the returned message in plain-text bounces.
+/// The text after them is ignored, since it contains the original headers.
+const COPY_MARKERS: &[&str] = &[
+    "------ This is a copy of the message",
+    "--- Below this line is a copy of the message.",
+    "Original message headers:",
+    "------ Original message ------",
+];
+
+/// Local parts of the addresses that MTAs send bounces from.
+const DAEMON_NAMES: &[&str] = &["mailer-daemon", "mailerdaemon", "mail-daemon", "postmaster"];
+
+/// Subject phrases of bounces sent by MTAs.
+const BOUNCE_SUBJECTS: &[&str] = &[
+    "undeliverable",
+    "undelivered mail",
+    "delivery status notification",
+    "delivery failure",
+    "mail delivery failed",
+    "returned mail",
+    "failure notice",
+    "could not be delivered",
+];
+
+/// The failed recipients found by the heuristics for one bounce format,
+/// along with the classification to use when a failure has no explanation.
+type Detected = Option<(Vec<FailedRecipient>, BounceKind)>;
+
+/// Returns the first SMTP reply code (like `550`) and the first enhanced
+/// status code (like `5.1.1`) found in the text.
+fn find_codes(text: &str) -> (Option<u16>, Option<StatusCode>) {
+    let mut smtp_code = None;
+    let mut status = None;
+    for token in text.split(|c: char| !c.is_ascii_alphanumeric() && c != '.') {
+        let token = token.trim_end_matches('.');
+        let bytes = token.as_bytes();
+        if smtp_code.is_none()
+            && bytes.len() == 3
+            && matches!(bytes[0], b'2' | b'4' | b'5')
+            && matches!(bytes[1], b'0'..=b'5')
+            && bytes[2].is_ascii_digit()
+        {
+            smtp_code = token.parse().ok();
+        } else if status.is_none() && token.contains('.') {
+            status = token.parse().ok();
+        }
+    }
+    (smtp_code, status)
+}
+
+/// Classifies a failure, going by the enhanced status code if there is one,
+/// then the SMTP reply code, then the wording of the diagnostic, and falling
+/// back to `default`.
+fn classify(
+    status: Option<StatusCode>,
+    smtp_code: Option<u16>,
+    diagnostic: &str,
+    default: BounceKind,
+) -> BounceKind {
+    if let Some(status) = status {
+        // X.2.2 (mailbox full) is nominally permanent when reported with
+        // class 5, but in practice the mailbox often gets emptied again.
+        if status.subject == 2 && status.detail == 2 {
+            return BounceKind::Soft;
+        }
+        if status.is_permanent_failure() {
+            return BounceKind::Hard;
+        }
+        if status.is_transient_failure() {
+            return BounceKind::Soft;
+        }
+    }
+    match smtp_code {
+        Some(500..=599) => return BounceKind::Hard,
+        Some(400..=499) => return BounceKind::Soft,
+        _ => (),
+    }
+    let diagnostic = diagnostic.to_lowercase();
+    if SOFT_PHRASES.iter().any(|p| diagnostic.contains(p)) {
+        BounceKind::Soft
+    } else if HARD_PHRASES.iter().any(|p| diagnostic.contains(p)) {
+        BounceKind::Hard
+    } else {
+        default
+    }
+}
+
+/// Returns the explanation given for an address in a plain-text bounce:
+/// each line that mentions the address and the lines following it, up to
+/// the next blank line. Lines consisting of just the address are left out.
+fn context_for(text: &str, address: &str) -> String {
+    let address = address.to_lowercase();
+    let mut context = Vec::new();
+    let mut in_context = false;
+    for line in text.lines() {
+        let line = line.trim();
+        let mentions_address = line.to_lowercase().contains(&address);
+        if line.is_empty() {
+            in_context = false;
+        } else if mentions_address && !line.contains(char::is_whitespace) {
+            in_context = true;
+        } else if mentions_address || in_context {
+            in_context = true;
+            context.push(line);
+        }
+    }
+    context.join(" ")
+}
+
+/// Extracts the address from a token like `<user@example.com>:`,
+/// `(user@example.com)` or `mailto:user@example.com`.
+fn address_in(token: &str) -> Option<String> {
+    let token = token.trim_matches(|c: char| "<>()[]\"',;:".contains(c));
+    let token = token.strip_prefix("mailto:").unwrap_or(token);
+    match split_once(token, '@') {
+        Some((local, domain)) if !local.is_empty() && domain.contains('.') => {
+            Some(token.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn push_unique(addresses: &mut Vec<String>, address: String) {
+    if !addresses.iter().any(|a| a.eq_ignore_ascii_case(&address)) {
+        addresses.push(address);
+    }
+}
+
+/// Builds the failed recipients of a plain-text bounce, looking up the
+/// explanation of each address in the text.
+fn recipients_from_text(
+    text: &str,
+    addresses: Vec<String>,
+    default: BounceKind,
+) -> Vec<FailedRecipient> {
+    addresses
+        .into_iter()
+        .map(|address| {
+            let context = context_for(text, &address);
+            let (smtp_code, status) = find_codes(&context);
+            FailedRecipient {
+                kind: classify(status, smtp_code, &context, default),
+                status,
+                smtp_code,
+                diagnostic: Some(context).filter(|c| !c.is_empty()),
+                address,
+            }
+        })
+        .collect()
+}
+
+fn from_dsn(mail: &ParsedMail) -> Option<Vec<FailedRecipient>> {
+    let report = mail.delivery_report().ok()??;
+    let recipients: Vec<FailedRecipient> = report
+        .status
+        .recipients
+        .into_iter()
+        .filter(|r| match &r.action {
+            Some(action) => matches!(action, Action::Failed | Action::Delayed),
+            None => r.status.map(|s| s.class != 2).unwrap_or(true),
+        })
+        .map(|r| {
+            let diagnostic = r.diagnostic_code.map(|d| d.value);
+            let (smtp_code, _) = find_codes(diagnostic.as_deref().unwrap_or(""));
+            let kind = if r.action == Some(Action::Delayed) {
+                BounceKind::Soft
+            } else {
+                classify(
+                    r.status,
+                    smtp_code,
+                    diagnostic.as_deref().unwrap_or(""),
+                    BounceKind::Unknown,
+                )
+            };
+            FailedRecipient {
+                address: r
+                    .final_recipient
+                    .value
+                    .trim_matches(|c| c == '<' || c == '>')
+                    .to_string(),
+                status: r.status,
+                smtp_code,
+                diagnostic,
+                kind,
+            }
+        })
+        .collect();
+    if recipients.is_empty() {
+        // A report of successful deliveries is not a bounce.
+        None
+    } else {
+        Some(recipients)
+    }
+}
+
+/// Recognizes Exim bounces and delay warnings. The failed addresses are
+/// listed after a summary line, indented by two spaces, with the details
+/// indented further below each of them.
+fn from_exim(mail: &ParsedMail, text: &str) -> Detected {
+    if !text.contains("This message was created automatically by mail delivery software") {
+        return None;
+    }
+    let default = if text.contains("has not yet been delivered") {
+        BounceKind::Soft
+    } else if text.contains("permanent error") {
+        BounceKind::Hard
+    } else {
+        BounceKind::Unknown
+    };
+    let mut addresses = Vec::new();
+    let mut in_list = false;
+    for line in text.lines() {
+        if line.ends_with("failed:") || line.ends_with("delivered is:") || line.ends_with("are:") {
+            in_list = true;
+        } else if in_list && line.starts_with("  ") && !line.starts_with("   ") {
+            if let Some(address) = line.split_whitespace().next().and_then(address_in) {
+                push_unique(&mut addresses, address);
+            }
+        } else if in_list && !line.is_empty() && !line.starts_with(' ') {
+            in_list = false;
+        }
+    }
+    if addresses.is_empty() {
+        for value in mail.headers.get_all_values("X-Failed-Recipients") {
+            for address in value.split(',').filter_map(address_in) {
+                push_unique(&mut addresses, address);
+            }
+        }
+    }
+    Some((recipients_from_text(text, addresses, default), default))
+}
+
+/// Recognizes qmail bounces (QSBMF), in which each failed recipient gets a
+/// paragraph starting with `<address>:`.
+fn from_qmail(text: &str) -> Detected {
+    if !text.contains("This is the qmail-send program")
+        && !text.contains("I wasn't able to deliver your message")
+    {
+        return None;
+    }
+    let default = if text.contains("permanent error") {
+        BounceKind::Hard
+    } else {
+        BounceKind::Unknown
+    };
+    let mut addresses = Vec::new();
+    for line in text.lines() {
+        if line.starts_with('<') && line.trim_end().ends_with(">:") {
+            if let Some(address) = address_in(line.trim_end()) {
+                push_unique(&mut addresses, address);
+            }
+        }
+    }
+    Some((recipients_from_text(text, addresses, default), default))
+}
+
+/// Recognizes the plain-text non-delivery reports of Exchange 2007 and later
+/// ("Delivery has failed to these recipients or groups") as well as those of
+/// earlier versions ("The following recipient(s) cannot be reached").
+fn from_exchange(text: &str) -> Detected {
+    const MARKERS: &[&str] = &[
+        "Delivery has failed to these recipients or groups:",
+        "The following recipient(s) cannot be reached:",
+        "The following recipient(s) could not be reached:",
+    ];
+    let start = MARKERS
+        .iter()
+        .find_map(|m| text.find(m).map(|ix| ix + m.len()))?;
+    let list = &text[start..];
+    let list = match list.find("Diagnostic information for administrators:") {
+        Some(end) => &list[..end],
+        None => list,
+    };
+    let mut addresses = Vec::new();
+    for line in list.lines() {
+        if let Some(address) = line.split_whitespace().find_map(address_in) {
+            push_unique(&mut addresses, address);
+        }
+    }
+    Some((
+        recipients_from_text(text, addresses, BounceKind::Unknown),
+        BounceKind::Unknown,
+    ))
+}
+
+fn sender_local_parts(mail: &ParsedMail) -> Vec<String> {
+    let mut local_parts = Vec::new();
+    for header in mail.headers.get_all_headers("From") {
+        if let Ok(list) = addrparse_header(header) {
+            for addr in list.iter() {
+                if let MailAddr::Single(info) = addr {
+                    let local = info.addr.split('@').next().unwrap_or("");
+                    local_parts.push(local.to_lowercase());
+                }
+            }
+        }
+    }
+    local_parts
+}
+
+/// Determines whether the message is a bounce (or an automatic reply) and
+/// if so, which recipients failed and why. Returns `None` if the message
+/// does not look like a bounce. Standard delivery status notifications are
+/// recognized reliably; everything else is based on heuristics that cover
+/// the most common plain-text bounce formats.
+///
+/// # Examples
+/// ```
+///     use mailparse::parse_mail;
+///     use mailparse::bounce::{classify_bounce, BounceFormat, BounceKind};
+///     let mail = parse_mail(concat!(
+///             "From: MAILER-DAEMON@mx.example.com\n",
+///             "Subject: failure notice\n",
+///             "\n",
+///             "Hi. This is the qmail-send program at mx.example.com.\n",
+///             "I'm afraid I wasn't able to deliver your message to the following addresses.\n",
+///             "This is a permanent error; I've given up. Sorry it didn't work out.\n",
+///             "\n",
+///             "<nobody@example.org>:\n",
+///             "Remote host said: 550 5.1.1 User unknown\n").as_bytes())
+///         .unwrap();
+///     let bounce = classify_bounce(&mail).unwrap();
+///     assert_eq!(bounce.format, BounceFormat::Qmail);
+///     assert_eq!(bounce.kind, BounceKind::Hard);
+///     assert_eq!(bounce.recipients[0].address, "nobody@example.org");
+///     assert_eq!(bounce.recipients[0].smtp_code, Some(550));
+/// ```
+pub fn classify_bounce(mail: &ParsedMail) -> Option<Bounce> {
+    if let Some(recipients) = from_dsn(mail) {
+        return Some(Bounce::new(
+            BounceFormat::Dsn,
+            recipients,
+            BounceKind::Unknown,
+        ));
+    }
+
+    let mut text = text_body(mail);
+    if let Some(end) = COPY_MARKERS.iter().filter_map(|m| text.find(m)).min() {
+        text.truncate(end);
+    }
+    let detected = from_exim(mail, &text)
+        .map(|r| (BounceFormat::Exim, r))
+        .or_else(|| from_qmail(&text).map(|r| (BounceFormat::Qmail, r)))
+        .or_else(|| from_exchange(&text).map(|r| (BounceFormat::Exchange, r)));
+    if let Some((format, (recipients, default))) = detected {
+        return Some(Bounce::new(format, recipients, default));
+    }
+
+    let subject = mail
+        .headers
+        .get_first_value("Subject")
+        .unwrap_or_default()
+        .to_lowercase();
+    let from_daemon = sender_local_parts(mail)
+        .iter()
+        .any(|local| DAEMON_NAMES.contains(&local.as_str()));
+    if !from_daemon && classify_automated(mail).kind == Some(AutomatedKind::AutoReply) {
+        let recipients = mail
+            .headers
+            .get_first_header("From")
+            .and_then(|from| addrparse_header(from).ok())
+            .and_then(|list| list.extract_single_info())
+            .map(|info| FailedRecipient {
+                address: info.addr,
+                status: None,
+                smtp_code: None,
+                diagnostic: None,
+                kind: BounceKind::AutoReply,
+            })
+            .into_iter()
+            .collect();
+        return Some(Bounce {
+            format: BounceFormat::AutoReply,
+            kind: BounceKind::AutoReply,
+            recipients,
+        });
+    }
+
+    if from_daemon || (BOUNCE_SUBJECTS.iter().any(|s| subject.contains(s)) && is_null_sender(mail))
+    {
+        let mut addresses = Vec::new();
+        for value in mail.headers.get_all_values("X-Failed-Recipients") {
+            for address in value.split(',').filter_map(address_in) {
+                push_unique(&mut addresses, address);
+            }
+        }
+        let (smtp_code, status) = find_codes(&text);
+        let default = classify(status, smtp_code, &text, BounceKind::Unknown);
+        let recipients = recipients_from_text(&text, addresses, default);
+        return Some(Bounce::new(BounceFormat::Generic, recipients, default));
+    }
+    None
+}
+
+/// Returns true if the message has an empty envelope sender, as bounces do.
+fn is_null_sender(mail: &ParsedMail) -> bool {
+    mail.headers
+        .get_first_value("Return-Path")
+        .map(|v| v.trim() == "<>")
+        .unwrap_or(false)
+}
+
+impl Bounce {
+    fn new(format: BounceFormat, recipients: Vec<FailedRecipient>, default: BounceKind) -> Self {
+        let has = |kind| recipients.iter().any(|r| r.kind == kind);
+        let kind = if has(BounceKind::Hard) {
+            BounceKind::Hard
+        } else if has(BounceKind::Soft) {
+            BounceKind::Soft
+        } else if recipients.is_empty() {
+            default
+        } else {
+            BounceKind::Unknown
+        };
+        Bounce {
+            format,
+            kind,
+            recipients,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mail;
+
+    fn classify_file(name: &str) -> Option<Bounce> {
+        let path = format!("./tests/files/bounces/{}", name);
+        let data =
+            std::fs::read(&path).unwrap_or_else(|_| panic!("Unable to open the file [{}]", path));
+        classify_bounce(&parse_mail(&data).unwrap())
+    }
+
+    fn summary(bounce: &Bounce) -> Vec<(String, Option<String>, Option<u16>, BounceKind)> {
+        bounce
+            .recipients
+            .iter()
+            .map(|r| {
+                (
+                    r.address.clone(),
+                    r.status.map(|s| s.to_string()),
+                    r.smtp_code,
+                    r.kind,
+                )
+            })
+            .collect()
+    }
+
+    fn expected(
+        address: &str,
+        status: Option<&str>,
+        smtp_code: Option<u16>,
+        kind: BounceKind,
+    ) -> (String, Option<String>, Option<u16>, BounceKind) {
+        (
+            address.to_string(),
+            status.map(str::to_string),
+            smtp_code,
+            kind,
+        )
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!(
+            find_codes("host [192.0.2.25] said: 550 5.1.1 <a@b.c>"),
+            (Some(550), "5.1.1".parse().ok())
+        );
+        assert_eq!(
+            find_codes("#4.2.2> (Exim 4.96) 2024"),
+            (None, "4.2.2".parse().ok())
+        );
+        assert_eq!(find_codes("no codes here 12 1234 600"), (None, None));
+    }
+
+    #[test]
+    fn dsn_bounce() {
+        let bounce = classify_file("dsn_postfix.eml").unwrap();
+        assert_eq!(bounce.format, BounceFormat::Dsn);
+        assert_eq!(bounce.kind, BounceKind::Hard);
+        assert_eq!(
+            summary(&bounce),
+            vec![
+                expected(
+                    "nobody@example.net",
+                    Some("5.1.1"),
+                    Some(550),
+                    BounceKind::Hard
+                ),
+                expected(
+                    "full@example.net",
+                    Some("4.2.2"),
+                    Some(452),
+                    BounceKind::Soft
+                ),
+            ]
+        );
+        assert!(bounce.recipients[0]
+            .diagnostic
+            .as_ref()
+            .unwrap()
+            .contains("User unknown"));
+    }
+
+    #[test]
+    fn exim_bounces() {
+        let bounce = classify_file("exim_failed.eml").unwrap();
+        assert_eq!(bounce.format, BounceFormat::Exim);
+        assert_eq!(bounce.kind, BounceKind::Hard);
+        assert_eq!(
+            summary(&bounce),
+            vec![expected(
+                "nobody@example.net",
+                Some("5.1.1"),
+                Some(550),
+                BounceKind::Hard
+            )]
+        );
+
+        let bounce = classify_file("exim_delayed.eml").unwrap();
+        assert_eq!(bounce.format, BounceFormat::Exim);
+        assert_eq!(bounce.kind, BounceKind::Soft);
+        assert_eq!(
+            summary(&bounce),
+            vec![expected(
+                "slow@example.net",
+                Some("4.7.1"),
+                Some(451),
+                BounceKind::Soft
+            )]
+        );
+    }
+
+    #[test]
+    fn qmail_bounce() {
+        let bounce = classify_file("qmail.eml").unwrap();
+        assert_eq!(bounce.format, BounceFormat::Qmail);
+        assert_eq!(bounce.kind, BounceKind::Hard);
+        assert_eq!(
+            summary(&bounce),
+            vec![
+                expected(
+                    "nobody@example.net",
+                    Some("5.1.1"),
+                    Some(550),
+                    BounceKind::Hard
+                ),
+                expected(
+                    "quota@example.net",
+                    Some("5.2.2"),
+                    Some(552),
+                    BounceKind::Soft
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn exchange_bounces() {
+        let bounce = classify_file("exchange.eml").unwrap();
+        assert_eq!(bounce.format, BounceFormat::Exchange);
+        assert_eq!(
+            summary(&bounce),
+            vec![expected(
+                "bob.smith@corp.example.com",
+                Some("5.1.1"),
+                Some(550),
+                BounceKind::Hard
+            )]
+        );
+
+        let bounce = classify_file("exchange_2003.eml").unwrap();
+        assert_eq!(bounce.format, BounceFormat::Exchange);
+        assert_eq!(
+            summary(&bounce),
+            vec![expected(
+                "carol@legacy.example.com",
+                Some("4.2.2"),
+                None,
+                BounceKind::Soft
+            )]
+        );
+    }
+
+    #[test]
+    fn autoreplies_and_non_bounces() {
+        let bounce = classify_file("autoreply.eml").unwrap();
+        assert_eq!(bounce.format, BounceFormat::AutoReply);
+        assert_eq!(bounce.kind, BounceKind::AutoReply);
+        assert_eq!(bounce.recipients[0].address, "dave@example.net");
+
+        assert_eq!(classify_file("not_a_bounce.eml"), None);
+    }
+
+    #[test]
+    fn generic_bounce() {
+        let mail = parse_mail(
+            concat!(
+                "From: Mail Delivery Subsystem <MAILER-DAEMON@mx.example.com>\n",
+                "X-Failed-Recipients: gone@example.org\n",
+                "Subject: Returned mail: see transcript for details\n",
+                "\n",
+                "----- The following addresses had permanent fatal errors -----\n",
+                "gone@example.org\n",
+                "    (reason: 550 Requested action not taken: mailbox unavailable)\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let bounce = classify_bounce(&mail).unwrap();
+        assert_eq!(bounce.format, BounceFormat::Generic);
+        assert_eq!(
+            summary(&bounce),
+            vec![expected(
+                "gone@example.org",
+                None,
+                Some(550),
+                BounceKind::Hard
+            )]
+        );
+    }
+}
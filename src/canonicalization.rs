@@ -0,0 +1,357 @@
+//! Canonicalization of header fields and bodies as defined in IETF RFC 6376
+//! section 3.4, which is used by DKIM and ARC (RFC 8617) signatures.
+//!
+//! The canonical forms are also useful on their own, e.g. to detect copies of
+//! a message that differ only in whitespace or header folding. These
+//! functions don't need the `dkim` feature. Lone LFs are treated as CRLFs,
+//! so messages stored with Unix line endings canonicalize the same way as
+//! they did on the wire.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{to_crlf, MailHeader, MailParseError};
+
+/// A canonicalization algorithm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Canonicalization {
+    /// The `simple` algorithm, which tolerates almost no modification.
+    Simple,
+    /// The `relaxed` algorithm, which tolerates common modifications such
+    /// as whitespace changes and header re-folding.
+    Relaxed,
+}
+
+impl Default for Canonicalization {
+    fn default() -> Self {
+        Canonicalization::Simple
+    }
+}
+
+impl FromStr for Canonicalization {
+    type Err = MailParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "simple" => Ok(Canonicalization::Simple),
+            "relaxed" => Ok(Canonicalization::Relaxed),
+            _ => Err(MailParseError::Generic(
+                "Unknown canonicalization algorithm",
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Canonicalization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Canonicalization::Simple => "simple",
+            Canonicalization::Relaxed => "relaxed",
+        })
+    }
+}
+
+const fn is_wsp(c: u8) -> bool {
+    c == b' ' || c == b'\t'
+}
+
+/// Splits a block of header fields, as returned by `Headers::get_raw_bytes`,
+/// into the raw fields, each including its line ending(s). The blank line
+/// that ends the block is not included.
+///
+/// # Examples
+/// ```
+///     use mailparse::canonicalization::split_fields;
+///     assert_eq!(split_fields(b"A: 1\r\nB: 2\r\n 3\r\n\r\nbody"),
+///         vec![&b"A: 1\r\n"[..], &b"B: 2\r\n 3\r\n"[..]]);
+/// ```
+pub fn split_fields(block: &[u8]) -> Vec<&[u8]> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let mut ix = 0;
+    while ix < block.len() {
+        let line_end = block[ix..]
+            .iter()
+            .position(|&c| c == b'\n')
+            .map_or(block.len(), |p| ix + p + 1);
+        let line = &block[ix..line_end];
+        if line == b"\n" || line == b"\r\n" {
+            break;
+        }
+        if ix > start && !is_wsp(line[0]) {
+            fields.push(&block[start..ix]);
+            start = ix;
+        }
+        ix = line_end;
+    }
+    if ix > start {
+        fields.push(&block[start..ix]);
+    }
+    fields
+}
+
+/// Equivalent of `<[u8]>::trim_ascii`, which is newer than the MSRV.
+pub(crate) fn trim_ascii(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |ix| ix + 1);
+    &bytes[start..end]
+}
+
+/// Returns the name of a raw header field, such as one returned by
+/// `split_fields`, without surrounding whitespace.
+pub fn field_name(field: &[u8]) -> &[u8] {
+    let name = match field.iter().position(|&c| c == b':') {
+        Some(colon) => &field[..colon],
+        None => field,
+    };
+    trim_ascii(name)
+}
+
+/// Returns the canonical form of a raw header field (name, colon, value and
+/// line ending), such as one returned by `split_fields`. The result always
+/// ends with CRLF.
+///
+/// # Examples
+/// ```
+///     use mailparse::canonicalization::{canonicalize_field, Canonicalization};
+///     assert_eq!(canonicalize_field(Canonicalization::Relaxed, b"SubJect :  Hi\r\n\tthere \r\n"),
+///         b"subject:Hi there\r\n");
+///     assert_eq!(canonicalize_field(Canonicalization::Simple, b"SubJect :  Hi\n"),
+///         b"SubJect :  Hi\r\n");
+/// ```
+pub fn canonicalize_field(c: Canonicalization, field: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(field.len() + 2);
+    write_field(c, field, &mut out);
+    out
+}
+
+/// Returns the canonical form of a parsed header field, built from its raw
+/// name and value (see `MailHeader::get_key_raw` and
+/// `MailHeader::get_value_raw`). The parser drops the whitespace between
+/// the colon and the value, so the `simple` form assumes there was a
+/// single space there, which is almost always the case; use
+/// `canonicalize_field` on the raw field where an exact result matters.
+///
+/// # Examples
+/// ```
+///     use mailparse::parse_header;
+///     use mailparse::canonicalization::{canonicalize_header, Canonicalization};
+///     let (header, _) = parse_header(b"Subject: Hello\r\n  world\r\n").unwrap();
+///     assert_eq!(canonicalize_header(Canonicalization::Relaxed, &header),
+///         b"subject:Hello world\r\n");
+///     assert_eq!(canonicalize_header(Canonicalization::Simple, &header),
+///         b"Subject: Hello\r\n  world\r\n");
+/// ```
+pub fn canonicalize_header(c: Canonicalization, header: &MailHeader) -> Vec<u8> {
+    let key = header.get_key_raw();
+    let value = header.get_value_raw();
+    let mut field = Vec::with_capacity(key.len() + value.len() + 4);
+    field.extend_from_slice(key);
+    field.extend_from_slice(b": ");
+    field.extend_from_slice(value);
+    field.extend_from_slice(b"\r\n");
+    canonicalize_field(c, &field)
+}
+
+/// Appends the canonical form of a raw header field to `out`.
+pub(crate) fn write_field(c: Canonicalization, field: &[u8], out: &mut Vec<u8>) {
+    match c {
+        Canonicalization::Simple => {
+            out.extend_from_slice(&to_crlf(field));
+            if !field.ends_with(b"\n") {
+                out.extend_from_slice(b"\r\n");
+            }
+        }
+        Canonicalization::Relaxed => {
+            let (name, value) = match field.iter().position(|&c| c == b':') {
+                Some(colon) => (&field[..colon], &field[colon + 1..]),
+                None => (field, &b""[..]),
+            };
+            out.extend(trim_ascii(name).iter().map(u8::to_ascii_lowercase));
+            out.push(b':');
+            let mut pending_space = false;
+            let mut started = false;
+            for &b in value {
+                if b == b'\r' || b == b'\n' {
+                    continue;
+                }
+                if is_wsp(b) {
+                    pending_space = started;
+                    continue;
+                }
+                if pending_space {
+                    out.push(b' ');
+                    pending_space = false;
+                }
+                out.push(b);
+                started = true;
+            }
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+}
+
+/// Returns the canonical form of a message body, i.e. everything after the
+/// blank line that ends the header fields.
+///
+/// # Examples
+/// ```
+///     use mailparse::canonicalization::{canonicalize_body, Canonicalization};
+///     assert_eq!(canonicalize_body(Canonicalization::Relaxed, b"Hi  there \n\n\n"),
+///         b"Hi there\r\n");
+///     assert_eq!(canonicalize_body(Canonicalization::Simple, b"Hi  there \n\n\n"),
+///         b"Hi  there \r\n");
+/// ```
+pub fn canonicalize_body(c: Canonicalization, body: &[u8]) -> Vec<u8> {
+    let mut lines: Vec<&[u8]> = body.split(|&b| b == b'\n').collect();
+    if body.ends_with(b"\n") {
+        // The split leaves an empty piece after the final line ending.
+        lines.pop();
+    }
+    let mut out = Vec::with_capacity(body.len() + lines.len());
+    // The number of empty lines that have been seen but not yet written,
+    // since trailing empty lines are dropped.
+    let mut empty_lines = 0;
+    for line in lines {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let mut canonical = Vec::new();
+        match c {
+            Canonicalization::Simple => canonical.extend_from_slice(line),
+            Canonicalization::Relaxed => {
+                let mut pending_space = false;
+                for &b in line {
+                    if is_wsp(b) {
+                        pending_space = true;
+                        continue;
+                    }
+                    if pending_space {
+                        canonical.push(b' ');
+                        pending_space = false;
+                    }
+                    canonical.push(b);
+                }
+            }
+        }
+        if canonical.is_empty() {
+            empty_lines += 1;
+            continue;
+        }
+        for _ in 0..empty_lines {
+            out.extend_from_slice(b"\r\n");
+        }
+        empty_lines = 0;
+        out.extend_from_slice(&canonical);
+        out.extend_from_slice(b"\r\n");
+    }
+    if out.is_empty() && c == Canonicalization::Simple {
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::parse_headers;
+
+    fn field(c: Canonicalization, field: &[u8]) -> Vec<u8> {
+        canonicalize_field(c, field)
+    }
+
+    #[test]
+    fn fields() {
+        let block = b"A: X\r\nB : Y\t\r\n\tZ  \r\nC:\n\n body";
+        let fields = split_fields(block);
+        assert_eq!(
+            fields,
+            vec![&b"A: X\r\n"[..], &b"B : Y\t\r\n\tZ  \r\n"[..], &b"C:\n"[..]]
+        );
+        assert_eq!(field_name(fields[1]), b"B");
+        assert_eq!(field_name(b" \t: X"), b"");
+        assert_eq!(trim_ascii(b" \tA B\r\n"), b"A B");
+
+        // The examples from RFC 6376 section 3.4.5.
+        assert_eq!(field(Canonicalization::Relaxed, fields[0]), b"a:X\r\n");
+        assert_eq!(field(Canonicalization::Relaxed, fields[1]), b"b:Y Z\r\n");
+        assert_eq!(
+            field(Canonicalization::Simple, fields[1]),
+            b"B : Y\t\r\n\tZ  \r\n"
+        );
+        assert_eq!(field(Canonicalization::Simple, fields[2]), b"C:\r\n");
+        assert_eq!(
+            field(Canonicalization::Simple, b"D: no newline"),
+            b"D: no newline\r\n"
+        );
+        assert_eq!(
+            split_fields(b"A: 1\nB: 2"),
+            vec![&b"A: 1\n"[..], &b"B: 2"[..]]
+        );
+        assert!(split_fields(b"\r\nbody").is_empty());
+    }
+
+    #[test]
+    fn headers() {
+        let (headers, _) = parse_headers(b"From:alice\nSubject:  A\n\t B  \nX-Empty:\n\n").unwrap();
+        let relaxed: Vec<Vec<u8>> = headers
+            .iter()
+            .map(|h| canonicalize_header(Canonicalization::Relaxed, h))
+            .collect();
+        assert_eq!(
+            relaxed,
+            vec![
+                b"from:alice\r\n".to_vec(),
+                b"subject:A B\r\n".to_vec(),
+                b"x-empty:\r\n".to_vec()
+            ]
+        );
+        assert_eq!(
+            canonicalize_header(Canonicalization::Simple, &headers[1]),
+            b"Subject: A\r\n\t B  \r\n"
+        );
+        assert_eq!(
+            "Relaxed".parse::<Canonicalization>().unwrap(),
+            Canonicalization::Relaxed
+        );
+        assert!("loose".parse::<Canonicalization>().is_err());
+        assert_eq!(Canonicalization::Simple.to_string(), "simple");
+    }
+
+    #[test]
+    fn bodies() {
+        let body = b" C \r\nD \t E\r\n\r\n\r\n";
+        assert_eq!(
+            canonicalize_body(Canonicalization::Relaxed, body),
+            b" C\r\nD E\r\n"
+        );
+        assert_eq!(
+            canonicalize_body(Canonicalization::Simple, body),
+            b" C \r\nD \t E\r\n"
+        );
+        assert_eq!(
+            canonicalize_body(Canonicalization::Simple, b"a\n\nb  \n \n"),
+            b"a\r\n\r\nb  \r\n \r\n"
+        );
+        assert_eq!(
+            canonicalize_body(Canonicalization::Relaxed, b"a\n\nb  \n \n"),
+            b"a\r\n\r\nb\r\n"
+        );
+        assert_eq!(
+            canonicalize_body(Canonicalization::Simple, b"no newline"),
+            b"no newline\r\n"
+        );
+        assert_eq!(canonicalize_body(Canonicalization::Simple, b""), b"\r\n");
+        assert_eq!(
+            canonicalize_body(Canonicalization::Simple, b"\r\n\r\n"),
+            b"\r\n"
+        );
+        assert_eq!(canonicalize_body(Canonicalization::Relaxed, b""), b"");
+        assert_eq!(canonicalize_body(Canonicalization::Relaxed, b" \r\n"), b"");
+    }
+}
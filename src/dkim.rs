@@ -0,0 +1,1181 @@
+//! Verification and signing of DKIM signatures as defined in IETF RFC 6376,
+//! with the `rsa-sha256` and `ed25519-sha256` (RFC 8463) algorithms. This
+//! module is only available with the `dkim` feature.
+//!
+//! Verifying a signature needs the signer's public key, which is normally
+//! published in DNS. The lookup is done through the `KeyLookup` trait so that
+//! applications can plug in their own resolver; it is implemented by every
+//! `dns::Resolver`, and `InMemoryKeyStore` is a simple implementation for
+//! tests and for keys that are known in advance.
+//!
+//! Signatures using `rsa-sha1` are reported as unsupported, since RFC 8301
+//! forbids considering them valid.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use data_encoding::BASE64;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rsa::pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey};
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePublicKey};
+use rsa::traits::PublicKeyParts;
+use rsa::{Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
+
+use crate::authres::AuthResult;
+use crate::body::decode_base64;
+pub use crate::canonicalization::Canonicalization;
+use crate::canonicalization::{
+    canonicalize_body, field_name, split_fields, trim_ascii, write_field,
+};
+pub use crate::dns::LookupError;
+use crate::dns::Resolver;
+use crate::{parse_headers, split_once, MailParseError, ParsedMail};
+
+/// Parses a tag-list, the `tag=value; tag=value` syntax used by
+/// `DKIM-Signature` headers and DKIM key records (RFC 6376 section 3.2).
+/// Whitespace around tags and values is removed; whitespace inside values
+/// is kept. Duplicate tags are an error.
+///
+/// # Examples
+/// ```
+///     use mailparse::dkim::parse_tag_list;
+///     assert_eq!(parse_tag_list("v=DKIM1; k=ed25519; p=abc").unwrap(), vec![
+///         ("v".to_string(), "DKIM1".to_string()),
+///         ("k".to_string(), "ed25519".to_string()),
+///         ("p".to_string(), "abc".to_string()),
+///     ]);
+/// ```
+pub fn parse_tag_list(s: &str) -> Result<Vec<(String, String)>, MailParseError> {
+    let mut tags: Vec<(String, String)> = Vec::new();
+    let specs: Vec<&str> = s.split(';').collect();
+    for (ix, spec) in specs.iter().enumerate() {
+        if spec.trim().is_empty() {
+            if ix == specs.len() - 1 {
+                // A trailing semicolon is allowed.
+                break;
+            }
+            return Err(MailParseError::Generic("Empty tag in tag-list"));
+        }
+        let (name, value) =
+            split_once(spec, '=').ok_or(MailParseError::Generic("Missing = in tag-list"))?;
+        let name = name.trim();
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(MailParseError::Generic("Invalid tag name in tag-list"));
+        }
+        if tags.iter().any(|(n, _)| n == name) {
+            return Err(MailParseError::Generic("Duplicate tag in tag-list"));
+        }
+        tags.push((name.to_string(), value.trim().to_string()));
+    }
+    Ok(tags)
+}
+
+/// A signing algorithm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// RSA with SHA-256 (`rsa-sha256`).
+    RsaSha256,
+    /// Ed25519 with SHA-256 (`ed25519-sha256`, RFC 8463).
+    Ed25519Sha256,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Algorithm::RsaSha256 => "rsa-sha256",
+            Algorithm::Ed25519Sha256 => "ed25519-sha256",
+        })
+    }
+}
+
+/// The parsed value of a `DKIM-Signature` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DkimSignature {
+    /// The signing algorithm (`a=`).
+    pub algorithm: Algorithm,
+    /// The signature (`b=`).
+    pub signature: Vec<u8>,
+    /// The hash of the canonicalized body (`bh=`).
+    pub body_hash: Vec<u8>,
+    /// The header canonicalization (first half of `c=`).
+    pub header_canonicalization: Canonicalization,
+    /// The body canonicalization (second half of `c=`).
+    pub body_canonicalization: Canonicalization,
+    /// The signing domain (`d=`).
+    pub domain: String,
+    /// The names of the signed header fields (`h=`), in order.
+    pub signed_headers: Vec<String>,
+    /// The identity of the user or agent on whose behalf the message was
+    /// signed (`i=`), which defaults to `@` followed by the domain.
+    pub identity: String,
+    /// The number of body bytes covered by the signature (`l=`), if not
+    /// the whole body.
+    pub body_length: Option<usize>,
+    /// The selector (`s=`) that, along with the domain, locates the key.
+    pub selector: String,
+    /// The signature timestamp (`t=`), as a UNIX timestamp.
+    pub timestamp: Option<u64>,
+    /// The signature expiration (`x=`), as a UNIX timestamp.
+    pub expiration: Option<u64>,
+}
+
+/// Like `DkimFailure::InvalidSignature`, for parsing errors.
+pub(crate) const fn invalid(reason: &'static str) -> DkimFailure {
+    DkimFailure::InvalidSignature(reason)
+}
+
+impl DkimSignature {
+    fn parse(value: &str) -> Result<DkimSignature, DkimFailure> {
+        let tags = parse_signature_tags(value)?;
+        let tags: HashMap<&str, &str> =
+            tags.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+        match tags.get("v") {
+            Some(&"1") => {}
+            Some(_) => return Err(invalid("Unsupported DKIM-Signature version")),
+            None => return Err(invalid("Missing required tag in DKIM-Signature")),
+        }
+        DkimSignature::from_tags(&tags)
+    }
+
+    /// Builds a signature from its tags, ignoring `v=`. This is shared with
+    /// `ARC-Message-Signature`, which has the same tags except for `v=` and
+    /// `i=`; the caller removes the latter.
+    pub(crate) fn from_tags(tags: &HashMap<&str, &str>) -> Result<DkimSignature, DkimFailure> {
+        let required = |name| {
+            tags.get(name)
+                .copied()
+                .ok_or(invalid("Missing required tag in DKIM-Signature"))
+        };
+
+        let algorithm = parse_algorithm(required("a")?)?;
+        let signature = decode_base64(required("b")?.as_bytes())
+            .ok()
+            .ok_or(invalid("Invalid base64 in b= tag"))?;
+        let body_hash = decode_base64(required("bh")?.as_bytes())
+            .ok()
+            .ok_or(invalid("Invalid base64 in bh= tag"))?;
+        let parse_canon = |c: &str| {
+            c.parse::<Canonicalization>()
+                .map_err(|_| invalid("Unknown canonicalization in c= tag"))
+        };
+        let (header_canonicalization, body_canonicalization) = match tags.get("c") {
+            None => (Canonicalization::Simple, Canonicalization::Simple),
+            Some(c) => match split_once(c, '/') {
+                Some((h, b)) => (parse_canon(h)?, parse_canon(b)?),
+                None => (parse_canon(c)?, Canonicalization::Simple),
+            },
+        };
+        let domain = required("d")?.to_string();
+        let signed_headers: Vec<String> = required("h")?
+            .split(':')
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .collect();
+        if !signed_headers
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case("from"))
+        {
+            return Err(invalid("The From header is not signed"));
+        }
+        let identity = match tags.get("i") {
+            Some(i) => i.to_string(),
+            None => format!("@{}", domain),
+        };
+        let identity_domain = identity.rsplit('@').next().unwrap_or("").to_lowercase();
+        let lower_domain = domain.to_lowercase();
+        if identity_domain != lower_domain
+            && !identity_domain.ends_with(&format!(".{}", lower_domain))
+        {
+            return Err(invalid("The i= domain is not within the d= domain"));
+        }
+        if let Some(q) = tags.get("q") {
+            if !q
+                .split(':')
+                .any(|m| m.trim().eq_ignore_ascii_case("dns/txt"))
+            {
+                return Err(invalid("Unsupported query method in q= tag"));
+            }
+        }
+        let number = |name| -> Result<Option<u64>, DkimFailure> {
+            tags.get(name)
+                .map(|v| {
+                    v.parse()
+                        .map_err(|_| invalid("Invalid number in DKIM-Signature"))
+                })
+                .transpose()
+        };
+        let body_length = number("l")?.map(|l| l as usize);
+        let timestamp = number("t")?;
+        let expiration = number("x")?;
+        if let (Some(t), Some(x)) = (timestamp, expiration) {
+            if x < t {
+                return Err(invalid("The x= tag is earlier than the t= tag"));
+            }
+        }
+
+        Ok(DkimSignature {
+            algorithm,
+            signature,
+            body_hash,
+            header_canonicalization,
+            body_canonicalization,
+            domain,
+            signed_headers,
+            identity,
+            body_length,
+            selector: required("s")?.to_string(),
+            timestamp,
+            expiration,
+        })
+    }
+}
+
+/// Parses a signature tag-list, as `parse_tag_list` but with errors
+/// reported as `DkimFailure`s.
+pub(crate) fn parse_signature_tags(value: &str) -> Result<Vec<(String, String)>, DkimFailure> {
+    parse_tag_list(value).map_err(|e| match e {
+        MailParseError::Generic(reason) => invalid(reason),
+        _ => invalid("Invalid tag-list"),
+    })
+}
+
+/// Parses the value of an `a=` tag.
+pub(crate) fn parse_algorithm(value: &str) -> Result<Algorithm, DkimFailure> {
+    match value.to_lowercase().as_str() {
+        "rsa-sha256" => Ok(Algorithm::RsaSha256),
+        "ed25519-sha256" => Ok(Algorithm::Ed25519Sha256),
+        other => Err(DkimFailure::UnsupportedAlgorithm(other.to_string())),
+    }
+}
+
+impl FromStr for DkimSignature {
+    type Err = MailParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DkimSignature::parse(s).map_err(|e| match e {
+            DkimFailure::InvalidSignature(reason) => MailParseError::Generic(reason),
+            _ => MailParseError::Generic("Unsupported DKIM-Signature algorithm"),
+        })
+    }
+}
+
+/// A source of DKIM public key records, normally DNS. Every `Resolver` is
+/// one.
+pub trait KeyLookup {
+    /// Returns the TXT records published at the given DNS name, such as
+    /// `selector._domainkey.example.com`. Records split into several
+    /// strings should be returned with the strings concatenated. Returns an
+    /// empty vector if the name has no TXT records.
+    fn lookup_txt(&self, name: &str) -> Result<Vec<String>, LookupError>;
+}
+
+impl<R: Resolver + ?Sized> KeyLookup for R {
+    fn lookup_txt(&self, name: &str) -> Result<Vec<String>, LookupError> {
+        Resolver::lookup_txt(self, name)
+    }
+}
+
+/// A `KeyLookup` backed by an in-memory map of DNS names to TXT records.
+///
+/// # Examples
+/// ```
+///     use mailparse::dkim::{InMemoryKeyStore, KeyLookup};
+///     let mut keys = InMemoryKeyStore::new();
+///     keys.add_key("sel1", "Example.com", "v=DKIM1; k=ed25519; p=abc");
+///     assert_eq!(keys.lookup_txt("sel1._domainkey.example.com").unwrap(),
+///         vec!["v=DKIM1; k=ed25519; p=abc".to_string()]);
+///     assert!(keys.lookup_txt("other._domainkey.example.com").unwrap().is_empty());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryKeyStore {
+    records: HashMap<String, Vec<String>>,
+}
+
+impl InMemoryKeyStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a TXT record at the given DNS name.
+    pub fn add_txt(&mut self, name: &str, record: &str) {
+        self.records
+            .entry(name.trim_end_matches('.').to_lowercase())
+            .or_default()
+            .push(record.to_string());
+    }
+
+    /// Adds a DKIM key record for the given selector and domain.
+    pub fn add_key(&mut self, selector: &str, domain: &str, record: &str) {
+        self.add_txt(&format!("{}._domainkey.{}", selector, domain), record);
+    }
+}
+
+impl KeyLookup for InMemoryKeyStore {
+    fn lookup_txt(&self, name: &str) -> Result<Vec<String>, LookupError> {
+        let name = name.trim_end_matches('.').to_lowercase();
+        Ok(self.records.get(&name).cloned().unwrap_or_default())
+    }
+}
+
+/// The reason a DKIM signature could not be verified.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DkimFailure {
+    /// The `DKIM-Signature` header is malformed or lacks required tags.
+    InvalidSignature(&'static str),
+    /// The signature uses an algorithm that is not supported, such as
+    /// `rsa-sha1`.
+    UnsupportedAlgorithm(String),
+    /// The key could not be looked up because of a temporary error.
+    KeyUnavailable(LookupError),
+    /// No key record was found for the selector and domain.
+    KeyNotFound,
+    /// The key record is malformed or does not fit the signature.
+    InvalidKey(&'static str),
+    /// The key has been revoked (its record has an empty `p=` tag).
+    KeyRevoked,
+    /// The signature has expired (its `x=` tag is in the past).
+    Expired,
+    /// The body does not match the body hash, i.e. it was modified.
+    BodyHashMismatch,
+    /// The signature does not match the signed headers.
+    SignatureMismatch,
+}
+
+impl DkimFailure {
+    /// Returns the result to record in an `Authentication-Results` header
+    /// for this failure, as defined in RFC 8601 section 2.7.1.
+    pub const fn auth_result(&self) -> AuthResult {
+        match self {
+            DkimFailure::KeyUnavailable(_) => AuthResult::TempError,
+            DkimFailure::Expired
+            | DkimFailure::BodyHashMismatch
+            | DkimFailure::SignatureMismatch => AuthResult::Fail,
+            _ => AuthResult::PermError,
+        }
+    }
+}
+
+impl fmt::Display for DkimFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DkimFailure::InvalidSignature(reason) => f.write_str(reason),
+            DkimFailure::UnsupportedAlgorithm(a) => write!(f, "unsupported algorithm {}", a),
+            DkimFailure::KeyUnavailable(e) => e.fmt(f),
+            DkimFailure::KeyNotFound => f.write_str("no key for signature"),
+            DkimFailure::InvalidKey(reason) => f.write_str(reason),
+            DkimFailure::KeyRevoked => f.write_str("key revoked"),
+            DkimFailure::Expired => f.write_str("signature expired"),
+            DkimFailure::BodyHashMismatch => f.write_str("body hash did not verify"),
+            DkimFailure::SignatureMismatch => f.write_str("signature did not verify"),
+        }
+    }
+}
+
+/// The outcome of verifying one `DKIM-Signature` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DkimVerification {
+    /// The parsed signature, or `None` if the header could not be parsed.
+    pub signature: Option<DkimSignature>,
+    /// `Ok` if the signature verified, or the reason it did not.
+    pub result: Result<(), DkimFailure>,
+}
+
+impl DkimVerification {
+    /// Returns the result to record in an `Authentication-Results` header.
+    pub const fn auth_result(&self) -> AuthResult {
+        match &self.result {
+            Ok(()) => AuthResult::Pass,
+            Err(failure) => failure.auth_result(),
+        }
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// A public key from a DKIM key record.
+pub(crate) enum PublicKey {
+    Rsa(RsaPublicKey),
+    Ed25519(VerifyingKey),
+}
+
+/// Looks up and parses the key for a signature made with the given
+/// algorithm by the given domain, selector and identity (`i=`).
+pub(crate) fn lookup_key<K: KeyLookup + ?Sized>(
+    keys: &K,
+    algorithm: Algorithm,
+    domain: &str,
+    selector: &str,
+    identity: &str,
+) -> Result<PublicKey, DkimFailure> {
+    let name = format!("{}._domainkey.{}", selector, domain);
+    let records = keys
+        .lookup_txt(&name)
+        .map_err(DkimFailure::KeyUnavailable)?;
+    // RFC 6376 leaves multiple records undefined; use the first valid one.
+    let tags = records
+        .iter()
+        .find_map(|r| parse_tag_list(r).ok())
+        .ok_or(DkimFailure::KeyNotFound)?;
+    let tags: HashMap<&str, &str> = tags.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+
+    if matches!(tags.get("v"), Some(&v) if v != "DKIM1") {
+        return Err(DkimFailure::InvalidKey("Unsupported key record version"));
+    }
+    if let Some(h) = tags.get("h") {
+        if !h
+            .split(':')
+            .any(|h| h.trim().eq_ignore_ascii_case("sha256"))
+        {
+            return Err(DkimFailure::InvalidKey("Key does not allow SHA-256"));
+        }
+    }
+    if let Some(s) = tags.get("s") {
+        if !s.split(':').any(|s| matches!(s.trim(), "*" | "email")) {
+            return Err(DkimFailure::InvalidKey("Key is not for email"));
+        }
+    }
+    if let Some(t) = tags.get("t") {
+        let strict = t.split(':').any(|f| f.trim() == "s");
+        let identity_domain = identity.rsplit('@').next().unwrap_or("");
+        if strict && !identity_domain.eq_ignore_ascii_case(domain) {
+            return Err(DkimFailure::InvalidKey(
+                "Key does not allow the i= domain to be a subdomain",
+            ));
+        }
+    }
+    let data = tags
+        .get("p")
+        .ok_or(DkimFailure::InvalidKey("Key record has no p= tag"))?;
+    if data.is_empty() {
+        return Err(DkimFailure::KeyRevoked);
+    }
+    let data = decode_base64(data.as_bytes())
+        .ok()
+        .ok_or(DkimFailure::InvalidKey("Invalid base64 in key"))?;
+    let key_type = tags.get("k").copied().unwrap_or("rsa").to_lowercase();
+    match (key_type.as_str(), algorithm) {
+        ("rsa", Algorithm::RsaSha256) => {
+            let key = RsaPublicKey::from_public_key_der(&data)
+                .or_else(|_| RsaPublicKey::from_pkcs1_der(&data))
+                .map_err(|_| DkimFailure::InvalidKey("Invalid RSA key"))?;
+            // RFC 8301 section 3.2.
+            if key.size() * 8 < 1024 {
+                return Err(DkimFailure::InvalidKey("RSA key is shorter than 1024 bits"));
+            }
+            Ok(PublicKey::Rsa(key))
+        }
+        ("ed25519", Algorithm::Ed25519Sha256) => {
+            let data: [u8; 32] = data
+                .try_into()
+                .map_err(|_| DkimFailure::InvalidKey("Invalid Ed25519 key"))?;
+            VerifyingKey::from_bytes(&data)
+                .map(PublicKey::Ed25519)
+                .map_err(|_| DkimFailure::InvalidKey("Invalid Ed25519 key"))
+        }
+        _ => Err(DkimFailure::InvalidKey(
+            "Key type does not match signature algorithm",
+        )),
+    }
+}
+
+/// Returns a raw signature field (`DKIM-Signature`, or one of the ARC
+/// fields) with the value of its `b=` tag removed, as needed to compute the
+/// header hash.
+pub(crate) fn strip_signature(field: &[u8]) -> Vec<u8> {
+    let colon = field.iter().position(|&c| c == b':').unwrap_or(0);
+    let mut out = field[..colon + 1].to_vec();
+    let mut first = true;
+    for spec in field[colon + 1..].split(|&c| c == b';') {
+        if !first {
+            out.push(b';');
+        }
+        first = false;
+        match spec.iter().position(|&c| c == b'=') {
+            Some(eq) if trim_ascii(&spec[..eq]) == b"b" => {
+                out.extend_from_slice(&spec[..eq + 1]);
+                // A field ending in the b= tag loses its line ending too,
+                // which the canonicalization adds back.
+            }
+            _ => out.extend_from_slice(spec),
+        }
+    }
+    out
+}
+
+/// Computes the data that the header hash is computed over: the signed
+/// header fields, followed by the `DKIM-Signature` field without its `b=`
+/// value and final line ending.
+pub(crate) fn signed_header_data(
+    fields: &[&[u8]],
+    signed_headers: &[String],
+    canon: Canonicalization,
+    signature_field: &[u8],
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut used = vec![false; fields.len()];
+    for name in signed_headers {
+        // Each name selects the bottom-most instance not selected yet; names
+        // without any instance left contribute nothing (RFC 6376 5.4.2).
+        let found = (0..fields.len())
+            .rev()
+            .find(|&ix| !used[ix] && field_name(fields[ix]).eq_ignore_ascii_case(name.as_bytes()));
+        if let Some(ix) = found {
+            used[ix] = true;
+            write_field(canon, fields[ix], &mut data);
+        }
+    }
+    write_field(canon, signature_field, &mut data);
+    data.truncate(data.len() - 2);
+    data
+}
+
+/// Checks a signature over the SHA-256 hash of `data`.
+pub(crate) fn verify_hash(key: &PublicKey, data: &[u8], signature: &[u8]) -> bool {
+    let hash = Sha256::digest(data);
+    match key {
+        PublicKey::Rsa(key) => key
+            .verify(Pkcs1v15Sign::new::<Sha256>(), &hash, signature)
+            .is_ok(),
+        PublicKey::Ed25519(key) => Signature::from_slice(signature)
+            .map(|s| key.verify_strict(&hash, &s).is_ok())
+            .unwrap_or(false),
+    }
+}
+
+pub(crate) fn verify_signature(
+    mail: &ParsedMail,
+    fields: &[&[u8]],
+    signature_field: &[u8],
+    sig: &DkimSignature,
+    key: &PublicKey,
+) -> Result<(), DkimFailure> {
+    let header_len = mail.get_headers().get_raw_bytes().len();
+    let body = canonicalize_body(sig.body_canonicalization, &mail.raw_bytes[header_len..]);
+    let body = match sig.body_length {
+        Some(l) if l > body.len() => return Err(DkimFailure::BodyHashMismatch),
+        Some(l) => &body[..l],
+        None => &body[..],
+    };
+    if Sha256::digest(body).as_slice() != sig.body_hash.as_slice() {
+        return Err(DkimFailure::BodyHashMismatch);
+    }
+
+    let data = signed_header_data(
+        fields,
+        &sig.signed_headers,
+        sig.header_canonicalization,
+        &strip_signature(signature_field),
+    );
+    if verify_hash(key, &data, &sig.signature) {
+        Ok(())
+    } else {
+        Err(DkimFailure::SignatureMismatch)
+    }
+}
+
+impl<'a> ParsedMail<'a> {
+    /// Verifies all the `DKIM-Signature` headers of this message, looking
+    /// up the public keys with `keys`. Returns one result per signature, in
+    /// the order of the headers. This should be called on the top-level
+    /// message, parsed from the bytes exactly as received.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     use mailparse::dkim::{DkimFailure, InMemoryKeyStore};
+    ///     let mail = parse_mail(concat!(
+    ///             "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=sel;\n",
+    ///             " h=From; bh=AAAA; b=AAAA\n",
+    ///             "From: alice@example.com\n",
+    ///             "\n",
+    ///             "Hello\n").as_bytes())
+    ///         .unwrap();
+    ///     let results = mail.verify_dkim(&InMemoryKeyStore::new());
+    ///     assert_eq!(results.len(), 1);
+    ///     assert_eq!(results[0].result, Err(DkimFailure::KeyNotFound));
+    /// ```
+    pub fn verify_dkim<K: KeyLookup + ?Sized>(&self, keys: &K) -> Vec<DkimVerification> {
+        let now = now();
+        let fields = split_fields(self.get_headers().get_raw_bytes());
+        let mut results = Vec::new();
+        for field in &fields {
+            if !field_name(field).eq_ignore_ascii_case(b"DKIM-Signature") {
+                continue;
+            }
+            // The tag-list is ASCII; parse it from the raw field rather than
+            // the decoded header value.
+            let colon = field.iter().position(|&c| c == b':').unwrap_or(0);
+            let value = String::from_utf8_lossy(&field[colon + 1..]);
+            let signature = match DkimSignature::parse(&value) {
+                Ok(signature) => signature,
+                Err(failure) => {
+                    results.push(DkimVerification {
+                        signature: None,
+                        result: Err(failure),
+                    });
+                    continue;
+                }
+            };
+            let result = if matches!(signature.expiration, Some(x) if x < now) {
+                Err(DkimFailure::Expired)
+            } else {
+                lookup_key(
+                    keys,
+                    signature.algorithm,
+                    &signature.domain,
+                    &signature.selector,
+                    &signature.identity,
+                )
+                .and_then(|key| verify_signature(self, &fields, field, &signature, &key))
+            };
+            results.push(DkimVerification {
+                signature: Some(signature),
+                result,
+            });
+        }
+        results
+    }
+}
+
+/// A private key to sign messages with.
+#[derive(Clone, Debug)]
+pub enum PrivateKey {
+    /// An RSA key, for the `rsa-sha256` algorithm.
+    Rsa(RsaPrivateKey),
+    /// An Ed25519 key, for the `ed25519-sha256` algorithm.
+    Ed25519(SigningKey),
+}
+
+impl PrivateKey {
+    /// Parses a PEM-encoded private key: an RSA key in PKCS#8 or PKCS#1
+    /// format, or an Ed25519 key in PKCS#8 format.
+    pub fn from_pem(pem: &str) -> Result<PrivateKey, MailParseError> {
+        if let Ok(key) = RsaPrivateKey::from_pkcs8_pem(pem) {
+            return Ok(PrivateKey::Rsa(key));
+        }
+        if let Ok(key) = RsaPrivateKey::from_pkcs1_pem(pem) {
+            return Ok(PrivateKey::Rsa(key));
+        }
+        SigningKey::from_pkcs8_pem(pem)
+            .map(PrivateKey::Ed25519)
+            .map_err(|_| MailParseError::Generic("Unsupported or invalid private key"))
+    }
+
+    /// Returns the algorithm that signatures made with this key use.
+    pub const fn algorithm(&self) -> Algorithm {
+        match self {
+            PrivateKey::Rsa(_) => Algorithm::RsaSha256,
+            PrivateKey::Ed25519(_) => Algorithm::Ed25519Sha256,
+        }
+    }
+
+    /// Returns the DKIM key record to publish in DNS (at
+    /// `selector._domainkey.domain`) for the public half of this key.
+    pub fn public_key_record(&self) -> Result<String, MailParseError> {
+        let (key_type, data) = match self {
+            PrivateKey::Rsa(key) => (
+                "rsa",
+                key.to_public_key()
+                    .to_public_key_der()
+                    .map_err(|_| MailParseError::Generic("Unable to encode RSA public key"))?
+                    .as_bytes()
+                    .to_vec(),
+            ),
+            PrivateKey::Ed25519(key) => ("ed25519", key.verifying_key().to_bytes().to_vec()),
+        };
+        Ok(format!(
+            "v=DKIM1; k={}; p={}",
+            key_type,
+            BASE64.encode(&data)
+        ))
+    }
+}
+
+/// The header fields signed by default, as recommended by RFC 6376 section
+/// 5.4.1. Only the ones present in a message are signed.
+const DEFAULT_SIGNED_HEADERS: &[&str] = &[
+    "From",
+    "Reply-To",
+    "Subject",
+    "Date",
+    "To",
+    "Cc",
+    "Resent-Date",
+    "Resent-From",
+    "Resent-To",
+    "Resent-Cc",
+    "In-Reply-To",
+    "References",
+    "List-Id",
+    "List-Help",
+    "List-Unsubscribe",
+    "List-Unsubscribe-Post",
+    "List-Subscribe",
+    "List-Post",
+    "List-Owner",
+    "List-Archive",
+    "Message-ID",
+    "MIME-Version",
+    "Content-Type",
+    "Content-Transfer-Encoding",
+];
+
+/// Signs messages with DKIM. Create one with `DkimSigner::new` and adjust
+/// the public fields to change the defaults.
+///
+/// # Examples
+/// ```
+///     use mailparse::dkim::{DkimSigner, InMemoryKeyStore, PrivateKey};
+///     use mailparse::parse_mail;
+///     # let pem = std::fs::read_to_string("tests/files/dkim/ed25519_private.pem").unwrap();
+///     let key = PrivateKey::from_pem(&pem).unwrap();
+///     let mut keys = InMemoryKeyStore::new();
+///     keys.add_key("sel1", "example.com", &key.public_key_record().unwrap());
+///
+///     let signer = DkimSigner::new("example.com", "sel1", key);
+///     let signed = signer.sign(concat!(
+///             "From: alice@example.com\r\n",
+///             "Subject: Hello\r\n",
+///             "\r\n",
+///             "Hi Bob\r\n").as_bytes())
+///         .unwrap();
+///     assert!(signed.starts_with(b"DKIM-Signature: v=1; a=ed25519-sha256;"));
+///     let mail = parse_mail(&signed).unwrap();
+///     assert_eq!(mail.verify_dkim(&keys)[0].result, Ok(()));
+/// ```
+#[derive(Clone, Debug)]
+pub struct DkimSigner {
+    /// The signing domain (`d=`).
+    pub domain: String,
+    /// The selector (`s=`) under which the public key is published.
+    pub selector: String,
+    /// The private key.
+    pub key: PrivateKey,
+    /// The header fields to sign if present in the message. This defaults
+    /// to the list recommended by RFC 6376. `From` is always signed.
+    pub signed_headers: Vec<String>,
+    /// The header fields to oversign, i.e. to list once more in `h=` than
+    /// they occur in the message, so that adding another instance of them
+    /// breaks the signature. This defaults to `From` and `Subject`.
+    pub oversigned_headers: Vec<String>,
+    /// The header canonicalization, `Relaxed` by default.
+    pub header_canonicalization: Canonicalization,
+    /// The body canonicalization, `Relaxed` by default.
+    pub body_canonicalization: Canonicalization,
+    /// Whether to add an `l=` tag with the length of the signed body. This
+    /// lets the signature survive content appended to the body (e.g. by
+    /// mailing lists), but also lets anyone append content. Off by default.
+    pub body_length: bool,
+    /// The number of seconds after which the signature expires, if any
+    /// (`x=`).
+    pub expire_after: Option<u64>,
+}
+
+impl DkimSigner {
+    /// Creates a signer with the default settings.
+    pub fn new(domain: &str, selector: &str, key: PrivateKey) -> Self {
+        DkimSigner {
+            domain: domain.to_string(),
+            selector: selector.to_string(),
+            key,
+            signed_headers: DEFAULT_SIGNED_HEADERS
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+            oversigned_headers: vec!["From".to_string(), "Subject".to_string()],
+            header_canonicalization: Canonicalization::Relaxed,
+            body_canonicalization: Canonicalization::Relaxed,
+            body_length: false,
+            expire_after: None,
+        }
+    }
+
+    /// Computes the `DKIM-Signature` header field for the given raw
+    /// message, including its name and final line ending. The line endings
+    /// match those of the message.
+    pub fn signature_header(&self, raw: &[u8]) -> Result<String, MailParseError> {
+        let (_, body_start) = parse_headers(raw)?;
+        let fields = split_fields(&raw[..body_start]);
+        let count = |name: &str| {
+            fields
+                .iter()
+                .filter(|f| field_name(f).eq_ignore_ascii_case(name.as_bytes()))
+                .count()
+        };
+        if count("From") == 0 {
+            return Err(MailParseError::Generic("Message has no From header"));
+        }
+
+        let mut names: Vec<&str> = vec!["From"];
+        for name in &self.signed_headers {
+            if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                names.push(name);
+            }
+        }
+        let mut signed_headers = Vec::new();
+        for name in names {
+            let mut n = count(name);
+            if self
+                .oversigned_headers
+                .iter()
+                .any(|o| o.eq_ignore_ascii_case(name))
+            {
+                n += 1;
+            }
+            for _ in 0..n {
+                signed_headers.push(name.to_lowercase());
+            }
+        }
+
+        let body = canonicalize_body(self.body_canonicalization, &raw[body_start..]);
+        let body_hash = BASE64.encode(&Sha256::digest(&body));
+        let now = now();
+
+        let mut field = format!(
+            "DKIM-Signature: v=1; a={}; c={}/{}; d={}; s={};\r\n\tt={};",
+            self.key.algorithm(),
+            self.header_canonicalization,
+            self.body_canonicalization,
+            self.domain,
+            self.selector,
+            now,
+        );
+        if let Some(expire_after) = self.expire_after {
+            field.push_str(&format!(" x={};", now + expire_after));
+        }
+        if self.body_length {
+            field.push_str(&format!(" l={};", body.len()));
+        }
+        field.push_str("\r\n\th=");
+        let mut line_len = 3;
+        for (ix, name) in signed_headers.iter().enumerate() {
+            if ix > 0 {
+                if line_len + name.len() > 70 {
+                    field.push_str("\r\n\t ");
+                    line_len = 1;
+                }
+                field.push(':');
+            }
+            field.push_str(name);
+            line_len += name.len() + 1;
+        }
+        field.push_str(&format!(";\r\n\tbh={};\r\n\tb=", body_hash));
+
+        let data = signed_header_data(
+            &fields,
+            &signed_headers,
+            self.header_canonicalization,
+            field.as_bytes(),
+        );
+        let hash = Sha256::digest(&data);
+        let signature = match &self.key {
+            PrivateKey::Rsa(key) => key
+                .sign(Pkcs1v15Sign::new::<Sha256>(), &hash)
+                .map_err(|_| MailParseError::Generic("RSA signing failed"))?,
+            PrivateKey::Ed25519(key) => key.sign(&hash).to_bytes().to_vec(),
+        };
+        let signature = BASE64.encode(&signature);
+        for (ix, chunk) in signature.as_bytes().chunks(64).enumerate() {
+            if ix > 0 {
+                field.push_str("\r\n\t ");
+            }
+            // Base64 is ASCII, so the chunks are valid UTF-8.
+            field.push_str(std::str::from_utf8(chunk).unwrap());
+        }
+        field.push_str("\r\n");
+
+        let uses_lf = matches!(
+            raw.iter().position(|&c| c == b'\n'),
+            Some(ix) if ix == 0 || raw[ix - 1] != b'\r'
+        );
+        if !uses_lf {
+            Ok(field)
+        } else {
+            Ok(field.replace("\r\n", "\n"))
+        }
+    }
+
+    /// Signs the given raw message, returning it with the `DKIM-Signature`
+    /// header prepended.
+    pub fn sign(&self, raw: &[u8]) -> Result<Vec<u8>, MailParseError> {
+        let mut signed = self.signature_header(raw)?.into_bytes();
+        signed.extend_from_slice(raw);
+        Ok(signed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mail;
+
+    fn read(name: &str) -> Vec<u8> {
+        let path = format!("./tests/files/dkim/{}", name);
+        std::fs::read(&path).unwrap_or_else(|_| panic!("Unable to open the file [{}]", path))
+    }
+
+    fn key_store() -> InMemoryKeyStore {
+        let mut keys = InMemoryKeyStore::new();
+        for line in String::from_utf8(read("keys.txt")).unwrap().lines() {
+            let (name, record) = split_once(line, ' ').unwrap();
+            keys.add_txt(name, record);
+        }
+        keys
+    }
+
+    #[test]
+    fn tag_lists() {
+        assert_eq!(
+            parse_tag_list(" a = b c ;\r\n\td=e;").unwrap(),
+            vec![
+                ("a".to_string(), "b c".to_string()),
+                ("d".to_string(), "e".to_string())
+            ]
+        );
+        assert!(parse_tag_list("").unwrap().is_empty());
+        assert!(parse_tag_list("a=1;;b=2").is_err());
+        assert!(parse_tag_list("a=1; a=2").is_err());
+        assert!(parse_tag_list("a").is_err());
+        assert!(parse_tag_list("1a=2").is_err());
+    }
+
+    #[test]
+    fn signature_tags() {
+        let sig: DkimSignature = "v=1; a=ed25519-sha256; c=relaxed; d=Example.com; \
+                                  i=user@mail.example.com; s=sel; h=from : to; \
+                                  l=10; t=1; x=2; bh=AAAA; b=AA AA"
+            .parse()
+            .unwrap();
+        assert_eq!(sig.algorithm, Algorithm::Ed25519Sha256);
+        assert_eq!(sig.header_canonicalization, Canonicalization::Relaxed);
+        assert_eq!(sig.body_canonicalization, Canonicalization::Simple);
+        assert_eq!(sig.signed_headers, vec!["from", "to"]);
+        assert_eq!(sig.body_length, Some(10));
+        assert_eq!(sig.signature, vec![0, 0, 0]);
+
+        let base = "v=1; a=rsa-sha256; d=example.com; s=sel; bh=AAAA; b=AAAA";
+        assert_eq!(
+            DkimSignature::parse(&format!("{}; h=to", base)),
+            Err(DkimFailure::InvalidSignature(
+                "The From header is not signed"
+            ))
+        );
+        assert!(format!("{}; h=from; i=a@example.net", base)
+            .parse::<DkimSignature>()
+            .is_err());
+        assert!(format!("{}; h=from; i=a@badexample.com", base)
+            .parse::<DkimSignature>()
+            .is_err());
+        assert!(format!("{}; h=from; t=5; x=4", base)
+            .parse::<DkimSignature>()
+            .is_err());
+        assert!(format!("{}; h=from; c=loose", base)
+            .parse::<DkimSignature>()
+            .is_err());
+        assert!(base.replace("v=1", "v=2").parse::<DkimSignature>().is_err());
+        assert_eq!(
+            DkimSignature::parse(&format!("{}; h=from", base.replace("sha256", "sha1"))),
+            Err(DkimFailure::UnsupportedAlgorithm("rsa-sha1".to_string()))
+        );
+    }
+
+    #[test]
+    fn verify() {
+        let keys = key_store();
+        for &name in &["rsa_relaxed.eml", "rsa_simple.eml", "ed25519.eml"] {
+            let raw = read(name);
+            let mail = parse_mail(&raw).unwrap();
+            let results = mail.verify_dkim(&keys);
+            assert!(!results.is_empty(), "{}", name);
+            for result in &results {
+                assert_eq!(result.result, Ok(()), "{}", name);
+                assert_eq!(result.auth_result(), AuthResult::Pass);
+            }
+        }
+    }
+
+    #[test]
+    fn verify_with_resolver() {
+        // Any `Resolver` can be used to look up the keys.
+        let mut zone = crate::dns::InMemoryResolver::new();
+        for line in String::from_utf8(read("keys.txt")).unwrap().lines() {
+            let (name, record) = split_once(line, ' ').unwrap();
+            zone.add_txt(name, record);
+        }
+        let raw = read("ed25519.eml");
+        let mail = parse_mail(&raw).unwrap();
+        let results = mail.verify_dkim(&zone);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.result.is_ok()));
+    }
+
+    #[test]
+    fn verify_lf_line_endings() {
+        let raw = read("rsa_relaxed.eml");
+        let raw = String::from_utf8(raw).unwrap().replace("\r\n", "\n");
+        let mail = parse_mail(raw.as_bytes()).unwrap();
+        assert_eq!(mail.verify_dkim(&key_store())[0].result, Ok(()));
+    }
+
+    #[test]
+    fn verify_failures() {
+        let keys = key_store();
+        let raw = String::from_utf8(read("rsa_relaxed.eml")).unwrap();
+
+        // Re-folding and whitespace changes are fine with relaxed
+        // canonicalization, but content changes are not.
+        let refolded = raw.replace("Subject: ", "Subject:\r\n    ");
+        let mail = parse_mail(refolded.as_bytes()).unwrap();
+        assert_eq!(mail.verify_dkim(&keys)[0].result, Ok(()));
+
+        let modified = raw.replace("Subject: ", "Subject: Re: ");
+        let mail = parse_mail(modified.as_bytes()).unwrap();
+        let result = &mail.verify_dkim(&keys)[0];
+        assert_eq!(result.result, Err(DkimFailure::SignatureMismatch));
+        assert_eq!(result.auth_result(), AuthResult::Fail);
+
+        let modified = format!("{}Appended\r\n", raw);
+        let mail = parse_mail(modified.as_bytes()).unwrap();
+        assert_eq!(
+            mail.verify_dkim(&keys)[0].result,
+            Err(DkimFailure::BodyHashMismatch)
+        );
+
+        let mail = parse_mail(raw.as_bytes()).unwrap();
+        let mut revoked = InMemoryKeyStore::new();
+        let signature = mail.verify_dkim(&keys).remove(0).signature.unwrap();
+        revoked.add_key(&signature.selector, &signature.domain, "v=DKIM1; p=");
+        assert_eq!(
+            mail.verify_dkim(&revoked)[0].result,
+            Err(DkimFailure::KeyRevoked)
+        );
+
+        let mut wrong_type = InMemoryKeyStore::new();
+        wrong_type.add_key(&signature.selector, &signature.domain, "k=ed25519; p=AAAA");
+        assert_eq!(
+            mail.verify_dkim(&wrong_type)[0].result,
+            Err(DkimFailure::InvalidKey(
+                "Key type does not match signature algorithm"
+            ))
+        );
+
+        struct Failing;
+        impl KeyLookup for Failing {
+            fn lookup_txt(&self, _: &str) -> Result<Vec<String>, LookupError> {
+                Err(LookupError("timeout".to_string()))
+            }
+        }
+        let result = &mail.verify_dkim(&Failing)[0];
+        assert_eq!(
+            result.result,
+            Err(DkimFailure::KeyUnavailable(LookupError(
+                "timeout".to_string()
+            )))
+        );
+        assert_eq!(result.auth_result(), AuthResult::TempError);
+    }
+
+    fn signer(key_file: &str, selector: &str) -> DkimSigner {
+        let pem = String::from_utf8(read(key_file)).unwrap();
+        DkimSigner::new("example.com", selector, PrivateKey::from_pem(&pem).unwrap())
+    }
+
+    #[test]
+    fn sign() {
+        let keys = key_store();
+        let raw = read("rsa_relaxed.eml");
+        // Strip the existing signature.
+        let start = raw.windows(7).position(|w| w == b"\r\nFrom:").unwrap() + 2;
+        let unsigned = &raw[start..];
+
+        for &(key_file, selector) in &[
+            ("rsa_private.pem", "rsa2048"),
+            ("ed25519_private.pem", "ed"),
+        ] {
+            for &canon in &[Canonicalization::Simple, Canonicalization::Relaxed] {
+                let mut signer = signer(key_file, selector);
+                signer.header_canonicalization = canon;
+                signer.body_canonicalization = canon;
+                let signed = signer.sign(unsigned).unwrap();
+                let mail = parse_mail(&signed).unwrap();
+                let results = mail.verify_dkim(&keys);
+                assert_eq!(results.len(), 1);
+                assert_eq!(results[0].result, Ok(()), "{} {:?}", key_file, canon);
+                let sig = results[0].signature.as_ref().unwrap();
+                assert_eq!(
+                    sig.signed_headers,
+                    vec![
+                        "from",
+                        "from",
+                        "subject",
+                        "subject",
+                        "date",
+                        "to",
+                        "message-id",
+                        "mime-version",
+                        "content-type"
+                    ]
+                );
+                assert_eq!(sig.header_canonicalization, canon);
+                assert_eq!(sig.body_length, None);
+            }
+        }
+
+        // The public key record matches the published one.
+        let rsa = signer("rsa_private.pem", "rsa2048");
+        assert_eq!(
+            keys.lookup_txt("rsa2048._domainkey.example.com").unwrap(),
+            vec![rsa.key.public_key_record().unwrap()]
+        );
+
+        // Oversigning prevents adding another From header.
+        let signed = rsa.sign(unsigned).unwrap();
+        let mut forged = b"From: mallory@example.net\r\n".to_vec();
+        forged.extend_from_slice(&signed);
+        let mail = parse_mail(&forged).unwrap();
+        assert_eq!(
+            mail.verify_dkim(&keys)[0].result,
+            Err(DkimFailure::SignatureMismatch)
+        );
+
+        assert!(rsa.sign(b"Subject: no from\r\n\r\nbody\r\n").is_err());
+        assert!(PrivateKey::from_pem("not a key").is_err());
+    }
+
+    #[test]
+    fn sign_options() {
+        let keys = key_store();
+        let mut signer = signer("ed25519_private.pem", "ed");
+        signer.body_length = true;
+        signer.expire_after = Some(3600);
+        signer.signed_headers = vec!["To".to_string(), "X-Missing".to_string()];
+        signer.oversigned_headers = vec![];
+
+        let raw = b"From: alice@example.com\nTo: bob@example.org\n\nHello\n";
+        let header = signer.signature_header(raw).unwrap();
+        assert!(!header.contains('\r'));
+        assert!(header.ends_with('\n'));
+        let mut signed = header.into_bytes();
+        signed.extend_from_slice(raw);
+        signed.extend_from_slice(b"Appended by a mailing list\n");
+
+        let mail = parse_mail(&signed).unwrap();
+        let result = &mail.verify_dkim(&keys)[0];
+        assert_eq!(result.result, Ok(()));
+        let sig = result.signature.as_ref().unwrap();
+        assert_eq!(sig.signed_headers, vec!["from", "to"]);
+        assert_eq!(sig.body_length, Some(7));
+        assert_eq!(sig.expiration, Some(sig.timestamp.unwrap() + 3600));
+    }
+}
@@ -0,0 +1,451 @@
+//! Evaluation of DMARC policies as defined in IETF RFC 7489.
+//!
+//! DMARC ties the SPF and DKIM results to the domain of the `From` header,
+//! which is the one users see. A message passes if SPF or DKIM passed for a
+//! domain that is aligned with it, i.e. the same domain or, in relaxed mode,
+//! one with the same organizational domain. Domain owners publish the policy
+//! to apply to messages that fail in a TXT record at `_dmarc.<domain>`,
+//! which is looked up through a `dns::Resolver`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::authres::AuthResult;
+use crate::dns::{LookupError, Resolver};
+use crate::spf::SpfVerification;
+use crate::{addrparse_header, rsplit_once, split_once, MailHeaderMap, MailParseError, ParsedMail};
+
+/// The policy a domain owner requests for messages that fail DMARC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Deliver the message as usual; the domain owner only wants reports.
+    None,
+    /// Treat the message as suspicious, e.g. deliver it to the spam folder.
+    Quarantine,
+    /// Reject the message.
+    Reject,
+}
+
+impl FromStr for Policy {
+    type Err = MailParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(Policy::None),
+            "quarantine" => Ok(Policy::Quarantine),
+            "reject" => Ok(Policy::Reject),
+            _ => Err(MailParseError::Generic("Unknown DMARC policy")),
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Policy::None => "none",
+            Policy::Quarantine => "quarantine",
+            Policy::Reject => "reject",
+        })
+    }
+}
+
+/// The identifier alignment mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    /// The domains must have the same organizational domain.
+    Relaxed,
+    /// The domains must be the same.
+    Strict,
+}
+
+impl Default for Alignment {
+    fn default() -> Self {
+        Alignment::Relaxed
+    }
+}
+
+impl FromStr for Alignment {
+    type Err = MailParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "r" => Ok(Alignment::Relaxed),
+            "s" => Ok(Alignment::Strict),
+            _ => Err(MailParseError::Generic("Unknown DMARC alignment mode")),
+        }
+    }
+}
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Alignment::Relaxed => "r",
+            Alignment::Strict => "s",
+        })
+    }
+}
+
+/// A DMARC policy record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DmarcRecord {
+    /// The policy for the domain (`p=`).
+    pub policy: Policy,
+    /// The policy for subdomains (`sp=`), if different.
+    pub subdomain_policy: Option<Policy>,
+    /// The DKIM alignment mode (`adkim=`).
+    pub dkim_alignment: Alignment,
+    /// The SPF alignment mode (`aspf=`).
+    pub spf_alignment: Alignment,
+    /// The percentage of failing messages the policy applies to (`pct=`).
+    /// It is up to the caller to apply the policy to a sample of messages
+    /// accordingly.
+    pub percentage: u8,
+    /// The URIs to send aggregate reports to (`rua=`).
+    pub aggregate_report_uris: Vec<String>,
+    /// The URIs to send failure reports to (`ruf=`).
+    pub failure_report_uris: Vec<String>,
+    /// The failure reporting options (`fo=`), such as `0` or `1:d`.
+    pub failure_options: String,
+    /// The requested interval between aggregate reports, in seconds
+    /// (`ri=`).
+    pub report_interval: u32,
+}
+
+fn parse_uris(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|uri| !uri.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl FromStr for DmarcRecord {
+    type Err = MailParseError;
+
+    /// Parses a DMARC record. As required by RFC 7489 section 6.6.3, a
+    /// record with an invalid or missing `p=` tag is treated as `p=none`
+    /// if it asks for aggregate reports. Unknown tags and invalid values
+    /// of optional tags are ignored.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::dmarc::{Alignment, DmarcRecord, Policy};
+    ///     let record: DmarcRecord = "v=DMARC1; p=reject; sp=none; aspf=s; rua=mailto:d@example.com"
+    ///         .parse()
+    ///         .unwrap();
+    ///     assert_eq!(record.policy, Policy::Reject);
+    ///     assert_eq!(record.subdomain_policy, Some(Policy::None));
+    ///     assert_eq!(record.spf_alignment, Alignment::Strict);
+    ///     assert_eq!(record.dkim_alignment, Alignment::Relaxed);
+    ///     assert_eq!(record.aggregate_report_uris, vec!["mailto:d@example.com".to_string()]);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tags = s
+            .split(';')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| match split_once(tag, '=') {
+                Some((name, value)) => (name.trim().to_lowercase(), value.trim()),
+                None => (tag.to_lowercase(), ""),
+            });
+        match tags.next() {
+            Some((name, value)) if name == "v" && value.eq_ignore_ascii_case("DMARC1") => {}
+            _ => return Err(MailParseError::Generic("Not a DMARC record")),
+        }
+
+        let mut policy = None;
+        let mut record = DmarcRecord {
+            policy: Policy::None,
+            subdomain_policy: None,
+            dkim_alignment: Alignment::Relaxed,
+            spf_alignment: Alignment::Relaxed,
+            percentage: 100,
+            aggregate_report_uris: Vec::new(),
+            failure_report_uris: Vec::new(),
+            failure_options: "0".to_string(),
+            report_interval: 86400,
+        };
+        for (name, value) in tags {
+            match name.as_str() {
+                "p" => policy = value.parse().ok(),
+                "sp" => record.subdomain_policy = value.parse().ok(),
+                "adkim" => record.dkim_alignment = value.parse().unwrap_or_default(),
+                "aspf" => record.spf_alignment = value.parse().unwrap_or_default(),
+                "pct" => {
+                    record.percentage = value.parse().ok().filter(|&p| p <= 100).unwrap_or(100)
+                }
+                "rua" => record.aggregate_report_uris = parse_uris(value),
+                "ruf" => record.failure_report_uris = parse_uris(value),
+                "fo" => record.failure_options = value.to_string(),
+                "ri" => record.report_interval = value.parse().unwrap_or(86400),
+                _ => {}
+            }
+        }
+        match policy {
+            Some(policy) => record.policy = policy,
+            None if !record.aggregate_report_uris.is_empty() => {}
+            None => return Err(MailParseError::Generic("Invalid p= tag in DMARC record")),
+        }
+        Ok(record)
+    }
+}
+
+/// The outcome of a DMARC check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DmarcVerification {
+    /// The domain of the `From` header, in lowercase. This is empty if the
+    /// message doesn't have exactly one `From` header with exactly one
+    /// address, in which case the result is `PermError`.
+    pub from_domain: String,
+    /// The domain where the policy record was found: the `From` domain or
+    /// its organizational domain.
+    pub policy_domain: Option<String>,
+    /// The policy record, if one was found.
+    pub record: Option<DmarcRecord>,
+    /// The result: `Pass`, `Fail`, `None` if the domain has no policy,
+    /// `TempError` if the policy could not be looked up, or `PermError`.
+    pub result: AuthResult,
+    /// Whether SPF passed for a domain aligned with the `From` domain.
+    pub spf_aligned: bool,
+    /// Whether DKIM passed for a domain aligned with the `From` domain.
+    pub dkim_aligned: bool,
+    /// The policy to apply to the message: that of the record (or its
+    /// subdomain policy) for a `Fail`, and `Policy::None` otherwise.
+    pub policy: Policy,
+}
+
+/// Returns the domain of the single `From` address, in lowercase.
+fn from_domain(mail: &ParsedMail) -> Option<String> {
+    let mut from = mail.headers.get_all_headers("From").into_iter();
+    let header = match (from.next(), from.next()) {
+        (Some(header), None) => header,
+        _ => return None,
+    };
+    let addr = addrparse_header(header).ok()?.extract_single_info()?.addr;
+    let domain = rsplit_once(&addr, '@')?.1.trim().trim_end_matches('.');
+    if domain.is_empty() {
+        return None;
+    }
+    Some(domain.to_lowercase())
+}
+
+/// Looks up the DMARC record at `_dmarc.<domain>`. Returns `Ok(None)` if
+/// there is no record, or more than one.
+fn lookup_record<R: Resolver + ?Sized>(
+    resolver: &R,
+    domain: &str,
+) -> Result<Option<DmarcRecord>, LookupError> {
+    let records = resolver.lookup_txt(&format!("_dmarc.{}", domain))?;
+    let mut records = records.iter().filter_map(|r| r.parse::<DmarcRecord>().ok());
+    match (records.next(), records.next()) {
+        (Some(record), None) => Ok(Some(record)),
+        _ => Ok(None),
+    }
+}
+
+impl<'a> ParsedMail<'a> {
+    /// Evaluates the DMARC policy of the `From` domain of this message,
+    /// given the outcome of the SPF check of the SMTP session and the
+    /// domains (`d=`) of the DKIM signatures that verified. The policy
+    /// record is looked up with `resolver`, which also determines the
+    /// organizational domains used for relaxed alignment.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::authres::AuthResult;
+    ///     use mailparse::dmarc::Policy;
+    ///     use mailparse::dns::InMemoryResolver;
+    ///     use mailparse::parse_mail;
+    ///     use mailparse::spf::{check_spf, ConnectionInfo};
+    ///     let mut zone = InMemoryResolver::new();
+    ///     zone.add_txt("example.com", "v=spf1 ip4:192.0.2.0/24 -all");
+    ///     zone.add_txt("_dmarc.example.com", "v=DMARC1; p=reject");
+    ///     let conn = ConnectionInfo {
+    ///         ip: "192.0.2.25".parse().unwrap(),
+    ///         helo: "mail.example.com".to_string(),
+    ///         mail_from: "bounces@mail.example.com".to_string(),
+    ///     };
+    ///     let spf = check_spf(&zone, &conn);
+    ///     let mail = parse_mail(b"From: Alice <alice@example.com>\n\nHi\n").unwrap();
+    ///
+    ///     // There is no SPF record for mail.example.com.
+    ///     let dmarc = mail.verify_dmarc(&spf, &[], &zone);
+    ///     assert_eq!(dmarc.result, AuthResult::Fail);
+    ///     assert_eq!(dmarc.policy, Policy::Reject);
+    ///
+    ///     // But a valid DKIM signature from a subdomain is aligned.
+    ///     let dmarc = mail.verify_dmarc(&spf, &["news.example.com"], &zone);
+    ///     assert_eq!(dmarc.result, AuthResult::Pass);
+    ///     assert!(dmarc.dkim_aligned);
+    /// ```
+    pub fn verify_dmarc<R: Resolver + ?Sized>(
+        &self,
+        spf: &SpfVerification,
+        dkim_domains: &[&str],
+        resolver: &R,
+    ) -> DmarcVerification {
+        let mut verification = DmarcVerification {
+            from_domain: String::new(),
+            policy_domain: None,
+            record: None,
+            result: AuthResult::PermError,
+            spf_aligned: false,
+            dkim_aligned: false,
+            policy: Policy::None,
+        };
+        let from_domain = match from_domain(self) {
+            Some(domain) => domain,
+            None => return verification,
+        };
+        let org_domain = resolver.organizational_domain(&from_domain);
+        verification.from_domain = from_domain.clone();
+
+        let mut found = lookup_record(resolver, &from_domain).map(|r| r.map(|r| (r, false)));
+        if matches!(found, Ok(None)) && org_domain != from_domain {
+            found = lookup_record(resolver, &org_domain).map(|r| r.map(|r| (r, true)));
+        }
+        let (record, inherited) = match found {
+            Ok(Some(found)) => found,
+            Ok(None) => {
+                verification.result = AuthResult::None;
+                return verification;
+            }
+            Err(_) => {
+                verification.result = AuthResult::TempError;
+                return verification;
+            }
+        };
+
+        let aligned = |domain: &str, mode: Alignment| {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+            match mode {
+                Alignment::Strict => domain == from_domain,
+                Alignment::Relaxed => resolver.organizational_domain(&domain) == org_domain,
+            }
+        };
+        verification.spf_aligned =
+            spf.result == AuthResult::Pass && aligned(&spf.domain, record.spf_alignment);
+        verification.dkim_aligned = dkim_domains
+            .iter()
+            .any(|d| aligned(d, record.dkim_alignment));
+        if verification.spf_aligned || verification.dkim_aligned {
+            verification.result = AuthResult::Pass;
+        } else {
+            verification.result = AuthResult::Fail;
+            verification.policy = match record.subdomain_policy {
+                Some(policy) if inherited => policy,
+                _ => record.policy,
+            };
+        }
+        verification.policy_domain = Some(if inherited { org_domain } else { from_domain });
+        verification.record = Some(record);
+        verification
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::InMemoryResolver;
+    use crate::parse_mail;
+
+    fn spf(result: AuthResult, domain: &str) -> SpfVerification {
+        SpfVerification {
+            result,
+            domain: domain.to_string(),
+            reason: None,
+            explanation: None,
+        }
+    }
+
+    #[test]
+    fn records() {
+        let record: DmarcRecord =
+            "v=DMARC1;p=quarantine; pct=20; adkim=s; ruf=mailto:a@x.com, mailto:b@x.com; fo=1; ri=3600;"
+                .parse()
+                .unwrap();
+        assert_eq!(record.policy, Policy::Quarantine);
+        assert_eq!(record.percentage, 20);
+        assert_eq!(record.dkim_alignment, Alignment::Strict);
+        assert_eq!(record.failure_report_uris.len(), 2);
+        assert_eq!(record.failure_options, "1");
+        assert_eq!(record.report_interval, 3600);
+
+        let record: DmarcRecord = "v=DMARC1; p=bogus; rua=mailto:d@example.com"
+            .parse()
+            .unwrap();
+        assert_eq!(record.policy, Policy::None);
+        assert!("v=DMARC1; p=bogus".parse::<DmarcRecord>().is_err());
+        assert!("p=reject; v=DMARC1".parse::<DmarcRecord>().is_err());
+        assert!("v=spf1 -all".parse::<DmarcRecord>().is_err());
+        assert_eq!(Policy::Quarantine.to_string(), "quarantine");
+    }
+
+    #[test]
+    fn evaluate() {
+        let mut zone = InMemoryResolver::new();
+        zone.add_txt(
+            "_dmarc.example.com",
+            "v=DMARC1; p=reject; sp=quarantine; aspf=s",
+        );
+        zone.add_txt("_dmarc.example.com", "some other record");
+        zone.add_txt(
+            "_dmarc.strict.example.org",
+            "v=DMARC1; p=quarantine; adkim=s",
+        );
+        zone.add_txt("_dmarc.twice.example.net", "v=DMARC1; p=none");
+        zone.add_txt("_dmarc.twice.example.net", "v=DMARC1; p=reject");
+
+        let mail = parse_mail(b"From: alice@example.com\n\nHi\n").unwrap();
+        let dmarc = mail.verify_dmarc(&spf(AuthResult::Pass, "example.com"), &[], &zone);
+        assert_eq!(dmarc.result, AuthResult::Pass);
+        assert!(dmarc.spf_aligned);
+        assert_eq!(dmarc.policy, Policy::None);
+        assert_eq!(dmarc.policy_domain, Some("example.com".to_string()));
+
+        // SPF alignment is strict for example.com.
+        let dmarc = mail.verify_dmarc(&spf(AuthResult::Pass, "bounce.example.com"), &[], &zone);
+        assert_eq!(dmarc.result, AuthResult::Fail);
+        assert_eq!(dmarc.policy, Policy::Reject);
+        let dmarc = mail.verify_dmarc(&spf(AuthResult::Fail, "example.com"), &[], &zone);
+        assert_eq!(dmarc.result, AuthResult::Fail);
+
+        // The subdomain policy applies to subdomains without their own
+        // record.
+        let mail = parse_mail(b"From: Alice <alice@Sub.Example.com>\n\nHi\n").unwrap();
+        let dmarc = mail.verify_dmarc(&spf(AuthResult::None, "other.org"), &["example.net"], &zone);
+        assert_eq!(dmarc.from_domain, "sub.example.com");
+        assert_eq!(dmarc.policy_domain, Some("example.com".to_string()));
+        assert_eq!(dmarc.result, AuthResult::Fail);
+        assert_eq!(dmarc.policy, Policy::Quarantine);
+        let dmarc = mail.verify_dmarc(&spf(AuthResult::None, "other.org"), &["example.com"], &zone);
+        assert_eq!(dmarc.result, AuthResult::Pass);
+
+        let mail = parse_mail(b"From: bob@strict.example.org\n\nHi\n").unwrap();
+        let dmarc = mail.verify_dmarc(&spf(AuthResult::None, ""), &["example.org"], &zone);
+        assert_eq!(dmarc.result, AuthResult::Fail);
+        let dmarc = mail.verify_dmarc(&spf(AuthResult::None, ""), &["strict.example.org"], &zone);
+        assert_eq!(dmarc.result, AuthResult::Pass);
+
+        for &raw in &[
+            &b"From: carol@twice.example.net\n\nHi\n"[..],
+            &b"From: dave@nowhere.example\n\nHi\n"[..],
+        ] {
+            let mail = parse_mail(raw).unwrap();
+            let dmarc = mail.verify_dmarc(&spf(AuthResult::Pass, ""), &[], &zone);
+            assert_eq!(dmarc.result, AuthResult::None);
+            assert_eq!(dmarc.record, None);
+        }
+
+        for &raw in &[
+            &b"To: bob@example.com\n\nHi\n"[..],
+            &b"From: a@example.com, b@example.com\n\nHi\n"[..],
+            &b"From: a@example.com\nFrom: b@example.com\n\nHi\n"[..],
+        ] {
+            let mail = parse_mail(raw).unwrap();
+            let dmarc = mail.verify_dmarc(&spf(AuthResult::Pass, "example.com"), &[], &zone);
+            assert_eq!(dmarc.result, AuthResult::PermError);
+        }
+    }
+}
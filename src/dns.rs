@@ -0,0 +1,197 @@
+//! The DNS lookups needed by SPF, DMARC and (with the `dkim` feature) DKIM
+//! and ARC.
+//!
+//! This crate does not talk to the network itself. Applications implement
+//! the `Resolver` trait on top of the DNS library of their choice, while
+//! `InMemoryResolver` serves a local zone, e.g. for tests.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// An error from a `Resolver` that prevented a lookup from completing, such
+/// as a DNS timeout. A name without records is not an error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LookupError(pub String);
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DNS lookup failed: {}", self.0)
+    }
+}
+
+/// A DNS resolver. All the lookups return an empty vector if the name does
+/// not exist or has no records of the requested type.
+pub trait Resolver {
+    /// Returns the TXT records at the given name. Records split into
+    /// several strings should be returned with the strings concatenated.
+    fn lookup_txt(&self, name: &str) -> Result<Vec<String>, LookupError>;
+
+    /// Returns the A records at the given name.
+    fn lookup_a(&self, name: &str) -> Result<Vec<Ipv4Addr>, LookupError>;
+
+    /// Returns the AAAA records at the given name.
+    fn lookup_aaaa(&self, name: &str) -> Result<Vec<Ipv6Addr>, LookupError>;
+
+    /// Returns the MX records at the given name, as pairs of preference and
+    /// exchange host name.
+    fn lookup_mx(&self, name: &str) -> Result<Vec<(u16, String)>, LookupError>;
+
+    /// Returns the host names that the given address maps back to (its PTR
+    /// records).
+    fn lookup_ptr(&self, ip: IpAddr) -> Result<Vec<String>, LookupError>;
+
+    /// Returns the organizational domain of a domain, as defined in RFC 7489
+    /// section 3.2: the domain registered under a public suffix, such as
+    /// `example.co.uk` for `mail.example.co.uk`. This is used by DMARC.
+    ///
+    /// Doing this properly needs the Public Suffix List, which this crate
+    /// does not include. The default implementation only knows that public
+    /// suffixes are either top-level domains or common second-level domains
+    /// of country code top-level domains such as `co.uk` and `com.au`;
+    /// applications that have the list at hand should override it.
+    fn organizational_domain(&self, domain: &str) -> String {
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        let labels: Vec<&str> = domain.split('.').collect();
+        let suffix_len = match labels.as_slice() {
+            [.., second, tld]
+                if tld.len() == 2
+                    && matches!(
+                        *second,
+                        "ac" | "co" | "com" | "edu" | "gov" | "ne" | "net" | "or" | "org"
+                    ) =>
+            {
+                2
+            }
+            _ => 1,
+        };
+        let len = labels.len().min(suffix_len + 1);
+        labels[labels.len() - len..].join(".")
+    }
+}
+
+/// Returns the name normalized for use as a key: lowercase, without the
+/// trailing dot.
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+/// A `Resolver` that serves records from an in-memory zone.
+///
+/// # Examples
+/// ```
+///     use mailparse::dns::{InMemoryResolver, Resolver};
+///     use std::net::Ipv4Addr;
+///     let mut zone = InMemoryResolver::new();
+///     zone.add_txt("Example.com", "v=spf1 mx -all");
+///     zone.add_mx("example.com", 10, "mx.example.com");
+///     zone.add_ip("mx.example.com", "192.0.2.1".parse().unwrap());
+///     assert_eq!(zone.lookup_txt("example.com.").unwrap(), vec!["v=spf1 mx -all".to_string()]);
+///     assert_eq!(zone.lookup_a("mx.example.com").unwrap(), vec![Ipv4Addr::new(192, 0, 2, 1)]);
+///     assert!(zone.lookup_aaaa("mx.example.com").unwrap().is_empty());
+///     assert_eq!(zone.organizational_domain("mail.example.co.uk"), "example.co.uk");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryResolver {
+    txt: HashMap<String, Vec<String>>,
+    ips: HashMap<String, Vec<IpAddr>>,
+    mx: HashMap<String, Vec<(u16, String)>>,
+    ptr: HashMap<IpAddr, Vec<String>>,
+}
+
+impl InMemoryResolver {
+    /// Creates an empty zone.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a TXT record.
+    pub fn add_txt(&mut self, name: &str, record: &str) {
+        self.txt
+            .entry(normalize(name))
+            .or_default()
+            .push(record.to_string());
+    }
+
+    /// Adds an A or AAAA record, depending on the type of address.
+    pub fn add_ip(&mut self, name: &str, ip: IpAddr) {
+        self.ips.entry(normalize(name)).or_default().push(ip);
+    }
+
+    /// Adds an MX record.
+    pub fn add_mx(&mut self, name: &str, preference: u16, exchange: &str) {
+        self.mx
+            .entry(normalize(name))
+            .or_default()
+            .push((preference, normalize(exchange)));
+    }
+
+    /// Adds a PTR record mapping the address back to a host name.
+    pub fn add_ptr(&mut self, ip: IpAddr, name: &str) {
+        self.ptr.entry(ip).or_default().push(normalize(name));
+    }
+}
+
+impl Resolver for InMemoryResolver {
+    fn lookup_txt(&self, name: &str) -> Result<Vec<String>, LookupError> {
+        Ok(self.txt.get(&normalize(name)).cloned().unwrap_or_default())
+    }
+
+    fn lookup_a(&self, name: &str) -> Result<Vec<Ipv4Addr>, LookupError> {
+        let ips = self
+            .ips
+            .get(&normalize(name))
+            .map_or(&[][..], Vec::as_slice);
+        Ok(ips
+            .iter()
+            .filter_map(|ip| match ip {
+                IpAddr::V4(ip) => Some(*ip),
+                IpAddr::V6(_) => None,
+            })
+            .collect())
+    }
+
+    fn lookup_aaaa(&self, name: &str) -> Result<Vec<Ipv6Addr>, LookupError> {
+        let ips = self
+            .ips
+            .get(&normalize(name))
+            .map_or(&[][..], Vec::as_slice);
+        Ok(ips
+            .iter()
+            .filter_map(|ip| match ip {
+                IpAddr::V4(_) => None,
+                IpAddr::V6(ip) => Some(*ip),
+            })
+            .collect())
+    }
+
+    fn lookup_mx(&self, name: &str) -> Result<Vec<(u16, String)>, LookupError> {
+        Ok(self.mx.get(&normalize(name)).cloned().unwrap_or_default())
+    }
+
+    fn lookup_ptr(&self, ip: IpAddr) -> Result<Vec<String>, LookupError> {
+        Ok(self.ptr.get(&ip).cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn organizational_domains() {
+        let zone = InMemoryResolver::new();
+        assert_eq!(zone.organizational_domain("example.com"), "example.com");
+        assert_eq!(
+            zone.organizational_domain("a.b.Example.com."),
+            "example.com"
+        );
+        assert_eq!(
+            zone.organizational_domain("example.com.au"),
+            "example.com.au"
+        );
+        assert_eq!(zone.organizational_domain("x.example.de"), "example.de");
+        assert_eq!(zone.organizational_domain("co.uk"), "co.uk");
+        assert_eq!(zone.organizational_domain("com"), "com");
+    }
+}
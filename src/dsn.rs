@@ -0,0 +1,541 @@
+//! Parsing of delivery status notifications (DSNs, a.k.a. bounces) as
+//! defined in IETF RFC 3464.
+//!
+//! A DSN is a `multipart/report; report-type=delivery-status` message whose
+//! second part, of type `message/delivery-status`, holds machine-readable
+//! blocks of fields: one block about the message as a whole, followed by one
+//! block for each recipient. `ParsedMail::delivery_report` finds and parses
+//! that part, and `parse_delivery_status` parses its body.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{
+    dateparse, parse_headers, split_once, MailHeader, MailHeaderMap, MailParseError, ParsedMail,
+};
+
+/// A field value that is qualified by a type, such as the `rfc822` in
+/// `Final-Recipient: rfc822; user@example.com` or the `smtp` in
+/// `Diagnostic-Code: smtp; 550 5.1.1 User unknown`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypedValue {
+    /// The type, such as `rfc822`, `dns` or `smtp`, lowercased.
+    pub value_type: String,
+    /// The value, with whitespace trimmed.
+    pub value: String,
+}
+
+impl TypedValue {
+    pub(crate) fn parse(value: &str) -> Self {
+        match split_once(value, ';') {
+            Some((value_type, value)) => TypedValue {
+                value_type: value_type.trim().to_lowercase(),
+                value: value.trim().to_string(),
+            },
+            // Not strictly valid, but some MTAs leave out the type.
+            None => TypedValue {
+                value_type: String::new(),
+                value: value.trim().to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for TypedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}; {}", self.value_type, self.value)
+    }
+}
+
+/// An enhanced mail system status code as defined in RFC 3463, e.g. `5.1.1`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StatusCode {
+    /// 2 for success, 4 for a persistent transient failure, 5 for a
+    /// permanent failure.
+    pub class: u8,
+    pub subject: u16,
+    pub detail: u16,
+}
+
+impl StatusCode {
+    /// Returns true for a permanent failure (class 5).
+    pub const fn is_permanent_failure(&self) -> bool {
+        self.class == 5
+    }
+
+    /// Returns true for a transient failure (class 4), i.e. one where a
+    /// later attempt may succeed.
+    pub const fn is_transient_failure(&self) -> bool {
+        self.class == 4
+    }
+}
+
+impl FromStr for StatusCode {
+    type Err = MailParseError;
+
+    /// Parses a status code, ignoring anything after it (some MTAs add a
+    /// comment, as in `5.1.1 (bad destination mailbox)`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.split_whitespace().next().unwrap_or("");
+        let mut numbers = code.split('.');
+        let mut next = || numbers.next().and_then(|n| n.parse::<u16>().ok());
+        let (class, subject, detail) = match (next(), next(), next(), next()) {
+            (Some(class), Some(subject), Some(detail), None) => (class, subject, detail),
+            _ => return Err(MailParseError::Generic("Invalid status code")),
+        };
+        match class {
+            2 | 4 | 5 => Ok(StatusCode {
+                class: class as u8,
+                subject,
+                detail,
+            }),
+            _ => Err(MailParseError::Generic("Invalid status code")),
+        }
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.class, self.subject, self.detail)
+    }
+}
+
+/// The action the reporting MTA performed for a recipient.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// The message could not be delivered.
+    Failed,
+    /// Delivery is delayed, but will be retried.
+    Delayed,
+    /// The message was delivered.
+    Delivered,
+    /// The message was relayed to an environment that does not send DSNs.
+    Relayed,
+    /// The message was delivered and forwarded to further recipients.
+    Expanded,
+    /// An action not defined by RFC 3464, lowercased.
+    Other(String),
+}
+
+impl Action {
+    fn parse(value: &str) -> Self {
+        // Drop any comment, as in "failed (bad destination)".
+        let action = value
+            .split(|c: char| c.is_whitespace() || c == '(')
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        match action.as_str() {
+            "failed" => Action::Failed,
+            "delayed" => Action::Delayed,
+            "delivered" => Action::Delivered,
+            "relayed" => Action::Relayed,
+            "expanded" => Action::Expanded,
+            _ => Action::Other(action),
+        }
+    }
+}
+
+/// The per-message fields of a delivery status notification.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessageStatus {
+    /// The envelope ID the sender passed with the ENVID SMTP parameter.
+    pub original_envelope_id: Option<String>,
+    /// The MTA that attempted the delivery and generated the DSN.
+    pub reporting_mta: Option<TypedValue>,
+    /// The gateway that translated a foreign delivery report, if any.
+    pub dsn_gateway: Option<TypedValue>,
+    /// The MTA from which the reporting MTA received the message.
+    pub received_from_mta: Option<TypedValue>,
+    /// When the reporting MTA received the message, as a UNIX timestamp.
+    pub arrival_date: Option<i64>,
+}
+
+/// The per-recipient fields of a delivery status notification.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecipientStatus {
+    /// The recipient as originally specified by the sender (with the ORCPT
+    /// SMTP parameter), if known.
+    pub original_recipient: Option<TypedValue>,
+    /// The recipient the delivery was attempted for. This is the only field
+    /// that is required for every recipient, along with `action` and
+    /// `status`.
+    pub final_recipient: TypedValue,
+    /// What happened to the message for this recipient. `None` if the field
+    /// was missing.
+    pub action: Option<Action>,
+    /// The status code for this recipient. `None` if the field was missing
+    /// or malformed.
+    pub status: Option<StatusCode>,
+    /// The MTA that reported the status (e.g. the one that rejected the
+    /// message), if different from the reporting MTA.
+    pub remote_mta: Option<TypedValue>,
+    /// The actual error reported by the remote MTA, e.g.
+    /// `smtp; 550 5.1.1 User unknown`.
+    pub diagnostic_code: Option<TypedValue>,
+    /// When the last delivery attempt was made, as a UNIX timestamp.
+    pub last_attempt_date: Option<i64>,
+    /// For delayed deliveries, until when the MTA will keep trying, as a
+    /// UNIX timestamp.
+    pub will_retry_until: Option<i64>,
+}
+
+/// The parsed contents of a `message/delivery-status` part.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeliveryStatus {
+    pub message: MessageStatus,
+    pub recipients: Vec<RecipientStatus>,
+}
+
+fn typed_field(fields: &[MailHeader], name: &str) -> Option<TypedValue> {
+    fields
+        .get_first_value(name)
+        .map(|value| TypedValue::parse(&value))
+}
+
+fn date_field(fields: &[MailHeader], name: &str) -> Option<i64> {
+    fields
+        .get_first_value(name)
+        .and_then(|value| dateparse(&value).ok())
+}
+
+fn parse_recipient(fields: &[MailHeader]) -> Result<RecipientStatus, MailParseError> {
+    let final_recipient = typed_field(fields, "Final-Recipient").ok_or(MailParseError::Generic(
+        "Missing Final-Recipient field in delivery status",
+    ))?;
+    Ok(RecipientStatus {
+        original_recipient: typed_field(fields, "Original-Recipient"),
+        final_recipient,
+        action: fields
+            .get_first_value("Action")
+            .map(|value| Action::parse(&value)),
+        status: fields
+            .get_first_value("Status")
+            .and_then(|value| value.parse().ok()),
+        remote_mta: typed_field(fields, "Remote-MTA"),
+        diagnostic_code: typed_field(fields, "Diagnostic-Code"),
+        last_attempt_date: date_field(fields, "Last-Attempt-Date"),
+        will_retry_until: date_field(fields, "Will-Retry-Until"),
+    })
+}
+
+/// Parses the (decoded) body of a `message/delivery-status` part. The body
+/// consists of blocks of header-like fields separated by blank lines; the
+/// first block holds the per-message fields and each following block holds
+/// the fields for one recipient. A missing per-message block is tolerated.
+/// An error is returned if there are no recipient blocks, or if a recipient
+/// block lacks the `Final-Recipient` field.
+///
+/// # Examples
+/// ```
+///     use mailparse::dsn::{parse_delivery_status, Action};
+///     let status = parse_delivery_status(concat!(
+///             "Reporting-MTA: dns; mx.example.com\n",
+///             "\n",
+///             "Final-Recipient: rfc822; nobody@example.org\n",
+///             "Action: failed\n",
+///             "Status: 5.1.1\n",
+///             "Diagnostic-Code: smtp; 550 5.1.1 User unknown\n").as_bytes())
+///         .unwrap();
+///     assert_eq!(status.message.reporting_mta.unwrap().value, "mx.example.com");
+///     let recipient = &status.recipients[0];
+///     assert_eq!(recipient.final_recipient.value, "nobody@example.org");
+///     assert_eq!(recipient.action, Some(Action::Failed));
+///     assert!(recipient.status.unwrap().is_permanent_failure());
+/// ```
+pub fn parse_delivery_status(body: &[u8]) -> Result<DeliveryStatus, MailParseError> {
+    let mut blocks = Vec::new();
+    let mut rest = body;
+    loop {
+        // Skip the blank lines between blocks.
+        while let Some(r) = rest
+            .strip_prefix(b"\r\n")
+            .or_else(|| rest.strip_prefix(b"\n"))
+        {
+            rest = r;
+        }
+        if rest.is_empty() {
+            break;
+        }
+        let (fields, consumed) = parse_headers(rest)?;
+        if !fields.is_empty() {
+            blocks.push(fields);
+        }
+        if consumed == 0 {
+            break;
+        }
+        rest = &rest[consumed..];
+    }
+
+    // The per-message block is required, but some MTAs leave it out.
+    let message_fields = match blocks.first() {
+        Some(fields) if fields.get_first_header("Final-Recipient").is_none() => blocks.remove(0),
+        _ => Vec::new(),
+    };
+    let message = MessageStatus {
+        original_envelope_id: message_fields
+            .get_first_value("Original-Envelope-Id")
+            .map(|value| value.trim().to_string()),
+        reporting_mta: typed_field(&message_fields, "Reporting-MTA"),
+        dsn_gateway: typed_field(&message_fields, "DSN-Gateway"),
+        received_from_mta: typed_field(&message_fields, "Received-From-MTA"),
+        arrival_date: date_field(&message_fields, "Arrival-Date"),
+    };
+    let recipients = blocks
+        .iter()
+        .map(|fields| parse_recipient(fields))
+        .collect::<Result<Vec<_>, _>>()?;
+    if recipients.is_empty() {
+        return Err(MailParseError::Generic(
+            "Delivery status has no per-recipient fields",
+        ));
+    }
+    Ok(DeliveryStatus {
+        message,
+        recipients,
+    })
+}
+
+/// A delivery status notification found in a message by
+/// `ParsedMail::delivery_report`.
+#[derive(Debug)]
+pub struct DeliveryReport<'a> {
+    /// The parsed delivery status.
+    pub status: DeliveryStatus,
+    /// The human-readable explanation of the report, if present.
+    pub explanation: Option<&'a ParsedMail<'a>>,
+    /// The part holding the returned message, if the report includes it.
+    /// This is either the full message (`message/rfc822` or
+    /// `message/global`) or just its headers (`text/rfc822-headers` or
+    /// `message/global-headers`). Either way, its decoded body can be parsed
+    /// with `parse_mail`.
+    pub original: Option<&'a ParsedMail<'a>>,
+}
+
+const STATUS_TYPES: &[&str] = &["message/delivery-status", "message/global-delivery-status"];
+
+/// The parts of a `multipart/report` message (RFC 6522): the
+/// human-readable explanation, the machine-readable report, and the
+/// returned original message or its headers.
+pub(crate) struct ReportParts<'a> {
+    pub(crate) explanation: Option<&'a ParsedMail<'a>>,
+    pub(crate) report: &'a ParsedMail<'a>,
+    pub(crate) original: Option<&'a ParsedMail<'a>>,
+}
+
+/// Finds the first `multipart/report` part in the message that contains a
+/// report part of one of the given types, and returns its parts.
+pub(crate) fn find_report<'a>(
+    mail: &'a ParsedMail<'a>,
+    report_types: &[&str],
+) -> Option<ReportParts<'a>> {
+    const ORIGINAL_TYPES: &[&str] = &[
+        "message/rfc822",
+        "message/global",
+        "text/rfc822-headers",
+        "message/global-headers",
+    ];
+    mail.parts()
+        .filter(|part| part.ctype.mimetype == "multipart/report")
+        .find_map(|container| {
+            let report = container
+                .subparts
+                .iter()
+                .find(|part| report_types.contains(&part.ctype.mimetype.as_str()))?;
+            let original = container
+                .subparts
+                .iter()
+                .find(|part| ORIGINAL_TYPES.contains(&part.ctype.mimetype.as_str()));
+            let explanation = container
+                .subparts
+                .first()
+                .filter(|part| !std::ptr::eq(*part, report));
+            Some(ReportParts {
+                explanation,
+                report,
+                original,
+            })
+        })
+}
+
+impl<'a> ParsedMail<'a> {
+    /// Looks for a delivery status notification in this message, i.e. a
+    /// `multipart/report` part with a `message/delivery-status` (or the
+    /// internationalized `message/global-delivery-status`) subpart, and
+    /// parses it. Returns `Ok(None)` if there is no such part, and an error
+    /// if it could not be parsed.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::{parse_mail, MailHeaderMap};
+    ///     let mail = parse_mail(concat!(
+    ///             "Content-Type: multipart/report; report-type=delivery-status; boundary=b\n",
+    ///             "\n",
+    ///             "--b\n",
+    ///             "\n",
+    ///             "Your message could not be delivered.\n",
+    ///             "--b\n",
+    ///             "Content-Type: message/delivery-status\n",
+    ///             "\n",
+    ///             "Reporting-MTA: dns; mx.example.com\n",
+    ///             "\n",
+    ///             "Final-Recipient: rfc822; nobody@example.org\n",
+    ///             "Action: failed\n",
+    ///             "Status: 5.1.1\n",
+    ///             "--b\n",
+    ///             "Content-Type: text/rfc822-headers\n",
+    ///             "\n",
+    ///             "Message-ID: <1@example.com>\n",
+    ///             "--b--\n").as_bytes())
+    ///         .unwrap();
+    ///     let report = mail.delivery_report().unwrap().unwrap();
+    ///     assert_eq!(report.status.recipients[0].final_recipient.value, "nobody@example.org");
+    ///
+    ///     let body = report.original.unwrap().get_body_raw().unwrap();
+    ///     let original = parse_mail(&body).unwrap();
+    ///     assert_eq!(original.headers.get_first_value("Message-ID").unwrap(), "<1@example.com>");
+    /// ```
+    pub fn delivery_report(&'a self) -> Result<Option<DeliveryReport<'a>>, MailParseError> {
+        let parts = match find_report(self, STATUS_TYPES) {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+        Ok(Some(DeliveryReport {
+            status: parse_delivery_status(&parts.report.get_body_raw()?)?,
+            explanation: parts.explanation,
+            original: parts.original,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mail;
+
+    #[test]
+    fn status_codes() {
+        let code: StatusCode = "5.1.1".parse().unwrap();
+        assert_eq!(
+            code,
+            StatusCode {
+                class: 5,
+                subject: 1,
+                detail: 1
+            }
+        );
+        assert_eq!(code.to_string(), "5.1.1");
+        assert!("4.4.7 (delivery time expired)"
+            .parse::<StatusCode>()
+            .unwrap()
+            .is_transient_failure());
+        assert!("3.1.1".parse::<StatusCode>().is_err());
+        assert!("5.1".parse::<StatusCode>().is_err());
+        assert!("5.1.1.1".parse::<StatusCode>().is_err());
+        assert!("".parse::<StatusCode>().is_err());
+    }
+
+    #[test]
+    fn parse_multiple_recipients() {
+        let status = parse_delivery_status(
+            concat!(
+                "Original-Envelope-Id: 0GK500B4HD0888@cougar.noc.ucla.edu\r\n",
+                "Reporting-MTA: dns; cs.utk.edu\r\n",
+                "Arrival-Date: Fri, 8 Jul 1994 09:21:47 -0400\r\n",
+                "\r\n",
+                "Original-Recipient: rfc822;arathib@vnet.ibm.com\r\n",
+                "Final-Recipient: RFC822; arathib@vnet.ibm.com\r\n",
+                "Action: failed (bad destination)\r\n",
+                "Status: 5.0.0 (permanent failure)\r\n",
+                "Diagnostic-Code: smtp;\r\n",
+                "  550 'arathib@vnet.IBM.COM' is not a registered gateway user\r\n",
+                "Remote-MTA: dns; vnet.ibm.com\r\n",
+                "\r\n",
+                "\r\n",
+                "Final-Recipient: rfc822; wsnell@sdcc13.ucsd.edu\r\n",
+                "Action: delayed\r\n",
+                "Status: 4.0.0\r\n",
+                "Will-Retry-Until: Sat, 9 Jul 1994 09:21:47 -0400\r\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            status.message.original_envelope_id.as_deref(),
+            Some("0GK500B4HD0888@cougar.noc.ucla.edu")
+        );
+        assert_eq!(
+            status.message.reporting_mta.as_ref().unwrap().to_string(),
+            "dns; cs.utk.edu"
+        );
+        assert_eq!(status.message.arrival_date, Some(773673707));
+        assert_eq!(status.recipients.len(), 2);
+
+        let first = &status.recipients[0];
+        assert_eq!(first.final_recipient.value_type, "rfc822");
+        assert_eq!(first.action, Some(Action::Failed));
+        assert_eq!(first.status.unwrap().to_string(), "5.0.0");
+        assert_eq!(
+            first.diagnostic_code.as_ref().unwrap().value,
+            "550 'arathib@vnet.IBM.COM' is not a registered gateway user"
+        );
+        assert_eq!(first.remote_mta.as_ref().unwrap().value, "vnet.ibm.com");
+
+        let second = &status.recipients[1];
+        assert_eq!(second.action, Some(Action::Delayed));
+        assert_eq!(second.will_retry_until, Some(773760107));
+        assert_eq!(second.diagnostic_code, None);
+    }
+
+    #[test]
+    fn invalid_delivery_status() {
+        assert!(parse_delivery_status(b"Reporting-MTA: dns; a.example\n").is_err());
+        assert!(parse_delivery_status(b"").is_err());
+        assert!(parse_delivery_status(b"Reporting-MTA: dns; a\n\nAction: failed\n").is_err());
+    }
+
+    #[test]
+    fn find_report() {
+        let mail = parse_mail(
+            concat!(
+                "Content-Type: multipart/mixed; boundary=outer\n",
+                "\n",
+                "--outer\n",
+                "Content-Type: multipart/report; report-type=delivery-status; boundary=b\n",
+                "\n",
+                "--b\n",
+                "Content-Type: text/plain\n",
+                "\n",
+                "Sorry.\n",
+                "--b\n",
+                "Content-Type: message/delivery-status\n",
+                "Content-Transfer-Encoding: base64\n",
+                "\n",
+                "RmluYWwtUmVjaXBpZW50OiByZmM4MjI7IGFAZXhhbXBsZS5jb20KQWN0aW9uOiBmYWlsZWQK\n",
+                "--b\n",
+                "Content-Type: message/rfc822\n",
+                "\n",
+                "Subject: hi\n",
+                "\n",
+                "hello\n",
+                "--b--\n",
+                "--outer--\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let report = mail.delivery_report().unwrap().unwrap();
+        assert_eq!(
+            report.status.recipients[0].final_recipient.value,
+            "a@example.com"
+        );
+        assert_eq!(report.explanation.unwrap().get_body().unwrap(), "Sorry.\n");
+        assert_eq!(
+            report.original.unwrap().get_body().unwrap(),
+            "Subject: hi\n\nhello\n"
+        );
+
+        let plain = parse_mail(b"Subject: hi\n\nhello\n").unwrap();
+        assert!(plain.delivery_report().unwrap().is_none());
+    }
+}
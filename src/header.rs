@@ -59,7 +59,7 @@ fn decode_word(encoded: &str) -> Option<String> {
             if d.is_ok() && to_decode.len() != trimmed.len() {
                 d.as_mut()
                     .unwrap()
-                    .extend_from_slice(to_decode[trimmed.len()..].as_bytes());
+                    .extend_from_slice(&to_decode.as_bytes()[trimmed.len()..]);
             }
             d.ok()?
         }
@@ -73,8 +73,8 @@ fn decode_word(encoded: &str) -> Option<String> {
 /// Tokenizes a single line of the header and produces a vector of
 /// tokens. Because this only processes a single line, it will never
 /// generate `HeaderToken::Newline` tokens.
-fn tokenize_header_line(line: &str) -> Vec<HeaderToken> {
-    fn maybe_whitespace(text: &str) -> HeaderToken {
+fn tokenize_header_line(line: &str) -> Vec<HeaderToken<'_>> {
+    fn maybe_whitespace(text: &str) -> HeaderToken<'_> {
         if text.trim_end().is_empty() {
             HeaderToken::Whitespace(text)
         } else {
@@ -135,7 +135,7 @@ fn tokenize_header_line(line: &str) -> Vec<HeaderToken> {
 /// the newline will be in a separate `HeaderToken::Whitespace` or
 /// `HeaderToken::Text` token. Semantically the `HeaderToken::Newline`
 /// tokens that come out of this still represent the CRLF newline.
-fn tokenize_header(value: &str) -> Vec<HeaderToken> {
+fn tokenize_header(value: &str) -> Vec<HeaderToken<'_>> {
     let mut tokens = Vec::new();
     let mut lines = value.lines();
     let mut first = true;
@@ -217,7 +217,7 @@ fn normalize_header_whitespace(tokens: Vec<HeaderToken>) -> Vec<HeaderToken> {
     result
 }
 
-pub fn normalized_tokens(raw_value: &str) -> Vec<HeaderToken> {
+pub fn normalized_tokens(raw_value: &str) -> Vec<HeaderToken<'_>> {
     normalize_header_whitespace(tokenize_header(raw_value))
 }
 
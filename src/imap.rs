@@ -0,0 +1,759 @@
+//! Computation of the IMAP `ENVELOPE`, `BODY` and `BODYSTRUCTURE` fetch
+//! data items defined in IETF RFC 3501 section 7.4.2, and their
+//! serialization in the IMAP protocol syntax.
+//!
+//! The structures are computed from a `ParsedMail` and hold owned data, so a
+//! server can cache them. As IMAP expects, header values are reported as
+//! they appear in the message, without decoding encoded words; that is left
+//! to the clients. Content-Type and Content-Disposition parameters are
+//! reported as parsed by this crate, i.e. with RFC 2231 encodings decoded.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::body::Body;
+use crate::{
+    addrparse, parse_mail, parse_param_content, rsplit_once, split_once, to_crlf, MailAddr,
+    MailHeader, MailHeaderMap, ParsedMail, SingleInfo,
+};
+
+/// An address in an `ENVELOPE`. Groups are represented as in IMAP: a
+/// marker with the group name in `mailbox` and no `host` starts the group,
+/// and a marker with neither ends it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Address {
+    /// The display name.
+    pub name: Option<String>,
+    /// The source route. This is obsolete and always `None` when computed
+    /// by this crate.
+    pub adl: Option<String>,
+    /// The local part of the address, or the name of a group.
+    pub mailbox: Option<String>,
+    /// The domain of the address.
+    pub host: Option<String>,
+}
+
+impl Address {
+    fn from_single(info: &SingleInfo) -> Self {
+        // The address parser only accepts addresses with a '@'.
+        let (mailbox, host) = rsplit_once(&info.addr, '@').unwrap_or((&info.addr, ""));
+        Address {
+            name: info.display_name.clone(),
+            adl: None,
+            mailbox: Some(mailbox.to_string()),
+            host: Some(host.to_string()),
+        }
+    }
+}
+
+/// The `ENVELOPE` of a message: its main headers, with the addresses
+/// parsed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Envelope {
+    /// The `Date` header.
+    pub date: Option<String>,
+    /// The `Subject` header.
+    pub subject: Option<String>,
+    /// The `From` addresses.
+    pub from: Vec<Address>,
+    /// The `Sender` addresses, or the `From` addresses if there are none.
+    pub sender: Vec<Address>,
+    /// The `Reply-To` addresses, or the `From` addresses if there are none.
+    pub reply_to: Vec<Address>,
+    /// The `To` addresses.
+    pub to: Vec<Address>,
+    /// The `Cc` addresses.
+    pub cc: Vec<Address>,
+    /// The `Bcc` addresses.
+    pub bcc: Vec<Address>,
+    /// The `In-Reply-To` header.
+    pub in_reply_to: Option<String>,
+    /// The `Message-ID` header.
+    pub message_id: Option<String>,
+}
+
+/// A `Content-Disposition` in a `BODYSTRUCTURE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BodyDisposition {
+    /// The disposition type, in lowercase, such as `attachment`.
+    pub disposition: String,
+    /// The disposition parameters, such as the filename.
+    pub params: Vec<(String, String)>,
+}
+
+/// A non-multipart part in a `BODYSTRUCTURE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SinglePart {
+    /// The media type, in lowercase, such as `text`.
+    pub media_type: String,
+    /// The media subtype, in lowercase, such as `plain`.
+    pub media_subtype: String,
+    /// The Content-Type parameters. Text parts always have a `charset`.
+    pub params: Vec<(String, String)>,
+    /// The `Content-ID` header.
+    pub id: Option<String>,
+    /// The `Content-Description` header.
+    pub description: Option<String>,
+    /// The `Content-Transfer-Encoding` header, `7BIT` if there is none.
+    pub encoding: String,
+    /// The size of the body in bytes, in its transfer encoding and with
+    /// CRLF line endings.
+    pub octets: usize,
+    /// For text parts and encapsulated messages, the number of lines in the
+    /// body.
+    pub lines: Option<usize>,
+    /// For `message/rfc822` parts, the envelope and structure of the
+    /// encapsulated message.
+    pub message: Option<Box<(Envelope, BodyStructure)>>,
+    /// The `Content-MD5` header.
+    pub md5: Option<String>,
+    /// The `Content-Disposition` header.
+    pub disposition: Option<BodyDisposition>,
+    /// The languages in the `Content-Language` header.
+    pub language: Vec<String>,
+    /// The `Content-Location` header.
+    pub location: Option<String>,
+}
+
+/// A multipart part in a `BODYSTRUCTURE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultiPart {
+    /// The subparts, in order.
+    pub parts: Vec<BodyStructure>,
+    /// The media subtype, in lowercase, such as `mixed`.
+    pub media_subtype: String,
+    /// The Content-Type parameters, including the boundary.
+    pub params: Vec<(String, String)>,
+    /// The `Content-Disposition` header.
+    pub disposition: Option<BodyDisposition>,
+    /// The languages in the `Content-Language` header.
+    pub language: Vec<String>,
+    /// The `Content-Location` header.
+    pub location: Option<String>,
+}
+
+/// The structure of a message, as returned by `BODYSTRUCTURE`. Its
+/// `Display` implementation produces the `BODYSTRUCTURE` syntax, while
+/// `to_body_string` produces the `BODY` syntax, which leaves out the
+/// extension data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BodyStructure {
+    /// A non-multipart part.
+    Single(SinglePart),
+    /// A multipart part.
+    Multipart(MultiPart),
+}
+
+/// Returns the raw value of a header, unfolded but otherwise unmodified.
+fn raw_value(header: &MailHeader) -> String {
+    header
+        .decode_utf8_or_latin1()
+        .replace("\r\n", "")
+        .replace('\n', "")
+}
+
+fn first_raw_value(mail: &ParsedMail, key: &str) -> Option<String> {
+    mail.headers.get_first_header(key).map(raw_value)
+}
+
+fn addresses(mail: &ParsedMail, key: &str) -> Vec<Address> {
+    let mut result = Vec::new();
+    for header in mail.headers.get_all_headers(key) {
+        let list = match addrparse(&raw_value(header)) {
+            Ok(list) => list,
+            Err(_) => continue,
+        };
+        for addr in list.iter() {
+            match addr {
+                MailAddr::Single(info) => result.push(Address::from_single(info)),
+                MailAddr::Group(group) => {
+                    result.push(Address {
+                        mailbox: Some(group.group_name.clone()),
+                        ..Default::default()
+                    });
+                    result.extend(group.addrs.iter().map(Address::from_single));
+                    result.push(Address::default());
+                }
+            }
+        }
+    }
+    result
+}
+
+fn envelope(mail: &ParsedMail) -> Envelope {
+    let from = addresses(mail, "From");
+    let or_from = |addresses: Vec<Address>| {
+        if addresses.is_empty() {
+            from.clone()
+        } else {
+            addresses
+        }
+    };
+    Envelope {
+        date: first_raw_value(mail, "Date"),
+        subject: first_raw_value(mail, "Subject"),
+        sender: or_from(addresses(mail, "Sender")),
+        reply_to: or_from(addresses(mail, "Reply-To")),
+        to: addresses(mail, "To"),
+        cc: addresses(mail, "Cc"),
+        bcc: addresses(mail, "Bcc"),
+        in_reply_to: first_raw_value(mail, "In-Reply-To"),
+        message_id: first_raw_value(mail, "Message-ID"),
+        from,
+    }
+}
+
+/// Returns the number of lines in a body, counting a last line without a
+/// line break.
+fn count_lines(body: &[u8]) -> usize {
+    let breaks = body.iter().filter(|&&c| c == b'\n').count();
+    if body.is_empty() || body.ends_with(b"\n") {
+        breaks
+    } else {
+        breaks + 1
+    }
+}
+
+/// Parses the message encapsulated in a `message/rfc822` part with the given
+/// body, returning its envelope and structure.
+fn encapsulated(part: &ParsedMail, body: &[u8]) -> Option<Box<(Envelope, BodyStructure)>> {
+    let decoded;
+    let raw = match part.get_body_encoded() {
+        Body::Base64(_) | Body::QuotedPrintable(_) => {
+            decoded = part.get_body_raw().ok()?;
+            &decoded
+        }
+        _ => body,
+    };
+    let inner = parse_mail(raw).ok()?;
+    Some(Box::new((envelope(&inner), body_structure(&inner))))
+}
+
+/// The envelope and structure used for an encapsulated message that can't
+/// be decoded, since the syntax requires them: an empty envelope and an empty
+/// `text/plain` body.
+fn undecodable_message() -> Box<(Envelope, BodyStructure)> {
+    let structure = BodyStructure::Single(SinglePart {
+        media_type: "text".to_string(),
+        media_subtype: "plain".to_string(),
+        params: vec![("charset".to_string(), "us-ascii".to_string())],
+        id: None,
+        description: None,
+        encoding: "7BIT".to_string(),
+        octets: 0,
+        lines: Some(0),
+        message: None,
+        md5: None,
+        disposition: None,
+        language: Vec::new(),
+        location: None,
+    });
+    Box::new((Envelope::default(), structure))
+}
+
+fn body_structure(part: &ParsedMail) -> BodyStructure {
+    let (media_type, media_subtype) =
+        split_once(&part.ctype.mimetype, '/').unwrap_or((part.ctype.mimetype.as_str(), ""));
+    let disposition = part
+        .headers
+        .get_first_value("Content-Disposition")
+        .map(|value| {
+            let content = parse_param_content(&value);
+            BodyDisposition {
+                disposition: content.value.to_lowercase(),
+                params: content.params.into_iter().collect(),
+            }
+        });
+    let language = part
+        .headers
+        .get_first_value("Content-Language")
+        .map(|value| {
+            value
+                .split(',')
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    let location = part.headers.get_first_value("Content-Location");
+
+    if !part.subparts.is_empty() {
+        return BodyStructure::Multipart(MultiPart {
+            parts: part.subparts.iter().map(body_structure).collect(),
+            media_subtype: media_subtype.to_string(),
+            params: part.ctype.params.clone().into_iter().collect(),
+            disposition,
+            language,
+            location,
+        });
+    }
+
+    let mut params: BTreeMap<String, String> = part.ctype.params.clone();
+    if media_type == "text" {
+        params
+            .entry("charset".to_string())
+            .or_insert_with(|| part.ctype.charset.clone());
+    }
+    let span = part.get_span();
+    let body_span = part.get_body_span();
+    let body = &part.raw_bytes[body_span.start - span.start..body_span.end - span.start];
+    let message = if media_type == "message" && matches!(media_subtype, "rfc822" | "global") {
+        Some(encapsulated(part, body).unwrap_or_else(undecodable_message))
+    } else {
+        None
+    };
+    let lines = if media_type == "text" || message.is_some() {
+        Some(count_lines(body))
+    } else {
+        None
+    };
+    BodyStructure::Single(SinglePart {
+        media_type: media_type.to_string(),
+        media_subtype: media_subtype.to_string(),
+        params: params.into_iter().collect(),
+        id: part.headers.get_first_value("Content-ID"),
+        description: first_raw_value(part, "Content-Description"),
+        encoding: part
+            .headers
+            .get_first_value("Content-Transfer-Encoding")
+            .unwrap_or_else(|| "7BIT".to_string()),
+        octets: to_crlf(body).len(),
+        lines,
+        message,
+        md5: part.headers.get_first_value("Content-MD5"),
+        disposition,
+        language,
+        location,
+    })
+}
+
+/// Writes an IMAP string: a quoted string if possible, and a literal for
+/// values with line breaks or non-ASCII characters.
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    if s.bytes()
+        .all(|c| c.is_ascii() && !matches!(c, b'\0' | b'\r' | b'\n'))
+    {
+        f.write_str("\"")?;
+        for c in s.chars() {
+            if c == '"' || c == '\\' {
+                f.write_str("\\")?;
+            }
+            write!(f, "{}", c)?;
+        }
+        f.write_str("\"")
+    } else {
+        write!(f, "{{{}}}\r\n{}", s.len(), s)
+    }
+}
+
+fn write_nstring(f: &mut fmt::Formatter, s: Option<&str>) -> fmt::Result {
+    match s {
+        Some(s) => write_string(f, s),
+        None => f.write_str("NIL"),
+    }
+}
+
+fn write_params(f: &mut fmt::Formatter, params: &[(String, String)]) -> fmt::Result {
+    if params.is_empty() {
+        return f.write_str("NIL");
+    }
+    f.write_str("(")?;
+    for (ix, (name, value)) in params.iter().enumerate() {
+        if ix > 0 {
+            f.write_str(" ")?;
+        }
+        write_string(f, name)?;
+        f.write_str(" ")?;
+        write_string(f, value)?;
+    }
+    f.write_str(")")
+}
+
+fn write_addresses(f: &mut fmt::Formatter, addresses: &[Address]) -> fmt::Result {
+    if addresses.is_empty() {
+        return f.write_str("NIL");
+    }
+    f.write_str("(")?;
+    for address in addresses {
+        f.write_str("(")?;
+        write_nstring(f, address.name.as_deref())?;
+        f.write_str(" ")?;
+        write_nstring(f, address.adl.as_deref())?;
+        f.write_str(" ")?;
+        write_nstring(f, address.mailbox.as_deref())?;
+        f.write_str(" ")?;
+        write_nstring(f, address.host.as_deref())?;
+        f.write_str(")")?;
+    }
+    f.write_str(")")
+}
+
+/// Writes the extension data shared by single and multipart parts: the
+/// disposition, language and location.
+fn write_extensions(
+    f: &mut fmt::Formatter,
+    disposition: &Option<BodyDisposition>,
+    language: &[String],
+    location: &Option<String>,
+) -> fmt::Result {
+    f.write_str(" ")?;
+    match disposition {
+        Some(disposition) => {
+            f.write_str("(")?;
+            write_string(f, &disposition.disposition)?;
+            f.write_str(" ")?;
+            write_params(f, &disposition.params)?;
+            f.write_str(")")?;
+        }
+        None => f.write_str("NIL")?,
+    }
+    f.write_str(" ")?;
+    match language {
+        [] => f.write_str("NIL")?,
+        [language] => write_string(f, language)?,
+        languages => {
+            f.write_str("(")?;
+            for (ix, language) in languages.iter().enumerate() {
+                if ix > 0 {
+                    f.write_str(" ")?;
+                }
+                write_string(f, language)?;
+            }
+            f.write_str(")")?;
+        }
+    }
+    f.write_str(" ")?;
+    write_nstring(f, location.as_deref())
+}
+
+impl fmt::Display for Envelope {
+    /// Formats the envelope in the IMAP syntax.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("(")?;
+        write_nstring(f, self.date.as_deref())?;
+        f.write_str(" ")?;
+        write_nstring(f, self.subject.as_deref())?;
+        for &addresses in &[
+            &self.from,
+            &self.sender,
+            &self.reply_to,
+            &self.to,
+            &self.cc,
+            &self.bcc,
+        ] {
+            f.write_str(" ")?;
+            write_addresses(f, addresses)?;
+        }
+        f.write_str(" ")?;
+        write_nstring(f, self.in_reply_to.as_deref())?;
+        f.write_str(" ")?;
+        write_nstring(f, self.message_id.as_deref())?;
+        f.write_str(")")
+    }
+}
+
+impl BodyStructure {
+    fn write(&self, f: &mut fmt::Formatter, extensions: bool) -> fmt::Result {
+        f.write_str("(")?;
+        match self {
+            BodyStructure::Single(part) => {
+                write_string(f, &part.media_type)?;
+                f.write_str(" ")?;
+                write_string(f, &part.media_subtype)?;
+                f.write_str(" ")?;
+                write_params(f, &part.params)?;
+                f.write_str(" ")?;
+                write_nstring(f, part.id.as_deref())?;
+                f.write_str(" ")?;
+                write_nstring(f, part.description.as_deref())?;
+                f.write_str(" ")?;
+                write_string(f, &part.encoding)?;
+                write!(f, " {}", part.octets)?;
+                if let Some(message) = &part.message {
+                    write!(f, " {} ", message.0)?;
+                    message.1.write(f, extensions)?;
+                }
+                if let Some(lines) = part.lines {
+                    write!(f, " {}", lines)?;
+                }
+                if extensions {
+                    f.write_str(" ")?;
+                    write_nstring(f, part.md5.as_deref())?;
+                    write_extensions(f, &part.disposition, &part.language, &part.location)?;
+                }
+            }
+            BodyStructure::Multipart(part) => {
+                for subpart in &part.parts {
+                    subpart.write(f, extensions)?;
+                }
+                f.write_str(" ")?;
+                write_string(f, &part.media_subtype)?;
+                if extensions {
+                    f.write_str(" ")?;
+                    write_params(f, &part.params)?;
+                    write_extensions(f, &part.disposition, &part.language, &part.location)?;
+                }
+            }
+        }
+        f.write_str(")")
+    }
+
+    /// Formats the structure in the IMAP syntax of the `BODY` fetch data
+    /// item, which leaves out the extension data.
+    pub fn to_body_string(&self) -> String {
+        struct Body<'a>(&'a BodyStructure);
+        impl fmt::Display for Body<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.write(f, false)
+            }
+        }
+        Body(self).to_string()
+    }
+}
+
+impl fmt::Display for BodyStructure {
+    /// Formats the structure in the IMAP syntax of the `BODYSTRUCTURE`
+    /// fetch data item.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, true)
+    }
+}
+
+impl<'a> ParsedMail<'a> {
+    /// Returns the IMAP `ENVELOPE` of this message.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     let mail = parse_mail(concat!(
+    ///             "From: \"Doe, John\" <john@example.com>\n",
+    ///             "To: team: a@example.com;\n",
+    ///             "Subject: =?utf-8?q?Caf=C3=A9?=\n",
+    ///             "\n",
+    ///             "Hello\n").as_bytes())
+    ///         .unwrap();
+    ///     assert_eq!(
+    ///         mail.imap_envelope().to_string(),
+    ///         concat!(
+    ///             "(NIL \"=?utf-8?q?Caf=C3=A9?=\" ",
+    ///             "((\"Doe, John\" NIL \"john\" \"example.com\")) ",
+    ///             "((\"Doe, John\" NIL \"john\" \"example.com\")) ",
+    ///             "((\"Doe, John\" NIL \"john\" \"example.com\")) ",
+    ///             "((NIL NIL \"team\" NIL)(NIL NIL \"a\" \"example.com\")(NIL NIL NIL NIL)) ",
+    ///             "NIL NIL NIL NIL)"));
+    /// ```
+    pub fn imap_envelope(&self) -> Envelope {
+        envelope(self)
+    }
+
+    /// Returns the IMAP `BODYSTRUCTURE` of this message or subpart. Sizes
+    /// are those of the message on the wire, so bare LF line endings, as in
+    /// messages stored with Unix line endings, count as CRLF.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     let mail = parse_mail(concat!(
+    ///             "Content-Type: multipart/alternative; boundary=XX\n",
+    ///             "\n",
+    ///             "--XX\n",
+    ///             "Content-Type: text/plain\n",
+    ///             "\n",
+    ///             "Hello\n",
+    ///             "--XX\n",
+    ///             "Content-Type: text/html; charset=utf-8\n",
+    ///             "Content-Transfer-Encoding: quoted-printable\n",
+    ///             "\n",
+    ///             "<p>Hello</p>\n",
+    ///             "--XX--\n").as_bytes())
+    ///         .unwrap();
+    ///     let structure = mail.imap_body_structure();
+    ///     assert_eq!(
+    ///         structure.to_string(),
+    ///         concat!(
+    ///             "((\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7BIT\" 5 1 ",
+    ///             "NIL NIL NIL NIL)",
+    ///             "(\"text\" \"html\" (\"charset\" \"utf-8\") NIL NIL \"quoted-printable\" 12 1 ",
+    ///             "NIL NIL NIL NIL) ",
+    ///             "\"alternative\" (\"boundary\" \"XX\") NIL NIL NIL)"));
+    ///     assert_eq!(
+    ///         structure.to_body_string(),
+    ///         concat!(
+    ///             "((\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7BIT\" 5 1)",
+    ///             "(\"text\" \"html\" (\"charset\" \"utf-8\") NIL NIL \"quoted-printable\" 12 1) ",
+    ///             "\"alternative\")"));
+    /// ```
+    pub fn imap_body_structure(&self) -> BodyStructure {
+        body_structure(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_message() {
+        let mail = parse_mail(
+            concat!(
+                "From: Alice <alice@example.com>\r\n",
+                "Sender: list@example.org\r\n",
+                "To: bob@example.org, Carol <carol@example.net>\r\n",
+                "Subject: Fwd:\r\n",
+                " the report\r\n",
+                "Date: Mon, 12 Oct 2026 10:00:00 +0000\r\n",
+                "Message-ID: <outer@example.com>\r\n",
+                "Content-Type: multipart/mixed; boundary=outer\r\n",
+                "\r\n",
+                "--outer\r\n",
+                "Content-Type: text/plain\r\n",
+                "Content-Language: en, fr\r\n",
+                "\r\n",
+                "See below.\r\n",
+                "\r\n",
+                "--outer\r\n",
+                "Content-Type: message/rfc822\r\n",
+                "Content-Description: The \"report\"\r\n",
+                "\r\n",
+                "From: carol@example.net\r\n",
+                "Subject: Report\r\n",
+                "In-Reply-To: <x@example.net>\r\n",
+                "\r\n",
+                "Numbers\r\n",
+                "--outer\r\n",
+                "Content-Type: application/pdf; name=\"r.pdf\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "Content-Disposition: attachment; filename=r.pdf\r\n",
+                "Content-MD5: Q2hlY2sgSW50ZWdyaXR5IQ==\r\n",
+                "Content-Location: http://example.com/r.pdf\r\n",
+                "Content-ID: <r@example.com>\r\n",
+                "\r\n",
+                "JVBERi0=\r\n",
+                "--outer--\r\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let envelope = mail.imap_envelope();
+        assert_eq!(envelope.subject.as_deref(), Some("Fwd: the report"));
+        assert_eq!(envelope.reply_to, envelope.from);
+        assert_eq!(envelope.sender[0].mailbox.as_deref(), Some("list"));
+        assert_eq!(envelope.to[1].name.as_deref(), Some("Carol"));
+        assert_eq!(
+            envelope.to_string(),
+            concat!(
+                "(\"Mon, 12 Oct 2026 10:00:00 +0000\" \"Fwd: the report\" ",
+                "((\"Alice\" NIL \"alice\" \"example.com\")) ",
+                "((NIL NIL \"list\" \"example.org\")) ",
+                "((\"Alice\" NIL \"alice\" \"example.com\")) ",
+                "((NIL NIL \"bob\" \"example.org\")(\"Carol\" NIL \"carol\" \"example.net\")) ",
+                "NIL NIL NIL \"<outer@example.com>\")"
+            )
+        );
+
+        let structure = mail.imap_body_structure();
+        assert_eq!(
+            structure.to_string(),
+            concat!(
+                "((\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7BIT\" 12 1 ",
+                "NIL NIL (\"en\" \"fr\") NIL)",
+                "(\"message\" \"rfc822\" NIL NIL \"The \\\"report\\\"\" \"7BIT\" 81 ",
+                "(NIL \"Report\" ((NIL NIL \"carol\" \"example.net\")) ",
+                "((NIL NIL \"carol\" \"example.net\")) ((NIL NIL \"carol\" \"example.net\")) ",
+                "NIL NIL NIL \"<x@example.net>\" NIL) ",
+                "(\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7BIT\" 7 1 ",
+                "NIL NIL NIL NIL) 5 NIL NIL NIL NIL)",
+                "(\"application\" \"pdf\" (\"name\" \"r.pdf\") \"<r@example.com>\" NIL ",
+                "\"base64\" 8 \"Q2hlY2sgSW50ZWdyaXR5IQ==\" ",
+                "(\"attachment\" (\"filename\" \"r.pdf\")) NIL \"http://example.com/r.pdf\") ",
+                "\"mixed\" (\"boundary\" \"outer\") NIL NIL NIL)"
+            )
+        );
+        assert_eq!(
+            structure.to_body_string(),
+            concat!(
+                "((\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7BIT\" 12 1)",
+                "(\"message\" \"rfc822\" NIL NIL \"The \\\"report\\\"\" \"7BIT\" 81 ",
+                "(NIL \"Report\" ((NIL NIL \"carol\" \"example.net\")) ",
+                "((NIL NIL \"carol\" \"example.net\")) ((NIL NIL \"carol\" \"example.net\")) ",
+                "NIL NIL NIL \"<x@example.net>\" NIL) ",
+                "(\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7BIT\" 7 1) 5)",
+                "(\"application\" \"pdf\" (\"name\" \"r.pdf\") \"<r@example.com>\" NIL ",
+                "\"base64\" 8) \"mixed\")"
+            )
+        );
+    }
+
+    #[test]
+    fn line_endings() {
+        let unix = "Content-Type: text/plain\n\nOne\nTwo\n";
+        let wire = unix.replace('\n', "\r\n");
+        let unix = parse_mail(unix.as_bytes()).unwrap().imap_body_structure();
+        let wire = parse_mail(wire.as_bytes()).unwrap().imap_body_structure();
+        assert_eq!(unix, wire);
+        assert_eq!(
+            unix.to_body_string(),
+            "(\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7BIT\" 10 2)"
+        );
+    }
+
+    #[test]
+    fn encoded_message() {
+        let mail = parse_mail(
+            concat!(
+                "Content-Type: message/rfc822\n",
+                "Content-Transfer-Encoding: base64\n",
+                "\n",
+                "U3ViamVjdDogSW5uZXINCg0KSGkNCg==\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        match mail.imap_body_structure() {
+            BodyStructure::Single(part) => {
+                assert_eq!(part.octets, 34);
+                assert_eq!(part.lines, Some(1));
+                let (envelope, body) = *part.message.unwrap();
+                assert_eq!(envelope.subject.as_deref(), Some("Inner"));
+                assert_eq!(
+                    body.to_body_string(),
+                    "(\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7BIT\" 4 1)"
+                );
+            }
+            BodyStructure::Multipart(_) => panic!("Expected a single part"),
+        }
+
+        let mail = parse_mail(
+            concat!(
+                "Content-Type: message/rfc822\n",
+                "Content-Transfer-Encoding: base64\n",
+                "\n",
+                "!!!!\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            mail.imap_body_structure().to_body_string(),
+            concat!(
+                "(\"message\" \"rfc822\" NIL NIL NIL \"base64\" 6 ",
+                "(NIL NIL NIL NIL NIL NIL NIL NIL NIL NIL) ",
+                "(\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7BIT\" 0 0) 1)"
+            )
+        );
+    }
+
+    #[test]
+    fn literals() {
+        let mail = parse_mail("Subject: Caf\u{e9}\n\n".as_bytes()).unwrap();
+        assert_eq!(
+            mail.imap_envelope().to_string(),
+            "(NIL {5}\r\nCaf\u{e9} NIL NIL NIL NIL NIL NIL NIL NIL)"
+        );
+        assert_eq!(
+            mail.imap_body_structure().to_string(),
+            "(\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7BIT\" 0 0 NIL NIL NIL NIL)"
+        );
+    }
+}
@@ -7,16 +7,40 @@ extern crate quoted_printable;
 use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::ops::Range;
 
 use charset::{decode_latin1, Charset};
 use thiserror::Error;
 
 mod addrparse;
+#[cfg(feature = "dkim")]
+pub mod arc;
+pub mod arf;
+pub mod authres;
+pub mod autocrypt;
+pub mod automated;
 pub mod body;
+pub mod bounce;
+pub mod canonicalization;
 mod dateparse;
+#[cfg(feature = "dkim")]
+pub mod dkim;
+pub mod dmarc;
+pub mod dns;
+pub mod dsn;
 mod header;
 pub mod headers;
+pub mod imap;
+pub mod list;
+pub mod mdn;
 mod msgidparse;
+pub mod pgp;
+pub mod received;
+mod reply;
+pub mod smime;
+pub mod spf;
+mod subject;
+pub mod threading;
 
 pub use crate::addrparse::{
     addrparse, addrparse_header, GroupInfo, MailAddr, MailAddrList, SingleInfo,
@@ -25,7 +49,12 @@ use crate::body::Body;
 pub use crate::dateparse::dateparse;
 use crate::header::HeaderToken;
 use crate::headers::Headers;
-pub use crate::msgidparse::{msgidparse, MessageIdList};
+pub use crate::msgidparse::{
+    generate_message_id, msgidparse, msgidparse_partial, msgidparse_strict, MessageIdList,
+    MsgIdParseMode, ReplyMessageIds,
+};
+pub use crate::reply::{Draft, ForwardMode};
+pub use crate::subject::{base_subject, base_subject_with_prefixes, BaseSubject, SubjectPrefixes};
 
 /// An error type that represents the different kinds of errors that may be
 /// encountered during message parsing.
@@ -57,6 +86,8 @@ pub enum MailParseError {
 pub struct MailHeader<'a> {
     key: &'a [u8],
     value: &'a [u8],
+    /// The byte range of the whole header within the original input.
+    span: Range<usize>,
 }
 
 /// Custom Debug trait for better formatting and printing of MailHeader items.
@@ -73,6 +104,55 @@ pub(crate) fn find_from(line: &str, ix_start: usize, key: &str) -> Option<usize>
     line[ix_start..].find(key).map(|v| ix_start + v)
 }
 
+// Equivalents of str::split_once and str::rsplit_once, which are newer than
+// the MSRV.
+pub(crate) fn split_once(s: &str, delimiter: char) -> Option<(&str, &str)> {
+    let ix = s.find(delimiter)?;
+    Some((&s[..ix], &s[ix + delimiter.len_utf8()..]))
+}
+
+pub(crate) fn rsplit_once(s: &str, delimiter: char) -> Option<(&str, &str)> {
+    let ix = s.rfind(delimiter)?;
+    Some((&s[..ix], &s[ix + delimiter.len_utf8()..]))
+}
+
+/// Returns the data with all line endings converted to CRLF, which is the
+/// form messages take on the wire and that signatures are computed over.
+pub(crate) fn to_crlf(data: &[u8]) -> Cow<'_, [u8]> {
+    let bare_lf = data
+        .iter()
+        .enumerate()
+        .any(|(ix, &c)| c == b'\n' && (ix == 0 || data[ix - 1] != b'\r'));
+    if !bare_lf {
+        return Cow::Borrowed(data);
+    }
+    let mut out = Vec::with_capacity(data.len() + data.len() / 32);
+    let mut prev = 0;
+    for &c in data {
+        if c == b'\n' && prev != b'\r' {
+            out.push(b'\r');
+        }
+        out.push(c);
+        prev = c;
+    }
+    Cow::Owned(out)
+}
+
+#[test]
+fn test_to_crlf() {
+    assert_eq!(to_crlf(b"a\r\nb"), Cow::Borrowed(&b"a\r\nb"[..]));
+    assert_eq!(to_crlf(b"\na\r\nb\n").as_ref(), b"\r\na\r\nb\r\n");
+}
+
+#[test]
+fn test_split_once() {
+    assert_eq!(split_once("a@b@c", '@'), Some(("a", "b@c")));
+    assert_eq!(split_once("\u{e9}=", '='), Some(("\u{e9}", "")));
+    assert_eq!(split_once("abc", '@'), None);
+    assert_eq!(rsplit_once("a@b@c", '@'), Some(("a@b", "c")));
+    assert_eq!(rsplit_once("abc", '@'), None);
+}
+
 fn find_from_u8(line: &[u8], ix_start: usize, key: &[u8]) -> Option<usize> {
     assert!(!key.is_empty());
     assert!(ix_start < line.len());
@@ -142,7 +222,7 @@ impl<'a> MailHeader<'a> {
 
     /// Get the name of the header, borrowing if it's ASCII-only.
     /// Note that header names are case-insensitive.
-    pub fn get_key_ref(&self) -> Cow<str> {
+    pub fn get_key_ref(&self) -> Cow<'_, str> {
         decode_latin1(self.key)
     }
 
@@ -251,6 +331,23 @@ impl<'a> MailHeader<'a> {
     pub const fn get_value_raw(&self) -> &[u8] {
         self.value
     }
+
+    /// Get the byte range of the header, from the start of its key to the
+    /// end of the line break that terminates it (including any folded
+    /// lines). The range is relative to the start of the input given to
+    /// `parse_mail`, `parse_headers` or `parse_header`.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     let raw = b"Subject: Hello,\n  world\nTo: bob\n\nBody";
+    ///     let mail = parse_mail(raw).unwrap();
+    ///     assert_eq!(mail.headers[0].get_span(), 0..24);
+    ///     assert_eq!(&raw[mail.headers[1].get_span()], b"To: bob\n");
+    /// ```
+    pub fn get_span(&self) -> Range<usize> {
+        self.span.clone()
+    }
 }
 
 #[derive(Debug)]
@@ -282,7 +379,7 @@ enum HeaderParseState {
 ///     assert_eq!(parsed.get_key(), "Subject");
 ///     assert_eq!(parsed.get_value(), "Hello, sir, I am multiline");
 /// ```
-pub fn parse_header(raw_data: &[u8]) -> Result<(MailHeader, usize), MailParseError> {
+pub fn parse_header(raw_data: &[u8]) -> Result<(MailHeader<'_>, usize), MailParseError> {
     let mut it = raw_data.iter();
     let mut ix = 0;
     let mut c = match it.next() {
@@ -360,6 +457,7 @@ pub fn parse_header(raw_data: &[u8]) -> Result<(MailHeader, usize), MailParseErr
             MailHeader {
                 key: &raw_data[0..v],
                 value: &raw_data[ix_value_start..ix_value_end],
+                span: 0..ix,
             },
             ix,
         )),
@@ -372,6 +470,7 @@ pub fn parse_header(raw_data: &[u8]) -> Result<(MailHeader, usize), MailParseErr
             MailHeader {
                 key: &raw_data[0..ix],
                 value: &raw_data[ix..ix],
+                span: 0..ix,
             },
             ix,
         )),
@@ -401,7 +500,7 @@ pub trait MailHeaderMap {
 
     /// Similar to `get_first_value`, except it returns a reference to the
     /// MailHeader struct instead of just extracting the value.
-    fn get_first_header(&self, key: &str) -> Option<&MailHeader>;
+    fn get_first_header(&self, key: &str) -> Option<&MailHeader<'_>>;
 
     /// Look through the list of headers and return the values of all headers
     /// matching the provided key. Returns an empty vector if no matching headers
@@ -423,7 +522,97 @@ pub trait MailHeaderMap {
 
     /// Similar to `get_all_values`, except it returns references to the
     /// MailHeader structs instead of just extracting the values.
-    fn get_all_headers(&self, key: &str) -> Vec<&MailHeader>;
+    fn get_all_headers(&self, key: &str) -> Vec<&MailHeader<'_>>;
+
+    /// Returns the parsed value of the `List-Id` header (RFC 2919), if any.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::{parse_mail, MailHeaderMap};
+    ///     let headers = parse_mail(concat!(
+    ///             "List-Id: Developers <dev.lists.example.com>\n",
+    ///             "\n",
+    ///             "This is a test message").as_bytes())
+    ///         .unwrap().headers;
+    ///     let list_id = headers.list_id().unwrap();
+    ///     assert_eq!(list_id.description, Some("Developers".to_string()));
+    ///     assert_eq!(list_id.id, "dev.lists.example.com");
+    /// ```
+    fn list_id(&self) -> Option<list::ListId> {
+        self.get_first_value("List-Id")
+            .and_then(|v| list::parse_list_id(&v))
+    }
+
+    /// Returns the URIs from the `List-Unsubscribe` header (RFC 2369), in
+    /// order of preference. See `list::parse_uri_list` for details.
+    fn list_unsubscribe(&self) -> Vec<String> {
+        self.get_first_value("List-Unsubscribe")
+            .map(|v| list::parse_uri_list(&v))
+            .unwrap_or_default()
+    }
+
+    /// Returns the URI to unsubscribe with a single HTTPS POST request, as
+    /// defined in RFC 8058. This requires a `List-Unsubscribe-Post` header
+    /// with the value `List-Unsubscribe=One-Click` and an HTTPS URI in the
+    /// `List-Unsubscribe` header. The POST request should have that value
+    /// as its body. Note that RFC 8058 also requires the message to have a
+    /// valid DKIM signature covering both headers, which this does not check.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::{parse_mail, MailHeaderMap};
+    ///     let headers = parse_mail(concat!(
+    ///             "List-Unsubscribe: <mailto:unsub@example.com>,\n",
+    ///             " <https://example.com/unsubscribe/opaque?a=1,2>\n",
+    ///             "List-Unsubscribe-Post: List-Unsubscribe=One-Click\n",
+    ///             "\n",
+    ///             "This is a test message").as_bytes())
+    ///         .unwrap().headers;
+    ///     assert_eq!(headers.list_unsubscribe_one_click(),
+    ///         Some("https://example.com/unsubscribe/opaque?a=1,2".to_string()));
+    /// ```
+    fn list_unsubscribe_one_click(&self) -> Option<String> {
+        if !list::is_one_click(&self.get_first_value("List-Unsubscribe-Post")?) {
+            return None;
+        }
+        self.list_unsubscribe()
+            .into_iter()
+            .find(|uri| uri.len() > 8 && uri.as_bytes()[..8].eq_ignore_ascii_case(b"https://"))
+    }
+
+    /// Returns the URIs from the `List-Subscribe` header (RFC 2369).
+    fn list_subscribe(&self) -> Vec<String> {
+        self.get_first_value("List-Subscribe")
+            .map(|v| list::parse_uri_list(&v))
+            .unwrap_or_default()
+    }
+
+    /// Returns the parsed value of the `List-Post` header (RFC 2369), if any.
+    fn list_post(&self) -> Option<list::ListPost> {
+        self.get_first_value("List-Post")
+            .map(|v| list::parse_list_post(&v))
+    }
+
+    /// Returns the URIs from the `List-Help` header (RFC 2369).
+    fn list_help(&self) -> Vec<String> {
+        self.get_first_value("List-Help")
+            .map(|v| list::parse_uri_list(&v))
+            .unwrap_or_default()
+    }
+
+    /// Returns the URIs from the `List-Archive` header (RFC 2369).
+    fn list_archive(&self) -> Vec<String> {
+        self.get_first_value("List-Archive")
+            .map(|v| list::parse_uri_list(&v))
+            .unwrap_or_default()
+    }
+
+    /// Returns the URIs from the `List-Owner` header (RFC 2369).
+    fn list_owner(&self) -> Vec<String> {
+        self.get_first_value("List-Owner")
+            .map(|v| list::parse_uri_list(&v))
+            .unwrap_or_default()
+    }
 }
 
 impl MailHeaderMap for [MailHeader<'_>] {
@@ -436,7 +625,7 @@ impl MailHeaderMap for [MailHeader<'_>] {
         None
     }
 
-    fn get_first_header(&self, key: &str) -> Option<&MailHeader> {
+    fn get_first_header(&self, key: &str) -> Option<&MailHeader<'_>> {
         self.iter()
             .find(|&x| x.get_key_ref().eq_ignore_ascii_case(key))
     }
@@ -451,7 +640,7 @@ impl MailHeaderMap for [MailHeader<'_>] {
         values
     }
 
-    fn get_all_headers(&self, key: &str) -> Vec<&MailHeader> {
+    fn get_all_headers(&self, key: &str) -> Vec<&MailHeader<'_>> {
         let mut headers: Vec<&MailHeader> = Vec::new();
         for x in self {
             if x.get_key_ref().eq_ignore_ascii_case(key) {
@@ -485,7 +674,7 @@ impl MailHeaderMap for [MailHeader<'_>] {
 ///     assert_eq!(headers[1].get_key(), "From");
 ///     assert_eq!(headers.get_first_value("To"), Some("you@yourself.com".to_string()));
 /// ```
-pub fn parse_headers(raw_data: &[u8]) -> Result<(Vec<MailHeader>, usize), MailParseError> {
+pub fn parse_headers(raw_data: &[u8]) -> Result<(Vec<MailHeader<'_>>, usize), MailParseError> {
     let mut headers: Vec<MailHeader> = Vec::new();
     let mut ix = 0;
     loop {
@@ -505,7 +694,8 @@ pub fn parse_headers(raw_data: &[u8]) -> Result<(Vec<MailHeader>, usize), MailPa
                 ));
             }
         }
-        let (header, ix_next) = parse_header(&raw_data[ix..])?;
+        let (mut header, ix_next) = parse_header(&raw_data[ix..])?;
+        header.span = ix..ix + ix_next;
         headers.push(header);
         ix += ix_next;
     }
@@ -687,6 +877,11 @@ pub struct ParsedMail<'a> {
     /// The subparts of this message or subpart. This vector is only non-empty
     /// if ctype.mimetype starts with "multipart/".
     pub subparts: Vec<ParsedMail<'a>>,
+    /// The byte range of this message or subpart within the original input.
+    span: Range<usize>,
+    /// Like `span`, but including the boundary before a subpart and the line
+    /// break before the next boundary.
+    outer_span: Range<usize>,
 }
 
 impl<'a> ParsedMail<'a> {
@@ -797,6 +992,93 @@ impl<'a> ParsedMail<'a> {
         Headers::new(self.header_bytes, &self.headers)
     }
 
+    /// Returns the byte range of this message or subpart within the input
+    /// given to `parse_mail`. For a subpart, this runs from the line after
+    /// its boundary up to the line break before the next boundary, which
+    /// belongs to that boundary as per RFC 2046 section 5.1.1. A multipart
+    /// range includes the boundaries of its own subparts. Note that
+    /// `raw_bytes` also includes the line break before the next boundary.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     let raw = concat!(
+    ///         "Content-Type: multipart/mixed; boundary=XX\n",
+    ///         "\n",
+    ///         "--XX\n",
+    ///         "Subject: first\n",
+    ///         "\n",
+    ///         "First part\n",
+    ///         "--XX--\n").as_bytes();
+    ///     let mail = parse_mail(raw).unwrap();
+    ///     assert_eq!(mail.get_span(), 0..raw.len());
+    ///     let part = &mail.subparts[0];
+    ///     assert_eq!(&raw[part.get_span()], b"Subject: first\n\nFirst part");
+    ///     assert_eq!(&raw[part.get_header_span()], b"Subject: first\n\n");
+    ///     assert_eq!(&raw[part.get_body_span()], b"First part");
+    ///     assert_eq!(&raw[part.headers[0].get_span()], b"Subject: first\n");
+    /// ```
+    pub fn get_span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Returns the byte range of this subpart together with its framing
+    /// within the input given to `parse_mail`: from the start of the
+    /// boundary line before it, through the line break before the next
+    /// boundary. The outer spans of the subparts of a multipart are thus
+    /// contiguous. For a message that is not a subpart, this is the same as
+    /// `get_span`.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     let raw = concat!(
+    ///         "Content-Type: multipart/mixed; boundary=XX\n",
+    ///         "\n",
+    ///         "--XX\n",
+    ///         "\n",
+    ///         "First part\n",
+    ///         "--XX\n",
+    ///         "\n",
+    ///         "Second part\n",
+    ///         "--XX--\n").as_bytes();
+    ///     let mail = parse_mail(raw).unwrap();
+    ///     assert_eq!(mail.get_outer_span(), mail.get_span());
+    ///     assert_eq!(&raw[mail.subparts[0].get_outer_span()], b"--XX\n\nFirst part\n");
+    ///     assert_eq!(&raw[mail.subparts[1].get_outer_span()], b"--XX\n\nSecond part\n");
+    /// ```
+    pub fn get_outer_span(&self) -> Range<usize> {
+        self.outer_span.clone()
+    }
+
+    /// Returns the byte range of the header block within the input given to
+    /// `parse_mail`, including the empty line that ends it.
+    pub fn get_header_span(&self) -> Range<usize> {
+        self.span.start..self.body_start()
+    }
+
+    /// Returns the byte range of the body within the input given to
+    /// `parse_mail`. Unlike `get_body_raw`, for a multipart this is the
+    /// whole body, including the boundaries, the subparts, and any preamble
+    /// and epilogue.
+    pub fn get_body_span(&self) -> Range<usize> {
+        self.body_start()..self.span.end
+    }
+
+    fn body_start(&self) -> usize {
+        // A part made of only headers may end with the line break that
+        // belongs to the next boundary.
+        (self.span.start + self.header_bytes.len()).min(self.span.end)
+    }
+
+    /// Returns the range of the signed content of a `multipart/signed` part
+    /// within its `raw_bytes`: the span of the first subpart, which leaves
+    /// out the line break that belongs to the boundary after it.
+    pub(crate) fn signed_content_range(&self) -> Option<Range<usize>> {
+        let first = self.subparts.first()?.get_span();
+        Some(first.start - self.span.start..first.end - self.span.start)
+    }
+
     /// Returns a struct containing a parsed representation of the
     /// Content-Disposition header. The first header with this name
     /// is used, if there are multiple. See the `parse_content_disposition`
@@ -878,15 +1160,20 @@ impl<'a> Iterator for PartsIterator<'a> {
 ///     assert!(parsed.subparts[1].get_body().unwrap().starts_with("<html>"));
 ///     assert_eq!(dateparse(parsed.headers.get_first_value("Date").unwrap().as_str()).unwrap(), 1475417182);
 /// ```
-pub fn parse_mail(raw_data: &[u8]) -> Result<ParsedMail, MailParseError> {
-    parse_mail_recursive(raw_data, false)
+pub fn parse_mail(raw_data: &[u8]) -> Result<ParsedMail<'_>, MailParseError> {
+    parse_mail_recursive(raw_data, 0, false)
 }
 
+/// Parses a message or subpart that starts at `offset` in the original input.
 fn parse_mail_recursive(
     raw_data: &[u8],
+    offset: usize,
     in_multipart_digest: bool,
-) -> Result<ParsedMail, MailParseError> {
-    let (headers, ix_body) = parse_headers(raw_data)?;
+) -> Result<ParsedMail<'_>, MailParseError> {
+    let (mut headers, ix_body) = parse_headers(raw_data)?;
+    for header in headers.iter_mut() {
+        header.span = offset + header.span.start..offset + header.span.end;
+    }
     let ctype = headers
         .get_first_value("Content-Type")
         .map(|s| parse_content_type(&s))
@@ -899,6 +1186,8 @@ fn parse_mail_recursive(
         ctype,
         body_bytes: &raw_data[ix_body..],
         subparts: Vec::<ParsedMail>::new(),
+        span: offset..offset + raw_data.len(),
+        outer_span: offset..offset + raw_data.len(),
     };
     if result.ctype.mimetype.starts_with("multipart/")
         && result.ctype.params.contains_key("boundary")
@@ -913,15 +1202,28 @@ fn parse_mail_recursive(
             while let Some(ix_part_start) =
                 find_from_u8(raw_data, ix_boundary_end, b"\n").map(|v| v + 1)
             {
+                let ix_delimiter = ix_boundary_end - boundary.len();
                 // if there is no terminating boundary, assume the part end is the end of the email
-                let ix_part_end =
-                    find_from_u8_line_prefix(raw_data, ix_part_start, boundary.as_bytes())
-                        .unwrap_or(raw_data.len());
+                let ix_boundary =
+                    find_from_u8_line_prefix(raw_data, ix_part_start, boundary.as_bytes());
+                let ix_part_end = ix_boundary.unwrap_or(raw_data.len());
 
-                result.subparts.push(parse_mail_recursive(
+                let mut subpart = parse_mail_recursive(
                     &raw_data[ix_part_start..ix_part_end],
+                    offset + ix_part_start,
                     in_multipart_digest,
-                )?);
+                )?;
+                if ix_boundary.is_some() {
+                    // The line break before the boundary belongs to the boundary.
+                    let part = subpart.raw_bytes;
+                    if part.ends_with(b"\r\n") {
+                        subpart.span.end -= 2;
+                    } else if part.ends_with(b"\n") {
+                        subpart.span.end -= 1;
+                    }
+                }
+                subpart.outer_span = offset + ix_delimiter..offset + ix_part_end;
+                result.subparts.push(subpart);
                 ix_boundary_end = ix_part_end + boundary.len();
                 if ix_boundary_end + 2 > raw_data.len()
                     || (raw_data[ix_boundary_end] == b'-' && raw_data[ix_boundary_end + 1] == b'-')
@@ -2046,6 +2348,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::useless_concat)]
     fn test_parts_iterator() {
         let mail = parse_mail(
             concat!(
@@ -2090,4 +2393,103 @@ mod tests {
         assert_eq!(parts.next().unwrap().ctype.mimetype, "text/plain");
         assert!(parts.next().is_none());
     }
+
+    #[test]
+    fn test_spans() {
+        let raw = concat!(
+            "Subject: spans\r\n",
+            "Content-Type: multipart/mixed; boundary=top\r\n",
+            "\r\n",
+            "preamble\r\n",
+            "--top\r\n",
+            "Content-Type: multipart/alternative;\r\n",
+            "  boundary=inner\r\n",
+            "\r\n",
+            "--inner\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "plain\r\n",
+            "\r\n",
+            "--inner\r\n",
+            "Content-Type: text/html\r\n",
+            "\r\n",
+            "<p>html</p>\r\n",
+            "--inner--\r\n",
+            "--top\r\n",
+            "Content-Type: text/unknown\r\n",
+            "\r\n",
+            "unterminated\r\n",
+        )
+        .as_bytes();
+        let mail = parse_mail(raw).unwrap();
+        let text = |range: Range<usize>| std::str::from_utf8(&raw[range]).unwrap();
+
+        assert_eq!(mail.get_span(), 0..raw.len());
+        assert_eq!(
+            text(mail.get_header_span()),
+            "Subject: spans\r\nContent-Type: multipart/mixed; boundary=top\r\n\r\n"
+        );
+        assert!(text(mail.get_body_span()).starts_with("preamble\r\n--top\r\n"));
+        assert_eq!(text(mail.headers[0].get_span()), "Subject: spans\r\n");
+
+        let alternative = &mail.subparts[0];
+        assert!(text(alternative.get_span()).starts_with("Content-Type: multipart/alternative;"));
+        assert!(text(alternative.get_span()).ends_with("--inner--"));
+        assert_eq!(
+            text(alternative.headers[0].get_span()),
+            "Content-Type: multipart/alternative;\r\n  boundary=inner\r\n"
+        );
+        assert_eq!(text(alternative.subparts[0].get_body_span()), "plain\r\n");
+        assert_eq!(
+            text(alternative.subparts[1].get_span()),
+            "Content-Type: text/html\r\n\r\n<p>html</p>"
+        );
+        assert_eq!(
+            text(alternative.subparts[1].headers[0].get_span()),
+            "Content-Type: text/html\r\n"
+        );
+
+        // Without a closing boundary, the last part runs to the end.
+        let unknown = &mail.subparts[1];
+        assert_eq!(text(unknown.get_body_span()), "unterminated\r\n");
+        assert_eq!(unknown.get_span().end, raw.len());
+
+        // Outer spans include the boundary line and the line break before
+        // the next boundary, at every level of nesting.
+        assert_eq!(mail.get_outer_span(), mail.get_span());
+        assert!(text(alternative.get_outer_span())
+            .starts_with("--top\r\nContent-Type: multipart/alternative;"));
+        assert!(text(alternative.get_outer_span()).ends_with("--inner--\r\n"));
+        assert_eq!(
+            text(alternative.subparts[0].get_outer_span()),
+            "--inner\r\nContent-Type: text/plain\r\n\r\nplain\r\n\r\n"
+        );
+        assert_eq!(
+            text(alternative.subparts[1].get_outer_span()),
+            "--inner\r\nContent-Type: text/html\r\n\r\n<p>html</p>\r\n"
+        );
+        assert_eq!(
+            alternative.subparts[0].get_outer_span().end,
+            alternative.subparts[1].get_outer_span().start
+        );
+        assert_eq!(
+            alternative.get_outer_span().end,
+            unknown.get_outer_span().start
+        );
+        assert_eq!(
+            text(unknown.get_outer_span()),
+            "--top\r\nContent-Type: text/unknown\r\n\r\nunterminated\r\n"
+        );
+
+        // A part made of headers only.
+        let mail =
+            parse_mail(b"Content-Type: multipart/mixed; boundary=b\n\n--b\nX: y\n--b--\n").unwrap();
+        let part = &mail.subparts[0];
+        assert_eq!(part.get_span(), 47..51);
+        assert_eq!(part.get_header_span(), 47..51);
+        assert_eq!(part.get_body_span(), 51..51);
+
+        let (headers, _) = parse_headers(b"A: b\nC: d\n\n").unwrap();
+        assert_eq!(headers[1].get_span(), 5..10);
+    }
 }
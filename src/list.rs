@@ -0,0 +1,259 @@
+//! Parsing of the mailing list headers defined in IETF RFC 2369
+//! (`List-Help`, `List-Unsubscribe`, `List-Subscribe`, `List-Post`,
+//! `List-Owner` and `List-Archive`), RFC 2919 (`List-Id`) and RFC 8058
+//! (`List-Unsubscribe-Post`).
+//!
+//! The accessors for these headers are provided by the `MailHeaderMap`
+//! trait; this module holds the types they return and the underlying parsers.
+
+use crate::msgidparse::{quoted_len, skip_cfws, unquote};
+
+/// The value of a `List-Id` header, as in
+/// `List-Id: "Developers" <dev.lists.example.com>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListId {
+    /// The human-readable description of the list, if any.
+    pub description: Option<String>,
+    /// The list identifier, without the angle brackets.
+    pub id: String,
+}
+
+/// The value of a `List-Post` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListPost {
+    /// Posting is done using the given URIs, in order of preference.
+    Allowed(Vec<String>),
+    /// The list does not allow posting, as in `List-Post: NO`.
+    NotAllowed,
+}
+
+/// Parses the value of a `List-Id` header. Returns `None` if the value holds
+/// no identifier.
+///
+/// # Examples
+/// ```
+///     use mailparse::list::parse_list_id;
+///     let list_id = parse_list_id("\"Developers\" <dev.lists.example.com>").unwrap();
+///     assert_eq!(list_id.description, Some("Developers".to_string()));
+///     assert_eq!(list_id.id, "dev.lists.example.com");
+/// ```
+pub fn parse_list_id(value: &str) -> Option<ListId> {
+    let value = value.trim();
+    let (description, id) = match value.rfind('<') {
+        Some(open) => {
+            let id = &value[open + 1..];
+            let id = id.split('>').next().unwrap_or(id);
+            (value[..open].trim(), id.trim())
+        }
+        // Be lenient with senders that omit the angle brackets.
+        None => ("", value),
+    };
+    if id.is_empty() {
+        return None;
+    }
+    let description = match quoted_len(description) {
+        Some(len) if description.starts_with('"') && len == description.len() => {
+            unquote(&description[1..len - 1])
+        }
+        _ => description.to_string(),
+    };
+    Some(ListId {
+        description: if description.is_empty() {
+            None
+        } else {
+            Some(description)
+        },
+        id: id.to_string(),
+    })
+}
+
+/// Splits a URI list, as in the RFC 2369 headers, into its URIs and any bare
+/// words found outside of angle brackets.
+fn split_uri_list(value: &str) -> (Vec<String>, Vec<&str>) {
+    let mut uris = Vec::new();
+    let mut words = Vec::new();
+    let mut rest = value;
+    loop {
+        rest = match skip_cfws(rest) {
+            Ok(rest) => rest,
+            // An unterminated comment swallows the rest of the value.
+            Err(_) => break,
+        };
+        if let Some(stripped) = rest.strip_prefix(',') {
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix('<') {
+            // The URI ends at the closing bracket, so it may contain commas
+            // and parentheses. Whitespace in it comes from folding and is
+            // ignored, as per RFC 2369 section 2.
+            let end = stripped.find('>').unwrap_or(stripped.len());
+            let uri: String = stripped[..end]
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            if !uri.is_empty() {
+                uris.push(uri);
+            }
+            rest = stripped.get(end + 1..).unwrap_or("");
+        } else if rest.is_empty() {
+            break;
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == '<')
+                .unwrap_or(rest.len());
+            words.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+    (uris, words)
+}
+
+/// Parses a list of angle-bracketed URIs, as used in the `List-Help`,
+/// `List-Unsubscribe`, `List-Subscribe`, `List-Post`, `List-Owner` and
+/// `List-Archive` headers. Commas and parentheses inside the brackets are
+/// part of the URI, while comments between the URIs are skipped. For
+/// robustness, bare words that look like URIs are accepted as well.
+///
+/// # Examples
+/// ```
+///     use mailparse::list::parse_uri_list;
+///     assert_eq!(parse_uri_list(concat!(
+///             "<https://example.com/unsub?id=1,2> (Web form),\n",
+///             " <mailto:list-request@example.com?subject=unsubscribe>")),
+///         vec![
+///             "https://example.com/unsub?id=1,2".to_string(),
+///             "mailto:list-request@example.com?subject=unsubscribe".to_string(),
+///         ]);
+/// ```
+pub fn parse_uri_list(value: &str) -> Vec<String> {
+    let (mut uris, words) = split_uri_list(value);
+    if uris.is_empty() {
+        uris.extend(
+            words
+                .into_iter()
+                .filter(|w| w.contains(':'))
+                .map(String::from),
+        );
+    }
+    uris
+}
+
+/// Parses the value of a `List-Post` header, which is either a URI list or
+/// `NO` (possibly followed by a comment) for lists that do not allow posting.
+///
+/// # Examples
+/// ```
+///     use mailparse::list::{parse_list_post, ListPost};
+///     assert_eq!(parse_list_post("NO (posting not allowed on this list)"),
+///         ListPost::NotAllowed);
+///     assert_eq!(parse_list_post("<mailto:dev@lists.example.com>"),
+///         ListPost::Allowed(vec!["mailto:dev@lists.example.com".to_string()]));
+/// ```
+pub fn parse_list_post(value: &str) -> ListPost {
+    let (uris, words) = split_uri_list(value);
+    if uris.is_empty() && words.iter().any(|w| w.eq_ignore_ascii_case("no")) {
+        return ListPost::NotAllowed;
+    }
+    ListPost::Allowed(parse_uri_list(value))
+}
+
+/// Returns true if the value of a `List-Unsubscribe-Post` header requests
+/// one-click unsubscription as defined in RFC 8058.
+pub(crate) fn is_one_click(value: &str) -> bool {
+    value
+        .trim()
+        .eq_ignore_ascii_case("List-Unsubscribe=One-Click")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_ids() {
+        assert_eq!(
+            parse_list_id("<dev.example.com>"),
+            Some(ListId {
+                description: None,
+                id: "dev.example.com".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_list_id("Dev <list> talk <dev.example.com>"),
+            Some(ListId {
+                description: Some("Dev <list> talk".to_string()),
+                id: "dev.example.com".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_list_id(r#""The \"dev\" list" <dev.example.com>"#),
+            Some(ListId {
+                description: Some("The \"dev\" list".to_string()),
+                id: "dev.example.com".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_list_id(" dev.example.com "),
+            Some(ListId {
+                description: None,
+                id: "dev.example.com".to_string(),
+            })
+        );
+        assert_eq!(parse_list_id("Dev <>"), None);
+        assert_eq!(parse_list_id(""), None);
+    }
+
+    #[test]
+    fn uri_lists() {
+        assert_eq!(
+            parse_uri_list("(Use this (or not)) <mailto:a@b.c>,<https://x.y/(z)>"),
+            vec!["mailto:a@b.c".to_string(), "https://x.y/(z)".to_string()]
+        );
+        assert_eq!(
+            parse_uri_list("<https://example.com/a\n  /very/long/path>"),
+            vec!["https://example.com/a/very/long/path".to_string()]
+        );
+        assert_eq!(
+            parse_uri_list("https://example.com/u, mailto:u@example.com"),
+            vec![
+                "https://example.com/u".to_string(),
+                "mailto:u@example.com".to_string()
+            ]
+        );
+        assert_eq!(
+            parse_uri_list("<mailto:a@b.c> (unterminated"),
+            vec!["mailto:a@b.c".to_string()]
+        );
+        assert_eq!(
+            parse_uri_list("<https://example.com"),
+            vec!["https://example.com".to_string()]
+        );
+        assert!(parse_uri_list("").is_empty());
+        assert!(parse_uri_list("none").is_empty());
+    }
+
+    #[test]
+    fn list_post() {
+        assert_eq!(parse_list_post("no"), ListPost::NotAllowed);
+        assert_eq!(parse_list_post(""), ListPost::Allowed(vec![]));
+        assert_eq!(
+            parse_list_post("<mailto:a@b.c> (no HTML)"),
+            ListPost::Allowed(vec!["mailto:a@b.c".to_string()])
+        );
+    }
+
+    #[test]
+    fn one_click() {
+        use crate::{parse_mail, MailHeaderMap};
+        let headers = parse_mail(
+            concat!(
+                "List-Unsubscribe: <mailto:\u{e9}@x>, <mailto:u@x>\n",
+                "List-Unsubscribe-Post: List-Unsubscribe=One-Click\n",
+                "\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap()
+        .headers;
+        assert_eq!(headers.list_unsubscribe_one_click(), None);
+    }
+}
@@ -0,0 +1,590 @@
+//! Parsing and generation of message disposition notifications (MDNs, a.k.a.
+//! read receipts) as defined in IETF RFC 8098.
+//!
+//! A sender requests an MDN by adding a `Disposition-Notification-To` header
+//! to a message. The MDN sent in response is a `multipart/report;
+//! report-type=disposition-notification` message whose second part, of type
+//! `message/disposition-notification`, holds a block of fields describing
+//! what happened to the message.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::dsn::{find_report, TypedValue};
+use crate::reply::{
+    encode_addr, encode_words, message_id_domain, new_boundary, write_crlf, write_header,
+};
+use crate::{
+    addrparse_header, generate_message_id, msgidparse, parse_headers, split_once, MailAddr,
+    MailHeaderMap, MailParseError, MessageIdList, ParsedMail, SingleInfo,
+};
+
+/// Whether the disposition was the result of a user action or happened
+/// automatically.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ActionMode {
+    /// `manual-action`: the user explicitly performed the disposition.
+    Manual,
+    /// `automatic-action`: the disposition was performed automatically,
+    /// e.g. by a filter.
+    Automatic,
+    /// An unrecognized action mode, lowercased.
+    Other(String),
+}
+
+/// Whether the user explicitly agreed to send the MDN.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SendingMode {
+    /// `MDN-sent-manually`: the user explicitly agreed to send the MDN.
+    Manual,
+    /// `MDN-sent-automatically`: the MDN was sent without asking the user.
+    Automatic,
+    /// An unrecognized sending mode, lowercased.
+    Other(String),
+}
+
+/// What happened to the message. Not to be confused with
+/// `mailparse::DispositionType`, the type of a `Content-Disposition` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MdnDispositionType {
+    /// The message was displayed to the user. This does not guarantee that
+    /// the message was read or understood.
+    Displayed,
+    /// The message was deleted without being displayed.
+    Deleted,
+    /// The message was printed, faxed or forwarded without being displayed.
+    Dispatched,
+    /// The message was processed in some other way without being displayed.
+    Processed,
+    /// An unrecognized disposition type, lowercased.
+    Other(String),
+}
+
+/// The value of a `Disposition` field, e.g.
+/// `manual-action/MDN-sent-manually; displayed`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Disposition {
+    pub action_mode: ActionMode,
+    pub sending_mode: SendingMode,
+    pub disposition_type: MdnDispositionType,
+    /// Disposition modifiers such as `error`, lowercased.
+    pub modifiers: Vec<String>,
+}
+
+impl Disposition {
+    /// Returns a disposition of the given type. If `automatic` is set, the
+    /// action and sending modes are `automatic-action/MDN-sent-automatically`
+    /// and otherwise `manual-action/MDN-sent-manually`.
+    pub fn new(disposition_type: MdnDispositionType, automatic: bool) -> Self {
+        let (action_mode, sending_mode) = if automatic {
+            (ActionMode::Automatic, SendingMode::Automatic)
+        } else {
+            (ActionMode::Manual, SendingMode::Manual)
+        };
+        Disposition {
+            action_mode,
+            sending_mode,
+            disposition_type,
+            modifiers: Vec::new(),
+        }
+    }
+}
+
+/// Removes comments from a field value.
+fn strip_comments(value: &str) -> String {
+    let mut result = String::new();
+    let mut depth = 0;
+    for c in value.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            c if depth == 0 => result.push(c),
+            _ => (),
+        }
+    }
+    result
+}
+
+impl FromStr for Disposition {
+    type Err = MailParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = strip_comments(s).to_lowercase();
+        let (modes, disposition) = split_once(&value, ';')
+            .ok_or(MailParseError::Generic("Disposition is missing a ';'"))?;
+        let (action_mode, sending_mode) = split_once(modes, '/').ok_or(MailParseError::Generic(
+            "Disposition is missing a sending mode",
+        ))?;
+        let action_mode = match action_mode.trim() {
+            "manual-action" => ActionMode::Manual,
+            "automatic-action" => ActionMode::Automatic,
+            other => ActionMode::Other(other.to_string()),
+        };
+        let sending_mode = match sending_mode.trim() {
+            "mdn-sent-manually" => SendingMode::Manual,
+            "mdn-sent-automatically" => SendingMode::Automatic,
+            other => SendingMode::Other(other.to_string()),
+        };
+        let (disposition_type, modifiers) = match split_once(disposition, '/') {
+            Some((disposition_type, modifiers)) => (disposition_type, Some(modifiers)),
+            None => (disposition, None),
+        };
+        let disposition_type = match disposition_type.trim() {
+            "displayed" => MdnDispositionType::Displayed,
+            "deleted" => MdnDispositionType::Deleted,
+            "dispatched" => MdnDispositionType::Dispatched,
+            "processed" => MdnDispositionType::Processed,
+            "" => return Err(MailParseError::Generic("Disposition is missing a type")),
+            other => MdnDispositionType::Other(other.to_string()),
+        };
+        let modifiers = modifiers
+            .map(|m| {
+                m.split(',')
+                    .map(|m| m.trim().to_string())
+                    .filter(|m| !m.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Disposition {
+            action_mode,
+            sending_mode,
+            disposition_type,
+            modifiers,
+        })
+    }
+}
+
+impl fmt::Display for Disposition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.action_mode {
+            ActionMode::Manual => write!(f, "manual-action")?,
+            ActionMode::Automatic => write!(f, "automatic-action")?,
+            ActionMode::Other(mode) => write!(f, "{}", mode)?,
+        }
+        match &self.sending_mode {
+            SendingMode::Manual => write!(f, "/MDN-sent-manually; ")?,
+            SendingMode::Automatic => write!(f, "/MDN-sent-automatically; ")?,
+            SendingMode::Other(mode) => write!(f, "/{}; ", mode)?,
+        }
+        write!(f, "{}", self.disposition_type.as_str())?;
+        if !self.modifiers.is_empty() {
+            write!(f, "/{}", self.modifiers.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl MdnDispositionType {
+    fn as_str(&self) -> &str {
+        match self {
+            MdnDispositionType::Displayed => "displayed",
+            MdnDispositionType::Deleted => "deleted",
+            MdnDispositionType::Dispatched => "dispatched",
+            MdnDispositionType::Processed => "processed",
+            MdnDispositionType::Other(other) => other,
+        }
+    }
+}
+
+/// The parsed contents of a `message/disposition-notification` part.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DispositionNotification {
+    /// The user agent that generated the MDN, e.g. `host.example.com;
+    /// Mail Client 1.0`.
+    pub reporting_ua: Option<String>,
+    /// The gateway that translated a foreign notification, if any.
+    pub mdn_gateway: Option<TypedValue>,
+    /// The recipient the original message was addressed to, if known.
+    pub original_recipient: Option<TypedValue>,
+    /// The recipient the disposition applies to.
+    pub final_recipient: TypedValue,
+    /// The message ID of the message the MDN is about, without angle
+    /// brackets.
+    pub original_message_id: Option<String>,
+    pub disposition: Disposition,
+    /// The values of any `Error` fields.
+    pub errors: Vec<String>,
+}
+
+/// Parses the (decoded) body of a `message/disposition-notification` part.
+/// Returns an error if the `Final-Recipient` or `Disposition` fields are
+/// missing or malformed.
+///
+/// # Examples
+/// ```
+///     use mailparse::mdn::{parse_disposition_notification, ActionMode, MdnDispositionType};
+///     let mdn = parse_disposition_notification(concat!(
+///             "Reporting-UA: bob.example.com; Mail 1.0\n",
+///             "Final-Recipient: rfc822; bob@example.com\n",
+///             "Original-Message-ID: <1@example.com>\n",
+///             "Disposition: manual-action/MDN-sent-manually; displayed\n").as_bytes())
+///         .unwrap();
+///     assert_eq!(mdn.original_message_id.as_deref(), Some("1@example.com"));
+///     assert_eq!(mdn.disposition.action_mode, ActionMode::Manual);
+///     assert_eq!(mdn.disposition.disposition_type, MdnDispositionType::Displayed);
+/// ```
+pub fn parse_disposition_notification(
+    body: &[u8],
+) -> Result<DispositionNotification, MailParseError> {
+    let (fields, _) = parse_headers(body)?;
+    let typed_field = |name| {
+        fields
+            .get_first_value(name)
+            .map(|value| TypedValue::parse(&value))
+    };
+    let final_recipient = typed_field("Final-Recipient").ok_or(MailParseError::Generic(
+        "Missing Final-Recipient field in disposition notification",
+    ))?;
+    let disposition = fields
+        .get_first_value("Disposition")
+        .ok_or(MailParseError::Generic(
+            "Missing Disposition field in disposition notification",
+        ))?
+        .parse()?;
+    Ok(DispositionNotification {
+        reporting_ua: fields
+            .get_first_value("Reporting-UA")
+            .map(|value| value.trim().to_string()),
+        mdn_gateway: typed_field("MDN-Gateway"),
+        original_recipient: typed_field("Original-Recipient"),
+        final_recipient,
+        original_message_id: fields
+            .get_first_value("Original-Message-ID")
+            .and_then(|value| msgidparse(&value).ok())
+            .and_then(|ids| ids.first().cloned()),
+        disposition,
+        errors: fields
+            .get_all_values("Error")
+            .into_iter()
+            .map(|value| value.trim().to_string())
+            .collect(),
+    })
+}
+
+/// A disposition notification found in a message by
+/// `ParsedMail::disposition_notification`.
+#[derive(Debug)]
+pub struct DispositionReport<'a> {
+    pub notification: DispositionNotification,
+    /// The human-readable explanation of the notification, if present.
+    pub explanation: Option<&'a ParsedMail<'a>>,
+    /// The part holding the original message or its headers, if included.
+    pub original: Option<&'a ParsedMail<'a>>,
+}
+
+const NOTIFICATION_TYPES: &[&str] = &[
+    "message/disposition-notification",
+    "message/global-disposition-notification",
+];
+
+impl<'a> ParsedMail<'a> {
+    /// Looks for a message disposition notification in this message, i.e. a
+    /// `multipart/report` part with a `message/disposition-notification`
+    /// subpart, and parses it. Returns `Ok(None)` if there is no such part,
+    /// and an error if it could not be parsed.
+    pub fn disposition_notification(
+        &'a self,
+    ) -> Result<Option<DispositionReport<'a>>, MailParseError> {
+        let parts = match find_report(self, NOTIFICATION_TYPES) {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+        Ok(Some(DispositionReport {
+            notification: parse_disposition_notification(&parts.report.get_body_raw()?)?,
+            explanation: parts.explanation,
+            original: parts.original,
+        }))
+    }
+
+    /// Returns the addresses in the `Disposition-Notification-To` header,
+    /// i.e. where the sender asked MDNs to be sent. An empty list means no
+    /// MDN was requested.
+    pub fn disposition_notification_to(&self) -> Vec<SingleInfo> {
+        let mut result = Vec::new();
+        for header in self.headers.get_all_headers("Disposition-Notification-To") {
+            if let Ok(list) = addrparse_header(header) {
+                for addr in list.into_inner() {
+                    match addr {
+                        MailAddr::Single(info) => result.push(info),
+                        MailAddr::Group(group) => result.extend(group.addrs),
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Generates an MDN for this message, reporting the given disposition
+    /// on behalf of the recipient `from`. `reporting_ua` identifies the
+    /// generating software, e.g. `host.example.com; Mail Client 1.0`.
+    /// Returns `None` if the message did not request an MDN.
+    ///
+    /// Note that RFC 8098 section 2.1 requires asking the user before
+    /// sending an MDN if the `Disposition-Notification-To` address differs
+    /// from the `Return-Path`, and that MDNs must never be sent
+    /// automatically in response to a message that is itself an MDN, a
+    /// bounce or other automatically generated message. These policy
+    /// decisions are left to the caller.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::mdn::{Disposition, MdnDispositionType};
+    ///     use mailparse::{parse_mail, MailHeaderMap, SingleInfo};
+    ///     let mail = parse_mail(concat!(
+    ///             "From: alice@example.com\n",
+    ///             "Disposition-Notification-To: alice@example.com\n",
+    ///             "Message-ID: <1@example.com>\n",
+    ///             "Subject: Lunch\n",
+    ///             "\n",
+    ///             "Pizza?\n").as_bytes())
+    ///         .unwrap();
+    ///     let me = SingleInfo { display_name: None, addr: "bob@example.com".to_string() };
+    ///     let disposition = Disposition::new(MdnDispositionType::Displayed, false);
+    ///     let raw = mail.create_mdn(&me, &disposition, "example.com; Mail 1.0").unwrap();
+    ///
+    ///     let mdn = parse_mail(&raw).unwrap();
+    ///     assert_eq!(mdn.headers.get_first_value("To").unwrap(), "alice@example.com");
+    ///     let report = mdn.disposition_notification().unwrap().unwrap();
+    ///     assert_eq!(report.notification.original_message_id.as_deref(), Some("1@example.com"));
+    ///     assert_eq!(report.notification.disposition, disposition);
+    /// ```
+    pub fn create_mdn(
+        &self,
+        from: &SingleInfo,
+        disposition: &Disposition,
+        reporting_ua: &str,
+    ) -> Option<Vec<u8>> {
+        let to = self.disposition_notification_to();
+        if to.is_empty() {
+            return None;
+        }
+        let original_id = self
+            .headers
+            .get_first_value("Message-ID")
+            .and_then(|value| msgidparse(&value).ok())
+            .filter(|ids| !ids.is_empty());
+        let subject = self.headers.get_first_value("Subject").unwrap_or_default();
+        let domain = message_id_domain(&from.addr);
+        let boundary = new_boundary();
+
+        let mut out = Vec::new();
+        write_header(&mut out, "From", &encode_addr(from));
+        let to_list = to.iter().map(encode_addr).collect::<Vec<_>>().join(", ");
+        write_header(&mut out, "To", &to_list);
+        write_header(
+            &mut out,
+            "Subject",
+            &encode_words(&format!(
+                "Disposition notification ({}): {}",
+                disposition.disposition_type.as_str(),
+                subject.trim()
+            )),
+        );
+        let message_id = MessageIdList::from(vec![generate_message_id(domain)]);
+        write_header(&mut out, "Message-ID", &message_id.to_string());
+        if let Some(ids) = &original_id {
+            write_header(&mut out, "In-Reply-To", &ids.to_string());
+            write_header(&mut out, "References", &ids.to_string());
+        }
+        if disposition.sending_mode == SendingMode::Automatic {
+            write_header(&mut out, "Auto-Submitted", "auto-replied");
+        }
+        write_header(&mut out, "MIME-Version", "1.0");
+        write_header(
+            &mut out,
+            "Content-Type",
+            &format!(
+                "multipart/report; report-type=disposition-notification; boundary=\"{}\"",
+                boundary
+            ),
+        );
+
+        let explanation = match disposition.disposition_type {
+            MdnDispositionType::Displayed => {
+                "was displayed on the recipient's computer. \
+                                           There is no guarantee that the message has \
+                                           been read or understood."
+            }
+            MdnDispositionType::Deleted => "was deleted without being displayed.",
+            _ => "was processed without being displayed.",
+        };
+        out.extend_from_slice(format!("\r\n--{}\r\n", boundary).as_bytes());
+        out.extend_from_slice(b"Content-Type: text/plain; charset=utf-8\r\n");
+        out.extend_from_slice(b"Content-Transfer-Encoding: 8bit\r\n\r\n");
+        write_crlf(
+            &mut out,
+            format!(
+                "The message sent to {} with subject \"{}\" {}\n",
+                from.addr,
+                subject.trim(),
+                explanation
+            )
+            .as_bytes(),
+        );
+
+        out.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        out.extend_from_slice(b"Content-Type: message/disposition-notification\r\n\r\n");
+        write_header(&mut out, "Reporting-UA", reporting_ua);
+        if let Some(original) = self.headers.get_first_value("Original-Recipient") {
+            write_header(&mut out, "Original-Recipient", original.trim());
+        }
+        write_header(
+            &mut out,
+            "Final-Recipient",
+            &format!("rfc822; {}", from.addr),
+        );
+        if let Some(ids) = &original_id {
+            write_header(&mut out, "Original-Message-ID", &ids.to_string());
+        }
+        write_header(&mut out, "Disposition", &disposition.to_string());
+
+        out.extend_from_slice(format!("\r\n--{}\r\n", boundary).as_bytes());
+        out.extend_from_slice(b"Content-Type: text/rfc822-headers\r\n\r\n");
+        write_crlf(&mut out, self.get_headers().get_raw_bytes());
+        if !out.ends_with(b"\r\n") {
+            out.extend_from_slice(b"\r\n");
+        }
+        out.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mail;
+
+    #[test]
+    fn parse_dispositions() {
+        let d: Disposition = "automatic-action/MDN-sent-automatically; deleted"
+            .parse()
+            .unwrap();
+        assert_eq!(d, Disposition::new(MdnDispositionType::Deleted, true));
+
+        let d: Disposition =
+            "Manual-Action/MDN-Sent-Manually (user said so); displayed/error, expired"
+                .parse()
+                .unwrap();
+        assert_eq!(d.action_mode, ActionMode::Manual);
+        assert_eq!(d.sending_mode, SendingMode::Manual);
+        assert_eq!(d.modifiers, vec!["error", "expired"]);
+        assert_eq!(
+            d.to_string(),
+            "manual-action/MDN-sent-manually; displayed/error,expired"
+        );
+
+        assert!("displayed".parse::<Disposition>().is_err());
+        assert!("manual-action; displayed".parse::<Disposition>().is_err());
+        assert!("manual-action/MDN-sent-manually;"
+            .parse::<Disposition>()
+            .is_err());
+    }
+
+    #[test]
+    fn find_notification() {
+        let mail = parse_mail(
+            concat!(
+                "From: bob@example.com\n",
+                "Subject: Disposition notification\n",
+                "Content-Type: multipart/report; report-type=disposition-notification;\n",
+                "  boundary=\"b\"\n",
+                "\n",
+                "--b\n",
+                "Content-Type: text/plain\n",
+                "\n",
+                "Your message was displayed.\n",
+                "--b\n",
+                "Content-Type: message/disposition-notification\n",
+                "\n",
+                "Reporting-UA: bob.example.com; Mail 1.0\n",
+                "Original-Recipient: rfc822; bob@example.org\n",
+                "Final-Recipient: rfc822; bob@example.com\n",
+                "Original-Message-ID: <1@example.com> (the lunch message)\n",
+                "Disposition: automatic-action/MDN-sent-automatically; processed/error\n",
+                "Error: could not decrypt\n",
+                "--b--\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let report = mail.disposition_notification().unwrap().unwrap();
+        let notification = report.notification;
+        assert_eq!(
+            notification.reporting_ua.as_deref(),
+            Some("bob.example.com; Mail 1.0")
+        );
+        assert_eq!(
+            notification.original_recipient.unwrap().value,
+            "bob@example.org"
+        );
+        assert_eq!(notification.final_recipient.value, "bob@example.com");
+        assert_eq!(
+            notification.original_message_id.as_deref(),
+            Some("1@example.com")
+        );
+        assert_eq!(
+            notification.disposition.disposition_type,
+            MdnDispositionType::Processed
+        );
+        assert_eq!(notification.errors, vec!["could not decrypt"]);
+        assert!(report.explanation.is_some());
+        assert!(report.original.is_none());
+
+        assert!(parse_mail(b"Subject: hi\n\nhello\n")
+            .unwrap()
+            .disposition_notification()
+            .unwrap()
+            .is_none());
+        assert!(parse_disposition_notification(b"Final-Recipient: rfc822; a@b.c\n").is_err());
+    }
+
+    #[test]
+    fn generate_mdn() {
+        let mail = parse_mail(
+            concat!(
+                "From: Alice <alice@example.com>\n",
+                "Subject: Lunch\n",
+                "Message-ID: <1@example.com>\n",
+                "\n",
+                "Pizza?\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let me = SingleInfo {
+            display_name: None,
+            addr: "bob@example.com".to_string(),
+        };
+        let disposition = Disposition::new(MdnDispositionType::Deleted, true);
+        assert_eq!(mail.create_mdn(&me, &disposition, "ua"), None);
+
+        let raw = format!(
+            "Disposition-Notification-To: Alice <alice@example.com>\n{}",
+            String::from_utf8_lossy(mail.raw_bytes)
+        );
+        let mail = parse_mail(raw.as_bytes()).unwrap();
+        assert_eq!(
+            mail.disposition_notification_to()[0].addr,
+            "alice@example.com"
+        );
+        let mdn = mail.create_mdn(&me, &disposition, "ua").unwrap();
+        let mdn = parse_mail(&mdn).unwrap();
+        assert_eq!(
+            mdn.headers.get_first_value("Auto-Submitted").as_deref(),
+            Some("auto-replied")
+        );
+        assert_eq!(
+            mdn.headers.get_first_value("In-Reply-To").as_deref(),
+            Some("<1@example.com>")
+        );
+        assert_eq!(mdn.subparts.len(), 3);
+        let report = mdn.disposition_notification().unwrap().unwrap();
+        assert_eq!(report.notification.disposition, disposition);
+        assert_eq!(report.notification.final_recipient.value, "bob@example.com");
+        let original = parse_mail(report.original.unwrap().get_body_raw().unwrap().as_slice())
+            .unwrap()
+            .headers
+            .get_first_value("Subject");
+        assert_eq!(original.as_deref(), Some("Lunch"));
+    }
+}
@@ -1,14 +1,52 @@
+use std::collections::hash_map::RandomState;
 use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::MailParseError;
+use crate::{split_once, MailHeader, MailHeaderMap, MailParseError};
 
 /// A simple wrapper around `Vec<String>`. This is primarily here so we can
 /// implement the Display trait on it, and allow user code to easily convert
 /// the return value from `msgidparse` back into a string. This also allows
 /// to add additional methods on this type in the future.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct MessageIdList(Vec<String>);
 
+impl From<Vec<String>> for MessageIdList {
+    fn from(ids: Vec<String>) -> Self {
+        MessageIdList(ids)
+    }
+}
+
+impl MessageIdList {
+    /// Shortens a `References` list to at most `max` IDs. As suggested by
+    /// RFC 5322 section 3.6.4 (and RFC 5537 for netnews), the first ID, which
+    /// identifies the start of the thread, and the most recent IDs are kept,
+    /// dropping IDs from the middle. With a `max` of 1 only the last ID is
+    /// kept.
+    pub fn trim_references(&mut self, max: usize) {
+        let len = self.0.len();
+        if len <= max {
+            return;
+        }
+        match max {
+            0 => self.0.clear(),
+            1 => {
+                self.0.drain(..len - 1);
+            }
+            _ => {
+                self.0.drain(1..1 + len - max);
+            }
+        }
+    }
+
+    /// Consumes the `MessageIdList` and returns the wrapped list of IDs.
+    pub fn into_inner(self) -> Vec<String> {
+        self.0
+    }
+}
+
 impl std::ops::Deref for MessageIdList {
     type Target = Vec<String>;
 
@@ -37,12 +75,246 @@ impl fmt::Display for MessageIdList {
     }
 }
 
+/// Controls how strictly `msgidparse_partial` interprets its input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MsgIdParseMode {
+    /// Accept what real-world mail clients produce: comments and whitespace
+    /// anywhere (including inside the angle brackets), quoted local parts,
+    /// IDs without angle brackets (as long as they contain an '@'), and
+    /// commas or other garbage between IDs, which is skipped.
+    Lenient,
+    /// Only accept message IDs of the form `<id-left@id-right>` as defined
+    /// in RFC 5322 section 3.6.4, separated by optional whitespace and
+    /// comments.
+    Strict,
+}
+
+/// Given a string starting with '(', returns what follows the (possibly
+/// nested) comment, or `None` if the comment is not terminated.
+pub(crate) fn skip_comment(s: &str) -> Option<&str> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (ix, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&s[ix + 1..]);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Skips whitespace and comments. If a comment is not terminated, returns
+/// the string starting at that comment as the error.
+pub(crate) fn skip_cfws(s: &str) -> Result<&str, &str> {
+    let mut rest = s.trim_start();
+    while rest.starts_with('(') {
+        rest = skip_comment(rest).ok_or(rest)?.trim_start();
+    }
+    Ok(rest)
+}
+
+/// Given a string starting with '"', returns the length of the quoted string
+/// including the quotes, or `None` if it is not terminated.
+pub(crate) fn quoted_len(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (ix, c) in s.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '"' => return Some(ix + 1),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Removes the backslash escapes from the contents of a quoted string.
+pub(crate) fn unquote(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut escaped = false;
+    for c in s.chars() {
+        if c == '\\' && !escaped {
+            escaped = true;
+            continue;
+        }
+        escaped = false;
+        result.push(c);
+    }
+    result
+}
+
+/// Given a string starting with '<', returns the message ID up to the
+/// closing '>' with comments and whitespace (outside of quoted strings)
+/// removed, along with what follows the '>'. A '<' before the closing '>'
+/// is taken to start the next ID, so it ends this one. Returns `None` if
+/// the ID is not terminated.
+fn lenient_bracketed(s: &str) -> Option<(String, &str)> {
+    let mut id = String::new();
+    let mut rest = &s[1..];
+    loop {
+        let c = rest.chars().next()?;
+        match c {
+            '>' => return Some((id, &rest[1..])),
+            '<' => return Some((id, rest)),
+            '(' => rest = skip_comment(rest)?,
+            '"' => {
+                let len = quoted_len(rest)?;
+                id.push_str(&rest[..len]);
+                rest = &rest[len..];
+            }
+            c => {
+                if !c.is_whitespace() {
+                    id.push(c);
+                }
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// Returns the length of the word at the start of `s`, which ends at
+/// whitespace, a comma, or the start of a comment or a bracketed ID.
+fn bare_word_len(s: &str) -> usize {
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() || matches!(c, ',' | '(' | '<') {
+            break;
+        }
+        match quoted_len(rest) {
+            Some(len) if c == '"' => rest = &rest[len..],
+            _ => rest = &rest[c.len_utf8()..],
+        }
+    }
+    s.len() - rest.len()
+}
+
+fn is_atext(c: char) -> bool {
+    // RFC 6532 allows non-ASCII UTF-8 wherever atext is allowed.
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c) || !c.is_ascii()
+}
+
+fn is_dot_atom_text(s: &str) -> bool {
+    !s.is_empty()
+        && s.split('.')
+            .all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+}
+
+fn is_no_fold_literal(s: &str) -> bool {
+    match s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        Some(dtext) => dtext
+            .chars()
+            .all(|c| matches!(c, '!'..='Z' | '^'..='~') || !c.is_ascii()),
+        None => false,
+    }
+}
+
+/// Returns true if `id` (without the angle brackets) is a message ID of the
+/// form `id-left@id-right` as defined in RFC 5322 section 3.6.4.
+fn is_valid_msg_id(id: &str) -> bool {
+    match split_once(id, '@') {
+        Some((left, right)) => {
+            is_dot_atom_text(left) && (is_dot_atom_text(right) || is_no_fold_literal(right))
+        }
+        None => false,
+    }
+}
+
+/// Parse as many message IDs as possible from the given header value, and
+/// return them along with the part of the input that could not be parsed.
+/// Parsing stops at the first thing that can't be interpreted in the given
+/// mode; the remainder is empty if the whole input was consumed. In strict
+/// mode that is anything that is not a valid `<id-left@id-right>`; in lenient
+/// mode it is only an ID with a missing closing '>' at the end of the input,
+/// since everything else is either interpreted or skipped over.
+///
+/// # Examples
+/// ```
+///     use mailparse::{msgidparse_partial, MsgIdParseMode};
+///     let header = "<one@foo.com> (the original) two@bar.com, <three> <x";
+///
+///     let (ids, rest) = msgidparse_partial(header, MsgIdParseMode::Lenient);
+///     assert_eq!(*ids, vec!["one@foo.com", "two@bar.com", "three"]);
+///     assert_eq!(rest, "<x");
+///
+///     let (ids, rest) = msgidparse_partial(header, MsgIdParseMode::Strict);
+///     assert_eq!(*ids, vec!["one@foo.com"]);
+///     assert_eq!(rest, "two@bar.com, <three> <x");
+/// ```
+pub fn msgidparse_partial(ids: &str, mode: MsgIdParseMode) -> (MessageIdList, &str) {
+    let mut msgids = Vec::new();
+    let mut remaining = ids;
+    loop {
+        remaining = match (skip_cfws(remaining), mode) {
+            (Ok(rest), _) => rest,
+            // An unterminated comment swallows the rest of the input.
+            (Err(_), MsgIdParseMode::Lenient) => "",
+            (Err(rest), MsgIdParseMode::Strict) => {
+                remaining = rest;
+                break;
+            }
+        };
+        if remaining.is_empty() {
+            break;
+        }
+        match mode {
+            MsgIdParseMode::Lenient => {
+                if remaining.starts_with('<') {
+                    let (id, rest) = match lenient_bracketed(remaining) {
+                        Some(result) => result,
+                        None => break,
+                    };
+                    if !id.is_empty() {
+                        msgids.push(id);
+                    }
+                    remaining = rest;
+                } else if let Some(rest) = remaining.strip_prefix(',') {
+                    remaining = rest;
+                } else {
+                    let len = bare_word_len(remaining);
+                    let word = &remaining[..len];
+                    if word.contains('@') && !word.contains('>') {
+                        msgids.push(word.to_string());
+                    }
+                    remaining = &remaining[len..];
+                }
+            }
+            MsgIdParseMode::Strict => {
+                let end = match remaining.find('>') {
+                    Some(end) if remaining.starts_with('<') => end,
+                    _ => break,
+                };
+                if !is_valid_msg_id(&remaining[1..end]) {
+                    break;
+                }
+                msgids.push(remaining[1..end].to_string());
+                remaining = &remaining[end + 1..];
+            }
+        }
+    }
+    (MessageIdList(msgids), remaining)
+}
+
 /// Parse an email header into a structured type holding a list of message ids.
 /// This function can be used to parse headers containing message IDs, such as
 /// `Message-ID`, `In-Reply-To`, and `References`.
-/// This function is currently mostly trivial (splits on whitespace and strips
-/// angle-brackets) but may be enhanced in the future to strip comments (which
-/// are technically allowed by the RFCs but never really used in practice).
+/// Parsing is lenient (see `MsgIdParseMode::Lenient`): comments are stripped,
+/// IDs missing their angle brackets are accepted, and garbage between IDs is
+/// skipped. An error is only returned if the last ID is missing its closing
+/// '>'; use `msgidparse_partial` to get the IDs before it anyway.
 ///
 /// # Examples
 /// ```
@@ -50,28 +322,148 @@ impl fmt::Display for MessageIdList {
 ///     let parsed_ids = msgidparse("<msg_one@foo.com>  <msg_two@bar.com>").unwrap();
 ///     assert_eq!(parsed_ids[0], "msg_one@foo.com");
 ///     assert_eq!(parsed_ids[1], "msg_two@bar.com");
+///
+///     let parsed_ids = msgidparse("<msg_one@foo.com> (Alice's message) msg_two@bar.com").unwrap();
+///     assert_eq!(parsed_ids.to_string(), "<msg_one@foo.com> <msg_two@bar.com>");
 /// ```
 pub fn msgidparse(ids: &str) -> Result<MessageIdList, MailParseError> {
-    let mut msgids = Vec::new();
+    match msgidparse_partial(ids, MsgIdParseMode::Lenient) {
+        (msgids, "") => Ok(msgids),
+        _ => Err(MailParseError::Generic("Message IDs must end with >")),
+    }
+}
 
-    // The remaining section of the header, not yet chomped
-    let mut remaining = ids.trim_start();
-    // While we have some value of the header remaining
-    while !remaining.is_empty() {
-        // The next character should be the start of a Message ID
-        if !remaining.starts_with('<') {
-            return Err(MailParseError::Generic("Message IDs must start with <"));
+/// Like `msgidparse`, but only accepts message IDs of the form
+/// `<id-left@id-right>` as defined in RFC 5322 section 3.6.4, separated by
+/// whitespace and comments. Returns an error if anything else is found.
+///
+/// # Examples
+/// ```
+///     use mailparse::msgidparse_strict;
+///     assert!(msgidparse_strict("<a.b@example.com> (comment) <c@[127.0.0.1]>").is_ok());
+///     assert!(msgidparse_strict("<a@b> c@d").is_err());
+///     assert!(msgidparse_strict("<no-at-sign>").is_err());
+///     assert!(msgidparse_strict("<a..b@example.com>").is_err());
+/// ```
+pub fn msgidparse_strict(ids: &str) -> Result<MessageIdList, MailParseError> {
+    match msgidparse_partial(ids, MsgIdParseMode::Strict) {
+        (msgids, "") => Ok(msgids),
+        _ => Err(MailParseError::Generic(
+            "Message IDs must be of the form <id-left@id-right>",
+        )),
+    }
+}
+
+/// The message ID headers of a reply, computed from the message being
+/// replied to as described in RFC 5322 section 3.6.4. Both lists can be
+/// formatted into header values using their `Display` implementation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplyMessageIds {
+    /// The contents of the `In-Reply-To` header: the parent's message ID.
+    pub in_reply_to: MessageIdList,
+    /// The contents of the `References` header: the parent's `References`
+    /// (or, failing that, its `In-Reply-To` if it contains a single ID)
+    /// followed by the parent's message ID.
+    pub references: MessageIdList,
+}
+
+impl ReplyMessageIds {
+    /// Computes the reply headers from the `Message-ID`, `In-Reply-To` and
+    /// `References` of the parent message. Empty lists stand for missing
+    /// headers.
+    pub fn new(
+        message_id: &MessageIdList,
+        in_reply_to: &MessageIdList,
+        references: &MessageIdList,
+    ) -> Self {
+        let mut chain = if !references.is_empty() {
+            references.clone()
+        } else if in_reply_to.len() == 1 {
+            in_reply_to.clone()
+        } else {
+            MessageIdList::default()
+        };
+        for id in message_id.iter() {
+            if !chain.contains(id) {
+                chain.push(id.clone());
+            }
         }
-        // The ID ends at the next '>'
-        let end_index = remaining
-            .find('>')
-            .ok_or(MailParseError::Generic("Message IDs must end with >"))?;
-        msgids.push(remaining[1..end_index].to_string());
+        ReplyMessageIds {
+            in_reply_to: message_id.clone(),
+            references: chain,
+        }
+    }
 
-        // Chomp the part of the string we just processed, and any trailing whitespace
-        remaining = remaining[end_index + 1..].trim_start();
+    /// Computes the reply headers from the headers of the parent message.
+    /// The message ID headers are parsed leniently, so anything that can't
+    /// be interpreted is dropped.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::{parse_headers, ReplyMessageIds};
+    ///     let (headers, _) = parse_headers(concat!(
+    ///         "Message-ID: <3@example.com>\n",
+    ///         "In-Reply-To: <2@example.com>\n",
+    ///         "References: <1@example.com> <2@example.com>\n",
+    ///         "\n").as_bytes()).unwrap();
+    ///     let mut ids = ReplyMessageIds::from_headers(&headers);
+    ///     assert_eq!(ids.in_reply_to.to_string(), "<3@example.com>");
+    ///     assert_eq!(ids.references.to_string(),
+    ///                "<1@example.com> <2@example.com> <3@example.com>");
+    ///
+    ///     ids.references.trim_references(2);
+    ///     assert_eq!(ids.references.to_string(), "<1@example.com> <3@example.com>");
+    /// ```
+    pub fn from_headers(headers: &[MailHeader]) -> Self {
+        let parse = |name| match headers.get_first_value(name) {
+            Some(value) => msgidparse_partial(&value, MsgIdParseMode::Lenient).0,
+            None => MessageIdList::default(),
+        };
+        Self::new(
+            &parse("Message-ID"),
+            &parse("In-Reply-To"),
+            &parse("References"),
+        )
     }
-    Ok(MessageIdList(msgids))
+}
+
+/// Generates a new, globally unique message ID for a message originating
+/// from `domain`, suitable for use in a `Message-ID` header. The ID is
+/// returned without the angle brackets, like the IDs returned by
+/// `msgidparse`. The left part combines the current time, the process ID, a
+/// per-process counter and random bits, so IDs generated concurrently by
+/// different processes or threads do not collide. `domain` should be a
+/// domain name (or address literal) that the sender controls.
+///
+/// # Examples
+/// ```
+///     use mailparse::{generate_message_id, msgidparse_strict, MessageIdList};
+///     let id = generate_message_id("example.com");
+///     assert!(id.ends_with("@example.com"));
+///     assert_ne!(id, generate_message_id("example.com"));
+///
+///     let header = MessageIdList::from(vec![id]).to_string();
+///     assert!(msgidparse_strict(&header).is_ok());
+/// ```
+pub fn generate_message_id(domain: &str) -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_usize(count);
+    hasher.write_u128(now.as_nanos());
+    format!(
+        "{}.{:09}.{}.{}.{:016x}@{}",
+        now.as_secs(),
+        now.subsec_nanos(),
+        std::process::id(),
+        count,
+        hasher.finish(),
+        domain
+    )
 }
 
 #[cfg(test)]
@@ -133,4 +525,129 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn parse_lenient() {
+        let ids = |s| msgidparse(s).unwrap().to_vec();
+        assert_eq!(
+            ids("<a@foo.com> (comment (nested) \\) ) <b@bar.com> (trailing"),
+            vec!["a@foo.com", "b@bar.com"]
+        );
+        assert_eq!(
+            ids("< a@foo.com (comment) >\n\t<b @ bar.com>"),
+            vec!["a@foo.com", "b@bar.com"]
+        );
+        assert_eq!(
+            ids("<\"quoted > local\"@foo.com>"),
+            vec!["\"quoted > local\"@foo.com"]
+        );
+        assert_eq!(
+            ids("a@foo.com b@bar.com,c@qux.com"),
+            vec!["a@foo.com", "b@bar.com", "c@qux.com"]
+        );
+        assert_eq!(
+            ids("Your message of 1 Jan <a@foo.com>, garbage>, <b@bar.com>"),
+            vec!["a@foo.com", "b@bar.com"]
+        );
+        assert_eq!(
+            ids("<a@foo.com <b@bar.com>"),
+            vec!["a@foo.com", "b@bar.com"]
+        );
+        assert_eq!(ids("<> (only a comment)"), Vec::<String>::new());
+
+        assert!(msgidparse("<a@foo.com> <b@bar.com").is_err());
+        let (ids, rest) = msgidparse_partial("<a@foo.com> <b@bar.com", MsgIdParseMode::Lenient);
+        assert_eq!(*ids, vec!["a@foo.com"]);
+        assert_eq!(rest, "<b@bar.com");
+    }
+
+    #[test]
+    fn parse_strict() {
+        let partial = |s| {
+            let (ids, rest) = msgidparse_partial(s, MsgIdParseMode::Strict);
+            (ids.to_vec(), rest)
+        };
+        assert_eq!(
+            partial(" (c) <a@foo.com>(c)<b.c@[1.2.3.4]> "),
+            (
+                vec!["a@foo.com".to_string(), "b.c@[1.2.3.4]".to_string()],
+                ""
+            )
+        );
+        assert_eq!(
+            partial("<a@foo.com> <b@bar.com> (unterminated"),
+            (
+                vec!["a@foo.com".to_string(), "b@bar.com".to_string()],
+                "(unterminated"
+            )
+        );
+        assert_eq!(
+            partial("<a@foo.com> <\"q\"@bar.com>"),
+            (vec!["a@foo.com".to_string()], "<\"q\"@bar.com>")
+        );
+        assert_eq!(partial("<a@b@c>"), (vec![], "<a@b@c>"));
+        assert_eq!(partial("<@foo.com>"), (vec![], "<@foo.com>"));
+        assert_eq!(partial("<a@foo.>"), (vec![], "<a@foo.>"));
+        assert_eq!(partial("<a@foo.com"), (vec![], "<a@foo.com"));
+        assert_eq!(
+            partial("<\u{e9}t\u{e9}@exemple.fr>"),
+            (vec!["\u{e9}t\u{e9}@exemple.fr".to_string()], "")
+        );
+        assert!(msgidparse_strict("a@foo.com").is_err());
+        assert!(msgidparse_strict("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reply_ids() {
+        let ids =
+            |v: &[&str]| MessageIdList::from(v.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+        let none = MessageIdList::default();
+
+        let reply = ReplyMessageIds::new(&ids(&["3@x"]), &ids(&["2@x"]), &ids(&["1@x", "2@x"]));
+        assert_eq!(*reply.in_reply_to, vec!["3@x"]);
+        assert_eq!(*reply.references, vec!["1@x", "2@x", "3@x"]);
+
+        // Without References, a single In-Reply-To ID is used instead.
+        let reply = ReplyMessageIds::new(&ids(&["3@x"]), &ids(&["2@x"]), &none);
+        assert_eq!(*reply.references, vec!["2@x", "3@x"]);
+        let reply = ReplyMessageIds::new(&ids(&["3@x"]), &ids(&["2@x", "1@x"]), &none);
+        assert_eq!(*reply.references, vec!["3@x"]);
+
+        // A parent without a Message-ID still passes on its References.
+        let reply = ReplyMessageIds::new(&none, &none, &ids(&["1@x"]));
+        assert!(reply.in_reply_to.is_empty());
+        assert_eq!(*reply.references, vec!["1@x"]);
+
+        assert_eq!(
+            ReplyMessageIds::new(&none, &none, &none),
+            ReplyMessageIds::default()
+        );
+        // The parent's ID isn't repeated if it is already in the chain.
+        let reply = ReplyMessageIds::new(&ids(&["2@x"]), &none, &ids(&["1@x", "2@x"]));
+        assert_eq!(*reply.references, vec!["1@x", "2@x"]);
+    }
+
+    #[test]
+    fn trim_references() {
+        let mut refs = MessageIdList::from((1..=6).map(|i| format!("{}@x", i)).collect::<Vec<_>>());
+        refs.trim_references(10);
+        assert_eq!(refs.len(), 6);
+        refs.trim_references(4);
+        assert_eq!(*refs, vec!["1@x", "4@x", "5@x", "6@x"]);
+        refs.trim_references(1);
+        assert_eq!(*refs, vec!["6@x"]);
+        refs.trim_references(0);
+        assert!(refs.is_empty());
+    }
+
+    #[test]
+    fn generated_ids_are_unique_and_valid() {
+        let generated: Vec<String> = (0..100)
+            .map(|_| generate_message_id("example.com"))
+            .collect();
+        for (i, id) in generated.iter().enumerate() {
+            assert!(is_valid_msg_id(id), "{}", id);
+            assert!(!generated[..i].contains(id));
+        }
+    }
 }
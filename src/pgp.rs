@@ -0,0 +1,460 @@
+//! Detection of OpenPGP messages, both PGP/MIME as defined in IETF RFC 3156
+//! and inline PGP blocks in plain text parts.
+//!
+//! A PGP/MIME signed message is a `multipart/signed` part whose first
+//! subpart is the signed content and whose second subpart is an armored
+//! detached signature. An encrypted message is a `multipart/encrypted` part
+//! whose second subpart is the armored encrypted data, which decrypts to a
+//! complete MIME entity. This crate does not implement OpenPGP itself:
+//! signatures are handed over as is, and decryption goes through the
+//! `Decryptor` trait so that applications can plug in the OpenPGP library
+//! of their choice.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+
+use crate::{parse_mail, to_crlf, MailParseError, ParsedMail};
+
+/// A signed `multipart/signed` PGP/MIME part.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgpSigned<'a> {
+    /// The exact bytes that were signed: the first subpart, headers
+    /// included, without the line break that precedes the boundary.
+    pub content: &'a [u8],
+    /// The range of `content` within the `raw_bytes` of the
+    /// `multipart/signed` part.
+    pub content_range: Range<usize>,
+    /// The ASCII-armored detached signature, without surrounding
+    /// whitespace.
+    pub signature: String,
+    /// The hash algorithm announced by the `micalg` parameter, such as
+    /// `pgp-sha256`.
+    pub micalg: Option<String>,
+}
+
+impl<'a> PgpSigned<'a> {
+    /// Returns the signed content with its line endings converted to CRLF,
+    /// the canonical form that the signature is computed over (RFC 3156
+    /// section 5). Messages stored with LF line endings must be converted
+    /// back before checking their signatures.
+    pub fn canonical_content(&self) -> Cow<'a, [u8]> {
+        to_crlf(self.content)
+    }
+}
+
+/// An encrypted `multipart/encrypted` PGP/MIME part.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgpEncrypted {
+    /// The ASCII-armored encrypted data, without surrounding whitespace.
+    pub encrypted: String,
+}
+
+/// An error from a `Decryptor`, such as a missing secret key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecryptionError(pub String);
+
+impl fmt::Display for DecryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Decryption failed: {}", self.0)
+    }
+}
+
+/// Decrypts OpenPGP messages. Applications implement this on top of their
+/// OpenPGP library and key store.
+pub trait Decryptor {
+    /// Decrypts an ASCII-armored OpenPGP message, returning the plaintext.
+    fn decrypt(&self, armored: &str) -> Result<Vec<u8>, DecryptionError>;
+}
+
+/// The decrypted content of a `multipart/encrypted` part: a complete MIME
+/// entity that stands in for the encrypted part.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decrypted {
+    raw: Vec<u8>,
+}
+
+impl Decrypted {
+    /// Returns the decrypted bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Parses the decrypted MIME entity. Its body is the body of the
+    /// message; it may itself be a signed message.
+    pub fn parse(&self) -> Result<ParsedMail<'_>, MailParseError> {
+        parse_mail(&self.raw)
+    }
+}
+
+impl PgpEncrypted {
+    /// Decrypts the message with the given decryptor.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     use mailparse::pgp::{DecryptionError, Decryptor};
+    ///
+    ///     struct Fake;
+    ///     impl Decryptor for Fake {
+    ///         fn decrypt(&self, armored: &str) -> Result<Vec<u8>, DecryptionError> {
+    ///             assert!(armored.starts_with("-----BEGIN PGP MESSAGE-----"));
+    ///             Ok(b"Content-Type: text/plain\n\nThe secret".to_vec())
+    ///         }
+    ///     }
+    ///
+    ///     let mail = parse_mail(concat!(
+    ///             "Content-Type: multipart/encrypted; protocol=\"application/pgp-encrypted\";\n",
+    ///             " boundary=XX\n",
+    ///             "\n",
+    ///             "--XX\n",
+    ///             "Content-Type: application/pgp-encrypted\n",
+    ///             "\n",
+    ///             "Version: 1\n",
+    ///             "--XX\n",
+    ///             "Content-Type: application/octet-stream\n",
+    ///             "\n",
+    ///             "-----BEGIN PGP MESSAGE-----\n",
+    ///             "\n",
+    ///             "hF4DAAAAAAAAAAASAQdA\n",
+    ///             "-----END PGP MESSAGE-----\n",
+    ///             "--XX--\n").as_bytes())
+    ///         .unwrap();
+    ///     let decrypted = mail.pgp_encrypted().unwrap().decrypt(&Fake).unwrap();
+    ///     assert_eq!(decrypted.parse().unwrap().get_body().unwrap(), "The secret");
+    /// ```
+    pub fn decrypt<D: Decryptor + ?Sized>(
+        &self,
+        decryptor: &D,
+    ) -> Result<Decrypted, DecryptionError> {
+        let raw = decryptor.decrypt(&self.encrypted)?;
+        Ok(Decrypted { raw })
+    }
+}
+
+/// An OpenPGP block found in a plain text part.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InlinePgp {
+    /// An armored encrypted (or signed) message.
+    Message(String),
+    /// A cleartext signed message (RFC 4880 section 7). The text is
+    /// dash-unescaped, with its lines joined by `\n`.
+    Signed { text: String, signature: String },
+    /// An armored public key.
+    PublicKey(String),
+}
+
+/// Collects the lines of an armored block, starting with the given BEGIN
+/// line, up to the END line. Returns `None` if the END line is missing.
+fn armored_block<'a, I: Iterator<Item = &'a str>>(
+    begin: &str,
+    lines: &mut I,
+    end: &str,
+) -> Option<String> {
+    let mut block = vec![begin.trim_end()];
+    for line in lines {
+        let line = line.trim_end();
+        block.push(line);
+        if line == end {
+            block.push("");
+            return Some(block.join("\n"));
+        }
+    }
+    None
+}
+
+/// Returns the body of a part holding armored data, which is plain ASCII.
+fn armored_body(part: &ParsedMail) -> Option<String> {
+    let body = String::from_utf8(part.get_body_raw().ok()?).ok()?;
+    Some(body.trim().to_string())
+}
+
+fn find_inline_pgp(body: &str) -> Vec<InlinePgp> {
+    let mut blocks = Vec::new();
+    let mut lines = body.lines();
+    while let Some(line) = lines.next() {
+        match line.trim_end() {
+            "-----BEGIN PGP MESSAGE-----" => {
+                if let Some(block) = armored_block(line, &mut lines, "-----END PGP MESSAGE-----") {
+                    blocks.push(InlinePgp::Message(block));
+                }
+            }
+            "-----BEGIN PGP PUBLIC KEY BLOCK-----" => {
+                let end = "-----END PGP PUBLIC KEY BLOCK-----";
+                if let Some(block) = armored_block(line, &mut lines, end) {
+                    blocks.push(InlinePgp::PublicKey(block));
+                }
+            }
+            "-----BEGIN PGP SIGNED MESSAGE-----" => {
+                // Skip the armor headers ("Hash: SHA256").
+                for header in lines.by_ref() {
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                }
+                let mut text = Vec::new();
+                let mut signature = None;
+                while let Some(line) = lines.next() {
+                    if line.trim_end() == "-----BEGIN PGP SIGNATURE-----" {
+                        signature = armored_block(line, &mut lines, "-----END PGP SIGNATURE-----");
+                        break;
+                    }
+                    text.push(line.strip_prefix("- ").unwrap_or(line));
+                }
+                if let Some(signature) = signature {
+                    blocks.push(InlinePgp::Signed {
+                        text: text.join("\n"),
+                        signature,
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+    blocks
+}
+
+impl<'a> ParsedMail<'a> {
+    /// If this is a `multipart/signed` part with an OpenPGP signature,
+    /// returns the signed content and the signature. Returns `None` for
+    /// other parts, including `multipart/signed` parts with other kinds of
+    /// signatures.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     let raw = concat!(
+    ///         "Content-Type: multipart/signed; micalg=pgp-sha256;\n",
+    ///         " protocol=\"application/pgp-signature\"; boundary=XX\n",
+    ///         "\n",
+    ///         "--XX\n",
+    ///         "Content-Type: text/plain\n",
+    ///         "\n",
+    ///         "Signed text\n",
+    ///         "--XX\n",
+    ///         "Content-Type: application/pgp-signature\n",
+    ///         "\n",
+    ///         "-----BEGIN PGP SIGNATURE-----\n",
+    ///         "\n",
+    ///         "iHUEARYIAB0WIQ\n",
+    ///         "-----END PGP SIGNATURE-----\n",
+    ///         "--XX--\n");
+    ///     let mail = parse_mail(raw.as_bytes()).unwrap();
+    ///     let signed = mail.pgp_signed().unwrap();
+    ///     assert_eq!(signed.content, b"Content-Type: text/plain\n\nSigned text");
+    ///     assert_eq!(&*signed.canonical_content(), b"Content-Type: text/plain\r\n\r\nSigned text");
+    ///     assert!(signed.signature.starts_with("-----BEGIN PGP SIGNATURE-----\n"));
+    ///     assert_eq!(signed.micalg, Some("pgp-sha256".to_string()));
+    /// ```
+    pub fn pgp_signed(&self) -> Option<PgpSigned<'a>> {
+        if self.ctype.mimetype != "multipart/signed"
+            || !self
+                .ctype
+                .params
+                .get("protocol")?
+                .eq_ignore_ascii_case("application/pgp-signature")
+        {
+            return None;
+        }
+        let signature_part = self.subparts.get(1)?;
+        if signature_part.ctype.mimetype != "application/pgp-signature" {
+            return None;
+        }
+        let content_range = self.signed_content_range()?;
+        Some(PgpSigned {
+            content: &self.raw_bytes[content_range.clone()],
+            content_range,
+            signature: armored_body(signature_part)?,
+            micalg: self.ctype.params.get("micalg").map(|m| m.to_lowercase()),
+        })
+    }
+
+    /// If this is a `multipart/encrypted` part with OpenPGP encrypted data,
+    /// returns the encrypted data. Returns `None` for other parts.
+    pub fn pgp_encrypted(&self) -> Option<PgpEncrypted> {
+        if self.ctype.mimetype != "multipart/encrypted"
+            || !self
+                .ctype
+                .params
+                .get("protocol")?
+                .eq_ignore_ascii_case("application/pgp-encrypted")
+        {
+            return None;
+        }
+        match self.subparts.as_slice() {
+            [control, data] if control.ctype.mimetype == "application/pgp-encrypted" => {
+                Some(PgpEncrypted {
+                    encrypted: armored_body(data)?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the OpenPGP blocks embedded in a `text/plain` part, in order.
+    /// Returns an empty vector for other parts.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     use mailparse::pgp::InlinePgp;
+    ///     let mail = parse_mail(concat!(
+    ///             "Content-Type: text/plain\n",
+    ///             "\n",
+    ///             "-----BEGIN PGP SIGNED MESSAGE-----\n",
+    ///             "Hash: SHA256\n",
+    ///             "\n",
+    ///             "- -- \n",
+    ///             "Signed\n",
+    ///             "-----BEGIN PGP SIGNATURE-----\n",
+    ///             "\n",
+    ///             "iHUEARYIAB0WIQ\n",
+    ///             "-----END PGP SIGNATURE-----\n").as_bytes())
+    ///         .unwrap();
+    ///     let blocks = mail.inline_pgp().unwrap();
+    ///     assert_eq!(blocks, vec![InlinePgp::Signed {
+    ///         text: "-- \nSigned".to_string(),
+    ///         signature: concat!(
+    ///             "-----BEGIN PGP SIGNATURE-----\n",
+    ///             "\n",
+    ///             "iHUEARYIAB0WIQ\n",
+    ///             "-----END PGP SIGNATURE-----\n").to_string(),
+    ///     }]);
+    /// ```
+    pub fn inline_pgp(&self) -> Result<Vec<InlinePgp>, MailParseError> {
+        if self.ctype.mimetype != "text/plain" {
+            return Ok(Vec::new());
+        }
+        Ok(find_inline_pgp(&self.get_body()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(name: &str) -> Vec<u8> {
+        let path = format!("./tests/files/pgp/{}", name);
+        std::fs::read(&path).unwrap_or_else(|_| panic!("Unable to open the file [{}]", path))
+    }
+
+    #[test]
+    fn signed() {
+        let raw = read("signed.eml");
+        let mail = parse_mail(&raw).unwrap();
+        let signed = mail.pgp_signed().unwrap();
+        assert_eq!(
+            signed.content,
+            &b"Content-Type: text/plain; charset=us-ascii\r\n\
+               Content-Transfer-Encoding: 7bit\r\n\
+               \r\n\
+               Hi Bob,\r\n\
+               \r\n\
+               The release is signed.\r\n\
+               \r\n\
+               Alice\r\n"[..]
+        );
+        assert_eq!(&raw[signed.content_range.clone()], signed.content);
+        assert_eq!(signed.canonical_content(), Cow::Borrowed(signed.content));
+        assert!(signed
+            .signature
+            .starts_with("-----BEGIN PGP SIGNATURE-----\r\n"));
+        assert!(signed.signature.ends_with("-----END PGP SIGNATURE-----"));
+        assert_eq!(signed.micalg, Some("pgp-sha256".to_string()));
+        assert_eq!(mail.pgp_encrypted(), None);
+        assert_eq!(mail.smime_signed(), None);
+
+        // The same content, stored with LF line endings, canonicalizes back.
+        let lf = String::from_utf8(raw.clone())
+            .unwrap()
+            .replace("\r\n", "\n");
+        let lf_mail = parse_mail(lf.as_bytes()).unwrap();
+        let lf_signed = lf_mail.pgp_signed().unwrap();
+        assert_eq!(
+            &lf.as_bytes()[lf_signed.content_range.clone()],
+            lf_signed.content
+        );
+        assert_eq!(lf_signed.canonical_content(), signed.content);
+    }
+
+    struct Keyring;
+
+    impl Decryptor for Keyring {
+        fn decrypt(&self, armored: &str) -> Result<Vec<u8>, DecryptionError> {
+            if !armored.starts_with("-----BEGIN PGP MESSAGE-----\n") {
+                return Err(DecryptionError("not an OpenPGP message".to_string()));
+            }
+            Ok("Content-Type: text/plain; charset=utf-8\r\n\
+                Content-Transfer-Encoding: 8bit\r\n\
+                \r\n\
+                Hi Bob,\r\n\r\nThe secret is safe. \u{2713}\r\n\r\nAlice\r\n"
+                .as_bytes()
+                .to_vec())
+        }
+    }
+
+    #[test]
+    fn encrypted() {
+        let raw = read("encrypted.eml");
+        let mail = parse_mail(&raw).unwrap();
+        assert_eq!(mail.pgp_signed(), None);
+        let encrypted = mail.pgp_encrypted().unwrap();
+        assert!(encrypted.encrypted.ends_with("-----END PGP MESSAGE-----"));
+        let decrypted = encrypted.decrypt(&Keyring).unwrap();
+        let body = decrypted.parse().unwrap();
+        assert_eq!(body.ctype.mimetype, "text/plain");
+        assert_eq!(
+            body.get_body().unwrap(),
+            "Hi Bob,\r\n\r\nThe secret is safe. \u{2713}\r\n\r\nAlice\r\n"
+        );
+
+        let bogus = PgpEncrypted {
+            encrypted: "junk".to_string(),
+        };
+        assert_eq!(
+            bogus.decrypt(&Keyring),
+            Err(DecryptionError("not an OpenPGP message".to_string()))
+        );
+        // The encrypted data is opaque to inline detection.
+        assert_eq!(mail.subparts[1].inline_pgp().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn inline() {
+        let mail = parse_mail(
+            concat!(
+                "Content-Type: text/plain\n",
+                "\n",
+                "Here is my key:\n",
+                "-----BEGIN PGP PUBLIC KEY BLOCK-----\n",
+                "\n",
+                "mDMEZ\n",
+                "-----END PGP PUBLIC KEY BLOCK-----\n",
+                "and the secret:\n",
+                "-----BEGIN PGP MESSAGE-----\r\n",
+                "\n",
+                "hF4D\n",
+                "-----END PGP MESSAGE-----\n",
+                "-----BEGIN PGP MESSAGE-----\n",
+                "truncated\n",
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            mail.inline_pgp().unwrap(),
+            vec![
+                InlinePgp::PublicKey(
+                    "-----BEGIN PGP PUBLIC KEY BLOCK-----\n\nmDMEZ\n-----END PGP PUBLIC KEY BLOCK-----\n"
+                        .to_string()
+                ),
+                InlinePgp::Message(
+                    "-----BEGIN PGP MESSAGE-----\n\nhF4D\n-----END PGP MESSAGE-----\n".to_string()
+                ),
+            ]
+        );
+
+        let mail = parse_mail(b"Content-Type: text/html\n\n-----BEGIN PGP MESSAGE-----\n").unwrap();
+        assert_eq!(mail.inline_pgp().unwrap(), vec![]);
+    }
+}
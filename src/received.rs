@@ -0,0 +1,500 @@
+//! Parsing of `Received` trace headers (IETF RFC 5321 section 4.4) and
+//! reconstruction of the path a message took.
+//!
+//! Every MTA that handles a message prepends a `Received` header recording
+//! where it got the message from, when, and how. The grammar in RFC 5321 is
+//! loose and MTAs take further liberties with it (mostly by putting useful
+//! information in comments), so `parse_received` extracts what it can and
+//! never fails. `trace_path` parses all the `Received` headers of a message
+//! into a list of hops in delivery order.
+
+use std::net::IpAddr;
+
+use crate::msgidparse::skip_comment;
+use crate::{dateparse, MailHeader, MailHeaderMap};
+
+/// The parsed value of a single `Received` header, as in
+/// `from mail.example.com (host.example.com [192.0.2.1]) by mx.example.org
+/// (Postfix) with ESMTPS id 4ABC for <bob@example.org>; Tue, 1 Nov 1994
+/// 08:12:34 -0500`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Received {
+    /// The host name given in the `from` clause. Depending on the MTA this
+    /// is the name the client gave in its HELO/EHLO command or its reverse
+    /// DNS name.
+    pub from: Option<String>,
+    /// The name the client gave in its HELO/EHLO command, as far as it can
+    /// be determined.
+    pub helo: Option<String>,
+    /// The name the receiving MTA found for the client's IP address with a
+    /// reverse DNS lookup.
+    pub reverse_dns: Option<String>,
+    /// The IP address of the client.
+    pub ip: Option<IpAddr>,
+    /// The host name of the receiving MTA, from the `by` clause.
+    pub by: Option<String>,
+    /// The link type, from the `via` clause.
+    pub via: Option<String>,
+    /// The protocol, such as `ESMTPS` or `LMTP`, from the `with` clause.
+    pub with: Option<String>,
+    /// The receiving MTA's identifier for the message, from the `id` clause.
+    pub id: Option<String>,
+    /// The recipient address, from the `for` clause, without angle brackets.
+    pub recipient: Option<String>,
+    /// The time of receipt, as a UNIX timestamp, if the date after the
+    /// semicolon could be parsed.
+    pub date: Option<i64>,
+}
+
+/// One step of the path a message took, as returned by `trace_path`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hop {
+    /// The parsed `Received` header recording this hop.
+    pub received: Received,
+    /// The number of seconds between the previous hop and this one, if both
+    /// are dated. This may be negative if the clocks of the MTAs involved
+    /// are not in sync.
+    pub delay: Option<i64>,
+}
+
+enum Token<'a> {
+    Word(&'a str),
+    Comment(&'a str),
+}
+
+/// Splits the value into words and comments, up to the semicolon that
+/// introduces the date, and returns those along with the date text.
+fn tokenize(value: &str) -> (Vec<Token<'_>>, Option<&str>) {
+    let mut tokens = Vec::new();
+    let mut rest = value.trim_start();
+    while !rest.is_empty() {
+        if rest.starts_with('(') {
+            match skip_comment(rest) {
+                Some(after) => {
+                    let len = rest.len() - after.len();
+                    tokens.push(Token::Comment(&rest[1..len - 1]));
+                    rest = after;
+                }
+                None => {
+                    tokens.push(Token::Comment(&rest[1..]));
+                    break;
+                }
+            }
+        } else if let Some(date) = rest.strip_prefix(';') {
+            return (tokens, Some(date));
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '(' || c == ';')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(&rest[..end]));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    (tokens, None)
+}
+
+struct Clause<'a> {
+    name: String,
+    words: Vec<&'a str>,
+    comments: Vec<&'a str>,
+}
+
+const CLAUSES: &[&str] = &["from", "by", "via", "with", "id", "for"];
+
+/// Parses an IP address as it appears in `Received` headers, e.g.
+/// `[192.0.2.1]`, `[IPv6:2001:db8::1]` or a bare address in a comment.
+fn parse_ip(word: &str) -> Option<IpAddr> {
+    let word = word.trim_end_matches([',', '.']);
+    let word = word
+        .strip_prefix('[')
+        .and_then(|w| w.split(']').next())
+        .unwrap_or(word);
+    let word = match word.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("ipv6:") => &word[5..],
+        _ => word,
+    };
+    word.parse().ok()
+}
+
+fn is_hostname(word: &str) -> bool {
+    (word.contains('.') || word.eq_ignore_ascii_case("localhost"))
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+}
+
+fn strip_brackets(word: &str) -> String {
+    word.trim_matches(|c| c == '<' || c == '>' || c == ',')
+        .to_string()
+}
+
+/// Fills in the `from`, `helo`, `reverse_dns` and `ip` fields from the
+/// `from` clause.
+fn parse_from_clause(clause: &Clause, received: &mut Received) {
+    let value = clause.words.first().copied();
+    let mut helo = None;
+    let mut reverse_dns = None;
+    let mut ip = None;
+    for (ix, comment) in clause.comments.iter().enumerate() {
+        let flattened = comment.replace(['(', ')'], " ");
+        let mut words = flattened.split_whitespace();
+        while let Some(word) = words.next() {
+            let lower = word.to_lowercase();
+            if let Some(name) = lower.strip_prefix("helo=") {
+                helo = helo.or_else(|| Some(word[word.len() - name.len()..].to_string()));
+            } else if lower == "helo" {
+                helo = helo.or_else(|| words.next().map(String::from));
+            } else if let Some(addr) = parse_ip(word) {
+                ip = ip.or(Some(addr));
+            } else if ix == 0 && reverse_dns.is_none() && lower != "unknown" {
+                let host = word.trim_end_matches('.');
+                if is_hostname(host) {
+                    reverse_dns = Some(host.to_string());
+                }
+            }
+        }
+    }
+
+    let value_ip = value.and_then(parse_ip);
+    let value_host = value
+        .filter(|_| value_ip.is_none())
+        .map(|v| v.trim_end_matches('.'))
+        .filter(|v| !v.eq_ignore_ascii_case("unknown"));
+    received.from = value.map(String::from);
+    received.ip = ip.or(value_ip);
+    if helo.is_some() {
+        // Exim and qmail put the HELO name in a comment, in which case the
+        // name outside the comment is the reverse DNS name.
+        received.helo = helo;
+        received.reverse_dns = reverse_dns.or_else(|| value_host.map(String::from));
+    } else {
+        received.helo = value_host.map(String::from);
+        received.reverse_dns = reverse_dns;
+    }
+}
+
+/// Parses the value of a `Received` header. Clauses that are missing or
+/// cannot be understood are left empty.
+///
+/// # Examples
+/// ```
+///     use mailparse::received::parse_received;
+///     let received = parse_received(concat!(
+///         "from mail.example.com (host.example.com [192.0.2.1])\n",
+///         " by mx.example.org (Postfix) with ESMTPS id 4ABC\n",
+///         " for <bob@example.org>; Tue, 1 Nov 1994 08:12:34 -0500"));
+///     assert_eq!(received.helo, Some("mail.example.com".to_string()));
+///     assert_eq!(received.reverse_dns, Some("host.example.com".to_string()));
+///     assert_eq!(received.ip, Some("192.0.2.1".parse().unwrap()));
+///     assert_eq!(received.by, Some("mx.example.org".to_string()));
+///     assert_eq!(received.with, Some("ESMTPS".to_string()));
+///     assert_eq!(received.id, Some("4ABC".to_string()));
+///     assert_eq!(received.recipient, Some("bob@example.org".to_string()));
+///     assert_eq!(received.date, Some(783695554));
+/// ```
+pub fn parse_received(value: &str) -> Received {
+    let (tokens, date) = tokenize(value);
+    let mut clauses: Vec<Clause> = Vec::new();
+    for token in tokens {
+        match token {
+            Token::Word(word) => {
+                let lower = word.to_lowercase();
+                // A keyword right after another keyword is a value, as in
+                // "by id.example.com".
+                let starts_clause = CLAUSES.contains(&lower.as_str())
+                    && !matches!(clauses.last(), Some(c) if c.words.is_empty());
+                if starts_clause {
+                    clauses.push(Clause {
+                        name: lower,
+                        words: Vec::new(),
+                        comments: Vec::new(),
+                    });
+                } else if let Some(clause) = clauses.last_mut() {
+                    clause.words.push(word);
+                }
+            }
+            Token::Comment(comment) => {
+                if let Some(clause) = clauses.last_mut() {
+                    clause.comments.push(comment);
+                }
+            }
+        }
+    }
+
+    let mut received = Received {
+        // dateparse is lenient enough to accept text without any date in it.
+        date: date
+            .filter(|d| d.contains(|c: char| c.is_ascii_digit()))
+            .and_then(|d| dateparse(d.trim()).ok()),
+        ..Default::default()
+    };
+    for clause in &clauses {
+        let first = clause.words.first().copied();
+        match clause.name.as_str() {
+            "from" if received.from.is_none() => parse_from_clause(clause, &mut received),
+            "by" => received.by = received.by.take().or_else(|| first.map(String::from)),
+            "via" => received.via = received.via.take().or_else(|| first.map(String::from)),
+            "with" if received.with.is_none() && first.is_some() => {
+                received.with = Some(clause.words.join(" "))
+            }
+            "id" => received.id = received.id.take().or_else(|| first.map(strip_brackets)),
+            "for" => {
+                received.recipient = received
+                    .recipient
+                    .take()
+                    .or_else(|| first.map(strip_brackets))
+            }
+            _ => (),
+        }
+    }
+    received
+}
+
+/// Parses all the `Received` headers in the given headers and returns them
+/// as hops in the order the message took them, i.e. starting with the
+/// bottom-most header.
+///
+/// # Examples
+/// ```
+///     use mailparse::{parse_mail, received::trace_path};
+///     let mail = parse_mail(concat!(
+///             "Received: from mx.example.org by imap.example.org with LMTP;\n",
+///             " Tue, 1 Nov 1994 08:12:44 -0500\n",
+///             "Received: from mail.example.com (mail.example.com [192.0.2.1])\n",
+///             " by mx.example.org with ESMTP; Tue, 1 Nov 1994 08:12:34 -0500\n",
+///             "Subject: Test\n",
+///             "\n",
+///             "This is a test message").as_bytes())
+///         .unwrap();
+///     let hops = trace_path(&mail.headers);
+///     assert_eq!(hops[0].received.by, Some("mx.example.org".to_string()));
+///     assert_eq!(hops[0].delay, None);
+///     assert_eq!(hops[1].received.by, Some("imap.example.org".to_string()));
+///     assert_eq!(hops[1].delay, Some(10));
+/// ```
+pub fn trace_path(headers: &[MailHeader]) -> Vec<Hop> {
+    let mut hops: Vec<Hop> = Vec::new();
+    for value in headers.get_all_values("Received").iter().rev() {
+        let received = parse_received(value);
+        let delay = match (hops.last().and_then(|h| h.received.date), received.date) {
+            (Some(previous), Some(date)) => Some(date - previous),
+            _ => None,
+        };
+        hops.push(Hop { received, delay });
+    }
+    hops
+}
+
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified()
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                // An IPv4-mapped address (::ffff:a.b.c.d), as opposed to the
+                // deprecated IPv4-compatible ones that to_ipv4 also accepts.
+                || matches!(ip.to_ipv4(), Some(v4) if ip.segments()[5] == 0xffff
+                    && is_internal(IpAddr::V4(v4)))
+        }
+    }
+}
+
+/// Returns the first relay outside of the local infrastructure that handled
+/// the message, i.e. the client recorded by the most recent hop whose IP
+/// address is neither private, loopback or link-local, nor trusted
+/// according to `is_trusted`. This is usually the host whose reputation
+/// matters for spam filtering. `hops` must be in delivery order, as returned
+/// by `trace_path`.
+///
+/// # Examples
+/// ```
+///     use mailparse::{parse_mail, received::{first_external_relay, trace_path}};
+///     let mail = parse_mail(concat!(
+///             "Received: from mx.example.org ([10.0.0.5]) by imap.example.org;\n",
+///             " Tue, 1 Nov 1994 08:12:44 -0500\n",
+///             "Received: from mail.example.com ([192.0.2.1]) by mx.example.org;\n",
+///             " Tue, 1 Nov 1994 08:12:34 -0500\n",
+///             "Received: from laptop ([10.1.2.3]) by mail.example.com;\n",
+///             " Tue, 1 Nov 1994 08:12:30 -0500\n",
+///             "\n",
+///             "This is a test message").as_bytes())
+///         .unwrap();
+///     let hops = trace_path(&mail.headers);
+///     let relay = first_external_relay(&hops, |_| false).unwrap();
+///     assert_eq!(relay.received.ip, Some("192.0.2.1".parse().unwrap()));
+/// ```
+pub fn first_external_relay<F>(hops: &[Hop], is_trusted: F) -> Option<&Hop>
+where
+    F: Fn(IpAddr) -> bool,
+{
+    hops.iter().rev().find(|hop| match hop.received.ip {
+        Some(ip) => !is_internal(ip) && !is_trusted(ip),
+        None => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mail;
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    fn some(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn postfix_and_sendmail() {
+        let r = parse_received(
+            "from helo.example.com (unknown [192.0.2.7])\n\t(using TLSv1.3 with cipher \
+             TLS_AES_256_GCM_SHA384 (256/256 bits))\n\t(No client certificate requested)\n\t\
+             by mx.example.org (Postfix) with ESMTPS id 4Lq2Xk0Zz9z4x\n\t\
+             for <user@example.org>; Mon, 3 Oct 2022 10:00:00 +0000 (UTC)",
+        );
+        assert_eq!(r.from, some("helo.example.com"));
+        assert_eq!(r.helo, some("helo.example.com"));
+        assert_eq!(r.reverse_dns, None);
+        assert_eq!(r.ip, ip("192.0.2.7"));
+        assert_eq!(r.by, some("mx.example.org"));
+        assert_eq!(r.with, some("ESMTPS"));
+        assert_eq!(r.id, some("4Lq2Xk0Zz9z4x"));
+        assert_eq!(r.recipient, some("user@example.org"));
+        assert_eq!(r.date, Some(1664791200));
+
+        let r = parse_received(
+            "from helo (rdns.example.net [198.51.100.4] (may be forged))\n\t\
+             by host.example.org (8.15.2/8.15.2) with ESMTP id x93A0F1a012345;\n\t\
+             Mon, 3 Oct 2022 10:00:15 GMT",
+        );
+        assert_eq!(r.helo, some("helo"));
+        assert_eq!(r.reverse_dns, some("rdns.example.net"));
+        assert_eq!(r.ip, ip("198.51.100.4"));
+        assert_eq!(r.date, Some(1664791215));
+
+        let r = parse_received(
+            "by host.example.org (Postfix, from userid 1000)\n\tid 6B1C81A2; \
+             Mon, 3 Oct 2022 10:00:00 +0000",
+        );
+        assert_eq!(r.from, None);
+        assert_eq!(r.ip, None);
+        assert_eq!(r.by, some("host.example.org"));
+        assert_eq!(r.id, some("6B1C81A2"));
+    }
+
+    #[test]
+    fn exim_qmail_and_others() {
+        let r = parse_received(
+            "from [192.0.2.9] (helo=client.example.com)\n\tby mx.example.org with esmtpsa \
+             (TLS1.3) tls TLS_AES_256_GCM_SHA384\n\t(Exim 4.96)\n\t\
+             (envelope-from <a@example.com>)\n\tid 1ofBZ2-0003Xv-1F\n\tfor bob@example.org; \
+             Mon, 03 Oct 2022 10:00:00 +0000",
+        );
+        assert_eq!(r.from, some("[192.0.2.9]"));
+        assert_eq!(r.helo, some("client.example.com"));
+        assert_eq!(r.reverse_dns, None);
+        assert_eq!(r.ip, ip("192.0.2.9"));
+        assert_eq!(r.with, some("esmtpsa tls TLS_AES_256_GCM_SHA384"));
+        assert_eq!(r.id, some("1ofBZ2-0003Xv-1F"));
+        assert_eq!(r.recipient, some("bob@example.org"));
+
+        let r = parse_received(
+            "from rdns.example.com ([IPv6:2001:db8::25] helo=other.example.com) by mx",
+        );
+        assert_eq!(r.helo, some("other.example.com"));
+        assert_eq!(r.reverse_dns, some("rdns.example.com"));
+        assert_eq!(r.ip, ip("2001:db8::25"));
+        assert_eq!(r.date, None);
+
+        let r = parse_received(
+            "from unknown (HELO helo.example.com) (192.0.2.33)\n  by mail.example.org \
+             with SMTP; 3 Oct 2022 10:00:00 -0000",
+        );
+        assert_eq!(r.helo, some("helo.example.com"));
+        assert_eq!(r.reverse_dns, None);
+        assert_eq!(r.ip, ip("192.0.2.33"));
+
+        let r = parse_received(
+            "from mail-sor-f41.google.com (mail-sor-f41.google.com. [209.85.220.41])\n\
+             \x20       by mx.google.com with SMTPS id a1sor1234567qkb.12.2022.10.03.03.00.00\n\
+             \x20       for <bob@gmail.com>\n        (Google Transport Security);\n\
+             \x20       Mon, 03 Oct 2022 03:00:00 -0700 (PDT)",
+        );
+        assert_eq!(r.reverse_dns, some("mail-sor-f41.google.com"));
+        assert_eq!(r.ip, ip("209.85.220.41"));
+        assert_eq!(r.recipient, some("bob@gmail.com"));
+
+        let r = parse_received(
+            "from DM6PR01MB1234.namprd01.prod.outlook.com (2603:10b6:5:1b0::12) by \
+             BN6PR01MB5678.namprd01.prod.outlook.com with Microsoft SMTP Server \
+             (version=TLS1_2, cipher=TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384) id 15.20.5676.17 \
+             via Frontend Transport; Mon, 3 Oct 2022 10:00:00 +0000",
+        );
+        assert_eq!(r.ip, ip("2603:10b6:5:1b0::12"));
+        assert_eq!(r.with, some("Microsoft SMTP Server"));
+        assert_eq!(r.id, some("15.20.5676.17"));
+        assert_eq!(r.via, some("Frontend"));
+
+        assert_eq!(parse_received(""), Received::default());
+        assert_eq!(
+            parse_received("(qmail 1234 invoked by uid 0)"),
+            Received::default()
+        );
+        assert_eq!(parse_received("by mx; unknown").date, None);
+        assert_eq!(
+            parse_received("from x (unterminated [192.0.2.1]").ip,
+            ip("192.0.2.1")
+        );
+    }
+
+    #[test]
+    fn hops() {
+        let mail = parse_mail(
+            concat!(
+                "Received: from mx.example.org (mx.example.org [::ffff:10.0.0.5])\n",
+                " by imap.example.org with LMTP; Mon, 3 Oct 2022 10:01:00 +0000\n",
+                "Received: from relay.example.net (relay.example.net [198.51.100.4])\n",
+                " by mx.example.org with ESMTP; Mon, 3 Oct 2022 10:00:30 +0000\n",
+                "Received: from origin.example.com ([203.0.113.8])\n",
+                " by relay.example.net with ESMTP; Mon, 3 Oct 2022 10:00:00 +0000\n",
+                "Received: by origin.example.com (Postfix, from userid 1000);\n",
+                " Mon, 3 Oct 2022 10:00:00 +0000\n",
+                "\n",
+                "body\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let hops = trace_path(&mail.headers);
+        assert_eq!(hops.len(), 4);
+        assert_eq!(
+            hops.iter().map(|h| h.delay).collect::<Vec<_>>(),
+            vec![None, Some(0), Some(30), Some(30)]
+        );
+        assert_eq!(
+            first_external_relay(&hops, |_| false).unwrap().received.ip,
+            ip("198.51.100.4")
+        );
+        let trusted: IpAddr = "198.51.100.4".parse().unwrap();
+        assert_eq!(
+            first_external_relay(&hops, |ip| ip == trusted)
+                .unwrap()
+                .received
+                .ip,
+            ip("203.0.113.8")
+        );
+        assert!(first_external_relay(&hops[..1], |_| false).is_none());
+
+        assert!(is_internal("::ffff:192.168.1.1".parse().unwrap()));
+        assert!(!is_internal("::ffff:198.51.100.4".parse().unwrap()));
+        assert!(!is_internal("::c0a8:101".parse().unwrap()));
+    }
+}
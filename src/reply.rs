@@ -0,0 +1,633 @@
+use data_encoding::BASE64;
+
+use crate::{
+    addrparse_header, generate_message_id, to_crlf, MailAddr, MailHeader, MailHeaderMap,
+    MessageIdList, ParsedMail, ReplyMessageIds, SingleInfo,
+};
+
+/// The maximum number of IDs put into the `References` header of a reply.
+/// Longer chains are trimmed using `MessageIdList::trim_references`.
+const MAX_REFERENCES: usize = 20;
+
+/// How `ParsedMail::forward` includes the original message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForwardMode {
+    /// The text body of the original message is included in the body of
+    /// the forward, below a block with the original's main headers. Any
+    /// attachments of the original are not included.
+    Inline,
+    /// The original message is attached unchanged as a `message/rfc822`
+    /// part, and the body of the forward is left empty.
+    AsAttachment,
+}
+
+/// A new message prepared as a reply to or forward of an existing message,
+/// as returned by `ParsedMail::reply` and `ParsedMail::forward`. The fields
+/// can be adjusted as needed (e.g. to add text above the quoted body) before
+/// turning it into a message with `to_bytes`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Draft<'a> {
+    /// The primary recipients.
+    pub to: Vec<SingleInfo>,
+    /// The carbon-copy recipients.
+    pub cc: Vec<SingleInfo>,
+    /// The subject, with a "Re:" or "Fwd:" prefix.
+    pub subject: String,
+    /// The value of the `In-Reply-To` header; empty for forwards.
+    pub in_reply_to: MessageIdList,
+    /// The value of the `References` header.
+    pub references: MessageIdList,
+    /// The plain text body.
+    pub body: String,
+    /// The raw original message, to be attached as a `message/rfc822` part.
+    pub attachment: Option<&'a [u8]>,
+}
+
+/// Returns all the mailboxes in the given address headers, with groups
+/// flattened into their members. Headers that fail to parse are skipped.
+fn mailboxes(headers: &[MailHeader], names: &[&str]) -> Vec<SingleInfo> {
+    let mut result = Vec::new();
+    for name in names {
+        for header in headers.get_all_headers(name) {
+            let list = match addrparse_header(header) {
+                Ok(list) => list,
+                Err(_) => continue,
+            };
+            for addr in list.into_inner() {
+                match addr {
+                    MailAddr::Single(info) => result.push(info),
+                    MailAddr::Group(group) => result.extend(group.addrs),
+                }
+            }
+        }
+    }
+    result
+}
+
+fn contains_addr(list: &[SingleInfo], addr: &str) -> bool {
+    list.iter().any(|info| info.addr.eq_ignore_ascii_case(addr))
+}
+
+/// Removes duplicates and addresses matching `exclude` from `list`.
+fn dedup_addrs(list: Vec<SingleInfo>, exclude: &[SingleInfo]) -> Vec<SingleInfo> {
+    let mut result: Vec<SingleInfo> = Vec::new();
+    for info in list {
+        if !contains_addr(&result, &info.addr) && !contains_addr(exclude, &info.addr) {
+            result.push(info);
+        }
+    }
+    result
+}
+
+/// Adds `prefix` (e.g. "Re") to the subject unless it is already there.
+fn prefixed_subject(prefix: &str, subject: &str) -> String {
+    let subject = subject.trim();
+    let marked = subject
+        .get(..prefix.len() + 1)
+        .map(|head| head.eq_ignore_ascii_case(&format!("{}:", prefix)))
+        .unwrap_or(false);
+    if marked {
+        subject.to_string()
+    } else {
+        format!("{}: {}", prefix, subject)
+    }
+}
+
+/// Returns the text that represents the message body: the first text/plain
+/// part that is not an attachment, decoded. Returns an empty string if
+/// there is no such part.
+pub(crate) fn text_body(mail: &ParsedMail) -> String {
+    mail.parts()
+        .find(|part| {
+            part.ctype.mimetype == "text/plain"
+                && part.get_content_disposition().disposition != crate::DispositionType::Attachment
+        })
+        .and_then(|part| part.get_body().ok())
+        .unwrap_or_default()
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::new();
+    for line in text.lines() {
+        if line.is_empty() {
+            quoted.push_str(">\n");
+        } else {
+            quoted.push_str("> ");
+            quoted.push_str(line);
+            quoted.push('\n');
+        }
+    }
+    quoted
+}
+
+fn format_addrs(addrs: &[SingleInfo]) -> String {
+    addrs
+        .iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl<'a> ParsedMail<'a> {
+    /// Prepares a reply to this message. The reply goes to the addresses in
+    /// `Reply-To`, or `From` if there is none; replying to a message we sent
+    /// ourselves (i.e. whose sender is one of `own_addresses`) goes to its
+    /// original recipients instead. If `reply_all` is set, the reply goes to
+    /// the addresses in `Mail-Followup-To` if present, and otherwise the
+    /// original `To` and `Cc` recipients are added as `Cc`. Addresses in
+    /// `own_addresses` are never included, and are compared
+    /// case-insensitively.
+    ///
+    /// The subject gets a "Re:" prefix (unless it already has one), the
+    /// threading headers are set as described in RFC 5322 section 3.6.4 (see
+    /// `ReplyMessageIds`), and the body contains the text body of this
+    /// message with every line quoted with "> ", below an attribution line.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     let mail = parse_mail(concat!(
+    ///             "From: Alice <alice@example.com>\n",
+    ///             "To: bob@example.com, carol@example.com\n",
+    ///             "Subject: Lunch\n",
+    ///             "Message-ID: <1@example.com>\n",
+    ///             "\n",
+    ///             "Pizza?\n").as_bytes())
+    ///         .unwrap();
+    ///     let reply = mail.reply(true, &["bob@example.com"]);
+    ///     assert_eq!(reply.to[0].addr, "alice@example.com");
+    ///     assert_eq!(reply.cc.len(), 1);
+    ///     assert_eq!(reply.cc[0].addr, "carol@example.com");
+    ///     assert_eq!(reply.subject, "Re: Lunch");
+    ///     assert_eq!(reply.in_reply_to.to_string(), "<1@example.com>");
+    ///     assert_eq!(reply.body, "\"Alice\" <alice@example.com> wrote:\n> Pizza?\n");
+    /// ```
+    pub fn reply(&self, reply_all: bool, own_addresses: &[&str]) -> Draft<'a> {
+        let own: Vec<SingleInfo> = own_addresses
+            .iter()
+            .map(|addr| SingleInfo {
+                display_name: None,
+                addr: addr.to_string(),
+            })
+            .collect();
+        let from = mailboxes(&self.headers, &["From"]);
+        let reply_to = mailboxes(&self.headers, &["Reply-To"]);
+        let followup = mailboxes(&self.headers, &["Mail-Followup-To"]);
+
+        let (to, cc) = if reply_all && !followup.is_empty() {
+            (followup, Vec::new())
+        } else {
+            let to = if !reply_to.is_empty() {
+                reply_to
+            } else if !from.is_empty() && from.iter().all(|f| contains_addr(&own, &f.addr)) {
+                mailboxes(&self.headers, &["To"])
+            } else {
+                from.clone()
+            };
+            let cc = if reply_all {
+                mailboxes(&self.headers, &["To", "Cc"])
+            } else {
+                Vec::new()
+            };
+            (to, cc)
+        };
+        let mut to = dedup_addrs(to, &own);
+        let mut excluded = own;
+        excluded.extend(to.iter().cloned());
+        let mut cc = dedup_addrs(cc, &excluded);
+        if to.is_empty() {
+            std::mem::swap(&mut to, &mut cc);
+        }
+
+        let ids = ReplyMessageIds::from_headers(&self.headers);
+        let mut references = ids.references;
+        references.trim_references(MAX_REFERENCES);
+
+        let author = if from.is_empty() {
+            "Someone".to_string()
+        } else {
+            format_addrs(&from)
+        };
+        let attribution = match self.headers.get_first_value("Date") {
+            Some(date) => format!("On {}, {} wrote:\n", date.trim(), author),
+            None => format!("{} wrote:\n", author),
+        };
+        Draft {
+            to,
+            cc,
+            subject: prefixed_subject("Re", &self.subject()),
+            in_reply_to: ids.in_reply_to,
+            references,
+            body: attribution + &quote(&text_body(self)),
+            attachment: None,
+        }
+    }
+
+    /// Prepares a forward of this message, without any recipients. The
+    /// subject gets a "Fwd:" prefix (unless it already has one), and the
+    /// `References` header links the forward to this message's thread. See
+    /// `ForwardMode` for how the original message is included.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::{parse_mail, ForwardMode};
+    ///     let raw = concat!(
+    ///             "From: alice@example.com\n",
+    ///             "Subject: Lunch\n",
+    ///             "\n",
+    ///             "Pizza?\n");
+    ///     let mail = parse_mail(raw.as_bytes()).unwrap();
+    ///
+    ///     let forward = mail.forward(ForwardMode::Inline);
+    ///     assert_eq!(forward.subject, "Fwd: Lunch");
+    ///     assert!(forward.body.ends_with("\n\nPizza?\n"));
+    ///
+    ///     let forward = mail.forward(ForwardMode::AsAttachment);
+    ///     assert_eq!(forward.attachment, Some(raw.as_bytes()));
+    /// ```
+    pub fn forward(&self, mode: ForwardMode) -> Draft<'a> {
+        let mut references = ReplyMessageIds::from_headers(&self.headers).references;
+        references.trim_references(MAX_REFERENCES);
+        let (body, attachment) = match mode {
+            ForwardMode::Inline => {
+                let mut body = String::from("---------- Forwarded message ----------\n");
+                for &name in &["From", "Date", "Subject", "To", "Cc"] {
+                    if let Some(value) = self.headers.get_first_value(name) {
+                        body.push_str(&format!("{}: {}\n", name, value.trim()));
+                    }
+                }
+                body.push('\n');
+                body.push_str(&text_body(self));
+                (body, None)
+            }
+            ForwardMode::AsAttachment => (String::new(), Some(self.raw_bytes)),
+        };
+        Draft {
+            to: Vec::new(),
+            cc: Vec::new(),
+            subject: prefixed_subject("Fwd", &self.subject()),
+            in_reply_to: MessageIdList::default(),
+            references,
+            body,
+            attachment,
+        }
+    }
+
+    fn subject(&self) -> String {
+        self.headers.get_first_value("Subject").unwrap_or_default()
+    }
+}
+
+/// Encodes a header value as RFC 2047 encoded words if it is not plain
+/// ASCII. The value is split into words short enough that a header line
+/// holding one of them stays within the recommended 78 characters.
+pub(crate) fn encode_words(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in value.chars() {
+        if chunk.len() + c.len_utf8() > 39 {
+            words.push(format!("=?utf-8?B?{}?=", BASE64.encode(chunk.as_bytes())));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    if !chunk.is_empty() {
+        words.push(format!("=?utf-8?B?{}?=", BASE64.encode(chunk.as_bytes())));
+    }
+    words.join(" ")
+}
+
+pub(crate) fn encode_addr(info: &SingleInfo) -> String {
+    match &info.display_name {
+        Some(name) if !name.is_ascii() => format!("{} <{}>", encode_words(name), info.addr),
+        _ => info.to_string(),
+    }
+}
+
+/// Writes a header, folding it at existing whitespace to keep lines under 78
+/// characters where possible. The value is otherwise written unchanged, so
+/// unfolding the header gives back the original value.
+pub(crate) fn write_header(out: &mut Vec<u8>, name: &str, value: &str) {
+    let is_wsp = |c: char| c == ' ' || c == '\t';
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(b": ");
+    let mut line_len = name.len() + 2;
+    let mut rest = value;
+    while !rest.is_empty() {
+        // Each chunk is a run of whitespace followed by a word, so that a
+        // fold can be inserted in front of it.
+        let word_start = rest.len() - rest.trim_start_matches(is_wsp).len();
+        let end = rest[word_start..]
+            .find(is_wsp)
+            .map_or(rest.len(), |ix| word_start + ix);
+        let (chunk, tail) = rest.split_at(end);
+        if word_start > 0 && line_len + chunk.len() > 78 && line_len > name.len() + 2 {
+            out.extend_from_slice(b"\r\n");
+            line_len = 0;
+        }
+        out.extend_from_slice(chunk.as_bytes());
+        line_len += chunk.len();
+        rest = tail;
+    }
+    out.extend_from_slice(b"\r\n");
+}
+
+/// Returns the `Content-Transfer-Encoding` for a part whose body is `data`
+/// and must not be encoded, as for `message/rfc822` parts.
+fn identity_encoding(data: &[u8]) -> &'static str {
+    let long_line = data.split(|&b| b == b'\n').any(|line| {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        line.len() > 998
+    });
+    if long_line || data.contains(&0) {
+        "binary"
+    } else if data.is_ascii() {
+        "7bit"
+    } else {
+        "8bit"
+    }
+}
+
+/// Returns a new, unique MIME boundary.
+pub(crate) fn new_boundary() -> String {
+    let id = generate_message_id("boundary");
+    format!("=_{}", id.split('@').next().unwrap_or(""))
+}
+
+/// Returns the domain of `addr`, which new message IDs are generated with.
+pub(crate) fn message_id_domain(addr: &str) -> &str {
+    crate::rsplit_once(addr, '@').map_or("localhost", |(_, domain)| domain)
+}
+
+/// Appends `data`, converting bare LF line endings to CRLF.
+pub(crate) fn write_crlf(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&to_crlf(data));
+}
+
+impl Draft<'_> {
+    /// Turns the draft into a complete message from `from`, with CRLF line
+    /// endings, ready for submission. A new `Message-ID` is generated using
+    /// the domain of the `from` address. The body is sent as UTF-8 text, and
+    /// non-ASCII header values are encoded as RFC 2047 encoded words. No
+    /// `Date` header is added; the submission server adds it (RFC 6409
+    /// section 8.3), or it can be prepended by the caller.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::{parse_mail, MailHeaderMap, SingleInfo};
+    ///     let mail = parse_mail(concat!(
+    ///             "From: alice@example.com\n",
+    ///             "Subject: Lunch\n",
+    ///             "Message-ID: <1@example.com>\n",
+    ///             "\n",
+    ///             "Pizza?\n").as_bytes())
+    ///         .unwrap();
+    ///     let me = SingleInfo { display_name: None, addr: "bob@example.com".to_string() };
+    ///     let raw = mail.reply(false, &[]).to_bytes(&me);
+    ///
+    ///     let reply = parse_mail(&raw).unwrap();
+    ///     assert_eq!(reply.headers.get_first_value("To").unwrap(), "alice@example.com");
+    ///     assert_eq!(reply.headers.get_first_value("In-Reply-To").unwrap(), "<1@example.com>");
+    ///     assert!(reply.headers.get_first_value("Message-ID").unwrap().ends_with("@example.com>"));
+    /// ```
+    pub fn to_bytes(&self, from: &SingleInfo) -> Vec<u8> {
+        let domain = message_id_domain(&from.addr);
+        let message_id = MessageIdList::from(vec![generate_message_id(domain)]);
+        let encode_list =
+            |addrs: &[SingleInfo]| addrs.iter().map(encode_addr).collect::<Vec<_>>().join(", ");
+
+        let mut out = Vec::new();
+        write_header(&mut out, "From", &encode_addr(from));
+        if !self.to.is_empty() {
+            write_header(&mut out, "To", &encode_list(&self.to));
+        }
+        if !self.cc.is_empty() {
+            write_header(&mut out, "Cc", &encode_list(&self.cc));
+        }
+        write_header(&mut out, "Subject", &encode_words(&self.subject));
+        write_header(&mut out, "Message-ID", &message_id.to_string());
+        if !self.in_reply_to.is_empty() {
+            write_header(&mut out, "In-Reply-To", &self.in_reply_to.to_string());
+        }
+        if !self.references.is_empty() {
+            write_header(&mut out, "References", &self.references.to_string());
+        }
+        write_header(&mut out, "MIME-Version", "1.0");
+
+        let text_headers = "Content-Type: text/plain; charset=utf-8\r\n\
+                            Content-Transfer-Encoding: 8bit\r\n\r\n";
+        match self.attachment {
+            None => {
+                out.extend_from_slice(text_headers.as_bytes());
+                write_crlf(&mut out, self.body.as_bytes());
+            }
+            Some(original) => {
+                let boundary = new_boundary();
+                write_header(
+                    &mut out,
+                    "Content-Type",
+                    &format!("multipart/mixed; boundary=\"{}\"", boundary),
+                );
+                out.extend_from_slice(format!("\r\n--{}\r\n", boundary).as_bytes());
+                out.extend_from_slice(text_headers.as_bytes());
+                write_crlf(&mut out, self.body.as_bytes());
+                if !out.ends_with(b"\r\n") {
+                    out.extend_from_slice(b"\r\n");
+                }
+                out.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+                out.extend_from_slice(
+                    format!(
+                        "Content-Type: message/rfc822\r\n\
+                         Content-Transfer-Encoding: {}\r\n\
+                         Content-Disposition: attachment\r\n\r\n",
+                        identity_encoding(original)
+                    )
+                    .as_bytes(),
+                );
+                write_crlf(&mut out, original);
+                if !out.ends_with(b"\r\n") {
+                    out.extend_from_slice(b"\r\n");
+                }
+                out.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_mail, MailHeaderMap};
+
+    const MESSAGE: &str = concat!(
+        "From: Alice <alice@example.com>\n",
+        "To: Bob <BOB@example.com>, list: carol@example.com, dave@example.com;\n",
+        "Cc: alice@example.com, erin@example.com\n",
+        "Subject: Re: Lunch\n",
+        "Date: Mon, 1 Jan 2024 12:00:00 +0000\n",
+        "Message-ID: <2@example.com>\n",
+        "References: <1@example.com>\n",
+        "Content-Type: multipart/mixed; boundary=b\n",
+        "\n",
+        "--b\n",
+        "Content-Type: text/plain\n",
+        "\n",
+        "Pizza?\n",
+        "\n",
+        "> Lunch?\n",
+        "--b\n",
+        "Content-Type: text/plain; name=menu.txt\n",
+        "Content-Disposition: attachment\n",
+        "\n",
+        "Menu\n",
+        "--b--\n",
+    );
+
+    fn addrs(list: &[SingleInfo]) -> Vec<&str> {
+        list.iter().map(|a| a.addr.as_str()).collect()
+    }
+
+    #[test]
+    fn reply_recipients() {
+        let mail = parse_mail(MESSAGE.as_bytes()).unwrap();
+        let reply = mail.reply(false, &["bob@example.com"]);
+        assert_eq!(addrs(&reply.to), vec!["alice@example.com"]);
+        assert!(reply.cc.is_empty());
+
+        let reply = mail.reply(true, &["bob@example.com"]);
+        assert_eq!(addrs(&reply.to), vec!["alice@example.com"]);
+        assert_eq!(
+            addrs(&reply.cc),
+            vec!["carol@example.com", "dave@example.com", "erin@example.com"]
+        );
+
+        let with_reply_to = format!(
+            "Reply-To: team@example.com\nMail-Followup-To: list@example.com, bob@example.com\n{}",
+            MESSAGE
+        );
+        let mail = parse_mail(with_reply_to.as_bytes()).unwrap();
+        let reply = mail.reply(false, &["bob@example.com"]);
+        assert_eq!(addrs(&reply.to), vec!["team@example.com"]);
+        let reply = mail.reply(true, &["bob@example.com"]);
+        assert_eq!(addrs(&reply.to), vec!["list@example.com"]);
+        assert!(reply.cc.is_empty());
+
+        // Replying to our own message goes to its recipients.
+        let mail = parse_mail(MESSAGE.as_bytes()).unwrap();
+        let reply = mail.reply(false, &["Alice@example.com"]);
+        assert_eq!(
+            addrs(&reply.to),
+            vec!["BOB@example.com", "carol@example.com", "dave@example.com"]
+        );
+        let reply = mail.reply(true, &["alice@example.com"]);
+        assert_eq!(addrs(&reply.cc), vec!["erin@example.com"]);
+    }
+
+    #[test]
+    fn reply_content() {
+        let mail = parse_mail(MESSAGE.as_bytes()).unwrap();
+        let reply = mail.reply(false, &[]);
+        assert_eq!(reply.subject, "Re: Lunch");
+        assert_eq!(reply.in_reply_to.to_string(), "<2@example.com>");
+        assert_eq!(
+            reply.references.to_string(),
+            "<1@example.com> <2@example.com>"
+        );
+        assert_eq!(
+            reply.body,
+            concat!(
+                "On Mon, 1 Jan 2024 12:00:00 +0000, \"Alice\" <alice@example.com> wrote:\n",
+                "> Pizza?\n",
+                ">\n",
+                "> > Lunch?\n",
+            )
+        );
+        assert_eq!(prefixed_subject("Re", "RE: x"), "RE: x");
+        assert_eq!(prefixed_subject("Re", "Rex"), "Re: Rex");
+        assert_eq!(prefixed_subject("Fwd", "Re: x"), "Fwd: Re: x");
+    }
+
+    #[test]
+    fn forwards() {
+        let mail = parse_mail(MESSAGE.as_bytes()).unwrap();
+        let forward = mail.forward(ForwardMode::Inline);
+        assert!(forward.to.is_empty());
+        assert_eq!(forward.subject, "Fwd: Re: Lunch");
+        assert!(forward.in_reply_to.is_empty());
+        assert_eq!(
+            forward.references.to_string(),
+            "<1@example.com> <2@example.com>"
+        );
+        assert!(forward.body.starts_with("---------- Forwarded message"));
+        assert!(forward.body.contains("\nSubject: Re: Lunch\n"));
+        assert!(forward.body.ends_with("\n\nPizza?\n\n> Lunch?\n"));
+        assert_eq!(forward.attachment, None);
+
+        let forward = mail.forward(ForwardMode::AsAttachment);
+        assert_eq!(forward.body, "");
+        let me = SingleInfo {
+            display_name: Some("Bj\u{f6}rn".to_string()),
+            addr: "bjorn@example.org".to_string(),
+        };
+        let raw = forward.to_bytes(&me);
+        let sent = parse_mail(&raw).unwrap();
+        assert_eq!(
+            sent.headers.get_first_value("From").unwrap(),
+            "Bj\u{f6}rn <bjorn@example.org>"
+        );
+        assert_eq!(sent.subparts.len(), 2);
+        assert_eq!(sent.subparts[1].ctype.mimetype, "message/rfc822");
+        assert_eq!(
+            sent.subparts[1]
+                .headers
+                .get_first_value("Content-Transfer-Encoding")
+                .unwrap(),
+            "7bit"
+        );
+        let original = parse_mail(sent.subparts[1].get_body_raw().unwrap().as_slice())
+            .unwrap()
+            .subparts
+            .len();
+        assert_eq!(original, 2);
+        assert!(!raw.windows(2).any(|w| w[1] == b'\n' && w[0] != b'\r'));
+    }
+
+    #[test]
+    fn header_encoding() {
+        let mut out = Vec::new();
+        let subject = "\u{e9}".repeat(40);
+        write_header(&mut out, "Subject", &encode_words(&subject));
+        let (header, _) = crate::parse_header(&out).unwrap();
+        assert_eq!(header.get_value(), subject);
+        assert!(out.split(|&b| b == b'\n').all(|line| line.len() <= 78));
+    }
+
+    #[test]
+    fn header_folding() {
+        let mut out = Vec::new();
+        let value = format!(
+            "{}  keep\tthese   spaces {}",
+            "x".repeat(60),
+            "y".repeat(90)
+        );
+        write_header(&mut out, "X-Test", &value);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "X-Test: {}  keep\r\n\tthese   spaces\r\n {}\r\n",
+                "x".repeat(60),
+                "y".repeat(90)
+            )
+        );
+
+        assert_eq!(message_id_domain("a@b@example.com"), "example.com");
+        assert_eq!(message_id_domain("postmaster"), "localhost");
+        assert_eq!(identity_encoding(b"a\r\nb"), "7bit");
+        assert_eq!(identity_encoding("\u{e9}".as_bytes()), "8bit");
+        assert_eq!(identity_encoding(&[b'a'; 999]), "binary");
+    }
+}
@@ -0,0 +1,483 @@
+//! Detection of S/MIME parts as defined in IETF RFC 8551, and verification
+//! of their signatures.
+//!
+//! A signed message is usually a `multipart/signed` part whose first subpart
+//! is the signed content and whose second subpart is a detached CMS
+//! signature (`application/pkcs7-signature`). The signature covers the exact
+//! bytes of the first subpart, headers included, which
+//! `ParsedMail::smime_signed` returns. Encrypted messages, and signed
+//! messages that embed their content in the signature, are single
+//! `application/pkcs7-mime` parts; `ParsedMail::smime_type` tells them
+//! apart.
+//!
+//! Verifying signatures needs cryptographic dependencies, so it is only
+//! available with the `smime` feature. It supports RSA signatures with
+//! SHA-256, SHA-384 and SHA-512.
+
+use std::borrow::Cow;
+#[cfg(feature = "smime")]
+use std::fmt;
+use std::ops::Range;
+
+#[cfg(feature = "smime")]
+use cms::cert::IssuerAndSerialNumber;
+#[cfg(feature = "smime")]
+use cms::content_info::ContentInfo;
+#[cfg(feature = "smime")]
+use cms::signed_data::{SignedData, SignerIdentifier, SignerInfo};
+#[cfg(feature = "smime")]
+use der::asn1::OctetString;
+#[cfg(feature = "smime")]
+use der::oid::ObjectIdentifier;
+#[cfg(feature = "smime")]
+use der::{Decode, DecodePem, Encode};
+#[cfg(feature = "smime")]
+use rsa::pkcs8::DecodePublicKey;
+#[cfg(feature = "smime")]
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+#[cfg(feature = "smime")]
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+#[cfg(feature = "smime")]
+use crate::MailParseError;
+use crate::{to_crlf, ParsedMail};
+
+/// The type of CMS object in an `application/pkcs7-mime` part, from its
+/// `smime-type` parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmimeType {
+    /// A signed message that includes the signed content.
+    SignedData,
+    /// An encrypted message.
+    EnvelopedData,
+    /// An encrypted message using authenticated encryption (RFC 5083).
+    AuthEnvelopedData,
+    /// A compressed message.
+    CompressedData,
+    /// A message that only carries certificates.
+    CertsOnly,
+    /// The `smime-type` parameter is missing or unknown.
+    Unknown,
+}
+
+/// A signed `multipart/signed` S/MIME part.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SmimeSigned<'a> {
+    /// The exact bytes that were signed: the first subpart, headers
+    /// included, without the line break that precedes the boundary.
+    pub content: &'a [u8],
+    /// The range of `content` within the `raw_bytes` of the
+    /// `multipart/signed` part.
+    pub content_range: Range<usize>,
+    /// The DER-encoded detached CMS signature.
+    pub signature: Vec<u8>,
+    /// The digest algorithm announced by the `micalg` parameter, such as
+    /// `sha-256`.
+    pub micalg: Option<String>,
+}
+
+impl<'a> ParsedMail<'a> {
+    /// Returns the type of CMS object if this is an `application/pkcs7-mime`
+    /// part (or its older `application/x-pkcs7-mime` name), or `None` for
+    /// other parts. The DER-encoded object is the decoded body, as returned
+    /// by `get_body_raw`.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     use mailparse::smime::SmimeType;
+    ///     let mail = parse_mail(concat!(
+    ///             "Content-Type: application/pkcs7-mime; smime-type=enveloped-data;\n",
+    ///             " name=smime.p7m\n",
+    ///             "Content-Transfer-Encoding: base64\n",
+    ///             "\n",
+    ///             "MIAGCSqGSIb3DQEHA6CAMIACAQAx\n").as_bytes())
+    ///         .unwrap();
+    ///     assert_eq!(mail.smime_type(), Some(SmimeType::EnvelopedData));
+    /// ```
+    pub fn smime_type(&self) -> Option<SmimeType> {
+        if !matches!(
+            self.ctype.mimetype.as_str(),
+            "application/pkcs7-mime" | "application/x-pkcs7-mime"
+        ) {
+            return None;
+        }
+        let smime_type = self
+            .ctype
+            .params
+            .get("smime-type")
+            .map(|t| t.to_lowercase());
+        Some(match smime_type.as_deref() {
+            Some("signed-data") => SmimeType::SignedData,
+            Some("enveloped-data") => SmimeType::EnvelopedData,
+            Some("authenveloped-data") => SmimeType::AuthEnvelopedData,
+            Some("compressed-data") => SmimeType::CompressedData,
+            Some("certs-only") => SmimeType::CertsOnly,
+            _ => SmimeType::Unknown,
+        })
+    }
+
+    /// If this is a `multipart/signed` part with an S/MIME signature,
+    /// returns the signed content and the signature. Returns `None` for
+    /// other parts, including `multipart/signed` parts with other kinds of
+    /// signatures.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     let raw = concat!(
+    ///         "Content-Type: multipart/signed; protocol=\"application/pkcs7-signature\";\n",
+    ///         " micalg=sha-256; boundary=XX\n",
+    ///         "\n",
+    ///         "--XX\n",
+    ///         "Content-Type: text/plain\n",
+    ///         "\n",
+    ///         "Signed text\n",
+    ///         "--XX\n",
+    ///         "Content-Type: application/pkcs7-signature\n",
+    ///         "Content-Transfer-Encoding: base64\n",
+    ///         "\n",
+    ///         "MIAGCSqG\n",
+    ///         "--XX--\n");
+    ///     let mail = parse_mail(raw.as_bytes()).unwrap();
+    ///     let signed = mail.smime_signed().unwrap();
+    ///     assert_eq!(signed.content, b"Content-Type: text/plain\n\nSigned text");
+    ///     assert_eq!(&raw.as_bytes()[signed.content_range], signed.content);
+    ///     assert_eq!(signed.signature, vec![0x30, 0x80, 0x06, 0x09, 0x2a, 0x86]);
+    ///     assert_eq!(signed.micalg, Some("sha-256".to_string()));
+    /// ```
+    pub fn smime_signed(&self) -> Option<SmimeSigned<'a>> {
+        if self.ctype.mimetype != "multipart/signed" {
+            return None;
+        }
+        let protocol = self.ctype.params.get("protocol")?.to_lowercase();
+        if protocol != "application/pkcs7-signature" && protocol != "application/x-pkcs7-signature"
+        {
+            return None;
+        }
+        let signature_part = self.subparts.get(1)?;
+        if signature_part.ctype.mimetype != protocol {
+            return None;
+        }
+        let content_range = self.signed_content_range()?;
+        Some(SmimeSigned {
+            content: &self.raw_bytes[content_range.clone()],
+            content_range,
+            signature: signature_part.get_body_raw().ok()?,
+            micalg: self.ctype.params.get("micalg").map(|m| m.to_lowercase()),
+        })
+    }
+}
+
+/// An X.509 certificate to verify S/MIME signatures with. This is only
+/// available with the `smime` feature.
+#[cfg(feature = "smime")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Certificate(x509_cert::Certificate);
+
+#[cfg(feature = "smime")]
+impl Certificate {
+    /// Parses a DER-encoded certificate.
+    pub fn from_der(der: &[u8]) -> Result<Certificate, MailParseError> {
+        x509_cert::Certificate::from_der(der)
+            .map(Certificate)
+            .map_err(|_| MailParseError::Generic("Invalid certificate"))
+    }
+
+    /// Parses a PEM-encoded certificate.
+    pub fn from_pem(pem: &str) -> Result<Certificate, MailParseError> {
+        x509_cert::Certificate::from_pem(pem)
+            .map(Certificate)
+            .map_err(|_| MailParseError::Generic("Invalid certificate"))
+    }
+
+    /// Returns the subject of the certificate, as an RFC 4514 string such
+    /// as `CN=Alice Example`.
+    pub fn subject(&self) -> String {
+        self.0.tbs_certificate.subject.to_string()
+    }
+
+    /// Returns true if the certificate is the one identified by a signer.
+    fn identifies(&self, sid: &SignerIdentifier) -> bool {
+        let tbs = &self.0.tbs_certificate;
+        match sid {
+            SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+                issuer,
+                serial_number,
+            }) => tbs.issuer == *issuer && tbs.serial_number == *serial_number,
+            SignerIdentifier::SubjectKeyIdentifier(ski) => tbs
+                .extensions
+                .iter()
+                .flatten()
+                .filter(|ext| ext.extn_id == SUBJECT_KEY_IDENTIFIER)
+                .any(|ext| {
+                    matches!(
+                        OctetString::from_der(ext.extn_value.as_bytes()),
+                        Ok(id) if id.as_bytes() == ski.0.as_bytes()
+                    )
+                }),
+        }
+    }
+}
+
+/// The reason an S/MIME signature could not be verified. This is only
+/// available with the `smime` feature.
+#[cfg(feature = "smime")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SmimeFailure {
+    /// The signature is not a valid CMS `SignedData` object.
+    InvalidSignature(&'static str),
+    /// The signature uses an unsupported algorithm, given by its OID.
+    UnsupportedAlgorithm(String),
+    /// None of the given certificates is that of a signer.
+    CertificateNotFound,
+    /// The signed content does not match the digest in the signature, i.e.
+    /// it was modified.
+    DigestMismatch,
+    /// The signature does not match.
+    SignatureMismatch,
+}
+
+#[cfg(feature = "smime")]
+impl fmt::Display for SmimeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SmimeFailure::InvalidSignature(reason) => f.write_str(reason),
+            SmimeFailure::UnsupportedAlgorithm(oid) => write!(f, "unsupported algorithm {}", oid),
+            SmimeFailure::CertificateNotFound => f.write_str("no certificate for signer"),
+            SmimeFailure::DigestMismatch => f.write_str("content digest did not verify"),
+            SmimeFailure::SignatureMismatch => f.write_str("signature did not verify"),
+        }
+    }
+}
+
+#[cfg(feature = "smime")]
+const SIGNED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.2");
+#[cfg(feature = "smime")]
+const MESSAGE_DIGEST: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.4");
+#[cfg(feature = "smime")]
+const SUBJECT_KEY_IDENTIFIER: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.14");
+#[cfg(feature = "smime")]
+const SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+#[cfg(feature = "smime")]
+const SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+#[cfg(feature = "smime")]
+const SHA512: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.3");
+/// `rsaEncryption` and `sha{256,384,512}WithRSAEncryption`.
+#[cfg(feature = "smime")]
+const RSA_SIGNATURES: &[&str] = &[
+    "1.2.840.113549.1.1.1",
+    "1.2.840.113549.1.1.11",
+    "1.2.840.113549.1.1.12",
+    "1.2.840.113549.1.1.13",
+];
+
+#[cfg(feature = "smime")]
+fn digest(algorithm: ObjectIdentifier, data: &[u8]) -> Result<Vec<u8>, SmimeFailure> {
+    Ok(match algorithm {
+        SHA256 => Sha256::digest(data).to_vec(),
+        SHA384 => Sha384::digest(data).to_vec(),
+        SHA512 => Sha512::digest(data).to_vec(),
+        _ => return Err(SmimeFailure::UnsupportedAlgorithm(algorithm.to_string())),
+    })
+}
+
+#[cfg(feature = "smime")]
+fn verify_signer(
+    signer: &SignerInfo,
+    certificate: &Certificate,
+    content: &[u8],
+) -> Result<(), SmimeFailure> {
+    let digest_algorithm = signer.digest_alg.oid;
+    let content_digest = digest(digest_algorithm, content)?;
+    let signed = match &signer.signed_attrs {
+        // The signature covers the signed attributes, which must include
+        // the digest of the content (RFC 5652 section 5.4).
+        Some(attrs) => {
+            let digest_attr = attrs
+                .iter()
+                .find(|attr| attr.oid == MESSAGE_DIGEST)
+                .and_then(|attr| attr.values.get(0))
+                .and_then(|value| value.decode_as::<OctetString>().ok())
+                .ok_or(SmimeFailure::InvalidSignature(
+                    "Missing message-digest attribute",
+                ))?;
+            if digest_attr.as_bytes() != content_digest.as_slice() {
+                return Err(SmimeFailure::DigestMismatch);
+            }
+            attrs
+                .to_der()
+                .map_err(|_| SmimeFailure::InvalidSignature("Invalid signed attributes"))?
+        }
+        None => content.to_vec(),
+    };
+
+    let algorithm = signer.signature_algorithm.oid.to_string();
+    if !RSA_SIGNATURES.contains(&algorithm.as_str()) {
+        return Err(SmimeFailure::UnsupportedAlgorithm(algorithm));
+    }
+    let spki = certificate
+        .0
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|_| SmimeFailure::InvalidSignature("Invalid certificate key"))?;
+    let key = RsaPublicKey::from_public_key_der(&spki)
+        .map_err(|_| SmimeFailure::UnsupportedAlgorithm(algorithm.clone()))?;
+    let hash = digest(digest_algorithm, &signed)?;
+    let scheme = match digest_algorithm {
+        SHA256 => Pkcs1v15Sign::new::<Sha256>(),
+        SHA384 => Pkcs1v15Sign::new::<Sha384>(),
+        _ => Pkcs1v15Sign::new::<Sha512>(),
+    };
+    key.verify(scheme, &hash, signer.signature.as_bytes())
+        .map_err(|_| SmimeFailure::SignatureMismatch)
+}
+
+impl<'a> SmimeSigned<'a> {
+    /// Returns the signed content with its line endings converted to CRLF,
+    /// the canonical form that the signature is computed over. Messages
+    /// stored with LF line endings must be converted back before checking
+    /// their signatures.
+    pub fn canonical_content(&self) -> Cow<'a, [u8]> {
+        to_crlf(self.content)
+    }
+
+    #[cfg(feature = "smime")]
+    /// Verifies the signature against the given certificates, returning the
+    /// certificate of the signer. If there are several signers, the first
+    /// one that verifies wins. Only the signature is checked: deciding
+    /// whether to trust the certificate, e.g. by validating its chain or
+    /// matching its address with the `From` header, is left to the caller.
+    /// This is only available with the `smime` feature.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     use mailparse::smime::{Certificate, SmimeFailure};
+    ///     # let raw = std::fs::read("tests/files/smime/signed.eml").unwrap();
+    ///     # let alice_pem = std::fs::read_to_string("tests/files/smime/alice.pem").unwrap();
+    ///     # let bob_pem = std::fs::read_to_string("tests/files/smime/bob.pem").unwrap();
+    ///     let alice = Certificate::from_pem(&alice_pem).unwrap();
+    ///     let bob = Certificate::from_pem(&bob_pem).unwrap();
+    ///     let mail = parse_mail(&raw).unwrap();
+    ///     let signed = mail.smime_signed().unwrap();
+    ///     let certificates = [bob.clone(), alice];
+    ///     let signer = signed.verify(&certificates).unwrap();
+    ///     assert!(signer.subject().contains("CN=Alice Example"));
+    ///     assert_eq!(signed.verify(&[bob]), Err(SmimeFailure::CertificateNotFound));
+    /// ```
+    pub fn verify<'c>(
+        &self,
+        certificates: &'c [Certificate],
+    ) -> Result<&'c Certificate, SmimeFailure> {
+        let invalid = SmimeFailure::InvalidSignature("Invalid CMS signature");
+        let content_info = ContentInfo::from_der(&self.signature).map_err(|_| invalid.clone())?;
+        if content_info.content_type != SIGNED_DATA {
+            return Err(SmimeFailure::InvalidSignature(
+                "The CMS object is not SignedData",
+            ));
+        }
+        let signed_data: SignedData = content_info
+            .content
+            .decode_as()
+            .map_err(|_| invalid.clone())?;
+        let content = self.canonical_content();
+
+        let mut failure = SmimeFailure::CertificateNotFound;
+        for signer in signed_data.signer_infos.0.iter() {
+            let certificate = match certificates.iter().find(|c| c.identifies(&signer.sid)) {
+                Some(certificate) => certificate,
+                None => continue,
+            };
+            match verify_signer(signer, certificate, &content) {
+                Ok(()) => return Ok(certificate),
+                Err(e) if failure == SmimeFailure::CertificateNotFound => failure = e,
+                Err(_) => {}
+            }
+        }
+        Err(failure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mail;
+
+    fn read(name: &str) -> Vec<u8> {
+        let path = format!("./tests/files/smime/{}", name);
+        std::fs::read(&path).unwrap_or_else(|_| panic!("Unable to open the file [{}]", path))
+    }
+
+    #[test]
+    fn detect() {
+        let raw = read("signed.eml");
+        let mail = parse_mail(&raw).unwrap();
+        assert_eq!(mail.smime_type(), None);
+        let signed = mail.smime_signed().unwrap();
+        assert_eq!(
+            signed.content,
+            &b"Content-Type: text/plain; charset=us-ascii\r\n\
+               Content-Transfer-Encoding: 7bit\r\n\
+               \r\n\
+               Hi Bob,\r\n\
+               \r\n\
+               The release is signed.\r\n\
+               \r\n\
+               Alice\r\n"[..]
+        );
+        assert_eq!(&raw[signed.content_range.clone()], signed.content);
+        assert_eq!(signed.signature[0], 0x30);
+        assert_eq!(signed.micalg, Some("sha-256".to_string()));
+        assert_eq!(mail.subparts[0].smime_signed(), None);
+
+        let raw = read("encrypted.eml");
+        let mail = parse_mail(&raw).unwrap();
+        assert_eq!(mail.smime_type(), Some(SmimeType::EnvelopedData));
+        assert_eq!(mail.smime_signed(), None);
+
+        let mail = parse_mail(b"Content-Type: application/pkcs7-mime\n\nMIA=\n").unwrap();
+        assert_eq!(mail.smime_type(), Some(SmimeType::Unknown));
+    }
+
+    #[cfg(feature = "smime")]
+    #[test]
+    fn verify() {
+        let alice = Certificate::from_pem(&String::from_utf8(read("alice.pem")).unwrap()).unwrap();
+        let bob = Certificate::from_pem(&String::from_utf8(read("bob.pem")).unwrap()).unwrap();
+        let certificates = [bob.clone(), alice.clone()];
+        let raw = String::from_utf8(read("signed.eml")).unwrap();
+
+        let mail = parse_mail(raw.as_bytes()).unwrap();
+        let signed = mail.smime_signed().unwrap();
+        assert_eq!(signed.verify(&certificates), Ok(&alice));
+        assert_eq!(
+            signed.verify(std::slice::from_ref(&bob)),
+            Err(SmimeFailure::CertificateNotFound)
+        );
+
+        // Line endings are canonicalized before hashing.
+        let lf = raw.replace("\r\n", "\n");
+        let mail = parse_mail(lf.as_bytes()).unwrap();
+        assert_eq!(
+            mail.smime_signed().unwrap().verify(&certificates),
+            Ok(&alice)
+        );
+
+        let modified = raw.replace("The release", "The forged release");
+        let mail = parse_mail(modified.as_bytes()).unwrap();
+        assert_eq!(
+            mail.smime_signed().unwrap().verify(&certificates),
+            Err(SmimeFailure::DigestMismatch)
+        );
+
+        let mut signed = signed.clone();
+        signed.signature.truncate(20);
+        assert!(matches!(
+            signed.verify(&certificates),
+            Err(SmimeFailure::InvalidSignature(_))
+        ));
+        assert!(Certificate::from_pem("junk").is_err());
+    }
+}
@@ -0,0 +1,894 @@
+//! Evaluation of Sender Policy Framework (SPF) records as defined in IETF
+//! RFC 7208.
+//!
+//! SPF lets a domain publish the hosts that may send mail using it in the
+//! `MAIL FROM` (envelope sender) or `HELO` identity of an SMTP session. The
+//! check therefore needs the connection information from the session rather
+//! than the message itself. The DNS lookups are done through a
+//! `dns::Resolver`.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::authres::AuthResult;
+use crate::dns::Resolver;
+use crate::{rsplit_once, split_once};
+
+/// The maximum number of terms that cause DNS lookups, from RFC 7208
+/// section 4.6.4.
+const MAX_LOOKUPS: usize = 10;
+/// The maximum number of lookups that may return no records.
+const MAX_VOID_LOOKUPS: usize = 2;
+/// The maximum number of MX or PTR names to look at for one mechanism.
+const MAX_NAMES: usize = 10;
+
+/// Information about the SMTP session in which a message was received.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// The IP address of the SMTP client.
+    pub ip: IpAddr,
+    /// The domain given by the client in the `HELO` or `EHLO` command.
+    pub helo: String,
+    /// The address given in the `MAIL FROM` command, without the angle
+    /// brackets. This is empty for bounces.
+    pub mail_from: String,
+}
+
+/// The outcome of an SPF check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpfVerification {
+    /// The result: `Pass`, `Fail`, `SoftFail`, `Neutral`, `None`,
+    /// `TempError` or `PermError`.
+    pub result: AuthResult,
+    /// The domain whose policy was checked.
+    pub domain: String,
+    /// The directive that determined the result, such as `-all`, or the
+    /// reason for a `TempError` or `PermError`.
+    pub reason: Option<String>,
+    /// For a `Fail`, the explanation published by the domain (with an
+    /// `exp=` modifier), if any.
+    pub explanation: Option<String>,
+}
+
+/// Checks the `MAIL FROM` identity of an SMTP session, or the `HELO`
+/// identity for messages with a null sender, as recommended by RFC 7208
+/// section 2.4.
+///
+/// # Examples
+/// ```
+///     use mailparse::authres::AuthResult;
+///     use mailparse::dns::InMemoryResolver;
+///     use mailparse::spf::{check_spf, ConnectionInfo};
+///     let mut zone = InMemoryResolver::new();
+///     zone.add_txt("example.com", "v=spf1 ip4:192.0.2.0/24 -all");
+///     let mut conn = ConnectionInfo {
+///         ip: "192.0.2.25".parse().unwrap(),
+///         helo: "mail.example.com".to_string(),
+///         mail_from: "alice@example.com".to_string(),
+///     };
+///     let spf = check_spf(&zone, &conn);
+///     assert_eq!(spf.result, AuthResult::Pass);
+///     assert_eq!(spf.domain, "example.com");
+///
+///     conn.ip = "198.51.100.1".parse().unwrap();
+///     let spf = check_spf(&zone, &conn);
+///     assert_eq!(spf.result, AuthResult::Fail);
+///     assert_eq!(spf.reason, Some("-all".to_string()));
+/// ```
+pub fn check_spf<R: Resolver + ?Sized>(resolver: &R, conn: &ConnectionInfo) -> SpfVerification {
+    let mail_from = conn.mail_from.trim();
+    let (sender, domain) = if mail_from.is_empty() {
+        (format!("postmaster@{}", conn.helo), conn.helo.as_str())
+    } else {
+        match rsplit_once(mail_from, '@') {
+            Some((_, domain)) => (mail_from.to_string(), domain),
+            None => (format!("postmaster@{}", mail_from), mail_from),
+        }
+    };
+    check_host(resolver, conn.ip, domain, &sender, &conn.helo)
+}
+
+/// Evaluates the SPF policy of `domain` for a message from `sender` sent
+/// by the client at `ip` that introduced itself as `helo`. This is the
+/// `check_host()` function of RFC 7208 section 4; use it directly to check
+/// the `HELO` identity on its own.
+pub fn check_host<R: Resolver + ?Sized>(
+    resolver: &R,
+    ip: IpAddr,
+    domain: &str,
+    sender: &str,
+    helo: &str,
+) -> SpfVerification {
+    let mut evaluation = Evaluation {
+        resolver,
+        ip,
+        sender,
+        helo,
+        lookups: 0,
+        void_lookups: 0,
+    };
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    let (result, reason, explanation) = match evaluation.check_host(&domain) {
+        Ok(outcome) => (outcome.result, outcome.directive, outcome.explanation),
+        Err(Abort::Temp(reason)) => (AuthResult::TempError, Some(reason), None),
+        Err(Abort::Perm(reason)) => (AuthResult::PermError, Some(reason), None),
+    };
+    SpfVerification {
+        result,
+        domain,
+        reason,
+        explanation,
+    }
+}
+
+/// A condition that ends the evaluation with a `TempError` or `PermError`.
+enum Abort {
+    Temp(String),
+    Perm(String),
+}
+
+/// The outcome of evaluating one record.
+struct Outcome {
+    result: AuthResult,
+    directive: Option<String>,
+    explanation: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Mechanism {
+    All,
+    Include(String),
+    A(Option<String>, u8, u8),
+    Mx(Option<String>, u8, u8),
+    Ptr(Option<String>),
+    Ip4(Ipv4Addr, u8),
+    Ip6(Ipv6Addr, u8),
+    Exists(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Directive {
+    qualifier: AuthResult,
+    mechanism: Mechanism,
+    text: String,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Record {
+    directives: Vec<Directive>,
+    redirect: Option<String>,
+    exp: Option<String>,
+}
+
+/// Parses the `/ip4-cidr-length` and `//ip6-cidr-length` suffix of an `a`
+/// or `mx` mechanism.
+fn parse_dual_cidr(s: &str) -> Option<(u8, u8)> {
+    let (v4, v6) = match s.find("//") {
+        Some(ix) => (&s[..ix], Some(&s[ix + 2..])),
+        None => (s, None),
+    };
+    let v4 = match v4.strip_prefix('/') {
+        Some(len) => len.parse().ok().filter(|&len| len <= 32)?,
+        None if v4.is_empty() => 32,
+        None => return None,
+    };
+    let v6 = match v6 {
+        Some(len) => len.parse().ok().filter(|&len| len <= 128)?,
+        None => 128,
+    };
+    Some((v4, v6))
+}
+
+/// Splits the argument of an `a`, `mx` or `ptr` mechanism into the domain
+/// spec and the CIDR suffix.
+fn split_domain_spec(arg: &str) -> (Option<String>, &str) {
+    let (spec, cidr) = match arg.strip_prefix(':') {
+        Some(rest) => match rest.find('/') {
+            Some(ix) => (Some(&rest[..ix]), &rest[ix..]),
+            None => (Some(rest), ""),
+        },
+        None => (None, arg),
+    };
+    (spec.map(String::from), cidr)
+}
+
+/// Splits an `ip4` or `ip6` argument into the address and prefix length.
+fn split_network(arg: &str, max_len: u8) -> Option<(&str, u8)> {
+    let arg = arg.strip_prefix(':')?;
+    match split_once(arg, '/') {
+        Some((ip, len)) => Some((ip, len.parse().ok().filter(|&len| len <= max_len)?)),
+        None => Some((arg, max_len)),
+    }
+}
+
+fn parse_record(record: &str) -> Result<Record, String> {
+    let mut parsed = Record::default();
+    // The version has already been checked by the caller.
+    for term in record.split_whitespace().skip(1) {
+        let error = || format!("Invalid term {} in SPF record", term);
+        let name_end = term.find([':', '/', '=']).unwrap_or(term.len());
+        if term[name_end..].starts_with('=') {
+            let (name, value) = (&term[..name_end], &term[name_end + 1..]);
+            if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                return Err(error());
+            }
+            check_macro_string(value, false).ok_or_else(error)?;
+            let slot = match name.to_lowercase().as_str() {
+                "redirect" => &mut parsed.redirect,
+                "exp" => &mut parsed.exp,
+                // Unknown modifiers are ignored (RFC 7208 section 6).
+                _ => continue,
+            };
+            if slot.is_some() {
+                return Err(format!("Duplicate {} modifier in SPF record", name));
+            }
+            *slot = Some(value.to_string());
+            continue;
+        }
+
+        let (qualifier, rest) = match term.chars().next() {
+            Some('+') => (AuthResult::Pass, &term[1..]),
+            Some('-') => (AuthResult::Fail, &term[1..]),
+            Some('~') => (AuthResult::SoftFail, &term[1..]),
+            Some('?') => (AuthResult::Neutral, &term[1..]),
+            _ => (AuthResult::Pass, term),
+        };
+        let name_end = rest.find([':', '/']).unwrap_or(rest.len());
+        let (name, arg) = (rest[..name_end].to_lowercase(), &rest[name_end..]);
+        let domain_spec = |arg: &str| -> Result<String, String> {
+            let spec = arg.strip_prefix(':').ok_or_else(error)?;
+            check_macro_string(spec, false).ok_or_else(error)?;
+            Ok(spec.to_string())
+        };
+        let mechanism = match name.as_str() {
+            "all" if arg.is_empty() => Mechanism::All,
+            "include" => Mechanism::Include(domain_spec(arg)?),
+            "exists" => Mechanism::Exists(domain_spec(arg)?),
+            "a" | "mx" => {
+                let (spec, cidr) = split_domain_spec(arg);
+                if let Some(spec) = &spec {
+                    check_macro_string(spec, false).ok_or_else(error)?;
+                }
+                let (v4, v6) = parse_dual_cidr(cidr).ok_or_else(error)?;
+                if name == "a" {
+                    Mechanism::A(spec, v4, v6)
+                } else {
+                    Mechanism::Mx(spec, v4, v6)
+                }
+            }
+            "ptr" => match split_domain_spec(arg) {
+                (spec, "") => {
+                    if let Some(spec) = &spec {
+                        check_macro_string(spec, false).ok_or_else(error)?;
+                    }
+                    Mechanism::Ptr(spec)
+                }
+                _ => return Err(error()),
+            },
+            "ip4" => {
+                let (ip, len) = split_network(arg, 32).ok_or_else(error)?;
+                Mechanism::Ip4(ip.parse().map_err(|_| error())?, len)
+            }
+            "ip6" => {
+                let (ip, len) = split_network(arg, 128).ok_or_else(error)?;
+                Mechanism::Ip6(ip.parse().map_err(|_| error())?, len)
+            }
+            _ => return Err(error()),
+        };
+        parsed.directives.push(Directive {
+            qualifier,
+            mechanism,
+            text: term.to_string(),
+        });
+    }
+    Ok(parsed)
+}
+
+/// Returns true if the IP address is in the network given by its address
+/// and prefix length.
+fn in_network(ip: IpAddr, network: IpAddr, len: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(len)).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(len)).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if the name is a syntactically valid multi-label domain.
+fn is_valid_domain(domain: &str) -> bool {
+    domain.len() <= 253
+        && domain.contains('.')
+        && domain
+            .split('.')
+            .all(|label| !label.is_empty() && label.len() <= 63)
+}
+
+/// Expands a macro string (RFC 7208 section 7), getting the value of each
+/// macro letter from `value`. Returns `None` for invalid syntax, including
+/// the letters that are only allowed in explanations if `explanation` is
+/// false.
+fn expand_macro_string(
+    s: &str,
+    explanation: bool,
+    value: &mut dyn FnMut(char) -> String,
+) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            // Explanations may also contain spaces.
+            if !(c.is_ascii_graphic() || explanation && c == ' ') {
+                return None;
+            }
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '%' => out.push('%'),
+            '_' => out.push(' '),
+            '-' => out.push_str("%20"),
+            '{' => {
+                let letter = chars.next()?;
+                let allowed = match letter.to_ascii_lowercase() {
+                    's' | 'l' | 'o' | 'd' | 'i' | 'p' | 'v' | 'h' => true,
+                    'c' | 'r' | 't' => explanation,
+                    _ => false,
+                };
+                if !allowed {
+                    return None;
+                }
+                let mut spec = String::new();
+                loop {
+                    match chars.next()? {
+                        '}' => break,
+                        c => spec.push(c),
+                    }
+                }
+                let digits_end = spec
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(spec.len());
+                let keep: Option<usize> = match &spec[..digits_end] {
+                    "" => None,
+                    digits => Some(digits.parse().ok().filter(|&n| n > 0)?),
+                };
+                let mut rest = &spec[digits_end..];
+                let reverse = rest.starts_with(['r', 'R']);
+                if reverse {
+                    rest = &rest[1..];
+                }
+                if !rest.chars().all(|c| ".-+,/_=".contains(c)) {
+                    return None;
+                }
+                let delimiters: Vec<char> = if rest.is_empty() {
+                    vec!['.']
+                } else {
+                    rest.chars().collect()
+                };
+
+                let raw = value(letter.to_ascii_lowercase());
+                let mut parts: Vec<&str> = raw.split(&delimiters[..]).collect();
+                if reverse {
+                    parts.reverse();
+                }
+                if let Some(keep) = keep {
+                    if keep < parts.len() {
+                        parts.drain(..parts.len() - keep);
+                    }
+                }
+                let expanded = parts.join(".");
+                if letter.is_ascii_uppercase() {
+                    for b in expanded.bytes() {
+                        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                            out.push(b as char);
+                        } else {
+                            out.push_str(&format!("%{:02X}", b));
+                        }
+                    }
+                } else {
+                    out.push_str(&expanded);
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// Checks the syntax of a macro string.
+fn check_macro_string(s: &str, explanation: bool) -> Option<()> {
+    expand_macro_string(s, explanation, &mut |_| String::new()).map(|_| ())
+}
+
+/// The state of an SPF evaluation, which is shared by nested evaluations of
+/// `include` and `redirect` targets so that the limits apply to the whole.
+struct Evaluation<'a, R: Resolver + ?Sized> {
+    resolver: &'a R,
+    ip: IpAddr,
+    sender: &'a str,
+    helo: &'a str,
+    lookups: usize,
+    void_lookups: usize,
+}
+
+impl<'a, R: Resolver + ?Sized> Evaluation<'a, R> {
+    fn count_lookup(&mut self) -> Result<(), Abort> {
+        self.lookups += 1;
+        if self.lookups > MAX_LOOKUPS {
+            return Err(Abort::Perm("Too many DNS lookups".to_string()));
+        }
+        Ok(())
+    }
+
+    fn count_void<T>(&mut self, records: &[T]) -> Result<(), Abort> {
+        if records.is_empty() {
+            self.void_lookups += 1;
+            if self.void_lookups > MAX_VOID_LOOKUPS {
+                return Err(Abort::Perm("Too many void DNS lookups".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the addresses of `name` in the same family as the client.
+    fn lookup_ips(&self, name: &str) -> Result<Vec<IpAddr>, Abort> {
+        let result = match self.ip {
+            IpAddr::V4(_) => self
+                .resolver
+                .lookup_a(name)
+                .map(|ips| ips.into_iter().map(IpAddr::V4).collect()),
+            IpAddr::V6(_) => self
+                .resolver
+                .lookup_aaaa(name)
+                .map(|ips| ips.into_iter().map(IpAddr::V6).collect()),
+        };
+        result.map_err(|e| Abort::Temp(e.to_string()))
+    }
+
+    /// Returns the PTR names of the client that map back to its address.
+    fn validated_names(&self) -> Vec<String> {
+        let names = self.resolver.lookup_ptr(self.ip).unwrap_or_default();
+        names
+            .into_iter()
+            .take(MAX_NAMES)
+            .map(|name| name.trim_end_matches('.').to_lowercase())
+            .filter(|name| matches!(self.lookup_ips(name), Ok(ips) if ips.contains(&self.ip)))
+            .collect()
+    }
+
+    fn expand(&self, s: &str, domain: &str, explanation: bool) -> Option<String> {
+        let (local, sender_domain) = rsplit_once(self.sender, '@').unwrap_or(("postmaster", ""));
+        expand_macro_string(s, explanation, &mut |letter| match letter {
+            's' => self.sender.to_string(),
+            'l' => local.to_string(),
+            'o' => sender_domain.to_string(),
+            'd' => domain.to_string(),
+            'i' => match self.ip {
+                IpAddr::V4(ip) => ip.to_string(),
+                IpAddr::V6(ip) => {
+                    let hex = format!("{:032x}", u128::from(ip));
+                    let nibbles: Vec<String> = hex.chars().map(String::from).collect();
+                    nibbles.join(".")
+                }
+            },
+            'p' => {
+                let names = self.validated_names();
+                let suffix = format!(".{}", domain);
+                names
+                    .iter()
+                    .find(|n| *n == domain || n.ends_with(&suffix))
+                    .or_else(|| names.first())
+                    .cloned()
+                    .unwrap_or_else(|| "unknown".to_string())
+            }
+            'v' => match self.ip {
+                IpAddr::V4(_) => "in-addr".to_string(),
+                IpAddr::V6(_) => "ip6".to_string(),
+            },
+            'h' => self.helo.to_string(),
+            'c' => self.ip.to_string(),
+            't' => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+                .to_string(),
+            _ => "unknown".to_string(),
+        })
+    }
+
+    /// Expands a domain spec, shortening the result to the maximum length
+    /// of a domain name (RFC 7208 section 7.3).
+    fn target(&self, spec: Option<&str>, domain: &str) -> Result<String, Abort> {
+        let spec = match spec {
+            Some(spec) => spec,
+            None => return Ok(domain.to_string()),
+        };
+        let mut target = self
+            .expand(spec, domain, false)
+            .ok_or_else(|| Abort::Perm(format!("Invalid domain spec {}", spec)))?;
+        while target.len() > 253 {
+            match target.find('.') {
+                Some(ix) => target.drain(..=ix),
+                None => break,
+            };
+        }
+        Ok(target.trim_end_matches('.').to_lowercase())
+    }
+
+    fn check_host(&mut self, domain: &str) -> Result<Outcome, Abort> {
+        let none = Outcome {
+            result: AuthResult::None,
+            directive: None,
+            explanation: None,
+        };
+        if !is_valid_domain(domain) {
+            return Ok(none);
+        }
+        let records = self
+            .resolver
+            .lookup_txt(domain)
+            .map_err(|e| Abort::Temp(e.to_string()))?;
+        let mut records = records.into_iter().filter(|r| {
+            let mut words = r.split_whitespace();
+            matches!(words.next(), Some(v) if v.eq_ignore_ascii_case("v=spf1"))
+        });
+        let record = match (records.next(), records.next()) {
+            (None, _) => return Ok(none),
+            (Some(record), None) => record,
+            (Some(_), Some(_)) => {
+                return Err(Abort::Perm(format!("Multiple SPF records for {}", domain)))
+            }
+        };
+        let record = parse_record(&record).map_err(Abort::Perm)?;
+
+        for directive in &record.directives {
+            if self.matches(&directive.mechanism, domain)? {
+                let explanation = match (&directive.qualifier, &record.exp) {
+                    (AuthResult::Fail, Some(exp)) => self.explanation(exp, domain),
+                    _ => None,
+                };
+                return Ok(Outcome {
+                    result: directive.qualifier.clone(),
+                    directive: Some(directive.text.clone()),
+                    explanation,
+                });
+            }
+        }
+
+        if let Some(redirect) = &record.redirect {
+            self.count_lookup()?;
+            let target = self.target(Some(redirect), domain)?;
+            let outcome = self.check_host(&target)?;
+            if outcome.result == AuthResult::None {
+                return Err(Abort::Perm(format!("No SPF record for {}", target)));
+            }
+            return Ok(outcome);
+        }
+        Ok(Outcome {
+            result: AuthResult::Neutral,
+            directive: None,
+            explanation: None,
+        })
+    }
+
+    fn matches(&mut self, mechanism: &Mechanism, domain: &str) -> Result<bool, Abort> {
+        let matches = match mechanism {
+            Mechanism::All => true,
+            Mechanism::Ip4(network, len) => in_network(self.ip, IpAddr::V4(*network), *len),
+            Mechanism::Ip6(network, len) => in_network(self.ip, IpAddr::V6(*network), *len),
+            Mechanism::Include(spec) => {
+                self.count_lookup()?;
+                let target = self.target(Some(spec), domain)?;
+                match self.check_host(&target)?.result {
+                    AuthResult::Pass => true,
+                    AuthResult::Fail | AuthResult::SoftFail | AuthResult::Neutral => false,
+                    _ => return Err(Abort::Perm(format!("No SPF record for {}", target))),
+                }
+            }
+            Mechanism::A(spec, v4, v6) => {
+                self.count_lookup()?;
+                let target = self.target(spec.as_deref(), domain)?;
+                let ips = self.lookup_ips(&target)?;
+                self.count_void(&ips)?;
+                let len = if self.ip.is_ipv4() { *v4 } else { *v6 };
+                ips.iter().any(|ip| in_network(self.ip, *ip, len))
+            }
+            Mechanism::Mx(spec, v4, v6) => {
+                self.count_lookup()?;
+                let target = self.target(spec.as_deref(), domain)?;
+                let hosts = self
+                    .resolver
+                    .lookup_mx(&target)
+                    .map_err(|e| Abort::Temp(e.to_string()))?;
+                self.count_void(&hosts)?;
+                if hosts.len() > MAX_NAMES {
+                    return Err(Abort::Perm(format!("Too many MX records for {}", target)));
+                }
+                let len = if self.ip.is_ipv4() { *v4 } else { *v6 };
+                let mut found = false;
+                for (_, host) in &hosts {
+                    if self
+                        .lookup_ips(host)?
+                        .iter()
+                        .any(|ip| in_network(self.ip, *ip, len))
+                    {
+                        found = true;
+                        break;
+                    }
+                }
+                found
+            }
+            Mechanism::Ptr(spec) => {
+                self.count_lookup()?;
+                let target = self.target(spec.as_deref(), domain)?;
+                let suffix = format!(".{}", target);
+                self.validated_names()
+                    .iter()
+                    .any(|name| *name == target || name.ends_with(&suffix))
+            }
+            Mechanism::Exists(spec) => {
+                self.count_lookup()?;
+                let target = self.target(Some(spec), domain)?;
+                // This always uses an A lookup, whatever the client address.
+                let ips = self
+                    .resolver
+                    .lookup_a(&target)
+                    .map_err(|e| Abort::Temp(e.to_string()))?;
+                self.count_void(&ips)?;
+                !ips.is_empty()
+            }
+        };
+        Ok(matches)
+    }
+
+    /// Computes the explanation for a `Fail` from the `exp=` modifier. Any
+    /// problem just means there is no explanation (RFC 7208 section 6.2).
+    fn explanation(&self, exp: &str, domain: &str) -> Option<String> {
+        let target = self.target(Some(exp), domain).ok()?;
+        let records = self.resolver.lookup_txt(&target).ok()?;
+        match records.as_slice() {
+            [record] => self.expand(record, domain, true),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{InMemoryResolver, LookupError};
+
+    fn check(zone: &InMemoryResolver, ip: &str, sender: &str) -> SpfVerification {
+        let conn = ConnectionInfo {
+            ip: ip.parse().unwrap(),
+            helo: "mail.example.org".to_string(),
+            mail_from: sender.to_string(),
+        };
+        check_spf(zone, &conn)
+    }
+
+    #[test]
+    fn parse() {
+        let record = parse_record("v=spf1 a/24//64 -mx:%{d}.example.com ?ptr ~ip4:192.0.2.0/24 ip6:2001:db8::/32 include:_spf.example.com exp=explain.%{d} unknown=x -all").unwrap();
+        assert_eq!(record.directives[0].mechanism, Mechanism::A(None, 24, 64));
+        assert_eq!(
+            record.directives[1].mechanism,
+            Mechanism::Mx(Some("%{d}.example.com".to_string()), 32, 128)
+        );
+        assert_eq!(record.directives[1].qualifier, AuthResult::Fail);
+        assert_eq!(record.directives[2].qualifier, AuthResult::Neutral);
+        assert_eq!(
+            record.directives[3].mechanism,
+            Mechanism::Ip4("192.0.2.0".parse().unwrap(), 24)
+        );
+        assert_eq!(
+            record.directives[4].mechanism,
+            Mechanism::Ip6("2001:db8::".parse().unwrap(), 32)
+        );
+        assert_eq!(record.directives.len(), 7);
+        assert_eq!(record.exp, Some("explain.%{d}".to_string()));
+        assert_eq!(record.redirect, None);
+
+        assert!(parse_record("v=spf1 ip4:192.0.2.0/33").is_err());
+        assert!(parse_record("v=spf1 a:example.com/24/").is_err());
+        assert!(parse_record("v=spf1 foo").is_err());
+        assert!(parse_record("v=spf1 all:x").is_err());
+        assert!(parse_record("v=spf1 include").is_err());
+        assert!(parse_record("v=spf1 redirect=a.com redirect=b.com").is_err());
+        assert!(parse_record("v=spf1 exists:%{x}").is_err());
+        assert!(parse_record("v=spf1 exists:%{c}").is_err());
+    }
+
+    #[test]
+    fn macros() {
+        // The examples from RFC 7208 section 7.4.
+        let zone = InMemoryResolver::new();
+        let evaluation = Evaluation {
+            resolver: &zone,
+            ip: "192.0.2.3".parse().unwrap(),
+            sender: "strong-bad@email.example.com",
+            helo: "mx.example.org",
+            lookups: 0,
+            void_lookups: 0,
+        };
+        let expand = |s: &str| evaluation.expand(s, "email.example.com", true).unwrap();
+        assert_eq!(expand("%{s}"), "strong-bad@email.example.com");
+        assert_eq!(expand("%{o}"), "email.example.com");
+        assert_eq!(expand("%{d4}"), "email.example.com");
+        assert_eq!(expand("%{d3}"), "email.example.com");
+        assert_eq!(expand("%{d2}"), "example.com");
+        assert_eq!(expand("%{d1}"), "com");
+        assert_eq!(expand("%{dr}"), "com.example.email");
+        assert_eq!(expand("%{d2r}"), "example.email");
+        assert_eq!(expand("%{l}"), "strong-bad");
+        assert_eq!(expand("%{l-}"), "strong.bad");
+        assert_eq!(expand("%{lr}"), "strong-bad");
+        assert_eq!(expand("%{lr-}"), "bad.strong");
+        assert_eq!(expand("%{l1r-}"), "strong");
+        assert_eq!(
+            expand("%{ir}.%{v}._spf.%{d2}"),
+            "3.2.0.192.in-addr._spf.example.com"
+        );
+        assert_eq!(
+            expand("%{lr-}.lp._spf.%{d2}"),
+            "bad.strong.lp._spf.example.com"
+        );
+        assert_eq!(expand("%{S}"), "strong-bad%40email.example.com");
+        assert_eq!(expand("%%%_%-"), "% %20");
+        assert_eq!(expand("%{p}"), "unknown");
+
+        let evaluation = Evaluation {
+            ip: "2001:db8::cb01".parse().unwrap(),
+            ..evaluation
+        };
+        assert_eq!(
+            evaluation.expand("%{ir}.%{v}._spf.%{d2}", "email.example.com", false),
+            Some(
+                "1.0.b.c.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6._spf.example.com"
+                    .to_string()
+            )
+        );
+        assert_eq!(evaluation.expand("%{d0}", "example.com", false), None);
+        assert_eq!(evaluation.expand("%{d", "example.com", false), None);
+        assert_eq!(evaluation.expand("%x", "example.com", false), None);
+    }
+
+    #[test]
+    fn mechanisms() {
+        let mut zone = InMemoryResolver::new();
+        zone.add_txt(
+            "example.com",
+            "v=spf1 ip6:2001:db8::/64 a:web.example.com/28 mx include:_spf.example.net -all",
+        );
+        zone.add_ip("web.example.com", "192.0.2.16".parse().unwrap());
+        zone.add_mx("example.com", 10, "mx.example.com");
+        zone.add_ip("mx.example.com", "198.51.100.7".parse().unwrap());
+        zone.add_txt("_spf.example.net", "v=spf1 ip4:203.0.113.0/24 ?all");
+        zone.add_txt("other.example.com", "unrelated");
+        zone.add_txt(
+            "other.example.com",
+            "v=spf1 ptr:example.com exists:%{l}.allowed.%{d} ~all",
+        );
+        zone.add_ptr("192.0.2.99".parse().unwrap(), "host.example.com.");
+        zone.add_ip("host.example.com", "192.0.2.99".parse().unwrap());
+        zone.add_ptr("192.0.2.98".parse().unwrap(), "forged.example.com");
+        zone.add_ip(
+            "bob.allowed.other.example.com",
+            "127.0.0.2".parse().unwrap(),
+        );
+
+        let result = |ip: &str, sender: &str| check(&zone, ip, sender).result;
+        assert_eq!(result("2001:db8::1", "alice@example.com"), AuthResult::Pass);
+        assert_eq!(
+            result("2001:db8:1::1", "alice@example.com"),
+            AuthResult::Fail
+        );
+        assert_eq!(result("192.0.2.31", "alice@example.com"), AuthResult::Pass);
+        assert_eq!(result("192.0.2.32", "alice@example.com"), AuthResult::Fail);
+        assert_eq!(
+            result("198.51.100.7", "alice@example.com"),
+            AuthResult::Pass
+        );
+        assert_eq!(result("203.0.113.5", "alice@Example.COM"), AuthResult::Pass);
+
+        assert_eq!(
+            result("192.0.2.99", "x@other.example.com"),
+            AuthResult::Pass
+        );
+        assert_eq!(
+            result("192.0.2.98", "x@other.example.com"),
+            AuthResult::SoftFail
+        );
+        assert_eq!(
+            result("192.0.2.1", "bob@other.example.com"),
+            AuthResult::Pass
+        );
+
+        let spf = check(&zone, "192.0.2.32", "alice@example.com");
+        assert_eq!(spf.reason, Some("-all".to_string()));
+        let spf = check(&zone, "192.0.2.1", "");
+        assert_eq!(spf.result, AuthResult::None);
+        assert_eq!(spf.domain, "mail.example.org");
+        assert_eq!(result("192.0.2.1", "alice@localhost"), AuthResult::None);
+    }
+
+    #[test]
+    fn modifiers_and_errors() {
+        let mut zone = InMemoryResolver::new();
+        zone.add_txt("example.com", "v=spf1 redirect=_spf.example.com");
+        zone.add_txt(
+            "_spf.example.com",
+            "v=spf1 ip4:192.0.2.1 -all exp=explain._spf.%{d}",
+        );
+        zone.add_txt(
+            "explain._spf._spf.example.com",
+            "%{i} is not one of %{d}'s designated mail servers.",
+        );
+        zone.add_txt("neutral.example.com", "v=spf1 ip4:192.0.2.1");
+        zone.add_txt("twice.example.com", "v=spf1 -all");
+        zone.add_txt("twice.example.com", "v=spf1 +all");
+        zone.add_txt("broken.example.com", "v=spf1 include:nowhere.example.com");
+        zone.add_txt("loop.example.com", "v=spf1 include:loop.example.com");
+        zone.add_txt(
+            "void.example.com",
+            "v=spf1 a:a.example.com a:b.example.com a:c.example.com",
+        );
+
+        assert_eq!(
+            check(&zone, "192.0.2.1", "a@example.com").result,
+            AuthResult::Pass
+        );
+        let spf = check(&zone, "192.0.2.2", "a@example.com");
+        assert_eq!(spf.result, AuthResult::Fail);
+        assert_eq!(
+            spf.explanation,
+            Some("192.0.2.2 is not one of _spf.example.com's designated mail servers.".to_string())
+        );
+        assert_eq!(
+            check(&zone, "192.0.2.2", "a@neutral.example.com").result,
+            AuthResult::Neutral
+        );
+        assert_eq!(
+            check(&zone, "192.0.2.2", "a@twice.example.com").result,
+            AuthResult::PermError
+        );
+        assert_eq!(
+            check(&zone, "192.0.2.2", "a@broken.example.com").result,
+            AuthResult::PermError
+        );
+        let spf = check(&zone, "192.0.2.2", "a@loop.example.com");
+        assert_eq!(spf.result, AuthResult::PermError);
+        assert_eq!(spf.reason, Some("Too many DNS lookups".to_string()));
+        let spf = check(&zone, "192.0.2.2", "a@void.example.com");
+        assert_eq!(spf.reason, Some("Too many void DNS lookups".to_string()));
+
+        struct Failing;
+        impl Resolver for Failing {
+            fn lookup_txt(&self, _: &str) -> Result<Vec<String>, LookupError> {
+                Err(LookupError("timeout".to_string()))
+            }
+            fn lookup_a(&self, _: &str) -> Result<Vec<Ipv4Addr>, LookupError> {
+                Err(LookupError("timeout".to_string()))
+            }
+            fn lookup_aaaa(&self, _: &str) -> Result<Vec<Ipv6Addr>, LookupError> {
+                Err(LookupError("timeout".to_string()))
+            }
+            fn lookup_mx(&self, _: &str) -> Result<Vec<(u16, String)>, LookupError> {
+                Err(LookupError("timeout".to_string()))
+            }
+            fn lookup_ptr(&self, _: IpAddr) -> Result<Vec<String>, LookupError> {
+                Err(LookupError("timeout".to_string()))
+            }
+        }
+        let conn = ConnectionInfo {
+            ip: "192.0.2.1".parse().unwrap(),
+            helo: "mail.example.org".to_string(),
+            mail_from: "a@example.com".to_string(),
+        };
+        assert_eq!(check_spf(&Failing, &conn).result, AuthResult::TempError);
+    }
+}
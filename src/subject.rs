@@ -0,0 +1,325 @@
+/// The reply and forward prefixes recognized by RFC 5256.
+const RFC5256_PREFIXES: &[&str] = &["re", "fw", "fwd"];
+
+/// Commonly used localized reply and forward prefixes. Prefixes consisting
+/// of a single letter (such as the Italian "R:" and "I:") are left out since
+/// they are too likely to be part of a real subject; they can be added with
+/// `SubjectPrefixes::add` if needed.
+const LOCALIZED_PREFIXES: &[&str] = &[
+    // Replies
+    "aw",   // German
+    "sv",   // Danish, Norwegian, Swedish
+    "vs",   // Finnish, Icelandic
+    "antw", // Dutch
+    "odp",  // Polish
+    "rif",  // Italian
+    "res",  // Portuguese
+    "ref",  // various
+    "vá",   // Hungarian
+    "ynt",  // Turkish
+    "atb",  // Latvian
+    "ats",  // Lithuanian
+    "odg",  // Croatian, Serbian
+    "bls",  // Indonesian
+    "отв",  // Russian
+    "відп", // Ukrainian
+    "απ",   // Greek
+    "σχετ", // Greek
+    "回复", // Chinese (simplified)
+    "回覆", // Chinese (traditional)
+    "答复", // Chinese (simplified)
+    // Forwards
+    "wg",         // German
+    "vl",         // Finnish
+    "tr",         // French
+    "rv",         // Spanish
+    "enc",        // Portuguese
+    "pd",         // Polish
+    "doorst",     // Dutch
+    "vb",         // Dutch
+    "továbbítás", // Hungarian
+    "ilt",        // Turkish
+    "pārs",       // Latvian
+    "przek",      // Polish
+    "пересл",     // Russian
+    "πρθ",        // Greek
+    "转发",       // Chinese (simplified)
+    "轉寄",       // Chinese (traditional)
+];
+
+/// A table of prefixes that mark a subject as a reply or forward, such as the
+/// "Re" in "Re: Lunch". Prefixes are matched case-insensitively and must be
+/// followed by a colon (optionally preceded by whitespace and a bracketed
+/// blob, as in "Re[2]:"). The default table contains the prefixes from RFC
+/// 5256 as well as commonly used localized ones like "AW", "SV" and "Antw".
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubjectPrefixes {
+    prefixes: Vec<String>,
+}
+
+impl Default for SubjectPrefixes {
+    fn default() -> Self {
+        let mut prefixes = Self::rfc5256();
+        for prefix in LOCALIZED_PREFIXES {
+            prefixes.add(prefix);
+        }
+        prefixes
+    }
+}
+
+impl SubjectPrefixes {
+    /// Returns a table with just the prefixes that RFC 5256 recognizes,
+    /// i.e. "Re", "Fw" and "Fwd".
+    pub fn rfc5256() -> Self {
+        SubjectPrefixes {
+            prefixes: RFC5256_PREFIXES.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    /// Adds a prefix to the table. The prefix should not include the colon.
+    pub fn add(&mut self, prefix: &str) {
+        let prefix = prefix.to_lowercase();
+        if !prefix.is_empty() && !self.prefixes.contains(&prefix) {
+            self.prefixes.push(prefix);
+        }
+    }
+
+    /// Returns the prefixes in the table, lowercased.
+    pub fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+
+    /// If `s` starts with a reply/forward prefix followed by an optional
+    /// bracketed blob and a colon, returns what follows the colon.
+    fn strip<'s>(&self, s: &'s str) -> Option<&'s str> {
+        for prefix in &self.prefixes {
+            let rest = match strip_prefix_ignore_case(s, prefix) {
+                Some(rest) => rest.trim_start_matches(is_wsp),
+                None => continue,
+            };
+            let rest = strip_blob(rest).unwrap_or(rest);
+            if let Some(rest) = rest.strip_prefix(':').or_else(|| rest.strip_prefix('：')) {
+                return Some(rest);
+            }
+        }
+        None
+    }
+}
+
+/// The result of extracting the base subject from a subject.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BaseSubject {
+    /// The base subject, with reply/forward markers, list tags and redundant
+    /// whitespace removed. Letter case is preserved.
+    pub subject: String,
+    /// True if markers indicating a reply or forward were removed.
+    pub is_reply_or_forward: bool,
+}
+
+const fn is_wsp(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+fn strip_prefix_ignore_case<'s>(s: &'s str, prefix: &str) -> Option<&'s str> {
+    let mut chars = s.char_indices();
+    for expected in prefix.chars() {
+        let (_, c) = chars.next()?;
+        if !c.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(chars.next().map_or("", |(ix, _)| &s[ix..]))
+}
+
+/// If `s` starts with a subj-blob (a bracketed string like "[list-name]"
+/// followed by optional whitespace), returns what follows it.
+fn strip_blob(s: &str) -> Option<&str> {
+    let rest = s.strip_prefix('[')?;
+    let end = rest.find([']', '['])?;
+    if rest[end..].starts_with('[') {
+        return None;
+    }
+    Some(rest[end + 1..].trim_start_matches(is_wsp))
+}
+
+/// Extracts the base subject of a subject as described in RFC 5256 section
+/// 2.1, using the default table of reply/forward prefixes (which includes
+/// localized ones). The base subject is what remains after removing
+/// reply/forward markers like "Re:" and "(fwd)", mailing list tags like
+/// "[list-name]", and redundant whitespace, and is suitable for grouping
+/// messages of the same conversation. The input should already have had its
+/// encoded words decoded, so pass it the output of `MailHeader::get_value`.
+///
+/// # Examples
+/// ```
+///     use mailparse::{base_subject, parse_header};
+///     let (header, _) = parse_header(
+///             b"Subject: =?utf-8?Q?AW:_[dev]_Re:_Caf=C3=A9?= (fwd)").unwrap();
+///     let base = base_subject(&header.get_value());
+///     assert_eq!(base.subject, "Caf\u{e9}");
+///     assert!(base.is_reply_or_forward);
+///
+///     assert_eq!(base_subject("[Fwd: Lunch?]").subject, "Lunch?");
+///     assert!(!base_subject("[announce] Release 1.0").is_reply_or_forward);
+/// ```
+pub fn base_subject(subject: &str) -> BaseSubject {
+    base_subject_with_prefixes(subject, &SubjectPrefixes::default())
+}
+
+/// Like `base_subject`, but with a custom table of reply/forward prefixes.
+///
+/// # Examples
+/// ```
+///     use mailparse::{base_subject_with_prefixes, SubjectPrefixes};
+///     let rfc = SubjectPrefixes::rfc5256();
+///     assert_eq!(base_subject_with_prefixes("AW: Lunch", &rfc).subject, "AW: Lunch");
+///
+///     let mut custom = SubjectPrefixes::rfc5256();
+///     custom.add("Odp");
+///     assert_eq!(base_subject_with_prefixes("ODP: Re: Lunch", &custom).subject, "Lunch");
+/// ```
+pub fn base_subject_with_prefixes(subject: &str, prefixes: &SubjectPrefixes) -> BaseSubject {
+    // Step 1: normalize whitespace (tabs and line continuations become
+    // spaces, and runs of spaces are collapsed).
+    let mut s = subject
+        .split([' ', '\t', '\r', '\n'])
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut is_reply_or_forward = false;
+
+    loop {
+        // Step 2: remove trailing "(fwd)" markers and whitespace.
+        let mut end = s.len();
+        loop {
+            let trimmed = s[..end].trim_end_matches(is_wsp);
+            let lower_tail = trimmed
+                .get(trimmed.len().saturating_sub(5)..)
+                .map(str::to_ascii_lowercase);
+            if lower_tail.as_deref() == Some("(fwd)") {
+                end = trimmed.len() - 5;
+                is_reply_or_forward = true;
+            } else {
+                end = trimmed.len();
+                break;
+            }
+        }
+        let mut rest = &s[..end];
+
+        // Steps 3 to 5: remove leading reply/forward markers, list tags
+        // and whitespace.
+        loop {
+            let mut changed = false;
+            // Step 3: subj-leader = (*subj-blob subj-refwd) / WSP
+            loop {
+                let mut candidate = rest;
+                while let Some(r) = strip_blob(candidate) {
+                    candidate = r;
+                }
+                if let Some(r) = prefixes.strip(candidate) {
+                    rest = r;
+                    is_reply_or_forward = true;
+                } else if rest.starts_with(is_wsp) {
+                    rest = rest.trim_start_matches(is_wsp);
+                } else {
+                    break;
+                }
+                changed = true;
+            }
+            // Step 4: remove a leading subj-blob if that leaves something.
+            if let Some(r) = strip_blob(rest) {
+                if !r.is_empty() {
+                    rest = r;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        // Step 6: unwrap "[fwd: ...]" and start over.
+        let lower_head = rest.get(..5).map(str::to_ascii_lowercase);
+        if lower_head.as_deref() == Some("[fwd:") && rest.ends_with(']') {
+            s = rest[5..rest.len() - 1].to_string();
+            is_reply_or_forward = true;
+            continue;
+        }
+        return BaseSubject {
+            subject: rest.to_string(),
+            is_reply_or_forward,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base(s: &str) -> (String, bool) {
+        let b = base_subject(s);
+        (b.subject, b.is_reply_or_forward)
+    }
+
+    #[test]
+    fn rfc5256_base_subjects() {
+        assert_eq!(base("Lunch"), ("Lunch".to_string(), false));
+        assert_eq!(
+            base("  Lunch \t  today  "),
+            ("Lunch today".to_string(), false)
+        );
+        assert_eq!(base("Re: Lunch"), ("Lunch".to_string(), true));
+        assert_eq!(base("RE:Re: re : Lunch"), ("Lunch".to_string(), true));
+        assert_eq!(base("Fw: Fwd: FW: Lunch"), ("Lunch".to_string(), true));
+        assert_eq!(base("Re[2]: Lunch"), ("Lunch".to_string(), true));
+        assert_eq!(base("Re [2] : Lunch"), ("Lunch".to_string(), true));
+        assert_eq!(base("Lunch (fwd)"), ("Lunch".to_string(), true));
+        assert_eq!(base("Lunch (FWD) (fwd) "), ("Lunch".to_string(), true));
+        assert_eq!(base("[list] Lunch"), ("Lunch".to_string(), false));
+        assert_eq!(base("[list] Re: [list] Lunch"), ("Lunch".to_string(), true));
+        assert_eq!(base("[Fwd: Re: Lunch]"), ("Lunch".to_string(), true));
+        assert_eq!(base("Re: [Fwd: Lunch (fwd)]"), ("Lunch".to_string(), true));
+
+        // A blob that is all there is gets kept.
+        assert_eq!(base("[list]"), ("[list]".to_string(), false));
+        assert_eq!(base("Re: [list]"), ("[list]".to_string(), true));
+        // Nested or unbalanced brackets aren't blobs.
+        assert_eq!(
+            base("[a [b] c] Lunch"),
+            ("[a [b] c] Lunch".to_string(), false)
+        );
+        assert_eq!(
+            base("[unclosed Lunch"),
+            ("[unclosed Lunch".to_string(), false)
+        );
+        // Prefixes must be followed by a colon.
+        assert_eq!(base("Rent: due"), ("Rent: due".to_string(), false));
+        assert_eq!(base("Fwd Lunch"), ("Fwd Lunch".to_string(), false));
+        assert_eq!(base("Re:"), ("".to_string(), true));
+        assert_eq!(base(""), ("".to_string(), false));
+    }
+
+    #[test]
+    fn localized_prefixes() {
+        assert_eq!(base("AW: Mittagessen"), ("Mittagessen".to_string(), true));
+        assert_eq!(base("SV: Sv: Lunsj"), ("Lunsj".to_string(), true));
+        assert_eq!(base("Antw: WG: Lunch"), ("Lunch".to_string(), true));
+        assert_eq!(base("Vá: Ebéd"), ("Ebéd".to_string(), true));
+        assert_eq!(base("ОТВ: Обед"), ("Обед".to_string(), true));
+        assert_eq!(base("回复：午饭"), ("午饭".to_string(), true));
+
+        let rfc = SubjectPrefixes::rfc5256();
+        assert_eq!(
+            base_subject_with_prefixes("AW: Re: Lunch", &rfc).subject,
+            "AW: Re: Lunch"
+        );
+        let mut custom = SubjectPrefixes::rfc5256();
+        custom.add("R");
+        custom.add("r");
+        assert_eq!(custom.prefixes(), &["re", "fw", "fwd", "r"]);
+        assert_eq!(
+            base_subject_with_prefixes("R: I: Pranzo", &custom).subject,
+            "I: Pranzo"
+        );
+    }
+}
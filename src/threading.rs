@@ -0,0 +1,608 @@
+//! Grouping of messages into conversation threads.
+//!
+//! This implements the threading algorithm described by Jamie Zawinski at
+//! <https://www.jwz.org/doc/threading.html>, as well as the REFERENCES and
+//! ORDEREDSUBJECT algorithms from IETF RFC 5256 (which are used by the IMAP
+//! THREAD command). All of them operate on a list of `ThreadMessage` items
+//! and produce a forest of `ThreadNode` trees.
+
+use std::collections::HashMap;
+
+use crate::{base_subject, dateparse, msgidparse, MailHeader, MailHeaderMap};
+
+/// The information about a message that is needed to thread it. This can be
+/// extracted from the headers of a message using `from_headers`, or filled
+/// in directly if the data comes from somewhere else (e.g. a database).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThreadMessage {
+    /// The message ID of the message, without angle brackets.
+    pub message_id: Option<String>,
+    /// The message IDs this message refers to, oldest first. This is the
+    /// contents of the References header or, if that is missing, the first
+    /// message ID in the In-Reply-To header.
+    pub references: Vec<String>,
+    /// The decoded Subject of the message.
+    pub subject: String,
+    /// The sent date of the message, as a UNIX timestamp.
+    pub date: Option<i64>,
+}
+
+impl ThreadMessage {
+    /// Extracts the threading information from the headers of a message.
+    /// Header values that cannot be parsed are treated as if they were absent.
+    ///
+    /// # Examples
+    /// ```
+    ///     use mailparse::parse_mail;
+    ///     use mailparse::threading::ThreadMessage;
+    ///     let mail = parse_mail(concat!(
+    ///             "Message-ID: <2@example.com>\n",
+    ///             "In-Reply-To: <1@example.com>\n",
+    ///             "Subject: Re: Hello\n",
+    ///             "Date: Sun, 02 Oct 2016 07:06:22 -0700\n",
+    ///             "\n",
+    ///             "Hi!").as_bytes())
+    ///         .unwrap();
+    ///     let msg = ThreadMessage::from_headers(&mail.headers);
+    ///     assert_eq!(msg.message_id, Some("2@example.com".to_string()));
+    ///     assert_eq!(msg.references, vec!["1@example.com".to_string()]);
+    ///     assert_eq!(msg.subject, "Re: Hello");
+    ///     assert_eq!(msg.date, Some(1475417182));
+    /// ```
+    pub fn from_headers(headers: &[MailHeader]) -> Self {
+        let parse_ids = |key: &str| {
+            headers
+                .get_first_value(key)
+                .and_then(|v| msgidparse(&v).ok())
+                .map(|ids| ids.to_vec())
+                .unwrap_or_default()
+        };
+
+        let mut references = parse_ids("References");
+        if references.is_empty() {
+            references = parse_ids("In-Reply-To");
+            references.truncate(1);
+        }
+        ThreadMessage {
+            message_id: parse_ids("Message-ID").into_iter().next(),
+            references,
+            subject: headers.get_first_value("Subject").unwrap_or_default(),
+            date: headers
+                .get_first_value("Date")
+                .and_then(|d| dateparse(&d).ok()),
+        }
+    }
+}
+
+/// A node in a thread tree. Each node either holds a message from the input,
+/// or is a dummy node standing in for a message that is referenced by other
+/// messages but was not itself provided, or that groups together messages
+/// with the same subject.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThreadNode {
+    /// The message ID of this node. This is `None` only for dummy nodes
+    /// created to group messages by subject.
+    pub message_id: Option<String>,
+    /// The index of this node's message in the input slice, or `None` if this
+    /// is a dummy node.
+    pub message: Option<usize>,
+    /// The replies to this message.
+    pub children: Vec<ThreadNode>,
+}
+
+impl ThreadNode {
+    /// Returns true if this is a dummy node that does not hold a message.
+    pub const fn is_dummy(&self) -> bool {
+        self.message.is_none()
+    }
+}
+
+struct Container {
+    message: Option<usize>,
+    message_id: Option<String>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+/// Returns the key used to group messages by subject: the base subject as
+/// defined by RFC 5256, lowercased since subjects are compared
+/// case-insensitively. Also returns whether the subject was marked as a
+/// reply or forward.
+fn subject_key(subject: &str) -> (String, bool) {
+    let base = base_subject(subject);
+    (base.subject.to_lowercase(), base.is_reply_or_forward)
+}
+
+struct Threader<'a> {
+    messages: &'a [ThreadMessage],
+    containers: Vec<Container>,
+    id_table: HashMap<String, usize>,
+}
+
+impl<'a> Threader<'a> {
+    fn new(messages: &'a [ThreadMessage]) -> Self {
+        Threader {
+            messages,
+            containers: Vec::new(),
+            id_table: HashMap::new(),
+        }
+    }
+
+    fn new_container(&mut self, message_id: Option<String>) -> usize {
+        self.containers.push(Container {
+            message: None,
+            message_id,
+            parent: None,
+            children: Vec::new(),
+        });
+        self.containers.len() - 1
+    }
+
+    fn container_for(&mut self, message_id: &str) -> usize {
+        if let Some(&c) = self.id_table.get(message_id) {
+            return c;
+        }
+        let c = self.new_container(Some(message_id.to_string()));
+        self.id_table.insert(message_id.to_string(), c);
+        c
+    }
+
+    /// Returns true if `ancestor` is `node` or one of its ancestors.
+    fn is_ancestor(&self, ancestor: usize, node: usize) -> bool {
+        let mut cur = Some(node);
+        while let Some(c) = cur {
+            if c == ancestor {
+                return true;
+            }
+            cur = self.containers[c].parent;
+        }
+        false
+    }
+
+    fn unlink(&mut self, child: usize) {
+        if let Some(parent) = self.containers[child].parent.take() {
+            self.containers[parent].children.retain(|&c| c != child);
+        }
+    }
+
+    fn link(&mut self, parent: usize, child: usize) {
+        if self.containers[child].parent == Some(parent) {
+            return;
+        }
+        self.unlink(child);
+        self.containers[child].parent = Some(parent);
+        self.containers[parent].children.push(child);
+    }
+
+    /// Step 1 of the algorithm: build containers for all messages and the
+    /// messages they reference, and link them into parent-child relations.
+    fn link_references(&mut self) {
+        for (ix, msg) in self.messages.iter().enumerate() {
+            let existing = msg.message_id.as_ref().map(|id| self.container_for(id));
+            let own = match existing {
+                Some(c) if self.containers[c].message.is_none() => c,
+                // Messages with no message ID, or a duplicate one, get a
+                // container of their own that can't be referenced.
+                _ => self.new_container(None),
+            };
+            self.containers[own].message = Some(ix);
+
+            let mut prev: Option<usize> = None;
+            for reference in &msg.references {
+                if msg.message_id.as_ref() == Some(reference) {
+                    continue;
+                }
+                let c = self.container_for(reference);
+                if let Some(p) = prev {
+                    if self.containers[c].parent.is_none() && !self.is_ancestor(c, p) {
+                        self.link(p, c);
+                    }
+                }
+                prev = Some(c);
+            }
+            // The message's own references are more trustworthy than what
+            // other messages implied, so they replace any existing parent. A
+            // message without references is unlinked from that parent.
+            match prev {
+                Some(p) if !self.is_ancestor(own, p) => self.link(p, own),
+                Some(_) => (),
+                None => self.unlink(own),
+            }
+        }
+    }
+
+    /// Step 3 of the algorithm: remove dummy containers without children,
+    /// and replace dummy containers with their children unless that would
+    /// put more than one child into the root set.
+    fn prune(&mut self, list: Vec<usize>, is_root: bool) -> Vec<usize> {
+        let mut result = Vec::new();
+        for c in list {
+            let children = std::mem::take(&mut self.containers[c].children);
+            let children = self.prune(children, false);
+            if self.containers[c].message.is_some() {
+                self.containers[c].children = children;
+                result.push(c);
+            } else if children.is_empty() {
+                continue;
+            } else if !is_root || children.len() == 1 {
+                result.extend(children);
+            } else {
+                self.containers[c].children = children;
+                result.push(c);
+            }
+        }
+        result
+    }
+
+    /// Returns the message that represents the container for subject and
+    /// date purposes: its own message, or that of its first child for dummies.
+    fn representative(&self, c: usize) -> Option<usize> {
+        let container = &self.containers[c];
+        container.message.or_else(|| {
+            container
+                .children
+                .first()
+                .and_then(|&f| self.representative(f))
+        })
+    }
+
+    fn subject_of(&self, c: usize) -> Option<(String, bool)> {
+        let msg = &self.messages[self.representative(c)?];
+        let (base, is_reply) = subject_key(&msg.subject);
+        if base.is_empty() {
+            None
+        } else {
+            Some((base, is_reply && self.containers[c].message.is_some()))
+        }
+    }
+
+    /// Step 5 of the algorithm: merge root containers whose messages share
+    /// the same base subject.
+    fn group_by_subject(&mut self, roots: Vec<usize>) -> Vec<usize> {
+        let mut table: HashMap<String, usize> = HashMap::new();
+        for &c in &roots {
+            let (subject, is_reply) = match self.subject_of(c) {
+                Some(s) => s,
+                None => continue,
+            };
+            let replace = match table.get(&subject) {
+                None => true,
+                Some(&old) => {
+                    let old_is_reply = matches!(self.subject_of(old), Some((_, true)));
+                    (self.containers[c].message.is_none() && self.containers[old].message.is_some())
+                        || (old_is_reply && !is_reply)
+                }
+            };
+            if replace {
+                table.insert(subject, c);
+            }
+        }
+
+        let mut result = Vec::new();
+        let mut merged = vec![false; self.containers.len()];
+        for c in roots {
+            if merged[c] {
+                continue;
+            }
+            let (subject, is_reply) = match self.subject_of(c) {
+                Some(s) => s,
+                None => {
+                    result.push(c);
+                    continue;
+                }
+            };
+            let old = table[&subject];
+            if old == c {
+                result.push(c);
+                continue;
+            }
+            let c_dummy = self.containers[c].message.is_none();
+            let old_dummy = self.containers[old].message.is_none();
+            let old_is_reply = matches!(self.subject_of(old), Some((_, true)));
+            if c_dummy && old_dummy {
+                let children = std::mem::take(&mut self.containers[c].children);
+                self.containers[old].children.extend(children);
+            } else if old_dummy || (!c_dummy && is_reply && !old_is_reply) {
+                self.containers[old].children.push(c);
+            } else {
+                let dummy = self.new_container(None);
+                self.containers[dummy].children = vec![old, c];
+                table.insert(subject, dummy);
+                match result.iter().position(|&r| r == old) {
+                    Some(pos) => result[pos] = dummy,
+                    None => {
+                        merged[old] = true;
+                        result.push(dummy);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn sort_key(&self, c: usize) -> (Option<i64>, usize) {
+        match self.representative(c) {
+            Some(m) => (self.messages[m].date, m),
+            None => (None, usize::MAX),
+        }
+    }
+
+    /// Sorts siblings by sent date, recursively. Dummy containers sort by the
+    /// date of their first child after that child list has been sorted.
+    fn sort(&mut self, list: &mut [usize]) {
+        for &c in list.iter() {
+            let mut children = std::mem::take(&mut self.containers[c].children);
+            self.sort(&mut children);
+            self.containers[c].children = children;
+        }
+        list.sort_by_key(|&c| self.sort_key(c));
+    }
+
+    fn to_node(&self, c: usize) -> ThreadNode {
+        let container = &self.containers[c];
+        ThreadNode {
+            message_id: container.message_id.clone().or_else(|| {
+                container
+                    .message
+                    .and_then(|m| self.messages[m].message_id.clone())
+            }),
+            message: container.message,
+            children: container
+                .children
+                .iter()
+                .map(|&child| self.to_node(child))
+                .collect(),
+        }
+    }
+
+    fn thread(mut self, sort_by_date: bool) -> Vec<ThreadNode> {
+        self.link_references();
+        let roots: Vec<usize> = (0..self.containers.len())
+            .filter(|&c| self.containers[c].parent.is_none())
+            .collect();
+        let roots = self.prune(roots, true);
+        let mut roots = self.group_by_subject(roots);
+        if sort_by_date {
+            self.sort(&mut roots);
+        }
+        roots.into_iter().map(|c| self.to_node(c)).collect()
+    }
+}
+
+/// Threads the given messages using Jamie Zawinski's algorithm. Messages are
+/// linked together based on their references, with dummy nodes for referenced
+/// messages that are missing. Threads whose root messages share a subject are
+/// then merged. The threads and the children of each node are left in the
+/// order the messages were encountered; use `thread_references` to have them
+/// sorted by date.
+///
+/// # Examples
+/// ```
+///     use mailparse::threading::{thread_jwz, ThreadMessage};
+///     let messages = vec![
+///         ThreadMessage {
+///             message_id: Some("2@x".to_string()),
+///             references: vec!["1@x".to_string()],
+///             subject: "Re: Lunch".to_string(),
+///             date: None,
+///         },
+///         ThreadMessage {
+///             message_id: Some("3@x".to_string()),
+///             references: vec!["1@x".to_string()],
+///             subject: "Re: Lunch".to_string(),
+///             date: None,
+///         },
+///     ];
+///     let threads = thread_jwz(&messages);
+///     assert_eq!(threads.len(), 1);
+///     // The root is a dummy for the missing message 1@x.
+///     assert!(threads[0].is_dummy());
+///     assert_eq!(threads[0].message_id, Some("1@x".to_string()));
+///     assert_eq!(threads[0].children[0].message, Some(0));
+///     assert_eq!(threads[0].children[1].message, Some(1));
+/// ```
+pub fn thread_jwz(messages: &[ThreadMessage]) -> Vec<ThreadNode> {
+    Threader::new(messages).thread(false)
+}
+
+/// Threads the given messages using the REFERENCES algorithm from RFC 5256
+/// section 3. This is the same as `thread_jwz`, except that the threads and
+/// the children of each node are sorted by date. Messages without a date
+/// sort first, and ties are broken by the order of the input.
+pub fn thread_references(messages: &[ThreadMessage]) -> Vec<ThreadNode> {
+    Threader::new(messages).thread(true)
+}
+
+/// Threads the given messages using the ORDEREDSUBJECT algorithm from RFC 5256
+/// section 3. This ignores references entirely: messages with the same base
+/// subject form a thread whose root is the earliest message, with all the
+/// other messages as its direct children in date order. Threads are sorted
+/// by the date of their root.
+///
+/// # Examples
+/// ```
+///     use mailparse::threading::{thread_orderedsubject, ThreadMessage};
+///     let msg = |subject: &str, date| ThreadMessage {
+///         subject: subject.to_string(),
+///         date: Some(date),
+///         ..Default::default()
+///     };
+///     let messages = vec![msg("Re: Lunch", 20), msg("Dinner", 15), msg("Lunch", 10)];
+///     let threads = thread_orderedsubject(&messages);
+///     assert_eq!(threads.len(), 2);
+///     assert_eq!(threads[0].message, Some(2));
+///     assert_eq!(threads[0].children[0].message, Some(0));
+///     assert_eq!(threads[1].message, Some(1));
+/// ```
+pub fn thread_orderedsubject(messages: &[ThreadMessage]) -> Vec<ThreadNode> {
+    let mut order: Vec<(String, Option<i64>, usize)> = messages
+        .iter()
+        .enumerate()
+        .map(|(ix, msg)| (subject_key(&msg.subject).0, msg.date, ix))
+        .collect();
+    order.sort();
+
+    let node = |ix: usize| ThreadNode {
+        message_id: messages[ix].message_id.clone(),
+        message: Some(ix),
+        children: Vec::new(),
+    };
+    let mut threads: Vec<(Option<i64>, ThreadNode)> = Vec::new();
+    let mut current_subject: Option<&str> = None;
+    for (subject, date, ix) in &order {
+        if current_subject == Some(subject.as_str()) {
+            threads.last_mut().unwrap().1.children.push(node(*ix));
+        } else {
+            threads.push((*date, node(*ix)));
+            current_subject = Some(subject);
+        }
+    }
+    threads.sort_by_key(|(date, node)| (*date, node.message));
+    threads.into_iter().map(|(_, node)| node).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(id: &str, refs: &[&str], subject: &str, date: i64) -> ThreadMessage {
+        ThreadMessage {
+            message_id: Some(id.to_string()),
+            references: refs.iter().map(|r| r.to_string()).collect(),
+            subject: subject.to_string(),
+            date: Some(date),
+        }
+    }
+
+    /// Renders a thread forest compactly for comparisons: messages are shown
+    /// as their input index, dummies as their message ID or `*`.
+    fn render(nodes: &[ThreadNode]) -> String {
+        nodes
+            .iter()
+            .map(|n| {
+                let label = match (n.message, &n.message_id) {
+                    (Some(m), _) => m.to_string(),
+                    (None, Some(id)) => format!("<{}>", id),
+                    (None, None) => "*".to_string(),
+                };
+                if n.children.is_empty() {
+                    label
+                } else {
+                    format!("{}({})", label, render(&n.children))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn subject_keys() {
+        assert_eq!(subject_key("Hello"), ("hello".to_string(), false));
+        assert_eq!(subject_key("AW: [list] HELLO"), ("hello".to_string(), true));
+    }
+
+    #[test]
+    fn references_build_tree() {
+        let messages = vec![
+            msg("a", &[], "Topic", 1),
+            msg("c", &["a", "b"], "Re: Topic", 3),
+            msg("b", &["a"], "Re: Topic", 2),
+            msg("d", &["a"], "Re: Topic", 4),
+        ];
+        // c is linked below b even though b is only seen later.
+        assert_eq!(render(&thread_jwz(&messages)), "0(2(1) 3)");
+
+        // b is not a reply to a, even though c's references imply it.
+        let messages = vec![
+            msg("c", &["a", "b"], "Re: Other", 3),
+            msg("a", &[], "Topic", 1),
+            msg("b", &[], "Other", 2),
+        ];
+        assert_eq!(render(&thread_references(&messages)), "1 2(0)");
+    }
+
+    #[test]
+    fn dummy_containers() {
+        // Two replies to a message we don't have: the dummy stays as the root.
+        let messages = vec![
+            msg("b", &["a"], "Re: Topic", 2),
+            msg("c", &["a"], "Re: Topic", 3),
+        ];
+        assert_eq!(render(&thread_references(&messages)), "<a>(0 1)");
+
+        // A single reply to a missing message gets promoted to the root.
+        let messages = vec![msg("b", &["a"], "Re: Topic", 2)];
+        assert_eq!(render(&thread_references(&messages)), "0");
+
+        // Missing messages in the middle of a chain are removed.
+        let messages = vec![
+            msg("a", &[], "Topic", 1),
+            msg("c", &["a", "b"], "Re: Topic", 3),
+        ];
+        assert_eq!(render(&thread_references(&messages)), "0(1)");
+    }
+
+    #[test]
+    fn loops_and_duplicates() {
+        let messages = vec![
+            msg("a", &["b"], "One", 1),
+            msg("b", &["a"], "Two", 2),
+            msg("a", &[], "Three", 3),
+            msg("c", &["c"], "Four", 4),
+        ];
+        let threads = thread_references(&messages);
+        // a and b claim to reply to each other; the second link is dropped.
+        assert_eq!(render(&threads), "1(0) 2 3");
+        assert_eq!(threads[1].message_id, Some("a".to_string()));
+    }
+
+    #[test]
+    fn subject_grouping() {
+        // A reply that lost its references is attached to the original.
+        let messages = vec![
+            msg("a", &[], "Topic", 1),
+            msg("b", &[], "Re: Topic", 2),
+            msg("c", &[], "Other", 3),
+        ];
+        assert_eq!(render(&thread_references(&messages)), "0(1) 2");
+
+        // Two non-replies with the same subject get a common dummy parent.
+        let messages = vec![msg("a", &[], "Topic", 1), msg("b", &[], "Topic", 2)];
+        assert_eq!(render(&thread_references(&messages)), "*(0 1)");
+
+        // A dummy root absorbs other roots with the same subject.
+        let messages = vec![
+            msg("b", &["a"], "Re: Topic", 2),
+            msg("c", &["a"], "Re: Topic", 3),
+            msg("d", &[], "Re: Topic", 4),
+        ];
+        assert_eq!(render(&thread_references(&messages)), "<a>(0 1 2)");
+
+        // Empty subjects are never grouped.
+        let messages = vec![msg("a", &[], "", 1), msg("b", &[], "Re:", 2)];
+        assert_eq!(render(&thread_references(&messages)), "0 1");
+    }
+
+    #[test]
+    fn date_sorting() {
+        let messages = vec![
+            msg("c", &["a"], "Re: Topic", 30),
+            msg("x", &[], "Unrelated", 5),
+            msg("a", &[], "Topic", 10),
+            msg("b", &["a"], "Re: Topic", 20),
+        ];
+        assert_eq!(render(&thread_jwz(&messages)), "2(0 3) 1");
+        assert_eq!(render(&thread_references(&messages)), "1 2(3 0)");
+    }
+
+    #[test]
+    fn ordered_subject() {
+        let messages = vec![
+            msg("a", &[], "Re: Topic", 30),
+            msg("b", &[], "topic", 40),
+            msg("c", &[], "Topic", 20),
+            msg("d", &[], "Else", 25),
+        ];
+        assert_eq!(render(&thread_orderedsubject(&messages)), "2(0 1) 3");
+    }
+}